@@ -1,6 +1,14 @@
-use std::{collections::HashMap, error::Error};
+use std::{collections::HashMap, error::Error, rc::Rc, sync::Arc};
 use ash::vk::{self, CommandBuffer, DescriptorSet};
-use ferrum_render::{CommandPool, FrameSync, GPUBuffer, RenderContext, RenderPass, RenderPipeline, Texture};
+#[cfg(feature = "watch")]
+use ferrum_render::ShaderHotReload;
+use ferrum_render::{needs_recreate, ClusteredRenderer, CommandPool, CommandPoolBuilder, ComputePipeline, DeferredRenderer, DeferredResource, DeviceHandle, FramePacer, FramePacerBuilder, FrameSubmit, FrameSync, GBufferTargets, GPUBuffer, GraphicsDevice, PresentBatch, RenderContext, RenderPass, RenderPipeline, ShadowRenderer, ShadowView, Swapchain, SwapchainError, SyncError, Synchronization2, Texture, TransientImage, TransientImageDesc, TransientImages, TransientLifetime, VulkanError, VulkanResult, WindowManager};
+use winit::window::WindowId;
+
+#[cfg(feature = "fsr1")]
+use ferrum_render::{Fsr1Pass, Fsr1Targets};
+#[cfg(feature = "fsr2")]
+use ferrum_render::{TemporalUpscalePass, TemporalUpscaleTargets};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ResourceAccess {
@@ -25,29 +33,164 @@ pub struct RenderPassNode {
     pub execute: Box<dyn Fn(&mut RenderGraphResource, &RenderContext, u32) -> Result<(), Box<dyn Error>>>,
 }
 
+pub type RawPass = Box<dyn Fn(&mut RenderGraphResource, &RenderContext, u32) -> Result<(), Box<dyn Error>>>;
+
+/// Records into the graph-owned compute command buffer of the current frame
+pub type ComputePass = Box<dyn Fn(&mut RenderGraphResource, &RenderContext, CommandBuffer) -> Result<(), Box<dyn Error>>>;
+
+/// Swapchain image of one window acquired for the current frame
+pub struct WindowFrame<'a> {
+    pub window: &'a WindowManager,
+    pub image_index: u32,
+    /// Already begun, ended by the graph after all passes of the window
+    pub command_buffer: CommandBuffer
+}
+
+/// Records the output of one window, see [`RenderGraph::add_window_pass`]
+pub type WindowPass = Box<dyn Fn(&mut RenderGraphResource, &RenderContext, &WindowFrame) -> Result<(), Box<dyn Error>>>;
+
+pub struct WindowNode {
+    pub id: WindowId,
+    pub name: &'static str,
+    /// Transient images the pass uses, see [`RenderGraph::create_transient_image`]
+    pub transients: Vec<String>,
+    pub pass: WindowPass
+}
+
+/// Transient images of one window with the plan they were created for
+struct WindowTransients {
+    plan: Vec<(String, TransientImageDesc, TransientLifetime)>,
+    extent: vk::Extent2D,
+    images: TransientImages
+}
+
+/// Per window semaphores and command buffers, one per frame slot
+struct WindowTarget {
+    device: DeviceHandle,
+    sync: Vec<FrameSync>,
+    /// Present semaphores by swapchain image, a present may still wait on one after its frame slot is reused
+    render_finished: Vec<vk::Semaphore>,
+    #[allow(dead_code)]
+    command_pool: CommandPool,
+    command_buffers: Vec<CommandBuffer>
+}
+
+impl WindowTarget {
+
+    fn new(device: &DeviceHandle, family_index: u32, frame_count: usize) -> Self {
+
+        let command_pool = CommandPoolBuilder::new()
+            .device(device)
+            .family_index(family_index)
+            .build();
+
+        let command_buffers = command_pool.create_command_buffers(device, frame_count as u32, vk::CommandBufferLevel::PRIMARY);
+        let sync = (0..frame_count).map(|_| FrameSync::new(device)).collect();
+
+        Self { device: device.clone(), sync, render_finished: vec![], command_pool, command_buffers }
+    }
+
+    /// Recreated when the swapchain image count changes, old semaphores are deferred
+    fn present_semaphore(&mut self, image_index: u32, image_count: usize) -> VulkanResult<vk::Semaphore> {
+
+        if self.render_finished.len() != image_count {
+            for semaphore in self.render_finished.drain(..) {
+                self.device.defer(DeferredResource::Semaphore(semaphore));
+            }
+
+            for _ in 0..image_count {
+                let semaphore = unsafe { self.device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None) }
+                    .map_err(|e| VulkanError::vk(e, |e| VulkanError::Sync(SyncError::CreateSemaphoreFailed(e))))?;
+                self.render_finished.push(semaphore);
+            }
+        }
+
+        Ok(self.render_finished[image_index as usize])
+    }
+}
+
+impl Drop for WindowTarget {
+    fn drop(&mut self) {
+        defer_frame_sync(&self.device, self.sync.drain(..));
+
+        for semaphore in self.render_finished.drain(..) {
+            self.device.defer(DeferredResource::Semaphore(semaphore));
+        }
+    }
+}
+
+fn defer_frame_sync(device: &DeviceHandle, sync: impl Iterator<Item = FrameSync>) {
+    for sync in sync {
+        device.defer(DeferredResource::Semaphore(sync.image_available));
+        device.defer(DeferredResource::Semaphore(sync.render_finished));
+        device.defer(DeferredResource::Fence(sync.fence));
+    }
+}
+
 #[derive(Default)]
 pub struct RenderGraphResource {
     pub pipeline: HashMap<&'static str, RenderPipeline>,
+    pub compute_pipeline: HashMap<&'static str, ComputePipeline>,
     pub buffers: HashMap<&'static str, GPUBuffer>,
     pub descriptor_set: HashMap<&'static str, DescriptorSet>,
     pub texture: HashMap<&'static str, Texture>,
     pub command_buffers: HashMap<u32, CommandBuffer>,
     pub command_pool: HashMap<&'static str, CommandPool>,
-    pub render_pass: HashMap<&'static str, RenderPass>
+    pub render_pass: HashMap<&'static str, RenderPass>,
+    /// Updated by the application with the camera and lights before each frame
+    pub clustered: HashMap<&'static str, ClusteredRenderer>,
+    /// Updated by the application with the camera and lights before each frame
+    pub deferred: HashMap<&'static str, DeferredRenderer>,
+    /// Built by [`RenderGraph::add_deferred_passes`] over the transient images, keyed by renderer
+    pub gbuffer_targets: HashMap<&'static str, GBufferTargets>,
+    /// Transient images of the window being recorded, valid until the graph recreates them
+    pub transient: HashMap<String, TransientImage>,
+    /// Updated by the application with the camera and lights before the renderers that read them
+    pub shadows: HashMap<&'static str, ShadowRenderer>,
+    #[cfg(feature = "fsr1")]
+    pub fsr1_targets: HashMap<&'static str, Fsr1Targets>,
+    /// Jitter of the frame being rendered is read from here by scene passes
+    #[cfg(feature = "fsr2")]
+    pub temporal_targets: HashMap<&'static str, TemporalUpscaleTargets>
 }
 
 #[derive(Default)]
 pub struct RenderGraph {
     pub resources: RenderGraphResource,
-    pub nodes: HashMap<&'static str, Box<dyn Fn(&mut RenderGraphResource, &RenderContext, u32) -> Result<(), Box<dyn Error>>>>,
+    pub nodes: Vec<(&'static str, RawPass)>,
+    /// Recorded before raw passes every frame, in insertion order
+    pub compute_nodes: Vec<(&'static str, ComputePass)>,
+    /// Used when the device has no synchronization2 support
     pub sync: Vec<FrameSync>,
-    pub current_frame: usize
+    /// Timeline semaphore frame pacing, created on first frame when supported
+    pub pacer: Option<FramePacer>,
+    pub current_frame: usize,
+    /// Per window outputs, when not empty raw passes are not executed
+    pub window_nodes: Vec<WindowNode>,
+    frames_in_flight: Option<usize>,
+    compute_command_pool: Option<CommandPool>,
+    compute_command_buffers: Vec<CommandBuffer>,
+    window_targets: HashMap<WindowId, WindowTarget>,
+    transient_descs: HashMap<String, TransientImageDesc>,
+    window_transients: HashMap<WindowId, WindowTransients>,
+    /// One per frame slot, shared by all windows
+    window_fences: Vec<vk::Fence>,
+    /// Set on first frame, frame sync objects are queued to it on drop
+    device: Option<DeviceHandle>,
+    #[cfg(feature = "watch")]
+    shader_reload: Option<ShaderHotReload>
 }
 
 impl RenderGraph {
 
     pub fn new() -> Self {
-        Self { ..Default::default() }
+        Self::default()
+    }
+
+    /// Frames recorded ahead of the GPU, independent of swapchain image count.
+    /// Default: 2. Has no effect after the first frame
+    pub fn set_frames_in_flight(&mut self, count: usize) {
+        self.frames_in_flight = Some(count);
     }
 
     pub fn register_texture(&mut self, name: &'static str, tex: Texture) {
@@ -74,87 +217,942 @@ impl RenderGraph {
         self.resources.pipeline.insert(name, pipeline);
     }
 
+    /// Reloaded pipelines replace the registered ones with the same name before a frame is recorded
+    #[cfg(feature = "watch")]
+    pub fn set_shader_reload(&mut self, reload: ShaderHotReload) {
+        self.shader_reload = Some(reload);
+    }
+
+    #[cfg(feature = "watch")]
+    pub fn shader_reload(&self) -> Option<&ShaderHotReload> {
+        self.shader_reload.as_ref()
+    }
+
+    /// Replaces the targets with the same name, register new ones after the swapchain is recreated
+    #[cfg(feature = "fsr1")]
+    pub fn register_fsr1_targets(&mut self, name: &'static str, targets: Fsr1Targets) {
+        self.resources.fsr1_targets.insert(name, targets);
+    }
+
+    pub fn register_clustered_renderer(&mut self, name: &'static str, renderer: ClusteredRenderer) {
+        self.resources.clustered.insert(name, renderer);
+    }
+
+    pub fn register_deferred_renderer(&mut self, name: &'static str, renderer: DeferredRenderer) {
+        self.resources.deferred.insert(name, renderer);
+    }
+
+    pub fn register_shadow_renderer(&mut self, name: &'static str, renderer: ShadowRenderer) {
+        self.resources.shadows.insert(name, renderer);
+    }
+
+    /// Replacing the targets drops the history, register new ones after the swapchain is recreated
+    #[cfg(feature = "fsr2")]
+    pub fn register_temporal_targets(&mut self, name: &'static str, targets: TemporalUpscaleTargets) {
+        self.resources.temporal_targets.insert(name, targets);
+    }
+
+    pub fn register_compute_pipeline(&mut self, name: &'static str, pipeline: ComputePipeline) {
+        self.resources.compute_pipeline.insert(name, pipeline);
+    }
+
     pub fn add_raw_pass<F>(&mut self, name: &'static str, clojure: F)
         where F: Fn(&mut RenderGraphResource, &RenderContext, u32) -> Result<(), Box<dyn Error>> + 'static
     {
-        self.nodes.insert(name, Box::new(clojure));
+        self.nodes.push((name, Box::new(clojure)));
     }
 
-    pub fn compile(&mut self) {
+    ///
+    /// Adds a compute pass. The command buffer is already begun, the graph
+    /// ends it with a barrier making compute writes visible to the raw passes
+    ///
+    /// # Example
+    /// ```
+    /// graph.add_compute_pass("particles", |res, ctx, cbuf| {
+    ///     let pipeline = res.compute_pipeline.get("simulate").ok_or("no pipeline")?;
+    ///     pipeline.dispatch_threads(ctx.device.raw_device(), cbuf, [PARTICLES, 1, 1], [64, 1, 1]);
+    ///     Ok(())
+    /// });
+    /// ```
+    ///
+    pub fn add_compute_pass<F>(&mut self, name: &'static str, clojure: F)
+        where F: Fn(&mut RenderGraphResource, &RenderContext, CommandBuffer) -> Result<(), Box<dyn Error>> + 'static
+    {
+        self.compute_nodes.push((name, Box::new(clojure)));
+    }
 
+    ///
+    /// Adds a pass rendering into the swapchain image of the window `id`.
+    /// All windows are submitted together and presented with one `vkQueuePresentKHR`,
+    /// raw passes are not executed once a window pass is added
+    ///
+    /// # Example
+    /// ```
+    /// let preview = ctx.add_window(preview_window)?;
+    ///
+    /// graph.add_window_pass(ctx.window.id(), "Scene", |res, ctx, frame| {
+    ///     frame.window.begin_rendering(&ctx.device, frame.command_buffer, frame.image_index, [0.0, 0.0, 0.0, 1.0])?;
+    ///     // draw
+    ///     frame.window.end_rendering(&ctx.device, frame.command_buffer, frame.image_index)?;
+    ///     Ok(())
+    /// });
+    ///
+    /// graph.add_window_pass(preview, "Preview", |res, ctx, frame| { ... });
+    /// ```
+    ///
+    pub fn add_window_pass<F>(&mut self, id: WindowId, name: &'static str, clojure: F)
+        where F: Fn(&mut RenderGraphResource, &RenderContext, &WindowFrame) -> Result<(), Box<dyn Error>> + 'static
+    {
+        self.add_transient_window_pass(id, name, &[], clojure);
     }
 
-    pub fn execute(&mut self, ctx: &RenderContext) {
+    ///
+    /// Declares an image the graph creates for the window passes that use it. It lives from the first
+    /// to the last of those passes, images with disjoint lifetimes share memory. Contents are undefined
+    /// at the first use, images are recreated when the window is resized
+    ///
+    pub fn create_transient_image(&mut self, name: impl Into<String>, desc: TransientImageDesc) {
+        self.transient_descs.insert(name.into(), desc);
+    }
 
-        for (name, pass) in &self.nodes {
+    ///
+    /// Same as [`Self::add_window_pass`] using the transient images `transients`,
+    /// found in [`RenderGraphResource::transient`] while the pass records
+    ///
+    /// # Example
+    /// ```ignore
+    /// graph.create_transient_image("bloom", TransientImageDesc::new(Format::R16G16B16A16_SFLOAT, usage));
+    ///
+    /// graph.add_transient_window_pass(id, "Bloom", &["bloom"], |res, ctx, frame| {
+    ///     let bloom = res.transient["bloom"];
+    ///     // bloom.raw starts in UNDEFINED layout
+    ///     Ok(())
+    /// });
+    /// ```
+    ///
+    pub fn add_transient_window_pass<F>(&mut self, id: WindowId, name: &'static str, transients: &[&str], clojure: F)
+        where F: Fn(&mut RenderGraphResource, &RenderContext, &WindowFrame) -> Result<(), Box<dyn Error>> + 'static
+    {
+        self.window_nodes.push(WindowNode {
+            id,
+            name,
+            transients: transients.iter().map(|x| x.to_string()).collect(),
+            pass: Box::new(clojure)
+        });
+    }
+
+    ///
+    /// Adds an FSR 1.0 upscale of the targets registered as `targets` to the window `id`.
+    /// Goes between the window pass rendering the scene and the one sampling [`Fsr1Targets::output`]
+    ///
+    /// # Example
+    /// ```
+    /// let input = Fsr1Input { view: scene.view, extent: scene_extent };
+    /// graph.register_fsr1_targets("fsr", fsr.create_targets(device, &memory_prop, input, extent)?);
+    ///
+    /// graph.add_window_pass(id, "Scene", |res, ctx, frame| { ... });
+    /// graph.add_fsr1_pass(id, "FSR1", fsr, "fsr");
+    /// graph.add_window_pass(id, "Present", |res, ctx, frame| { ... });
+    /// ```
+    ///
+    #[cfg(feature = "fsr1")]
+    pub fn add_fsr1_pass(&mut self, id: WindowId, name: &'static str, pass: Fsr1Pass, targets: &'static str) {
+        self.add_window_pass(id, name, move |res, ctx, frame| {
+            let targets = res.fsr1_targets.get(targets).ok_or("no FSR1 targets")?;
+            pass.record(ctx.device.raw_device(), frame.command_buffer, targets);
+            Ok(())
+        });
+    }
 
-            if self.sync.is_empty() {
-                let frame_count = ctx.window.frame_buffers.raw.len();
-                for _ in 0..frame_count {
-                    self.sync.push(FrameSync::new(ctx.device.raw_device()));
+    ///
+    /// Adds a temporal upscale of the targets registered as `targets` to the window `id`,
+    /// between the scene pass and the one sampling [`TemporalUpscaleTargets::output_view`].
+    /// The jitter advances after each recorded frame
+    ///
+    /// # Example
+    /// ```
+    /// graph.register_temporal_targets("taa", taa.create_targets(device, &memory_prop, inputs, extent)?);
+    ///
+    /// graph.add_window_pass(id, "Scene", |res, ctx, frame| {
+    ///     let jitter = res.temporal_targets["taa"].jitter_clip_offset();
+    ///     // draw with the jittered projection
+    ///     Ok(())
+    /// });
+    /// graph.add_temporal_upscale_pass(id, "TAAU", taa, "taa");
+    /// graph.add_window_pass(id, "Present", |res, ctx, frame| { ... });
+    ///
+    /// // camera cut
+    /// graph.resources.temporal_targets.get_mut("taa").unwrap().reset();
+    /// ```
+    ///
+    #[cfg(feature = "fsr2")]
+    pub fn add_temporal_upscale_pass(&mut self, id: WindowId, name: &'static str, pass: TemporalUpscalePass, targets: &'static str) {
+        self.add_window_pass(id, name, move |res, ctx, frame| {
+            let targets = res.temporal_targets.get_mut(targets).ok_or("no temporal upscale targets")?;
+            pass.record(ctx.device.raw_device(), frame.command_buffer, targets);
+            Ok(())
+        });
+    }
+
+    ///
+    /// Adds a forward+ pass to the window `id`: light culling of the renderer registered
+    /// as `renderer` is recorded first, then `draw` renders the scene with it
+    ///
+    /// # Example
+    /// ```
+    /// graph.register_clustered_renderer("forward", clustered);
+    ///
+    /// graph.add_clustered_pass(id, "Forward+", "forward", move |clustered, res, ctx, frame| {
+    ///     let device = ctx.device.raw_device();
+    ///
+    ///     frame.window.begin_rendering(&ctx.device, frame.command_buffer, frame.image_index, [0.0, 0.0, 0.0, 1.0])?;
+    ///     clustered.bind(device, frame.command_buffer, frame.window.extent());
+    ///     clustered.draw(device, frame.command_buffer, &mesh, &PbrPushConstants::new(model, &material));
+    ///     frame.window.end_rendering(&ctx.device, frame.command_buffer, frame.image_index)?;
+    ///     Ok(())
+    /// });
+    ///
+    /// // before graph.execute
+    /// graph.resources.clustered.get_mut("forward").unwrap().update(device, &camera, extent, &lights);
+    /// ```
+    ///
+    pub fn add_clustered_pass<F>(&mut self, id: WindowId, name: &'static str, renderer: &'static str, draw: F)
+        where F: Fn(&ClusteredRenderer, &RenderGraphResource, &RenderContext, &WindowFrame) -> Result<(), Box<dyn Error>> + 'static
+    {
+        self.add_window_pass(id, name, move |res, ctx, frame| {
+            let clustered = res.clustered.get(renderer).ok_or("no clustered renderer")?;
+            clustered.record_culling(ctx.device.raw_device(), frame.command_buffer);
+            draw(clustered, res, ctx, frame)
+        });
+    }
+
+    ///
+    /// Adds the deferred passes of the renderer registered as `renderer` to the window `id`:
+    /// "GBuffer" where `draw` renders the scene, "Deferred lighting" and "Deferred composition"
+    /// writing [`DeferredRenderer::view`] into the window. The GBuffer and the lit image are
+    /// transient images named `"{renderer}.gbuffer{i}"`, `"{renderer}.depth"` and `"{renderer}.lighting"`,
+    /// the GBuffer memory is reused by the passes after the composition.
+    /// Fails if the renderer is not registered
+    ///
+    /// # Example
+    /// ```ignore
+    /// graph.register_deferred_renderer("deferred", deferred);
+    ///
+    /// graph.add_deferred_passes(id, "deferred", move |deferred, res, ctx, frame| {
+    ///     deferred.draw(ctx.device.raw_device(), frame.command_buffer, &mesh, &PbrPushConstants::new(model, &material));
+    ///     Ok(())
+    /// })?;
+    ///
+    /// // before graph.execute
+    /// graph.resources.deferred.get_mut("deferred").unwrap().update(device, &camera, extent, &lights);
+    /// ```
+    ///
+    pub fn add_deferred_passes<F>(&mut self, id: WindowId, renderer: &'static str, draw: F) -> VulkanResult<()>
+        where F: Fn(&DeferredRenderer, &RenderGraphResource, &RenderContext, &WindowFrame) -> Result<(), Box<dyn Error>> + 'static
+    {
+        let descs = self.resources.deferred.get(renderer)
+            .ok_or(VulkanError::missing("RenderGraph", "deferred renderer"))?
+            .target_descs();
+
+        // Цвета, глубина и освещение в порядке target_descs
+        let color_count = descs.len() - 2;
+        let names = (0..color_count).map(|i| format!("{renderer}.gbuffer{i}"))
+            .chain([format!("{renderer}.depth"), format!("{renderer}.lighting")])
+            .collect::<Vec<_>>();
+
+        for (name, desc) in names.iter().zip(descs) {
+            self.create_transient_image(name.clone(), desc);
+        }
+
+        let uses = names.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+        let gbuffer_names = names.clone();
+
+        self.add_transient_window_pass(id, "GBuffer", &uses, move |res, ctx, frame| {
+            let images = gbuffer_names.iter()
+                .map(|x| res.transient.get(x))
+                .collect::<Option<Vec<_>>>()
+                .ok_or("no GBuffer images")?;
+
+            let views = images.iter().map(|x| x.view).collect::<Vec<_>>();
+
+            // Наборы дескрипторов пересоздаются вместе с транзиентными образами
+            if res.gbuffer_targets.get(renderer).is_none_or(|x| x.views() != views) {
+                let deferred = res.deferred.get(renderer).ok_or("no deferred renderer")?;
+                let handles = images.iter().map(|x| (x.raw, x.view)).collect::<Vec<_>>();
+                let targets = deferred.bind_targets(ctx.device.raw_device(), images[0].extent, &handles)?;
+                res.gbuffer_targets.insert(renderer, targets);
+            }
+
+            let deferred = res.deferred.get(renderer).ok_or("no deferred renderer")?;
+            let gbuffer = &res.gbuffer_targets[renderer];
+
+            deferred.begin_gbuffer(&ctx.device, frame.command_buffer, gbuffer)?;
+            draw(deferred, res, ctx, frame)?;
+            deferred.end_gbuffer(&ctx.device, frame.command_buffer, gbuffer)?;
+            Ok(())
+        });
+
+        self.add_transient_window_pass(id, "Deferred lighting", &uses, move |res, ctx, frame| {
+            let deferred = res.deferred.get(renderer).ok_or("no deferred renderer")?;
+            let gbuffer = res.gbuffer_targets.get(renderer).ok_or("no GBuffer targets")?;
+
+            deferred.record_lighting(ctx.device.raw_device(), frame.command_buffer, gbuffer);
+            Ok(())
+        });
+
+        // Композиция читает только освещение, GBuffer свободен после lighting
+        self.add_transient_window_pass(id, "Deferred composition", &uses[color_count + 1..], move |res, ctx, frame| {
+            let deferred = res.deferred.get(renderer).ok_or("no deferred renderer")?;
+            let gbuffer = res.gbuffer_targets.get(renderer).ok_or("no GBuffer targets")?;
+
+            frame.window.begin_rendering(&ctx.device, frame.command_buffer, frame.image_index, [0.0, 0.0, 0.0, 1.0])?;
+            deferred.record_composite(ctx.device.raw_device(), frame.command_buffer, gbuffer, frame.window.extent());
+            frame.window.end_rendering(&ctx.device, frame.command_buffer, frame.image_index)?;
+            Ok(())
+        });
+
+        Ok(())
+    }
+
+    ///
+    /// Adds "Shadow cascades" and "Shadow atlas" of the renderer registered as `shadows` to the window `id`,
+    /// before the passes that sample them. `draw` renders the shadow casters into every [`ShadowView`]
+    ///
+    /// # Example
+    /// ```ignore
+    /// graph.register_shadow_renderer("shadows", shadows);
+    ///
+    /// graph.add_shadow_passes(id, "shadows", move |shadows, view, res, ctx, frame| {
+    ///     shadows.draw(ctx.device.raw_device(), frame.command_buffer, &mesh, &ShadowPushConstants::new(model, view));
+    ///     Ok(())
+    /// });
+    ///
+    /// graph.add_deferred_passes(id, "deferred", move |deferred, res, ctx, frame| {
+    ///     // the compute binding stays for the lighting pass
+    ///     deferred.bind_shadows(ctx.device.raw_device(), frame.command_buffer, &res.shadows["shadows"]);
+    ///     ...
+    /// })?;
+    ///
+    /// // before graph.execute, shadows first: they write Light::shadow
+    /// graph.resources.shadows.get_mut("shadows").unwrap().update(device, &camera, &mut lights);
+    /// graph.resources.deferred.get_mut("deferred").unwrap().update(device, &camera, extent, &lights);
+    /// ```
+    ///
+    pub fn add_shadow_passes<F>(&mut self, id: WindowId, shadows: &'static str, draw: F)
+        where F: Fn(&ShadowRenderer, &ShadowView, &RenderGraphResource, &RenderContext, &WindowFrame) -> Result<(), Box<dyn Error>> + 'static
+    {
+        let draw = Rc::new(draw);
+        let draw_cascades = draw.clone();
+
+        // Ошибка первого вида прерывает остальные, проходы всё равно завершаются барьерами
+        self.add_window_pass(id, "Shadow cascades", move |res, ctx, frame| {
+            let res: &RenderGraphResource = res;
+            let renderer = res.shadows.get(shadows).ok_or("no shadow renderer")?;
+            let mut result = Ok(());
+
+            renderer.record_cascades(&ctx.device, frame.command_buffer, |view| {
+                if result.is_ok() {
+                    result = draw_cascades(renderer, view, res, ctx, frame);
+                }
+            });
+
+            result
+        });
+
+        self.add_window_pass(id, "Shadow atlas", move |res, ctx, frame| {
+            let res: &RenderGraphResource = res;
+            let renderer = res.shadows.get(shadows).ok_or("no shadow renderer")?;
+            let mut result = Ok(());
+
+            renderer.record_atlas(&ctx.device, frame.command_buffer, |view| {
+                if result.is_ok() {
+                    result = draw(renderer, view, res, ctx, frame);
                 }
+            });
+
+            result
+        });
+    }
+
+    /// Drops passes and per window resources of `id`, call before [`RenderContext::remove_window`]
+    pub fn remove_window(&mut self, _ctx: &RenderContext, id: WindowId) {
+
+        self.window_nodes.retain(|x| x.id != id);
+        self.window_transients.remove(&id);
+
+        // Семафоры и command pool уйдут в очередь удаления устройства
+        self.window_targets.remove(&id);
+    }
+
+    fn record_compute_passes(&mut self, ctx: &RenderContext, frame_slot: usize, frame_count: usize) -> VulkanResult<Option<CommandBuffer>> {
+
+        if self.compute_nodes.is_empty() {
+            return Ok(None);
+        }
+
+        let device = ctx.device.raw_device();
+
+        if self.compute_command_buffers.is_empty() {
+            let pool = CommandPoolBuilder::new()
+                .device(device)
+                .family_index(ctx.device.universal_queue.graphics_index())
+                .build();
+
+            self.compute_command_buffers = pool.create_command_buffers(device, frame_count as u32, vk::CommandBufferLevel::PRIMARY);
+            self.compute_command_pool = Some(pool);
+        }
+
+        let command_buffer = self.compute_command_buffers[frame_slot];
+
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe {
+            device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                .and_then(|_| device.begin_command_buffer(command_buffer, &begin_info))
+                .map_err(|e| VulkanError::vk(e, |e| VulkanError::Sync(SyncError::RecordCommandBufferFailed(e))))?;
+        }
+
+        for (name, pass) in &self.compute_nodes {
+            if let Err(err) = pass(&mut self.resources, ctx, command_buffer) {
+                log::error!("Error in {:?} compute pass: {:?}", name, err);
             }
+        }
 
-            let current_frame = self.current_frame;
-            let fence = self.sync[current_frame].fence;
-            let swapchain = &ctx.window.swapchain;
-            let queue = ctx.device.universal_queue.raw_graphics();
-            let device = ctx.device.raw_device();
-            let sync = &self.sync;
+        let barrier = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(
+                vk::AccessFlags::SHADER_READ |
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ |
+                vk::AccessFlags::INDEX_READ |
+                vk::AccessFlags::INDIRECT_COMMAND_READ
+            );
 
-            // 2. Дождаться завершения предыдущего кадра
-            unsafe {
-                device.wait_for_fences(&[fence], false, u64::MAX).unwrap();
-                device.reset_fences(&[fence]).unwrap();
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::DRAW_INDIRECT |
+                vk::PipelineStageFlags::VERTEX_INPUT |
+                vk::PipelineStageFlags::VERTEX_SHADER |
+                vk::PipelineStageFlags::FRAGMENT_SHADER |
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[barrier],
+                &[],
+                &[]
+            );
+
+            device.end_command_buffer(command_buffer)
+                .map_err(|e| VulkanError::vk(e, |e| VulkanError::Sync(SyncError::RecordCommandBufferFailed(e))))?;
+        }
+
+        Ok(Some(command_buffer))
+    }
+
+    pub fn compile(&mut self) {
+
+    }
+
+    ///
+    /// Records and presents one frame. The swapchain is recreated on
+    /// `ERROR_OUT_OF_DATE_KHR` / `SUBOPTIMAL_KHR`, frames are skipped while minimized.
+    ///
+    /// On [`VulkanError::DeviceLost`] the render context and every GPU resource
+    /// have to be created again, other errors only drop the frame
+    ///
+    pub fn execute(&mut self, ctx: &mut RenderContext) -> VulkanResult<()> {
+
+        // Граница кадра: старый пайплайн уходит в очередь удаления и живёт, пока кадры в полёте его используют
+        #[cfg(feature = "watch")]
+        if let Some(reload) = &mut self.shader_reload {
+            for (name, pipeline) in reload.poll(ctx) {
+                self.resources.pipeline.insert(name, pipeline);
             }
+        }
+
+        if !self.window_nodes.is_empty() {
+            return self.execute_windows(ctx);
+        }
 
-            // 3. Получить новое изображение из swapchain
-            let (image_index, b) = unsafe {
-                swapchain.swapchain_load.acquire_next_image(
-                    swapchain.raw,
-                    u64::MAX,
-                    sync[current_frame].image_available,
-                    vk::Fence::null(),
-                )
-            }.unwrap();
+        if self.nodes.is_empty() && self.compute_nodes.is_empty() {
+            return Ok(());
+        }
+
+        let device = ctx.device.clone();
+        self.device.get_or_insert_with(|| device.raw_device().clone());
 
-            //log::info!("current frame: {} current image_index: {}", current_frame, image_index);
+        if ctx.window.minimized && !ctx.window.recreate(&device)? {
+            return Ok(());
+        }
 
-            // 4. Выполнить рендер-пассы (теперь безопасно)
+        match &device.synchronization2 {
+            Some(sync2) => self.execute_paced(ctx, sync2),
+            None => self.execute_legacy(ctx)
+        }
+    }
+
+    fn record_passes(&mut self, ctx: &RenderContext, frame_slot: usize, frame_count: usize, image_index: u32) -> VulkanResult<Vec<CommandBuffer>> {
+
+        // Compute пассы записываются первыми, в том же сабмите
+        let mut command_buffers = vec![];
+        command_buffers.extend(self.record_compute_passes(ctx, frame_slot, frame_count)?);
+
+        // Выполнить рендер-пассы
+        for (name, pass) in &self.nodes {
             if let Err(err) = pass(&mut self.resources, ctx, image_index) {
                 log::error!("Error in {:?} pass: {:?}", name, err);
+            }
+        }
+
+        if let Some(cbuf) = self.resources.command_buffers.get(&image_index) {
+            command_buffers.push(*cbuf);
+        }
+
+        Ok(command_buffers)
+    }
+
+    fn execute_paced(&mut self, ctx: &mut RenderContext, sync2: &Synchronization2) -> VulkanResult<()> {
+
+        let graphics_device = ctx.device.clone();
+        let device = graphics_device.raw_device();
+        let queue = graphics_device.universal_queue.raw_graphics();
+
+        if self.pacer.is_none() {
+            let pacer = FramePacerBuilder::new()
+                .with_device(device)
+                .with_frames_in_flight(self.frames_in_flight.unwrap_or(2))
+                .with_image_count(ctx.window.image_count())
+                .build()?;
+
+            self.pacer = Some(pacer);
+        }
+
+        let pacer = self.pacer.as_mut().unwrap();
+        let frame_count = pacer.frames_in_flight();
+
+        pacer.set_image_count(device, ctx.window.image_count())?;
+        let frame = pacer.begin_frame(device, sync2)?;
+
+        // Слот свободен, кадры старше frame_count закончены
+        ctx.window.collect_retired(device, frame_count);
+
+        let (image_index, suboptimal) = match acquire_image(&ctx.window.swapchain, frame.image_available) {
+            Ok(result) => result,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                ctx.window.recreate(&graphics_device)?;
+                return Ok(());
+            },
+            Err(e) => return Err(VulkanError::vk(e, |e| VulkanError::Swapchain(SwapchainError::AcquireImageFailed(e))))
+        };
+
+        self.current_frame = frame.index;
+        let command_buffers = self.record_passes(ctx, frame.index, frame_count, image_index)?;
+
+        let pacer = self.pacer.as_mut().unwrap();
+
+        let submit = FrameSubmit { frame: &frame, image_index, command_buffers: &command_buffers };
+        let render_finished = pacer.submit(device, sync2, queue, submit)?;
+        device.next_frame(frame_count as u64);
+        let out_of_date = present_image(&ctx.window.swapchain, queue, render_finished, image_index)?;
+
+        pacer.collect_garbage(device, sync2)?;
+
+        if suboptimal || out_of_date {
+            ctx.window.recreate(&graphics_device)?;
+        }
+
+        Ok(())
+    }
+
+    fn execute_legacy(&mut self, ctx: &mut RenderContext) -> VulkanResult<()> {
+
+        let graphics_device = ctx.device.clone();
+        let device = graphics_device.raw_device();
+        let queue = graphics_device.universal_queue.raw_graphics();
+
+        if self.sync.is_empty() {
+            let frame_count = ctx.window.image_count();
+            for _ in 0..frame_count {
+                self.sync.push(FrameSync::new(device));
+            }
+        }
+
+        let current_frame = self.current_frame;
+        let fence = self.sync[current_frame].fence;
+
+        // 2. Дождаться завершения предыдущего кадра
+        wait_fence(device, fence)?;
+
+        ctx.window.collect_retired(device, self.sync.len());
+
+        // 3. Получить новое изображение из swapchain
+        let (image_index, suboptimal) = match acquire_image(&ctx.window.swapchain, self.sync[current_frame].image_available) {
+            Ok(result) => result,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                ctx.window.recreate(&graphics_device)?;
+                return Ok(());
+            },
+            Err(e) => return Err(VulkanError::vk(e, |e| VulkanError::Swapchain(SwapchainError::AcquireImageFailed(e))))
+        };
+
+        // 4. Выполнить пассы (теперь безопасно)
+        let frame_count = self.sync.len();
+        let command_buffers = self.record_passes(ctx, current_frame, frame_count, image_index)?;
+
+        // Fence сбрасывается только если кадр точно будет отправлен
+        reset_fence(device, fence)?;
+
+        let sync = &self.sync;
+        let binding1 = [sync[current_frame].image_available];
+        let binding2 = [sync[current_frame].render_finished];
+
+        let submit_info = vk::SubmitInfo::default()
+            .wait_semaphores(&binding1)
+            .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&binding2);
+
+        queue_submit(device, queue, submit_info, fence)?;
+        device.next_frame(self.sync.len() as u64);
+
+        let out_of_date = present_image(&ctx.window.swapchain, queue, sync[current_frame].render_finished, image_index)?;
+
+        self.current_frame = (current_frame + 1) % self.sync.len();
+
+        if suboptimal || out_of_date {
+            ctx.window.recreate(&graphics_device)?;
+        }
+
+        Ok(())
+    }
+
+    /// Windows in order of their first pass
+    fn window_ids(&self) -> Vec<WindowId> {
+        let mut ids = vec![];
+
+        for node in &self.window_nodes {
+            if !ids.contains(&node.id) {
+                ids.push(node.id);
+            }
+        }
+
+        ids
+    }
+
+    /// Transient images of the window `id` in order of their first use, lifetimes in indices of its passes
+    fn transient_plan(&self, id: WindowId) -> Vec<(String, TransientImageDesc, TransientLifetime)> {
+        let mut plan: Vec<(String, TransientImageDesc, TransientLifetime)> = vec![];
+
+        for (pass, node) in self.window_nodes.iter().filter(|x| x.id == id).enumerate() {
+            for name in &node.transients {
+                if let Some(entry) = plan.iter_mut().find(|x| x.0 == *name) {
+                    entry.2 = entry.2.with_pass(pass);
+                    continue;
+                }
+
+                match self.transient_descs.get(name) {
+                    Some(desc) => plan.push((name.clone(), *desc, TransientLifetime { first: pass, last: pass })),
+                    None => log::warn!("Transient image {:?} of {:?} pass is not declared", name, node.name)
+                }
+            }
+        }
+
+        plan
+    }
+
+    /// Recreates the transient images of `id` when its passes or `extent` changed and exposes them in resources
+    fn realize_transients(&mut self, ctx: &RenderContext, id: WindowId, extent: vk::Extent2D) -> VulkanResult<()> {
+
+        self.resources.transient.clear();
+
+        let plan = self.transient_plan(id);
+
+        if plan.is_empty() {
+            self.window_transients.remove(&id);
+            return Ok(());
+        }
+
+        let current = self.window_transients.get(&id).is_some_and(|x| x.plan == plan && x.extent == extent);
+
+        if !current {
+            // Старые образы уходят в очередь удаления, кадры в полёте их ещё читают
+            self.window_transients.remove(&id);
+            // Наборы дескрипторов ссылаются на старые вьюхи
+            self.resources.gbuffer_targets.clear();
+
+            let descs = plan.iter().map(|(_, desc, lifetime)| (*desc, *lifetime)).collect::<Vec<_>>();
+            let images = TransientImages::new(ctx.device.raw_device(), &ctx.device.phys_dev.phys_info.memory_prop, &descs, extent)?;
+
+            log::debug!("Transient images of {:?}: {} bytes, {} without aliasing", id, images.memory_size, images.unaliased_size);
+            self.window_transients.insert(id, WindowTransients { plan, extent, images });
+        }
+
+        let transients = &self.window_transients[&id];
+
+        for ((name, _, _), image) in transients.plan.iter().zip(&transients.images.images) {
+            self.resources.transient.insert(name.clone(), *image);
+        }
+
+        Ok(())
+    }
+
+    fn execute_windows(&mut self, ctx: &mut RenderContext) -> VulkanResult<()> {
+
+        let graphics_device = ctx.device.clone();
+        let device = graphics_device.raw_device();
+        let queue = graphics_device.universal_queue.raw_graphics();
+        let family_index = graphics_device.universal_queue.graphics_index();
+        self.device.get_or_insert_with(|| device.clone());
+
+        if self.window_fences.is_empty() {
+            let fence_info = vk::FenceCreateInfo::default()
+                .flags(vk::FenceCreateFlags::SIGNALED);
+
+            for _ in 0..self.frames_in_flight.unwrap_or(2) {
+                let fence = unsafe { device.create_fence(&fence_info, None) }
+                    .map_err(|e| VulkanError::vk(e, |e| VulkanError::Sync(SyncError::CreateFenceFailed(e))))?;
+                self.window_fences.push(fence);
+            }
+        }
+
+        let frame_count = self.window_fences.len();
+        let current_frame = self.current_frame % frame_count;
+        let fence = self.window_fences[current_frame];
+
+        wait_fence(device, fence)?;
+
+        // (window, image index, suboptimal, present semaphore)
+        let mut acquired = vec![];
+        let mut first_error = None;
+
+        for id in self.window_ids() {
+
+            let Some(window) = ctx.window_mut(id) else {
+                log::warn!("Window {:?} has passes but is not in render context", id);
                 continue;
+            };
+
+            let target = self.window_targets
+                .entry(id)
+                .or_insert_with(|| WindowTarget::new(device, family_index, frame_count));
+
+            // Окна, уже получившие образ, держат сигнальные семафоры: ошибка одного окна их не прерывает
+            match acquire_window(window, &graphics_device, target.sync[current_frame].image_available, frame_count) {
+                Ok(Some((image_index, suboptimal))) => {
+                    let render_finished = target.present_semaphore(image_index, window.swapchain_images.len())?;
+                    acquired.push((id, image_index, suboptimal, render_finished));
+                },
+                Ok(None) => {},
+                Err(err) if err.is_device_lost() => return Err(err),
+                Err(err) => {
+                    log::error!("Skipping window {:?}: {}", id, err);
+                    first_error.get_or_insert(err);
+                }
             }
+        }
+
+        // Все окна свёрнуты или пропущены, fence остаётся сигнальным
+        if acquired.is_empty() {
+            return first_error.map_or(Ok(()), Err);
+        }
 
-            let binding1 = [sync[current_frame].image_available];
-            let binding2 = [sync[current_frame].render_finished];
-            let cbuf = &[*self.resources.command_buffers.get(&image_index).unwrap()];
+        let mut command_buffers = vec![];
+        command_buffers.extend(self.record_compute_passes(ctx, current_frame, frame_count)?);
 
-            let submit_info = vk::SubmitInfo::default()
-                .wait_semaphores(&binding1)
-                .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
-                .command_buffers(cbuf)
-                .signal_semaphores(&binding2);
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        let mut wait_semaphores = vec![];
+        let mut signal_semaphores = vec![];
+        let mut present = PresentBatch::new();
+
+        for (id, image_index, _, render_finished) in &acquired {
+
+            let window = ctx.window(*id).unwrap();
+
+            // Окно уже получило образ, без транзиентных образов проходы только залогируют ошибки
+            if let Err(err) = self.realize_transients(ctx, *id, window.extent()) {
+                if err.is_device_lost() {
+                    return Err(err);
+                }
+                log::error!("Failed to create transient images of {:?}: {:?}", id, err);
+            }
+
+            let target = &self.window_targets[id];
+            let command_buffer = target.command_buffers[current_frame];
+            let sync = &target.sync[current_frame];
 
             unsafe {
-                device.queue_submit(queue, &[submit_info], fence).unwrap();
+                device.reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                    .and_then(|_| device.begin_command_buffer(command_buffer, &begin_info))
+                    .map_err(|e| VulkanError::vk(e, |e| VulkanError::Sync(SyncError::RecordCommandBufferFailed(e))))?;
             }
 
-            let binding1 = [sync[current_frame].render_finished];
-            let binding2 = [swapchain.raw];
-            let binding3 = [image_index];
+            let frame = WindowFrame { window, image_index: *image_index, command_buffer };
+
+            let aliased = self.resources.transient.values()
+                .filter(|x| x.aliased)
+                .map(|x| x.lifetime.first)
+                .collect::<Vec<_>>();
+
+            for (index, node) in self.window_nodes.iter().filter(|x| x.id == *id).enumerate() {
 
-            let present_info = vk::PresentInfoKHR::default()
-                .wait_semaphores(&binding1)
-                .swapchains(&binding2)
-                .image_indices(&binding3);
+                // Память образа могли писать прошлые проходы через другой образ
+                if aliased.contains(&index) {
+                    alias_barrier(device, command_buffer);
+                }
+
+                if let Err(err) = (node.pass)(&mut self.resources, ctx, &frame) {
+                    log::error!("Error in {:?} window pass: {:?}", node.name, err);
+                }
+            }
 
             unsafe {
-                swapchain.swapchain_load.queue_present(queue, &present_info).unwrap();
+                device.end_command_buffer(command_buffer)
+                    .map_err(|e| VulkanError::vk(e, |e| VulkanError::Sync(SyncError::RecordCommandBufferFailed(e))))?;
+            }
+
+            command_buffers.push(command_buffer);
+            wait_semaphores.push(sync.image_available);
+            signal_semaphores.push(*render_finished);
+            present.add(window.swapchain.raw, *image_index, *render_finished);
+        }
+
+        let wait_stages = vec![vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT; wait_semaphores.len()];
+
+        let submit_info = vk::SubmitInfo::default()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&signal_semaphores);
+
+        // Fence сбрасывается только если кадр точно будет отправлен
+        reset_fence(device, fence)?;
+        queue_submit(device, queue, submit_info, fence)?;
+        device.next_frame(frame_count as u64);
+
+        let results = present.present(&ctx.window.swapchain.swapchain_load, queue);
+
+        self.current_frame = (current_frame + 1) % frame_count;
+
+        let mut present_error = None;
+
+        for ((id, _, suboptimal, _), result) in acquired.into_iter().zip(results) {
+
+            if !suboptimal && !needs_recreate(result) {
+                if result != vk::Result::SUCCESS {
+                    present_error.get_or_insert(VulkanError::vk(result, |e| VulkanError::Swapchain(SwapchainError::PresentFailed(e))));
+                }
+                continue;
+            }
+
+            if let Some(window) = ctx.window_mut(id) {
+                window.recreate(&graphics_device)?;
             }
+        }
+
+        present_error.map_or(Ok(()), Err)
+    }
+}
+
+impl Drop for RenderGraph {
+    fn drop(&mut self) {
+
+        let Some(device) = &self.device else { return };
+
+        defer_frame_sync(device, self.sync.drain(..));
+
+        for fence in self.window_fences.drain(..) {
+            device.defer(DeferredResource::Fence(fence));
+        }
 
-            self.current_frame = (current_frame + 1) % self.sync.len();
+        if let Some(mut pacer) = self.pacer.take() {
+            device.defer(DeferredResource::Custom(Box::new(move |device| pacer.destroy(device))));
         }
     }
 }
 
+/// Orders the writes of earlier passes before a pass reusing their memory
+fn alias_barrier(device: &ash::Device, command_buffer: CommandBuffer) {
+
+    let barrier = vk::MemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::MEMORY_WRITE)
+        .dst_access_mask(vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE);
+
+    unsafe {
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::PipelineStageFlags::ALL_COMMANDS,
+            vk::DependencyFlags::empty(),
+            &[barrier],
+            &[],
+            &[]
+        );
+    }
+}
+
+/// (image index, suboptimal) of one window, `None` if it is minimized or its swapchain was just recreated
+fn acquire_window(window: &mut WindowManager, device: &Arc<GraphicsDevice>, semaphore: vk::Semaphore, frame_count: usize) -> VulkanResult<Option<(u32, bool)>> {
+
+    if window.minimized && !window.recreate(device)? {
+        return Ok(None);
+    }
+
+    window.collect_retired(device.raw_device(), frame_count);
+
+    match acquire_image(&window.swapchain, semaphore) {
+        Ok(acquired) => Ok(Some(acquired)),
+        Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+            window.recreate(device)?;
+            Ok(None)
+        },
+        Err(e) => Err(VulkanError::vk(e, |e| VulkanError::Swapchain(SwapchainError::AcquireImageFailed(e))))
+    }
+}
+
+fn acquire_image(swapchain: &Swapchain, semaphore: vk::Semaphore) -> Result<(u32, bool), vk::Result> {
+    unsafe {
+        swapchain.swapchain_load.acquire_next_image(
+            swapchain.raw,
+            u64::MAX,
+            semaphore,
+            vk::Fence::null(),
+        )
+    }
+}
+
+fn wait_fence(device: &ash::Device, fence: vk::Fence) -> VulkanResult<()> {
+    unsafe { device.wait_for_fences(&[fence], false, u64::MAX) }
+        .map_err(|e| VulkanError::vk(e, |e| VulkanError::Sync(SyncError::WaitFenceFailed(e))))
+}
+
+fn reset_fence(device: &ash::Device, fence: vk::Fence) -> VulkanResult<()> {
+    unsafe { device.reset_fences(&[fence]) }
+        .map_err(|e| VulkanError::vk(e, |e| VulkanError::Sync(SyncError::ResetFenceFailed(e))))
+}
+
+fn queue_submit(device: &ash::Device, queue: vk::Queue, submit_info: vk::SubmitInfo, fence: vk::Fence) -> VulkanResult<()> {
+    unsafe { device.queue_submit(queue, &[submit_info], fence) }
+        .map_err(|e| VulkanError::vk(e, |e| VulkanError::Sync(SyncError::SubmitFailed(e))))
+}
+
+/// Returns true when the swapchain has to be recreated
+fn present_image(swapchain: &Swapchain, queue: vk::Queue, wait: vk::Semaphore, image_index: u32) -> VulkanResult<bool> {
+
+    let binding1 = [wait];
+    let binding2 = [swapchain.raw];
+    let binding3 = [image_index];
+
+    let present_info = vk::PresentInfoKHR::default()
+        .wait_semaphores(&binding1)
+        .swapchains(&binding2)
+        .image_indices(&binding3);
+
+    match unsafe { swapchain.swapchain_load.queue_present(queue, &present_info) } {
+        Ok(suboptimal) => Ok(suboptimal),
+        Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(true),
+        Err(e) => Err(VulkanError::vk(e, |e| VulkanError::Swapchain(SwapchainError::PresentFailed(e))))
+    }
+}
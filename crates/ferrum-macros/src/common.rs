@@ -0,0 +1,32 @@
+use syn::spanned::Spanned;
+use syn::{Attribute, Expr, Lit, LitInt, Path};
+
+pub fn last_ident(path: &Path) -> Option<String> {
+    path.segments.last().map(|x| x.ident.to_string())
+}
+
+pub fn array_len(len: &Expr) -> syn::Result<u32> {
+    match len {
+        Expr::Lit(expr) => match &expr.lit {
+            Lit::Int(lit) => lit.base10_parse::<u32>(),
+            _ => Err(syn::Error::new(len.span(), "array length must be an integer literal"))
+        },
+        _ => Err(syn::Error::new(len.span(), "array length must be an integer literal, constants are not visible to the derive"))
+    }
+}
+
+pub fn parse_u32(attr: &Attribute) -> syn::Result<u32> {
+    attr.parse_args::<LitInt>()?.base10_parse::<u32>()
+}
+
+/// Первый атрибут с таким именем, повтор - ошибка
+pub fn find_attr<'a>(attrs: &'a [Attribute], name: &str, errors: &mut Vec<syn::Error>) -> Option<&'a Attribute> {
+    let mut found = attrs.iter().filter(|x| x.path().is_ident(name));
+    let first = found.next();
+
+    for duplicate in found {
+        errors.push(syn::Error::new_spanned(duplicate, format!("duplicate #[{}] attribute", name)));
+    }
+
+    first
+}
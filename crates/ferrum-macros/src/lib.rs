@@ -1,127 +1,87 @@
 use proc_macro::TokenStream;
-use quote::{quote, ToTokens};
-use syn::{Expr, Lit};
-use syn::{parse_macro_input, DeriveInput, Data, Fields, Type, parse::Parser};
-use syn::spanned::Spanned;
+use syn::{parse_macro_input, DeriveInput};
 
-#[proc_macro_derive(Vertex, attributes(binding, location))]
+mod common;
+mod uniform;
+mod vertex;
+
+///
+/// Vertex input descriptions of a struct, one binding per struct with `stride = size_of::<Self>()`
+/// and one attribute per field at `offset_of!(Self, field)`.
+///
+/// Formats are inferred from `f32`, `u32`, `i32`, `f16`, `u16`, `i16` (as is), `u8`, `i8` (normalized),
+/// arrays of 1..=4 of them and glam vectors. Matrices (`[[f32; 4]; 4]`, `Mat4`) take one location per column.
+///
+/// Attributes:
+/// - `#[binding(n)]` on the struct, default 0
+/// - `#[instance]` on the struct, per-instance input rate
+/// - `#[location(n)]` on a field, following fields continue from it
+/// - `#[format(R16G16_SFLOAT)]` on a field, any `vk::Format` variant
+///
+/// # Example
+/// ```ignore
+/// #[repr(C)]
+/// #[derive(Vertex)]
+/// struct Vertex {
+///     pos: [f32; 3],
+///     #[format(R8G8B8A8_SRGB)]
+///     color: [u8; 4]
+/// }
+///
+/// #[repr(C)]
+/// #[derive(Vertex)]
+/// #[binding(1)]
+/// #[instance]
+/// struct Instance {
+///     #[location(2)]
+///     model: [[f32; 4]; 4]
+/// }
+///
+/// let bindings = [Vertex::get_binding_descriptions(), Instance::get_binding_descriptions()].concat();
+/// ```
+///
+#[proc_macro_derive(Vertex, attributes(binding, location, instance, format))]
 pub fn derive_vertex(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
-    let name = &input.ident;
-
-    let fields = match &input.data {
-        Data::Struct(data) => match &data.fields {
-            Fields::Named(fields) => &fields.named,
-            _ => panic!("Vertex can only be derived for structs with named fields"),
-        },
-        _ => panic!("Vertex can only be derived for structs"),
-    };
-
-    let mut bindings = Vec::new();
-    let mut attributes = Vec::new();
-    let mut binding_counter = 0;
-    let mut location_counter = 0;
-
-    for field in fields {
-        let field_name = &field.ident.as_ref().unwrap();
-        let field_ty = &field.ty;
-        let span = field.span();
-
-        // Парсинг binding с обработкой ошибок
-        let binding: u32 = field.attrs.iter()
-            .find(|attr| attr.path().is_ident("binding"))
-            .map(|attr| {
-                attr.parse_args_with(|input: syn::parse::ParseStream| {
-                    input.parse::<syn::LitInt>()?
-                        .base10_parse::<u32>()
-                })
-                .unwrap_or_else(|_| {
-                    panic!("Failed to parse binding attribute for field {}", field_name);
-                })
-            })
-            .unwrap_or(binding_counter);
-
-        let location: u32 = field.attrs.iter()
-            .find(|attr| attr.path().is_ident("location"))
-            .map(|attr| {
-                attr.parse_args_with(|input: syn::parse::ParseStream| {
-                    input.parse::<syn::LitInt>()?
-                        .base10_parse::<u32>()
-                })
-                .unwrap_or_else(|_| {
-                    panic!("Failed to parse location attribute for field {}", field_name);
-                })
-            })
-            .unwrap_or_else(|| {
-                let loc = location_counter;
-                location_counter += 1;
-                loc
-            });
-
-        let format = match field_ty {
-            Type::Array(arr) => {
-                match &*arr.elem {
-                    Type::Path(type_path) => {
-                        if type_path.path.is_ident("f32") {
-                            match arr.len.clone() {
-                                Expr::Lit(expr_lit) => {
-                                    match expr_lit.lit {
-                                        Lit::Int(lit_int) => {
-                                            match lit_int.base10_parse::<usize>().unwrap() {
-                                                2 => quote! { ash::vk::Format::R32G32B32A32_SFLOAT },
-                                                3 => quote! { ash::vk::Format::R32G32B32A32_SFLOAT },
-                                                4 => quote! { ash::vk::Format::R32G32B32A32_SFLOAT },
-                                                _ => panic!("Unsupported array length for vertex attribute"),
-                                            }
-                                        }
-                                        _ => panic!("Array length must be an integer literal"),
-                                    }
-                                }
-                                _ => panic!("Array length must be a literal"),
-                            }
-                        } else {
-                            panic!("Only f32 arrays are supported as vertex attributes")
-                        }
-                    }
-                    _ => panic!("Vertex attributes must be arrays of primitive types"),
-                }
-            }
-            _ => panic!("Vertex attributes must be arrays"),
-        };
+    vertex::derive(input).into()
+}
 
-        if binding as usize >= bindings.len() {
-            bindings.push(quote! {
-                ash::vk::VertexInputBindingDescription {
-                    binding: #binding,
-                    stride: std::mem::size_of::<#field_ty>() as u32,
-                    input_rate: ash::vk::VertexInputRate::VERTEX,
-                }
-            });
-        }
-
-        attributes.push(quote! {
-            ash::vk::VertexInputAttributeDescription {
-                location: #location,
-                binding: #binding,
-                format: #format,
-                offset: 0,
-            }
-        });
-
-        binding_counter = binding + 1;
-    }
-
-    let expanded = quote! {
-        impl #name {
-            pub fn get_binding_descriptions() -> Vec<ash::vk::VertexInputBindingDescription> {
-                vec![#(#bindings),*]
-            }
-
-            pub fn get_attribute_descriptions() -> Vec<ash::vk::VertexInputAttributeDescription> {
-                vec![#(#attributes),*]
-            }
-        }
-    };
-
-    expanded.into()
-}
\ No newline at end of file
+///
+/// Checks a `#[repr(C)]` struct against the std140 (default) or std430 GLSL layout at compile time.
+/// A derive can not change the struct, so padding is written by hand as `#[padding]` fields,
+/// a misplaced field or a wrong array/matrix stride is a compile error.
+///
+/// Generates `GLSL_ALIGN`/`GLSL_SIZE` for nesting into other uniforms and
+/// `descriptor_set_layout_binding()`: `UNIFORM_BUFFER` for std140, `STORAGE_BUFFER` for std430.
+///
+/// Attributes:
+/// - `#[layout(std140)]` or `#[layout(std430)]` on the struct
+/// - `#[binding(n)]` on the struct, default 0
+/// - `#[stages(VERTEX, FRAGMENT)]` on the struct, default `ALL`
+/// - `#[padding]` on a field, skipped by the layout checks
+///
+/// # Example
+/// ```ignore
+/// #[repr(C)]
+/// #[derive(ShaderUniform)]
+/// #[stages(FRAGMENT)]
+/// struct Light {
+///     position: [f32; 3],
+///     intensity: f32,
+///     color: [f32; 3],
+///     #[padding]
+///     _pad: f32,
+///     view_proj: [[f32; 4]; 4]
+/// }
+///
+/// let layout = DescriptorSetLayoutBuilder::new()
+///     .with_device(ctx.device.raw_device())
+///     .with_bindings(&[Light::descriptor_set_layout_binding()])
+///     .build();
+/// ```
+///
+#[proc_macro_derive(ShaderUniform, attributes(layout, binding, stages, padding))]
+pub fn derive_shader_uniform(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    uniform::derive(input).into()
+}
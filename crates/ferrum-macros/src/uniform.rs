@@ -0,0 +1,289 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::{Data, DeriveInput, Fields, Ident, Token, Type};
+
+use crate::common::{array_len, find_attr, last_ident, parse_u32};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Layout {
+    Std140,
+    Std430
+}
+
+impl Layout {
+    fn name(self) -> &'static str {
+        match self {
+            Layout::Std140 => "std140",
+            Layout::Std430 => "std430"
+        }
+    }
+}
+
+/// GLSL base alignment and size of a member, const expressions of `usize`
+struct Member {
+    align: TokenStream,
+    size: TokenStream
+}
+
+impl Member {
+    fn known(align: usize, size: usize) -> Self {
+        Self { align: quote! { #align }, size: quote! { #size } }
+    }
+}
+
+fn round_up(value: &TokenStream, align: &TokenStream) -> TokenStream {
+    quote! { (#value).next_multiple_of(#align) }
+}
+
+/// Size of a scalar component in bytes
+fn scalar(ident: &str) -> Option<usize> {
+    match ident {
+        "f32" | "u32" | "i32" => Some(4),
+        "f64" => Some(8),
+        _ => None
+    }
+}
+
+/// vec2 выравнивается на 2 компоненты, vec3 и vec4 на 4
+fn vector(component: usize, count: usize) -> Member {
+    let align = if count == 2 { 2 * component } else { 4 * component };
+    Member::known(align, count * component)
+}
+
+/// Arrays and matrices (arrays of column vectors) share the stride rules
+fn array(elem: Member, len: TokenStream, layout: Layout) -> Member {
+
+    let align = match layout {
+        Layout::Std140 => round_up(&elem.align, &quote! { 16usize }),
+        Layout::Std430 => elem.align
+    };
+
+    let stride = round_up(&elem.size, &align);
+    Member { size: quote! { (#len * #stride) }, align }
+}
+
+fn glam(ident: &str, layout: Layout) -> Option<Member> {
+    Some(match ident {
+        "Vec2" | "IVec2" | "UVec2" => vector(4, 2),
+        "Vec3" | "IVec3" | "UVec3" => vector(4, 3),
+        "Vec4" | "IVec4" | "UVec4" | "Quat" => vector(4, 4),
+        "Mat2" => array(vector(4, 2), quote! { 2usize }, layout),
+        "Mat3" => array(vector(4, 3), quote! { 3usize }, layout),
+        "Mat4" => array(vector(4, 4), quote! { 4usize }, layout),
+        _ => return None
+    })
+}
+
+fn member(ty: &Type, layout: Layout) -> syn::Result<Member> {
+    match ty {
+        Type::Path(path) => {
+            let ident = last_ident(&path.path).unwrap_or_default();
+
+            if let Some(size) = scalar(&ident) {
+                return Ok(Member::known(size, size));
+            }
+
+            if ident == "bool" {
+                return Err(syn::Error::new_spanned(ty, "GLSL bool is 4 bytes, use u32"));
+            }
+
+            if let Some(member) = glam(&ident, layout) {
+                return Ok(member);
+            }
+
+            if ident == "Vec3A" {
+                return Err(syn::Error::new_spanned(ty, "Vec3A is 16 bytes while GLSL vec3 is 12, use Vec3 or Vec4"));
+            }
+
+            // Вложенная структура, тоже с #[derive(ShaderUniform)]
+            Ok(Member {
+                align: quote! { <#ty>::GLSL_ALIGN },
+                size: quote! { <#ty>::GLSL_SIZE }
+            })
+        }
+        Type::Array(arr) => {
+            let len = array_len(&arr.len)?;
+
+            if let Type::Path(elem) = &*arr.elem
+                && let Some(component) = last_ident(&elem.path).as_deref().and_then(scalar)
+                && (2..=4).contains(&len) {
+                return Ok(vector(component, len as usize));
+            }
+
+            let len = len as usize;
+            Ok(array(member(&arr.elem, layout)?, quote! { #len }, layout))
+        }
+        _ => Err(syn::Error::new_spanned(ty, "unsupported uniform member type"))
+    }
+}
+
+fn has_repr_c(input: &DeriveInput) -> bool {
+    input.attrs.iter()
+        .filter(|x| x.path().is_ident("repr"))
+        .any(|attr| {
+            let mut is_c = false;
+            let _ = attr.parse_nested_meta(|meta| {
+                is_c |= meta.path.is_ident("C");
+                // align(16) и подобные
+                if meta.input.peek(syn::token::Paren) {
+                    let _ = meta.input.parse::<TokenStream>();
+                }
+                Ok(())
+            });
+            is_c
+        })
+}
+
+fn parse_layout(attr: &syn::Attribute) -> syn::Result<Layout> {
+    let ident = attr.parse_args::<Ident>()?;
+    match ident.to_string().as_str() {
+        "std140" => Ok(Layout::Std140),
+        "std430" => Ok(Layout::Std430),
+        _ => Err(syn::Error::new(ident.span(), "expected std140 or std430"))
+    }
+}
+
+fn parse_stages(attr: &syn::Attribute) -> syn::Result<TokenStream> {
+    let stages = attr.parse_args_with(Punctuated::<Ident, Token![,]>::parse_terminated)?;
+
+    if stages.is_empty() {
+        return Err(syn::Error::new_spanned(attr, "expected at least one shader stage"));
+    }
+
+    let stages = stages.iter().map(|x| quote! { ash::vk::ShaderStageFlags::#x });
+    Ok(quote! { #(#stages)|* })
+}
+
+pub fn derive(input: DeriveInput) -> TokenStream {
+
+    let mut errors = Vec::new();
+    let name = &input.ident;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return syn::Error::new(name.span(), "ShaderUniform can only be derived for structs with named fields").to_compile_error()
+        },
+        _ => return syn::Error::new(name.span(), "ShaderUniform can only be derived for structs").to_compile_error()
+    };
+
+    if !input.generics.params.is_empty() {
+        errors.push(syn::Error::new_spanned(&input.generics, "ShaderUniform can not be derived for generic structs"));
+    }
+
+    if !has_repr_c(&input) {
+        errors.push(syn::Error::new(name.span(), "ShaderUniform requires #[repr(C)], otherwise field order is not guaranteed"));
+    }
+
+    let layout = match find_attr(&input.attrs, "layout", &mut errors).map(parse_layout) {
+        Some(Ok(x)) => x,
+        Some(Err(e)) => { errors.push(e); Layout::Std140 }
+        None => Layout::Std140
+    };
+
+    let binding = match find_attr(&input.attrs, "binding", &mut errors).map(parse_u32) {
+        Some(Ok(x)) => x,
+        Some(Err(e)) => { errors.push(e); 0 }
+        None => 0
+    };
+
+    let stages = match find_attr(&input.attrs, "stages", &mut errors).map(parse_stages) {
+        Some(Ok(x)) => x,
+        Some(Err(e)) => { errors.push(e); quote! {} }
+        None => quote! { ash::vk::ShaderStageFlags::ALL }
+    };
+
+    let layout_name = layout.name();
+    let mut checks = Vec::new();
+    let mut aligns = Vec::new();
+    let mut end = quote! { 0usize };
+
+    for field in fields {
+
+        if find_attr(&field.attrs, "padding", &mut errors).is_some() {
+            continue;
+        }
+
+        let field_name = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+
+        let Member { align, size } = match member(ty, layout) {
+            Ok(x) => x,
+            Err(e) => { errors.push(e); continue; }
+        };
+
+        let expected = round_up(&end, &align);
+        let offset_msg = format!(
+            "`{}.{}` is not at its {} offset, add or remove #[padding] fields before it",
+            name, field_name, layout_name
+        );
+        let size_msg = format!(
+            "`{}.{}` has a different size than in {}, array and matrix strides are rounded to the element alignment{}",
+            name, field_name, layout_name, if layout == Layout::Std140 { " and 16 bytes" } else { "" }
+        );
+
+        checks.push(quote! {
+            assert!(std::mem::offset_of!(#name, #field_name) == #expected, #offset_msg);
+            assert!(std::mem::size_of::<#ty>() == #size, #size_msg);
+        });
+
+        if let Type::Path(path) = ty && member_is_nested(path) {
+            let nested_msg = format!("`{}.{}` must use the {} layout as well", name, field_name, layout_name);
+            let is_std430 = layout == Layout::Std430;
+            checks.push(quote! { assert!(<#ty>::GLSL_STD430 == #is_std430, #nested_msg); });
+        }
+
+        aligns.push(align);
+        end = quote! { (std::mem::offset_of!(#name, #field_name) + #size) };
+    }
+
+    if aligns.is_empty() && errors.is_empty() {
+        errors.push(syn::Error::new(name.span(), "ShaderUniform needs at least one non-padding field"));
+    }
+
+    if let Some(error) = errors.into_iter().reduce(|mut acc, e| { acc.combine(e); acc }) {
+        return error.to_compile_error();
+    }
+
+    let max_align = quote! {{
+        let mut align = 0usize;
+        #( if #aligns > align { align = #aligns; } )*
+        align
+    }};
+
+    // std140 - uniform буфер, std430 - storage буфер
+    let (struct_align, descriptor_type, is_std430) = match layout {
+        Layout::Std140 => (round_up(&max_align, &quote! { 16usize }), quote! { UNIFORM_BUFFER }, false),
+        Layout::Std430 => (max_align, quote! { STORAGE_BUFFER }, true)
+    };
+
+    let struct_size = round_up(&end, &quote! { Self::GLSL_ALIGN });
+
+    quote! {
+        impl #name {
+            /// Base alignment of the block when nested in another one
+            pub const GLSL_ALIGN: usize = #struct_align;
+            /// Size of the block when nested in another one, rounded to [`Self::GLSL_ALIGN`]
+            pub const GLSL_SIZE: usize = #struct_size;
+            pub const GLSL_STD430: bool = #is_std430;
+
+            pub fn descriptor_set_layout_binding() -> ash::vk::DescriptorSetLayoutBinding<'static> {
+                ash::vk::DescriptorSetLayoutBinding::default()
+                    .binding(#binding)
+                    .descriptor_type(ash::vk::DescriptorType::#descriptor_type)
+                    .descriptor_count(1)
+                    .stage_flags(#stages)
+            }
+        }
+
+        const _: () = {
+            #(#checks)*
+        };
+    }
+}
+
+fn member_is_nested(path: &syn::TypePath) -> bool {
+    let ident = last_ident(&path.path).unwrap_or_default();
+    scalar(&ident).is_none() && glam(&ident, Layout::Std140).is_none()
+}
@@ -0,0 +1,211 @@
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{Data, DeriveInput, Fields, Ident, Type};
+
+use crate::common::{array_len, find_attr, last_ident, parse_u32};
+
+/// Format of one attribute, `locations` > 1 for matrices (one location per column)
+struct AttributeFormat {
+    format: TokenStream,
+    locations: u32,
+    /// Size of one column in bytes, only used when `locations` > 1
+    column_size: u32
+}
+
+impl AttributeFormat {
+
+    fn single(format: &str) -> Self {
+        let format = Ident::new(format, Span::call_site());
+        Self { format: quote! { ash::vk::Format::#format }, locations: 1, column_size: 0 }
+    }
+
+    fn columns(format: &str, locations: u32, column_size: u32) -> Self {
+        Self { locations, column_size, ..Self::single(format) }
+    }
+}
+
+/// Scalar component of an attribute: format prefix by component count and size in bytes
+fn component(ident: &str) -> Option<(&'static [&'static str; 4], u32)> {
+    Some(match ident {
+        "f32" => (&["R32_SFLOAT", "R32G32_SFLOAT", "R32G32B32_SFLOAT", "R32G32B32A32_SFLOAT"], 4),
+        "u32" => (&["R32_UINT", "R32G32_UINT", "R32G32B32_UINT", "R32G32B32A32_UINT"], 4),
+        "i32" => (&["R32_SINT", "R32G32_SINT", "R32G32B32_SINT", "R32G32B32A32_SINT"], 4),
+        "f16" => (&["R16_SFLOAT", "R16G16_SFLOAT", "R16G16B16_SFLOAT", "R16G16B16A16_SFLOAT"], 2),
+        "u16" => (&["R16_UINT", "R16G16_UINT", "R16G16B16_UINT", "R16G16B16A16_UINT"], 2),
+        "i16" => (&["R16_SINT", "R16G16_SINT", "R16G16B16_SINT", "R16G16B16A16_SINT"], 2),
+        // 8-битные чаще всего цвета и упакованные нормали
+        "u8" => (&["R8_UNORM", "R8G8_UNORM", "R8G8B8_UNORM", "R8G8B8A8_UNORM"], 1),
+        "i8" => (&["R8_SNORM", "R8G8_SNORM", "R8G8B8_SNORM", "R8G8B8A8_SNORM"], 1),
+        _ => return None
+    })
+}
+
+/// glam types by the last path segment
+fn glam_format(ident: &str) -> Option<AttributeFormat> {
+    Some(match ident {
+        "Vec2" => AttributeFormat::single("R32G32_SFLOAT"),
+        "Vec3" | "Vec3A" => AttributeFormat::single("R32G32B32_SFLOAT"),
+        "Vec4" | "Quat" => AttributeFormat::single("R32G32B32A32_SFLOAT"),
+        "IVec2" => AttributeFormat::single("R32G32_SINT"),
+        "IVec3" => AttributeFormat::single("R32G32B32_SINT"),
+        "IVec4" => AttributeFormat::single("R32G32B32A32_SINT"),
+        "UVec2" => AttributeFormat::single("R32G32_UINT"),
+        "UVec3" => AttributeFormat::single("R32G32B32_UINT"),
+        "UVec4" => AttributeFormat::single("R32G32B32A32_UINT"),
+        "Mat2" => AttributeFormat::columns("R32G32_SFLOAT", 2, 8),
+        "Mat3" => AttributeFormat::columns("R32G32B32_SFLOAT", 3, 12),
+        "Mat4" => AttributeFormat::columns("R32G32B32A32_SFLOAT", 4, 16),
+        _ => return None
+    })
+}
+
+fn scalar_format(ty: &Type, count: u32) -> Option<AttributeFormat> {
+    let Type::Path(path) = ty else { return None };
+    let (formats, _) = component(&last_ident(&path.path)?)?;
+    formats.get(count as usize - 1).map(|x| AttributeFormat::single(x))
+}
+
+fn infer_format(ty: &Type) -> syn::Result<AttributeFormat> {
+
+    let unsupported = || syn::Error::new_spanned(
+        ty,
+        "unsupported vertex attribute type, expected f32/u32/i32/f16/u16/i16/u8/i8, \
+         an array of 1..=4 of them, a glam vector or matrix, or an explicit #[format(...)]"
+    );
+
+    match ty {
+        Type::Path(path) => {
+            let ident = last_ident(&path.path).ok_or_else(unsupported)?;
+
+            if let Some(format) = glam_format(&ident) {
+                return Ok(format);
+            }
+
+            scalar_format(ty, 1).ok_or_else(unsupported)
+        }
+        Type::Array(array) => {
+            let len = array_len(&array.len)?;
+
+            // [[f32; 4]; 4] - матрица по столбцам
+            if let Type::Array(column) = &*array.elem {
+                let rows = array_len(&column.len)?;
+                let Type::Path(elem) = &*column.elem else { return Err(unsupported()) };
+                let (formats, size) = last_ident(&elem.path).and_then(|x| component(&x)).ok_or_else(unsupported)?;
+
+                if !(1..=4).contains(&len) || !(1..=4).contains(&rows) {
+                    return Err(syn::Error::new_spanned(ty, "matrices must have 1..=4 columns and rows"));
+                }
+
+                return Ok(AttributeFormat::columns(formats[rows as usize - 1], len, rows * size));
+            }
+
+            if !(1..=4).contains(&len) {
+                return Err(syn::Error::new(array.len.span(), "vertex attribute arrays must have 1..=4 components"));
+            }
+
+            scalar_format(&array.elem, len).ok_or_else(unsupported)
+        }
+        _ => Err(unsupported())
+    }
+}
+
+pub fn derive(input: DeriveInput) -> TokenStream {
+
+    let mut errors = Vec::new();
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return syn::Error::new(input.ident.span(), "Vertex can only be derived for structs with named fields").to_compile_error()
+        },
+        _ => return syn::Error::new(input.ident.span(), "Vertex can only be derived for structs").to_compile_error()
+    };
+
+    let binding = match find_attr(&input.attrs, "binding", &mut errors).map(parse_u32) {
+        Some(Ok(x)) => x,
+        Some(Err(e)) => { errors.push(e); 0 }
+        None => 0
+    };
+
+    let input_rate = match find_attr(&input.attrs, "instance", &mut errors) {
+        Some(attr) => {
+            if let Err(e) = attr.meta.require_path_only() {
+                errors.push(e);
+            }
+            quote! { ash::vk::VertexInputRate::INSTANCE }
+        }
+        None => quote! { ash::vk::VertexInputRate::VERTEX }
+    };
+
+    let mut attributes = Vec::new();
+    let mut next_location = 0u32;
+
+    for field in fields {
+
+        let field_name = field.ident.as_ref().unwrap();
+
+        if let Some(attr) = find_attr(&field.attrs, "binding", &mut errors) {
+            errors.push(syn::Error::new_spanned(attr, "#[binding] applies to the whole struct, move it next to #[derive(Vertex)]"));
+        }
+
+        let location = match find_attr(&field.attrs, "location", &mut errors).map(parse_u32) {
+            Some(Ok(x)) => x,
+            Some(Err(e)) => { errors.push(e); next_location }
+            None => next_location
+        };
+
+        let format = match find_attr(&field.attrs, "format", &mut errors) {
+            Some(attr) => match attr.parse_args::<Ident>() {
+                Ok(format) => {
+                    let format = quote_spanned! { format.span() => ash::vk::Format::#format };
+                    AttributeFormat { format, locations: 1, column_size: 0 }
+                }
+                Err(e) => { errors.push(e); continue; }
+            },
+            None => match infer_format(&field.ty) {
+                Ok(format) => format,
+                Err(e) => { errors.push(e); continue; }
+            }
+        };
+
+        for column in 0..format.locations {
+            let location = location + column;
+            let column_offset = column * format.column_size;
+            let format = &format.format;
+
+            attributes.push(quote! {
+                ash::vk::VertexInputAttributeDescription {
+                    location: #location,
+                    binding: #binding,
+                    format: #format,
+                    offset: std::mem::offset_of!(Self, #field_name) as u32 + #column_offset,
+                }
+            });
+        }
+
+        next_location = location + format.locations;
+    }
+
+    if let Some(error) = errors.into_iter().reduce(|mut acc, e| { acc.combine(e); acc }) {
+        return error.to_compile_error();
+    }
+
+    quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            pub fn get_binding_descriptions() -> Vec<ash::vk::VertexInputBindingDescription> {
+                vec![ash::vk::VertexInputBindingDescription {
+                    binding: #binding,
+                    stride: std::mem::size_of::<Self>() as u32,
+                    input_rate: #input_rate,
+                }]
+            }
+
+            pub fn get_attribute_descriptions() -> Vec<ash::vk::VertexInputAttributeDescription> {
+                vec![#(#attributes),*]
+            }
+        }
+    }
+}
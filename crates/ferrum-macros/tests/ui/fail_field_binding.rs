@@ -0,0 +1,11 @@
+use ferrum_macros::Vertex;
+
+#[derive(Vertex)]
+struct Vertex {
+    #[binding(1)]
+    pos: [f32; 3],
+    #[location(x)]
+    uv: [f32; 2]
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use ferrum_macros::Vertex;
+
+#[derive(Vertex)]
+struct Vertex([f32; 3]);
+
+fn main() {}
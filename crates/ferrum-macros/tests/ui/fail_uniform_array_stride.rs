@@ -0,0 +1,9 @@
+use ferrum_macros::ShaderUniform;
+
+#[repr(C)]
+#[derive(ShaderUniform)]
+struct Weights {
+    weights: [f32; 8]
+}
+
+fn main() {}
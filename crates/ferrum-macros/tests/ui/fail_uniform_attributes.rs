@@ -0,0 +1,10 @@
+use ferrum_macros::ShaderUniform;
+
+#[derive(ShaderUniform)]
+#[layout(std150)]
+struct Ubo {
+    flag: bool,
+    position: glam::Vec3A
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use ferrum_macros::ShaderUniform;
+
+#[repr(C)]
+#[derive(ShaderUniform)]
+struct Light {
+    position: [f32; 3],
+    color: [f32; 4]
+}
+
+fn main() {}
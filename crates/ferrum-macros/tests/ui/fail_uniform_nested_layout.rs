@@ -0,0 +1,16 @@
+use ferrum_macros::ShaderUniform;
+
+#[repr(C)]
+#[derive(ShaderUniform)]
+#[layout(std430)]
+struct Material {
+    albedo: [f32; 4]
+}
+
+#[repr(C)]
+#[derive(ShaderUniform)]
+struct Ubo {
+    material: Material
+}
+
+fn main() {}
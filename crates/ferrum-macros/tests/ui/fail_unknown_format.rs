@@ -0,0 +1,9 @@
+use ferrum_macros::Vertex;
+
+#[derive(Vertex)]
+struct Vertex {
+    #[format(R32G32B32_FLOAT)]
+    pos: [f32; 3]
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use ferrum_macros::Vertex;
+
+#[derive(Vertex)]
+struct Vertex {
+    pos: [f32; 3],
+    weight: f64,
+    indices: [u32; 5]
+}
+
+fn main() {}
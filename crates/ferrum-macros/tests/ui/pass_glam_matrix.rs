@@ -0,0 +1,14 @@
+use ferrum_macros::Vertex;
+
+#[derive(Vertex)]
+#[instance]
+#[allow(dead_code)]
+struct Instance {
+    model: glam::Mat4,
+    normal: glam::Mat3,
+    scale: f32
+}
+
+fn main() {
+    assert_eq!(Instance::get_attribute_descriptions().len(), 8);
+}
@@ -0,0 +1,25 @@
+use ferrum_macros::ShaderUniform;
+
+#[repr(C)]
+#[derive(ShaderUniform)]
+#[layout(std430)]
+#[allow(dead_code)]
+struct Material {
+    albedo: glam::Vec3,
+    roughness: f32
+}
+
+#[repr(C)]
+#[derive(ShaderUniform)]
+#[layout(std430)]
+#[allow(dead_code)]
+struct Materials {
+    count: u32,
+    #[padding]
+    _pad: [u32; 3],
+    items: [Material; 16]
+}
+
+fn main() {
+    assert_eq!(Materials::GLSL_SIZE, 16 + 16 * 16);
+}
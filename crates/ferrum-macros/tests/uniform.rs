@@ -0,0 +1,67 @@
+use std::mem::offset_of;
+
+use ash::vk::{DescriptorType, ShaderStageFlags};
+use ferrum_macros::ShaderUniform;
+
+#[repr(C)]
+#[derive(ShaderUniform)]
+#[allow(dead_code)]
+struct Camera {
+    view: [[f32; 4]; 4],
+    projection: glam::Mat4,
+    position: [f32; 3],
+    exposure: f32
+}
+
+#[repr(C)]
+#[derive(ShaderUniform)]
+#[binding(2)]
+#[stages(VERTEX, FRAGMENT)]
+#[allow(dead_code)]
+struct Scene {
+    camera: Camera,
+    sun_direction: [f32; 3],
+    #[padding]
+    _pad0: f32,
+    lights: [[f32; 4]; 8],
+    count: u32
+}
+
+#[repr(C)]
+#[derive(ShaderUniform)]
+#[layout(std430)]
+#[allow(dead_code)]
+struct Particles {
+    weights: [f32; 8],
+    offset: [f32; 2],
+    scale: f32
+}
+
+#[test]
+fn test_std140_layout() {
+
+    assert_eq!(Camera::GLSL_ALIGN, 16);
+    assert_eq!(Camera::GLSL_SIZE, 144);
+
+    assert_eq!(offset_of!(Scene, sun_direction), 144);
+    assert_eq!(offset_of!(Scene, lights), 160);
+    assert_eq!(Scene::GLSL_SIZE, 304);
+
+    let binding = Scene::descriptor_set_layout_binding();
+    assert_eq!(binding.binding, 2);
+    assert_eq!(binding.descriptor_type, DescriptorType::UNIFORM_BUFFER);
+    assert_eq!(binding.descriptor_count, 1);
+    assert_eq!(binding.stage_flags, ShaderStageFlags::VERTEX | ShaderStageFlags::FRAGMENT);
+}
+
+#[test]
+fn test_std430_layout() {
+
+    assert_eq!(Particles::GLSL_ALIGN, 8);
+    assert_eq!(Particles::GLSL_SIZE, 48);
+
+    let binding = Particles::descriptor_set_layout_binding();
+    assert_eq!(binding.binding, 0);
+    assert_eq!(binding.descriptor_type, DescriptorType::STORAGE_BUFFER);
+    assert_eq!(binding.stage_flags, ShaderStageFlags::ALL);
+}
@@ -0,0 +1,78 @@
+use std::mem::{offset_of, size_of};
+
+use ash::vk::{Format, VertexInputRate};
+use ferrum_macros::Vertex;
+
+#[repr(C)]
+#[derive(Vertex)]
+#[allow(dead_code)]
+struct Vertex {
+    pos: [f32; 3],
+    uv: [f32; 2],
+    color: [u8; 4],
+    #[format(R16G16_SFLOAT)]
+    packed: [half::f16; 2],
+    id: u32,
+    normal: glam::Vec3
+}
+
+#[repr(C)]
+#[derive(Vertex)]
+#[binding(1)]
+#[instance]
+#[allow(dead_code)]
+struct Instance {
+    #[location(6)]
+    model: [[f32; 4]; 4],
+    tint: glam::Vec4,
+    layer: i32
+}
+
+#[test]
+fn test_vertex_formats_and_offsets() {
+
+    let bindings = Vertex::get_binding_descriptions();
+    assert_eq!(bindings.len(), 1);
+    assert_eq!(bindings[0].binding, 0);
+    assert_eq!(bindings[0].stride, size_of::<Vertex>() as u32);
+    assert_eq!(bindings[0].input_rate, VertexInputRate::VERTEX);
+
+    let attributes = Vertex::get_attribute_descriptions()
+        .iter()
+        .map(|x| (x.location, x.binding, x.format, x.offset))
+        .collect::<Vec<_>>();
+
+    assert_eq!(attributes, vec![
+        (0, 0, Format::R32G32B32_SFLOAT, offset_of!(Vertex, pos) as u32),
+        (1, 0, Format::R32G32_SFLOAT, offset_of!(Vertex, uv) as u32),
+        (2, 0, Format::R8G8B8A8_UNORM, offset_of!(Vertex, color) as u32),
+        (3, 0, Format::R16G16_SFLOAT, offset_of!(Vertex, packed) as u32),
+        (4, 0, Format::R32_UINT, offset_of!(Vertex, id) as u32),
+        (5, 0, Format::R32G32B32_SFLOAT, offset_of!(Vertex, normal) as u32),
+    ]);
+}
+
+#[test]
+fn test_instance_binding() {
+
+    let bindings = Instance::get_binding_descriptions();
+    assert_eq!(bindings[0].binding, 1);
+    assert_eq!(bindings[0].stride, size_of::<Instance>() as u32);
+    assert_eq!(bindings[0].input_rate, VertexInputRate::INSTANCE);
+
+    let attributes = Instance::get_attribute_descriptions()
+        .iter()
+        .map(|x| (x.location, x.binding, x.format, x.offset))
+        .collect::<Vec<_>>();
+
+    let model = offset_of!(Instance, model) as u32;
+
+    assert_eq!(attributes, vec![
+        (6, 1, Format::R32G32B32A32_SFLOAT, model),
+        (7, 1, Format::R32G32B32A32_SFLOAT, model + 16),
+        (8, 1, Format::R32G32B32A32_SFLOAT, model + 32),
+        (9, 1, Format::R32G32B32A32_SFLOAT, model + 48),
+        (10, 1, Format::R32G32B32A32_SFLOAT, offset_of!(Instance, tint) as u32),
+        (11, 1, Format::R32_SINT, offset_of!(Instance, layer) as u32),
+    ]);
+}
@@ -0,0 +1,245 @@
+use ash::vk::{self, Extent2D, Format, PhysicalDeviceMemoryProperties, SampleCountFlags};
+
+use crate::find_memorytype_index;
+
+/// Depth formats in order of preference
+pub const DEPTH_FORMATS: &[Format] = &[
+    Format::D32_SFLOAT,
+    Format::D24_UNORM_S8_UINT,
+    Format::D32_SFLOAT_S8_UINT,
+    Format::D16_UNORM,
+];
+
+/// Depth formats with a stencil aspect in order of preference
+pub const DEPTH_STENCIL_FORMATS: &[Format] = &[
+    Format::D24_UNORM_S8_UINT,
+    Format::D32_SFLOAT_S8_UINT,
+    Format::D16_UNORM_S8_UINT,
+];
+
+pub fn has_stencil(format: Format) -> bool {
+    matches!(format, Format::D16_UNORM_S8_UINT | Format::D24_UNORM_S8_UINT | Format::D32_SFLOAT_S8_UINT | Format::S8_UINT)
+}
+
+/// First format in `candidates` accepted by `is_supported`
+pub fn choose_depth_format(candidates: &[Format], is_supported: impl Fn(Format) -> bool) -> Option<Format> {
+    candidates.iter().copied().find(|x| is_supported(*x))
+}
+
+/// Depth (and stencil) format usable as an optimal tiling attachment
+pub fn find_depth_format(instance: &ash::Instance, phys_dev: vk::PhysicalDevice, stencil: bool) -> Option<Format> {
+    let candidates = if stencil { DEPTH_STENCIL_FORMATS } else { DEPTH_FORMATS };
+
+    choose_depth_format(candidates, |format| {
+        let props = unsafe { instance.get_physical_device_format_properties(phys_dev, format) };
+        props.optimal_tiling_features.contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+    })
+}
+
+/// Highest count not above `requested` supported by both colour and depth framebuffers
+pub fn clamp_sample_count(requested: SampleCountFlags, limits: &vk::PhysicalDeviceLimits) -> SampleCountFlags {
+    let supported = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+    [
+        SampleCountFlags::TYPE_64,
+        SampleCountFlags::TYPE_32,
+        SampleCountFlags::TYPE_16,
+        SampleCountFlags::TYPE_8,
+        SampleCountFlags::TYPE_4,
+        SampleCountFlags::TYPE_2,
+    ].into_iter()
+        .find(|x| x.as_raw() <= requested.as_raw() && supported.contains(*x))
+        .unwrap_or(SampleCountFlags::TYPE_1)
+}
+
+///
+/// Device local image with a view, used for depth and MSAA targets
+///
+/// # Example
+/// ```
+/// let depth = AttachmentImageBuilder::new()
+///     .with_device(device)
+///     .with_memory_properties(&phys_dev.phys_info.memory_prop)
+///     .with_extent(extent)
+///     .with_format(Format::D32_SFLOAT)
+///     .with_usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
+///     .build()?;
+/// ```
+///
+pub struct AttachmentImage {
+    pub raw: vk::Image,
+    pub memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub format: Format,
+    pub extent: Extent2D,
+    pub samples: SampleCountFlags,
+    /// Array layers, [`AttachmentImage::view`] covers all of them
+    pub layers: u32
+}
+
+impl AttachmentImage {
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.raw, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct AttachmentImageBuilder<'n> {
+    device: Option<&'n ash::Device>,
+    memory_prop: Option<&'n PhysicalDeviceMemoryProperties>,
+    extent: Option<Extent2D>,
+    format: Option<Format>,
+    usage: Option<vk::ImageUsageFlags>,
+    samples: Option<SampleCountFlags>,
+    layers: Option<u32>
+}
+
+impl<'n> AttachmentImageBuilder<'n> {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with_device(mut self, device: &'n ash::Device) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    pub fn with_memory_properties(mut self, memory_prop: &'n PhysicalDeviceMemoryProperties) -> Self {
+        self.memory_prop = Some(memory_prop);
+        self
+    }
+
+    pub fn with_extent(mut self, extent: Extent2D) -> Self {
+        self.extent = Some(extent);
+        self
+    }
+
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    pub fn with_usage(mut self, usage: vk::ImageUsageFlags) -> Self {
+        self.usage = Some(usage);
+        self
+    }
+
+    /// Default: TYPE_1
+    pub fn with_samples(mut self, samples: SampleCountFlags) -> Self {
+        self.samples = Some(samples);
+        self
+    }
+
+    /// Creates an array image with a `TYPE_2D_ARRAY` view, even for one layer
+    pub fn with_layers(mut self, layers: u32) -> Self {
+        self.layers = Some(layers);
+        self
+    }
+
+    ///
+    /// # Panics
+    /// if device, memory properties, extent, format or usage is missing
+    ///
+    pub fn build(self) -> Result<AttachmentImage, vk::Result> {
+
+        let device = self.device.expect("Device is missing");
+        let memory_prop = self.memory_prop.expect("Memory properties is missing");
+        let extent = self.extent.expect("Extent is missing");
+        let format = self.format.expect("Format is missing");
+        let usage = self.usage.expect("Usage is missing");
+        let samples = self.samples.unwrap_or(SampleCountFlags::TYPE_1);
+        let layers = self.layers.unwrap_or(1).max(1);
+
+        let aspect_mask = if usage.contains(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT) {
+            if has_stencil(format) {
+                vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+            } else {
+                vk::ImageAspectFlags::DEPTH
+            }
+        } else {
+            vk::ImageAspectFlags::COLOR
+        };
+
+        let image_info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+            .mip_levels(1)
+            .array_layers(layers)
+            .samples(samples)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let image = unsafe { device.create_image(&image_info, None)? };
+        let req = unsafe { device.get_image_memory_requirements(image) };
+
+        // Transient attachments prefer lazily allocated memory on tilers
+        let lazy = usage.contains(vk::ImageUsageFlags::TRANSIENT_ATTACHMENT)
+            .then(|| find_memorytype_index(&req, memory_prop, vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::LAZILY_ALLOCATED))
+            .flatten();
+
+        let memory_type_index = lazy
+            .or_else(|| find_memorytype_index(&req, memory_prop, vk::MemoryPropertyFlags::DEVICE_LOCAL))
+            .ok_or(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY)?;
+
+        let alloc_info = vk::MemoryAllocateInfo::default()
+            .allocation_size(req.size)
+            .memory_type_index(memory_type_index);
+
+        let memory = unsafe { device.allocate_memory(&alloc_info, None)? };
+        unsafe { device.bind_image_memory(image, memory, 0)? };
+
+        let view_type = match self.layers {
+            Some(_) => vk::ImageViewType::TYPE_2D_ARRAY,
+            None => vk::ImageViewType::TYPE_2D
+        };
+
+        let view_info = vk::ImageViewCreateInfo::default()
+            .image(image)
+            .view_type(view_type)
+            .format(format)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: layers,
+            });
+
+        let view = unsafe { device.create_image_view(&view_info, None)? };
+
+        Ok(AttachmentImage { raw: image, memory, view, format, extent, samples, layers })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_depth_format() {
+        let supported = [Format::D24_UNORM_S8_UINT, Format::D16_UNORM];
+        assert_eq!(choose_depth_format(DEPTH_FORMATS, |x| supported.contains(&x)), Some(Format::D24_UNORM_S8_UINT));
+        assert_eq!(choose_depth_format(DEPTH_STENCIL_FORMATS, |x| x == Format::D16_UNORM), None);
+    }
+
+    #[test]
+    fn test_clamp_sample_count() {
+        let limits = vk::PhysicalDeviceLimits {
+            framebuffer_color_sample_counts: SampleCountFlags::TYPE_1 | SampleCountFlags::TYPE_2 | SampleCountFlags::TYPE_4 | SampleCountFlags::TYPE_8,
+            framebuffer_depth_sample_counts: SampleCountFlags::TYPE_1 | SampleCountFlags::TYPE_2 | SampleCountFlags::TYPE_4,
+            ..Default::default()
+        };
+
+        assert_eq!(clamp_sample_count(SampleCountFlags::TYPE_8, &limits), SampleCountFlags::TYPE_4);
+        assert_eq!(clamp_sample_count(SampleCountFlags::TYPE_2, &limits), SampleCountFlags::TYPE_2);
+        assert_eq!(clamp_sample_count(SampleCountFlags::TYPE_1, &limits), SampleCountFlags::TYPE_1);
+    }
+}
@@ -0,0 +1,473 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use ash::vk;
+
+use crate::{
+    DeferredResource,
+    DescriptorPool,
+    DescriptorPoolBuilder,
+    DescriptorSetLayout,
+    DescriptorSetLayoutBuilder,
+    DeviceHandle,
+    GPUBuffer,
+    PipelineError,
+    VulkanError,
+    VulkanResult
+};
+
+/// Binding of the `sampler2D textures[]` array in the global set
+pub const BINDLESS_SAMPLED_IMAGE_BINDING: u32 = 0;
+/// Binding of the `image2D images[]` array in the global set
+pub const BINDLESS_STORAGE_IMAGE_BINDING: u32 = 1;
+/// Binding of the `buffer Buffers { ... } buffers[]` array in the global set
+pub const BINDLESS_STORAGE_BUFFER_BINDING: u32 = 2;
+
+const DEFAULT_MAX_SAMPLED_IMAGES: u32 = 16384;
+const DEFAULT_MAX_STORAGE_IMAGES: u32 = 1024;
+const DEFAULT_MAX_STORAGE_BUFFERS: u32 = 4096;
+
+///
+/// Stable index of a resource inside the global bindless set.
+///
+/// The value stays valid until the resource is released and can be passed
+/// to shaders as is (push constants, material buffers, ...).
+/// [`crate::GPUBuffer`] and [`crate::Texture`] release their slot on drop
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BindlessHandle(u32);
+
+impl BindlessHandle {
+    pub fn index(&self) -> u32 {
+        self.0
+    }
+}
+
+/// Update-after-bind descriptor limits of a device, see [`BindlessDescriptorSetBuilder::with_limits`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BindlessLimits {
+    pub max_sampled_images: u32,
+    pub max_storage_images: u32,
+    pub max_storage_buffers: u32
+}
+
+impl BindlessLimits {
+
+    /// Lower of the per-set and per-stage `maxDescriptorSetUpdateAfterBind*` limits
+    pub fn query(instance: &ash::Instance, phys_dev: vk::PhysicalDevice) -> Self {
+
+        let mut indexing = vk::PhysicalDeviceDescriptorIndexingProperties::default();
+        let mut props2 = vk::PhysicalDeviceProperties2::default().push_next(&mut indexing);
+
+        unsafe { instance.get_physical_device_properties2(phys_dev, &mut props2) };
+
+        Self::from_properties(&indexing)
+    }
+
+    pub fn from_properties(props: &vk::PhysicalDeviceDescriptorIndexingProperties) -> Self {
+        Self {
+            // Комбинированный сэмплер считается и как образ, и как сэмплер
+            max_sampled_images: props.max_descriptor_set_update_after_bind_sampled_images
+                .min(props.max_descriptor_set_update_after_bind_samplers)
+                .min(props.max_per_stage_descriptor_update_after_bind_sampled_images)
+                .min(props.max_per_stage_descriptor_update_after_bind_samplers),
+            max_storage_images: props.max_descriptor_set_update_after_bind_storage_images
+                .min(props.max_per_stage_descriptor_update_after_bind_storage_images),
+            max_storage_buffers: props.max_descriptor_set_update_after_bind_storage_buffers
+                .min(props.max_per_stage_descriptor_update_after_bind_storage_buffers)
+        }
+    }
+}
+
+/// Hands out indices of one descriptor array, reusing released ones first
+#[derive(Debug, Default)]
+struct HandleAllocator {
+    next: u32,
+    free: Vec<u32>,
+    capacity: u32
+}
+
+impl HandleAllocator {
+
+    fn new(capacity: u32) -> Self {
+        Self { next: 0, free: vec![], capacity }
+    }
+
+    fn allocate(&mut self) -> Option<u32> {
+
+        if let Some(index) = self.free.pop() {
+            return Some(index);
+        }
+
+        if self.next >= self.capacity {
+            return None;
+        }
+
+        let index = self.next;
+        self.next += 1;
+        Some(index)
+    }
+
+    fn release(&mut self, index: u32) {
+        debug_assert!(index < self.next, "Release of never allocated bindless index {}", index);
+        debug_assert!(!self.free.contains(&index), "Double release of bindless index {}", index);
+        self.free.push(index);
+    }
+}
+
+///
+/// One global descriptor set with large `UPDATE_AFTER_BIND | PARTIALLY_BOUND`
+/// arrays of sampled images, storage images and storage buffers.
+///
+/// Bind it once per command buffer and index textures/buffers directly in shaders:
+///
+/// ```glsl
+/// #extension GL_EXT_nonuniform_qualifier : require
+/// layout(set = 0, binding = 0) uniform sampler2D textures[];
+///
+/// vec4 albedo = texture(textures[nonuniformEXT(material.albedo)], uv);
+/// ```
+///
+/// # Example
+/// ```ignore
+/// let mut bindless = BindlessDescriptorSetBuilder::new()
+///     .with_device(ctx.device.raw_device())
+///     .with_limits(BindlessLimits::query(&ctx.device.instance.raw, ctx.device.phys_dev.raw))
+///     .build()?;
+///
+/// let albedo = bindless.register_sampled_image(ctx.device.raw_device(), image_view, sampler)?;
+///
+/// let pipeline = RenderPipelineBuilder::new()
+///     .with_descriptor_set_layouts(&[bindless.layout.raw])
+///     ...
+/// ```
+///
+pub struct BindlessDescriptorSet {
+    pub raw: vk::DescriptorSet,
+    pub layout: DescriptorSetLayout,
+    pub pool: DescriptorPool,
+    sampled_images: SharedAllocator,
+    storage_images: SharedAllocator,
+    storage_buffers: SharedAllocator
+}
+
+/// Shared with the deferred releases of dropped resources
+type SharedAllocator = Arc<Mutex<HandleAllocator>>;
+
+impl BindlessDescriptorSet {
+
+    /// Writes a combined image sampler into the `textures[]` array, fails if the array is full
+    pub fn register_sampled_image(&mut self, device: &ash::Device, image_view: vk::ImageView, sampler: vk::Sampler) -> VulkanResult<BindlessHandle> {
+
+        let index = lock(&self.sampled_images).allocate().ok_or(VulkanError::Pipeline(PipelineError::BindlessArrayFull("sampled image")))?;
+
+        let image_info = [vk::DescriptorImageInfo {
+            sampler,
+            image_view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.raw)
+            .dst_binding(BINDLESS_SAMPLED_IMAGE_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_info);
+
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+
+        Ok(BindlessHandle(index))
+    }
+
+    /// Writes a storage image (in `GENERAL` layout) into the `images[]` array, fails if the array is full
+    pub fn register_storage_image(&mut self, device: &ash::Device, image_view: vk::ImageView) -> VulkanResult<BindlessHandle> {
+
+        let index = lock(&self.storage_images).allocate().ok_or(VulkanError::Pipeline(PipelineError::BindlessArrayFull("storage image")))?;
+
+        let image_info = [vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view,
+            image_layout: vk::ImageLayout::GENERAL,
+        }];
+
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.raw)
+            .dst_binding(BINDLESS_STORAGE_IMAGE_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .image_info(&image_info);
+
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+
+        Ok(BindlessHandle(index))
+    }
+
+    /// Writes the whole buffer into the `buffers[]` array, fails if the array is full
+    pub fn register_storage_buffer(&mut self, device: &ash::Device, buffer: &GPUBuffer) -> VulkanResult<BindlessHandle> {
+
+        let index = lock(&self.storage_buffers).allocate().ok_or(VulkanError::Pipeline(PipelineError::BindlessArrayFull("storage buffer")))?;
+
+        let buffer_info = [vk::DescriptorBufferInfo::default()
+            .buffer(buffer.raw)
+            .offset(0)
+            .range(vk::WHOLE_SIZE)
+        ];
+
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.raw)
+            .dst_binding(BINDLESS_STORAGE_BUFFER_BINDING)
+            .dst_array_element(index)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(&buffer_info);
+
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+
+        Ok(BindlessHandle(index))
+    }
+
+    /// The slot may be reused by the next registration, so the resource
+    /// must not be referenced by frames that are still in flight
+    pub fn release_sampled_image(&mut self, handle: BindlessHandle) {
+        lock(&self.sampled_images).release(handle.0);
+    }
+
+    pub fn release_storage_image(&mut self, handle: BindlessHandle) {
+        lock(&self.storage_images).release(handle.0);
+    }
+
+    pub fn release_storage_buffer(&mut self, handle: BindlessHandle) {
+        lock(&self.storage_buffers).release(handle.0);
+    }
+
+    /// Release of the slot for a [`crate::ResourceOwner`], runs once the frames in flight are done with it
+    pub fn deferred_release_sampled_image(&self, handle: BindlessHandle) -> DeferredResource {
+        deferred_release(&self.sampled_images, handle)
+    }
+
+    pub fn deferred_release_storage_image(&self, handle: BindlessHandle) -> DeferredResource {
+        deferred_release(&self.storage_images, handle)
+    }
+
+    pub fn deferred_release_storage_buffer(&self, handle: BindlessHandle) -> DeferredResource {
+        deferred_release(&self.storage_buffers, handle)
+    }
+}
+
+fn lock(allocator: &SharedAllocator) -> MutexGuard<'_, HandleAllocator> {
+    allocator.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn deferred_release(allocator: &SharedAllocator, handle: BindlessHandle) -> DeferredResource {
+    let allocator = allocator.clone();
+    DeferredResource::Custom(Box::new(move |_| lock(&allocator).release(handle.0)))
+}
+
+///
+/// Builder of [`BindlessDescriptorSet`]
+///
+/// The device must be created with [`crate::DeviceBuilder::with_descriptor_indexing`]
+///
+/// Default values, lowered to [`Self::with_limits`]:
+/// - `max_sampled_images`: 16384
+/// - `max_storage_images`: 1024
+/// - `max_storage_buffers`: 4096
+/// - `stage_flags`: ALL
+///
+#[derive(Default)]
+pub struct BindlessDescriptorSetBuilder<'n> {
+    device: Option<&'n DeviceHandle>,
+    limits: Option<BindlessLimits>,
+    max_sampled_images: Option<u32>,
+    max_storage_images: Option<u32>,
+    max_storage_buffers: Option<u32>,
+    stage_flags: Option<vk::ShaderStageFlags>
+}
+
+impl<'n> BindlessDescriptorSetBuilder<'n> {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with_device(mut self, device: &'n DeviceHandle) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    /// Explicit counts above the limits fail the build, defaults are lowered to them
+    pub fn with_limits(mut self, limits: BindlessLimits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    pub fn with_max_sampled_images(mut self, count: u32) -> Self {
+        self.max_sampled_images = Some(count);
+        self
+    }
+
+    pub fn with_max_storage_images(mut self, count: u32) -> Self {
+        self.max_storage_images = Some(count);
+        self
+    }
+
+    pub fn with_max_storage_buffers(mut self, count: u32) -> Self {
+        self.max_storage_buffers = Some(count);
+        self
+    }
+
+    pub fn with_stage_flags(mut self, stages: vk::ShaderStageFlags) -> Self {
+        self.stage_flags = Some(stages);
+        self
+    }
+
+    pub fn build(self) -> VulkanResult<BindlessDescriptorSet> {
+
+        let device = self.device.ok_or(VulkanError::missing("BindlessDescriptorSetBuilder", "device"))?;
+        let max_sampled_images = array_size("sampled image", self.max_sampled_images, DEFAULT_MAX_SAMPLED_IMAGES, self.limits.map(|x| x.max_sampled_images))?;
+        let max_storage_images = array_size("storage image", self.max_storage_images, DEFAULT_MAX_STORAGE_IMAGES, self.limits.map(|x| x.max_storage_images))?;
+        let max_storage_buffers = array_size("storage buffer", self.max_storage_buffers, DEFAULT_MAX_STORAGE_BUFFERS, self.limits.map(|x| x.max_storage_buffers))?;
+        let stage_flags = self.stage_flags.unwrap_or(vk::ShaderStageFlags::ALL);
+
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(BINDLESS_SAMPLED_IMAGE_BINDING)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(max_sampled_images)
+                .stage_flags(stage_flags),
+
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(BINDLESS_STORAGE_IMAGE_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(max_storage_images)
+                .stage_flags(stage_flags),
+
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(BINDLESS_STORAGE_BUFFER_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(max_storage_buffers)
+                .stage_flags(stage_flags),
+        ];
+
+        let flags = vk::DescriptorBindingFlags::UPDATE_AFTER_BIND | vk::DescriptorBindingFlags::PARTIALLY_BOUND;
+        let binding_flags = [flags; 3];
+
+        let layout = DescriptorSetLayoutBuilder::new()
+            .with_device(device)
+            .with_bindings(&bindings)
+            .with_binding_flags(&binding_flags)
+            .with_flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+            .build()?;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(max_sampled_images),
+
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(max_storage_images),
+
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(max_storage_buffers),
+        ];
+
+        let pool = DescriptorPoolBuilder::new()
+            .with_device(device)
+            .with_pool_sizes(&pool_sizes)
+            .with_max_sets(1)
+            .with_flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+            .build()?;
+
+        let set_layouts = [layout.raw];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool.raw)
+            .set_layouts(&set_layouts);
+
+        let raw = unsafe { device.allocate_descriptor_sets(&allocate_info) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::AllocateDescriptorSetFailed(e))))?[0];
+
+        Ok(BindlessDescriptorSet {
+            raw,
+            layout,
+            pool,
+            sampled_images: Arc::new(Mutex::new(HandleAllocator::new(max_sampled_images))),
+            storage_images: Arc::new(Mutex::new(HandleAllocator::new(max_storage_images))),
+            storage_buffers: Arc::new(Mutex::new(HandleAllocator::new(max_storage_buffers)))
+        })
+    }
+}
+
+/// Requested count checked against `limit`, the default is lowered to it
+fn array_size(array: &'static str, requested: Option<u32>, default: u32, limit: Option<u32>) -> VulkanResult<u32> {
+
+    let limit = limit.unwrap_or(u32::MAX);
+
+    match requested {
+        Some(requested) if requested > limit => {
+            Err(VulkanError::Pipeline(PipelineError::BindlessLimitExceeded { array, requested, limit }))
+        },
+        Some(requested) => Ok(requested),
+        None => Ok(default.min(limit))
+    }
+}
+
+/// Features enabled by [`crate::DeviceBuilder::with_descriptor_indexing`], below 1.2 through `VK_EXT_descriptor_indexing`
+pub(crate) fn bindless_vulkan12_features(features: vk::PhysicalDeviceVulkan12Features<'static>) -> vk::PhysicalDeviceVulkan12Features<'static> {
+    features
+        .runtime_descriptor_array(true)
+        .descriptor_binding_partially_bound(true)
+        .descriptor_binding_sampled_image_update_after_bind(true)
+        .descriptor_binding_storage_image_update_after_bind(true)
+        .descriptor_binding_storage_buffer_update_after_bind(true)
+        .shader_sampled_image_array_non_uniform_indexing(true)
+        .shader_storage_image_array_non_uniform_indexing(true)
+        .shader_storage_buffer_array_non_uniform_indexing(true)
+}
+
+/// Check if physical device supports everything the bindless set needs
+pub fn supports_bindless(instance: &ash::Instance, phys_dev: vk::PhysicalDevice) -> bool {
+
+    let mut indexing = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+    let mut features2 = vk::PhysicalDeviceFeatures2::default().push_next(&mut indexing);
+
+    unsafe { instance.get_physical_device_features2(phys_dev, &mut features2) };
+
+    // Всё, что включает bindless_vulkan12_features
+    indexing.runtime_descriptor_array == vk::TRUE
+        && indexing.descriptor_binding_partially_bound == vk::TRUE
+        && indexing.descriptor_binding_sampled_image_update_after_bind == vk::TRUE
+        && indexing.descriptor_binding_storage_image_update_after_bind == vk::TRUE
+        && indexing.descriptor_binding_storage_buffer_update_after_bind == vk::TRUE
+        && indexing.shader_sampled_image_array_non_uniform_indexing == vk::TRUE
+        && indexing.shader_storage_image_array_non_uniform_indexing == vk::TRUE
+        && indexing.shader_storage_buffer_array_non_uniform_indexing == vk::TRUE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_allocator_reuses_released() {
+
+        let mut allocator = HandleAllocator::new(2);
+
+        assert_eq!(allocator.allocate(), Some(0));
+        assert_eq!(allocator.allocate(), Some(1));
+        assert_eq!(allocator.allocate(), None);
+
+        allocator.release(0);
+        assert_eq!(allocator.allocate(), Some(0));
+        assert_eq!(allocator.allocate(), None);
+    }
+
+    #[test]
+    fn test_array_size_respects_limits() {
+        assert_eq!(array_size("sampled image", None, 16384, Some(1000)).unwrap(), 1000);
+        assert_eq!(array_size("sampled image", None, 16384, None).unwrap(), 16384);
+        assert_eq!(array_size("storage image", Some(64), 1024, Some(500)).unwrap(), 64);
+
+        let result = array_size("storage buffer", Some(8192), 4096, Some(4096));
+        assert!(matches!(result, Err(VulkanError::Pipeline(PipelineError::BindlessLimitExceeded { requested: 8192, limit: 4096, .. }))));
+    }
+}
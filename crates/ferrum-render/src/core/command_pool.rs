@@ -1,7 +1,11 @@
 use ash::vk::{CommandBuffer, CommandBufferAllocateInfo, CommandBufferLevel, CommandPoolCreateFlags, CommandPoolCreateInfo};
 
+use crate::{DeferredResource, DeviceHandle, ResourceOwner};
+
+/// Command buffers allocated from the pool are freed together with it
 pub struct CommandPool {
-    pub raw: ash::vk::CommandPool
+    pub raw: ash::vk::CommandPool,
+    _owner: ResourceOwner
 }
 
 impl CommandPool {
@@ -19,7 +23,7 @@ impl CommandPool {
 
 #[derive(Default)]
 pub struct CommandPoolBuilder<'n> {
-    device: Option<&'n ash::Device>,
+    device: Option<&'n DeviceHandle>,
     family_index: Option<u32>
 }
 
@@ -29,7 +33,7 @@ impl<'n> CommandPoolBuilder<'n> {
         Self { ..Default::default() }
     }
 
-    pub fn device(mut self, device: &'n ash::Device) -> Self {
+    pub fn device(mut self, device: &'n DeviceHandle) -> Self {
         self.device = Some(device);
         self
     }
@@ -45,7 +49,9 @@ impl<'n> CommandPoolBuilder<'n> {
             .flags(CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
             .queue_family_index(self.family_index.unwrap());
 
-        let command_pool = unsafe { self.device.unwrap().create_command_pool(&create_info, None).unwrap() };
-        CommandPool { raw: command_pool }
+        let device = self.device.unwrap();
+        let command_pool = unsafe { device.create_command_pool(&create_info, None).unwrap() };
+
+        CommandPool { raw: command_pool, _owner: ResourceOwner::new(device, vec![DeferredResource::CommandPool(command_pool)]) }
     }
 }
\ No newline at end of file
@@ -0,0 +1,290 @@
+use std::{ffi::CStr, hash::{DefaultHasher, Hash, Hasher}, sync::Arc};
+
+use ash::vk::{self, *};
+
+use crate::{DeferredResource, DeviceHandle, ResourceOwner, hash_layout, GPUBuffer, PipelineError, ShaderProgram, VulkanError, VulkanResult};
+
+///
+/// Values for `layout(constant_id = N)` declarations
+///
+/// # Example
+/// ```
+/// let constants = SpecializationConstants::new()
+///     .with_u32(0, 64)
+///     .with_bool(1, true);
+///
+/// let pipeline = ComputePipelineBuilder::new()
+///     .with_device(device)
+///     .with_shader(shader.compute_shader)
+///     .with_entry_point(&shader.compute_entry_point)
+///     .with_specialization(&constants)
+///     .build()?;
+/// ```
+///
+#[derive(Default, Clone)]
+pub struct SpecializationConstants {
+    entries: Vec<SpecializationMapEntry>,
+    data: Vec<u8>
+}
+
+impl SpecializationConstants {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    fn with_bytes(mut self, constant_id: u32, bytes: &[u8]) -> Self {
+        self.entries.push(SpecializationMapEntry {
+            constant_id,
+            offset: self.data.len() as u32,
+            size: bytes.len()
+        });
+        self.data.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn with_u32(self, constant_id: u32, value: u32) -> Self {
+        self.with_bytes(constant_id, &value.to_ne_bytes())
+    }
+
+    pub fn with_i32(self, constant_id: u32, value: i32) -> Self {
+        self.with_bytes(constant_id, &value.to_ne_bytes())
+    }
+
+    pub fn with_f32(self, constant_id: u32, value: f32) -> Self {
+        self.with_bytes(constant_id, &value.to_ne_bytes())
+    }
+
+    /// SPIR-V booleans are 32 bit wide
+    pub fn with_bool(self, constant_id: u32, value: bool) -> Self {
+        self.with_u32(constant_id, value as u32)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn info(&self) -> SpecializationInfo<'_> {
+        SpecializationInfo::default()
+            .map_entries(&self.entries)
+            .data(&self.data)
+    }
+}
+
+/// Clones share the handles, they are queued for destruction with the last clone
+#[derive(Clone)]
+pub struct ComputePipeline {
+    pub raw: Pipeline,
+    pub raw_layout: PipelineLayout,
+    _owner: Arc<ResourceOwner>
+}
+
+impl ComputePipeline {
+
+    pub fn bind(&self, device: &ash::Device, command_buffer: CommandBuffer) {
+        unsafe { device.cmd_bind_pipeline(command_buffer, PipelineBindPoint::COMPUTE, self.raw) };
+    }
+
+    pub fn bind_descriptor_sets(&self, device: &ash::Device, command_buffer: CommandBuffer, first_set: u32, sets: &[DescriptorSet]) {
+        unsafe { device.cmd_bind_descriptor_sets(command_buffer, PipelineBindPoint::COMPUTE, self.raw_layout, first_set, sets, &[]) };
+    }
+
+    pub fn push_constants(&self, device: &ash::Device, command_buffer: CommandBuffer, offset: u32, data: &[u8]) {
+        unsafe { device.cmd_push_constants(command_buffer, self.raw_layout, ShaderStageFlags::COMPUTE, offset, data) };
+    }
+
+    /// Binds the pipeline and dispatches `groups` workgroups
+    pub fn dispatch(&self, device: &ash::Device, command_buffer: CommandBuffer, groups: [u32; 3]) {
+        self.bind(device, command_buffer);
+        unsafe { device.cmd_dispatch(command_buffer, groups[0], groups[1], groups[2]) };
+    }
+
+    /// Dispatches enough workgroups of `workgroup_size` to cover `threads` invocations
+    pub fn dispatch_threads(&self, device: &ash::Device, command_buffer: CommandBuffer, threads: [u32; 3], workgroup_size: [u32; 3]) {
+        self.dispatch(device, command_buffer, workgroup_count(threads, workgroup_size));
+    }
+
+    /// Reads [`vk::DispatchIndirectCommand`] from `buffer` at `offset`
+    pub fn dispatch_indirect(&self, device: &ash::Device, command_buffer: CommandBuffer, buffer: &GPUBuffer, offset: u64) {
+        self.bind(device, command_buffer);
+        unsafe { device.cmd_dispatch_indirect(command_buffer, buffer.raw, offset) };
+    }
+}
+
+/// Number of workgroups needed to cover `threads` invocations
+pub fn workgroup_count(threads: [u32; 3], workgroup_size: [u32; 3]) -> [u32; 3] {
+    [
+        threads[0].div_ceil(workgroup_size[0].max(1)),
+        threads[1].div_ceil(workgroup_size[1].max(1)),
+        threads[2].div_ceil(workgroup_size[2].max(1)),
+    ]
+}
+
+#[derive(Default)]
+pub struct ComputePipelineBuilder<'n> {
+    device: Option<&'n DeviceHandle>,
+    shader: Option<ShaderModule>,
+    entry_point: Option<&'n CStr>,
+    shader_hash: Option<u64>,
+    specialization: Option<&'n SpecializationConstants>,
+    descriptor_set_layout: Option<&'n [DescriptorSetLayout]>,
+    push_constant_ranges: Option<&'n [PushConstantRange]>,
+    pipeline_cache: Option<PipelineCache>
+}
+
+impl<'n> ComputePipelineBuilder<'n> {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with_device(mut self, dev: &'n DeviceHandle) -> Self {
+        self.device = Some(dev);
+        self
+    }
+
+    /// Without the SPIR-V hash the pipeline can not go through [`crate::PipelineRegistry`],
+    /// see [`Self::with_shader_program`]
+    pub fn with_shader(mut self, shader: ShaderModule) -> Self {
+        self.shader = Some(shader);
+        self.shader_hash = None;
+        self
+    }
+
+    /// Compute stage of `program` with its entry point and SPIR-V hash
+    pub fn with_shader_program(mut self, program: &'n ShaderProgram) -> Self {
+        self.shader = Some(program.compute_shader);
+        self.entry_point = Some(&program.compute_entry_point);
+        self.shader_hash = Some(program.compute_hash);
+        self
+    }
+
+    /// Default: `main`
+    pub fn with_entry_point(mut self, name: &'n CStr) -> Self {
+        self.entry_point = Some(name);
+        self
+    }
+
+    pub fn with_specialization(mut self, constants: &'n SpecializationConstants) -> Self {
+        self.specialization = Some(constants);
+        self
+    }
+
+    pub fn with_descriptor_set_layouts(mut self, desc_set_layout: &'n [DescriptorSetLayout]) -> Self {
+        self.descriptor_set_layout = Some(desc_set_layout);
+        self
+    }
+
+    pub fn with_push_constant_ranges(mut self, ranges: &'n [PushConstantRange]) -> Self {
+        self.push_constant_ranges = Some(ranges);
+        self
+    }
+
+    pub fn with_pipeline_cache(mut self, cache: PipelineCache) -> Self {
+        self.pipeline_cache = Some(cache);
+        self
+    }
+
+    /// Hash of everything that affects the created pipeline, used by [`crate::PipelineRegistry`].
+    /// `None` if the shader module was set without its SPIR-V hash
+    pub fn hash_key(&self) -> Option<u64> {
+
+        if self.shader.is_some() && self.shader_hash.is_none() {
+            return None;
+        }
+
+        let mut hasher = DefaultHasher::new();
+
+        self.shader_hash.hash(&mut hasher);
+        self.entry_point.hash(&mut hasher);
+
+        if let Some(constants) = self.specialization {
+            for x in &constants.entries {
+                (x.constant_id, x.offset, x.size).hash(&mut hasher);
+            }
+            constants.data.hash(&mut hasher);
+        }
+
+        hash_layout(&mut hasher, self.descriptor_set_layout, self.push_constant_ranges);
+
+        Some(hasher.finish())
+    }
+
+    pub fn build(self) -> VulkanResult<ComputePipeline> {
+
+        let device = self.device.ok_or(VulkanError::missing("ComputePipelineBuilder", "device"))?;
+        let shader = self.shader.ok_or(VulkanError::missing("ComputePipelineBuilder", "compute shader"))?;
+
+        let layout_info = PipelineLayoutCreateInfo::default()
+            .set_layouts(self.descriptor_set_layout.unwrap_or(&[]))
+            .push_constant_ranges(self.push_constant_ranges.unwrap_or(&[]));
+
+        let pipeline_layout = unsafe { device.create_pipeline_layout(&layout_info, None) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreatePipelineLayoutFailed(e))))?;
+
+        let specialization_info = self.specialization.map(|x| x.info());
+
+        let mut stage_info = PipelineShaderStageCreateInfo::default()
+            .module(shader)
+            .name(self.entry_point.unwrap_or(c"main"))
+            .stage(ShaderStageFlags::COMPUTE);
+
+        if let Some(info) = &specialization_info {
+            stage_info = stage_info.specialization_info(info);
+        }
+
+        let pipeline_info = vk::ComputePipelineCreateInfo::default()
+            .stage(stage_info)
+            .layout(pipeline_layout);
+
+        let pipeline = unsafe {
+            device.create_compute_pipelines(
+                self.pipeline_cache.unwrap_or(PipelineCache::null()),
+                std::slice::from_ref(&pipeline_info),
+                None
+            )
+            .map_err(|e| e.1)
+        };
+
+        match pipeline {
+            Ok(pipeline) => Ok(ComputePipeline {
+                raw: pipeline[0],
+                raw_layout: pipeline_layout,
+                _owner: Arc::new(ResourceOwner::new(device, vec![
+                    DeferredResource::Pipeline(pipeline[0]),
+                    DeferredResource::PipelineLayout(pipeline_layout)
+                ]))
+            }),
+            Err(e) => {
+                unsafe { device.destroy_pipeline_layout(pipeline_layout, None) };
+                Err(VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreatePipelineFailed(e))))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workgroup_count_rounds_up() {
+        assert_eq!(workgroup_count([1920, 1080, 1], [16, 16, 1]), [120, 68, 1]);
+        assert_eq!(workgroup_count([1, 1, 1], [64, 1, 1]), [1, 1, 1]);
+        assert_eq!(workgroup_count([0, 0, 0], [8, 8, 1]), [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_specialization_constants_layout() {
+        let constants = SpecializationConstants::new()
+            .with_u32(0, 7)
+            .with_f32(3, 1.5)
+            .with_bool(5, true);
+
+        let info = constants.info();
+        assert_eq!(info.map_entry_count, 3);
+        assert_eq!(info.data_size, 12);
+        assert_eq!(constants.entries[1].offset, 4);
+        assert_eq!(constants.entries[2].constant_id, 5);
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+
+use ash::vk;
+
+/// Resource waiting until the GPU is done with it
+pub enum DeferredResource {
+    Buffer(vk::Buffer, vk::DeviceMemory),
+    Image(vk::Image, vk::DeviceMemory),
+    ImageView(vk::ImageView),
+    Sampler(vk::Sampler),
+    Framebuffer(vk::Framebuffer),
+    RenderPass(vk::RenderPass),
+    Pipeline(vk::Pipeline),
+    PipelineLayout(vk::PipelineLayout),
+    DescriptorPool(vk::DescriptorPool),
+    CommandPool(vk::CommandPool),
+    DescriptorSetLayout(vk::DescriptorSetLayout),
+    ShaderModule(vk::ShaderModule),
+    Semaphore(vk::Semaphore),
+    Fence(vk::Fence),
+    Custom(Box<dyn FnOnce(&ash::Device) + Send>)
+}
+
+impl DeferredResource {
+
+    pub fn destroy(self, device: &ash::Device) {
+        unsafe {
+            match self {
+                DeferredResource::Buffer(buffer, memory) => {
+                    device.destroy_buffer(buffer, None);
+                    device.free_memory(memory, None);
+                },
+                DeferredResource::Image(image, memory) => {
+                    device.destroy_image(image, None);
+                    device.free_memory(memory, None);
+                },
+                DeferredResource::ImageView(x) => device.destroy_image_view(x, None),
+                DeferredResource::Sampler(x) => device.destroy_sampler(x, None),
+                DeferredResource::Framebuffer(x) => device.destroy_framebuffer(x, None),
+                DeferredResource::RenderPass(x) => device.destroy_render_pass(x, None),
+                DeferredResource::Pipeline(x) => device.destroy_pipeline(x, None),
+                DeferredResource::PipelineLayout(x) => device.destroy_pipeline_layout(x, None),
+                DeferredResource::DescriptorPool(x) => device.destroy_descriptor_pool(x, None),
+                DeferredResource::CommandPool(x) => device.destroy_command_pool(x, None),
+                DeferredResource::DescriptorSetLayout(x) => device.destroy_descriptor_set_layout(x, None),
+                DeferredResource::ShaderModule(x) => device.destroy_shader_module(x, None),
+                DeferredResource::Semaphore(x) => device.destroy_semaphore(x, None),
+                DeferredResource::Fence(x) => device.destroy_fence(x, None),
+                DeferredResource::Custom(f) => f(device),
+            }
+        }
+    }
+}
+
+///
+/// Destroys resources once the GPU timeline has passed their last use
+///
+/// # Example
+/// ```
+/// // buffer was last used by the frame that signals `pacer.current_value()`
+/// pacer.deletion_queue.push(pacer.current_value(), DeferredResource::Buffer(buf.raw, buf.memory));
+///
+/// // every frame
+/// pacer.collect_garbage(device)?;
+/// ```
+///
+#[derive(Default)]
+pub struct DeletionQueue {
+    pending: VecDeque<(u64, DeferredResource)>
+}
+
+impl DeletionQueue {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    /// `last_use` is the timeline value signaled after the last GPU use
+    pub fn push(&mut self, last_use: u64, resource: DeferredResource) {
+        // Values are pushed mostly in order, keep the queue sorted
+        let index = self.pending.partition_point(|(value, _)| *value <= last_use);
+        self.pending.insert(index, (last_use, resource));
+    }
+
+    /// Removes resources whose last use is not after `completed`
+    pub fn drain_completed(&mut self, completed: u64) -> Vec<DeferredResource> {
+        let count = self.pending.partition_point(|(value, _)| *value <= completed);
+        self.pending.drain(..count).map(|(_, resource)| resource).collect()
+    }
+
+    pub fn flush(&mut self, device: &ash::Device, completed: u64) {
+        for resource in self.drain_completed(completed) {
+            resource.destroy(device);
+        }
+    }
+
+    /// Destroys everything, the device must be idle
+    pub fn flush_all(&mut self, device: &ash::Device) {
+        self.flush(device, u64::MAX);
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drain_respects_timeline() {
+        let mut queue = DeletionQueue::new();
+        queue.push(3, DeferredResource::Sampler(vk::Sampler::null()));
+        queue.push(1, DeferredResource::Fence(vk::Fence::null()));
+        queue.push(2, DeferredResource::Semaphore(vk::Semaphore::null()));
+
+        assert!(queue.drain_completed(0).is_empty());
+
+        let done = queue.drain_completed(2);
+        assert_eq!(done.len(), 2);
+        assert!(matches!(done[0], DeferredResource::Fence(_)));
+        assert!(matches!(done[1], DeferredResource::Semaphore(_)));
+
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.drain_completed(u64::MAX).len(), 1);
+        assert!(queue.is_empty());
+    }
+}
@@ -1,15 +1,20 @@
 
 use ash::vk::{self, DescriptorPoolSize};
 
+use crate::{DeferredResource, DeviceHandle, ResourceOwner, PipelineError, VulkanError, VulkanResult};
+
+/// Sets allocated from the pool are freed together with it
 pub struct DescriptorPool {
-    pub raw: vk::DescriptorPool
+    pub raw: vk::DescriptorPool,
+    _owner: ResourceOwner
 }
 
 #[derive(Default)]
 pub struct DescriptorPoolBuilder<'n> {
     pub pool_sizes: Option<&'n [DescriptorPoolSize]>,
     pub max_sets: Option<u32>,
-    pub device: Option<&'n ash::Device>,
+    pub flags: Option<vk::DescriptorPoolCreateFlags>,
+    pub device: Option<&'n DeviceHandle>,
 }
 
 impl<'n> DescriptorPoolBuilder<'n> {
@@ -28,27 +33,34 @@ impl<'n> DescriptorPoolBuilder<'n> {
         self
     }
 
-    pub fn with_device(mut self, dev: &'n ash::Device) -> Self {
+    pub fn with_flags(mut self, flags: vk::DescriptorPoolCreateFlags) -> Self {
+        self.flags = Some(flags);
+        self
+    }
+
+    pub fn with_device(mut self, dev: &'n DeviceHandle) -> Self {
         self.device = Some(dev);
         self
     }
 
-    pub fn build(self) -> DescriptorPool {
+    pub fn build(self) -> VulkanResult<DescriptorPool> {
 
-        let device = self.device.expect("Device is missing");
-        let pool_sizes = self.pool_sizes.expect("Pool sizes is missing");
+        let device = self.device.ok_or(VulkanError::missing("DescriptorPoolBuilder", "device"))?;
+        let pool_sizes = self.pool_sizes.ok_or(VulkanError::missing("DescriptorPoolBuilder", "pool sizes"))?;
         let max_sets = self.max_sets.unwrap_or(1);
+        let flags = self.flags.unwrap_or_default();
 
         let pool_info = vk::DescriptorPoolCreateInfo::default()
             .pool_sizes(&pool_sizes)
-            .max_sets(max_sets);
+            .max_sets(max_sets)
+            .flags(flags);
 
-        let descriptor_pool = unsafe {
-            device
-                .create_descriptor_pool(&pool_info, None)
-                .expect("Error create Description Pool")
-        };
+        let descriptor_pool = unsafe { device.create_descriptor_pool(&pool_info, None) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateDescriptorPoolFailed(e))))?;
 
-        DescriptorPool { raw: descriptor_pool }
+        Ok(DescriptorPool {
+            raw: descriptor_pool,
+            _owner: ResourceOwner::new(device, vec![DeferredResource::DescriptorPool(descriptor_pool)])
+        })
     }
 }
\ No newline at end of file
@@ -1,9 +1,13 @@
 use ash::vk;
 
+use crate::{DeferredResource, DeviceHandle, PipelineError, ResourceOwner, VulkanError, VulkanResult};
+
 #[derive(Default)]
 pub struct DescriptorSetLayoutBuilder<'n> {
     pub bindings: Option<&'n [vk::DescriptorSetLayoutBinding<'n>]>,
-    pub device: Option<&'n ash::Device>,
+    pub binding_flags: Option<&'n [vk::DescriptorBindingFlags]>,
+    pub flags: Option<vk::DescriptorSetLayoutCreateFlags>,
+    pub device: Option<&'n DeviceHandle>,
     pub allocation: ()
 }
 
@@ -12,7 +16,7 @@ impl<'n> DescriptorSetLayoutBuilder<'n> {
         Self { ..Default::default() }
     }
 
-    pub fn with_device(mut self, dev: &'n ash::Device) -> Self {
+    pub fn with_device(mut self, dev: &'n DeviceHandle) -> Self {
         self.device = Some(dev);
         self
     }
@@ -22,20 +26,50 @@ impl<'n> DescriptorSetLayoutBuilder<'n> {
         self
     }
 
-    pub fn build(self) -> DescriptorSetLayout {
-        let device = self.device.expect("Device is missing");
-        let bindings = self.bindings.expect("Bingings is missing");
-        let layout_info = vk::DescriptorSetLayoutCreateInfo::default()
-            .bindings(&bindings);
+    /// Per-binding flags (`PARTIALLY_BOUND`, `UPDATE_AFTER_BIND`, ...),
+    /// must have the same length as the bindings
+    pub fn with_binding_flags(mut self, flags: &'n [vk::DescriptorBindingFlags]) -> Self {
+        self.binding_flags = Some(flags);
+        self
+    }
+
+    pub fn with_flags(mut self, flags: vk::DescriptorSetLayoutCreateFlags) -> Self {
+        self.flags = Some(flags);
+        self
+    }
+
+    pub fn build(self) -> VulkanResult<DescriptorSetLayout> {
+        let device = self.device.ok_or(VulkanError::missing("DescriptorSetLayoutBuilder", "device"))?;
+        let bindings = self.bindings.ok_or(VulkanError::missing("DescriptorSetLayoutBuilder", "bindings"))?;
+        let flags = self.flags.unwrap_or_default();
+
+        let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::default()
+            .binding_flags(self.binding_flags.unwrap_or(&[]));
+
+        let mut layout_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .bindings(&bindings)
+            .flags(flags);
+
+        if let Some(binding_flags) = self.binding_flags {
+            assert_eq!(binding_flags.len(), bindings.len(), "Binding flags count must match bindings count");
+            layout_info = layout_info.push_next(&mut binding_flags_info);
+        }
+
+        let layout = unsafe { device.create_descriptor_set_layout(&layout_info, None) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateDescriptorSetLayoutFailed(e))))?;
 
-        let layout = unsafe { device.create_descriptor_set_layout(&layout_info, None).unwrap() };
-        DescriptorSetLayout { raw: layout }
+        Ok(DescriptorSetLayout {
+            raw: layout,
+            _owner: Some(ResourceOwner::new(device, vec![DeferredResource::DescriptorSetLayout(layout)]))
+        })
     }
 }
 
+/// Keep alive while sets allocated with this layout are updated
 #[derive(Default)]
 pub struct DescriptorSetLayout {
-    pub raw: vk::DescriptorSetLayout
+    pub raw: vk::DescriptorSetLayout,
+    _owner: Option<ResourceOwner>
 }
 
 
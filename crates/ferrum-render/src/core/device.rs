@@ -2,18 +2,35 @@ use std::ffi::CStr;
 use ash::vk::*;
 
 use crate::core::*;
+use crate::{DeviceError, VulkanError, VulkanResult};
 
 pub struct Device {
-    pub raw: ash::Device
+    pub raw: DeviceHandle,
+    /// Dynamic rendering feature was enabled
+    pub dynamic_rendering: bool,
+    /// Timeline semaphore and synchronization2 features were enabled
+    pub synchronization2: bool,
+    /// `VK_EXT_hdr_metadata` was enabled
+    pub hdr_metadata: bool,
+    /// Everything enabled on the device, required and the supported part of optional
+    pub features: FeatureSet
 }
 
+/// Descriptor indexing, dynamic rendering and synchronization2 are required features:
+/// the build fails with [`DeviceError::MissingFeatures`] if the device lacks them
 #[derive(Default)]
 pub struct DeviceBuilder<'n> {
-    extensions: Vec<*const i8>,
+    extensions: Vec<&'static CStr>,
     features: Option<PhysicalDeviceFeatures>,
+    required: RequiredFeatures,
+    optional: OptionalFeatures,
     family: Option<&'n Vec<QueueFamilies>>,
-    insatnce: Option<&'n ash::Instance>,
+    insatnce: Option<&'n crate::Instance>,
     phys_dev: Option<&'n ash::vk::PhysicalDevice>,
+    descriptor_indexing: bool,
+    dynamic_rendering: bool,
+    synchronization2: bool,
+    hdr_metadata: bool,
     #[allow(dead_code)]
     allocation: ()
 }
@@ -28,8 +45,48 @@ impl<'n> DeviceBuilder<'n> {
         self
     }
 
+    /// Build fails with [`DeviceError::MissingFeatures`] when something is not supported
+    pub fn with_required_features(mut self, features: RequiredFeatures) -> Self {
+        self.required.merge(&features);
+        self
+    }
+
+    /// Supported ones are enabled, check [`Device::features`] for the result
+    pub fn with_optional_features(mut self, features: OptionalFeatures) -> Self {
+        self.optional.merge(&features);
+        self
+    }
+
+    /// Enables the descriptor indexing features required by [`BindlessDescriptorSet`].
+    /// On Vulkan 1.1 devices `VK_EXT_descriptor_indexing` must also be passed to [`Self::with_extensions`]
+    pub fn with_descriptor_indexing(mut self) -> Self {
+        self.descriptor_indexing = true;
+        self
+    }
+
+    /// Enables `dynamicRendering`. Below Vulkan 1.3 [`DYNAMIC_RENDERING_EXTENSION`]
+    /// must also be passed to [`Self::with_extensions`]
+    pub fn with_dynamic_rendering(mut self) -> Self {
+        self.dynamic_rendering = true;
+        self
+    }
+
+    /// Enables `timelineSemaphore` and `synchronization2` used by [`FramePacer`].
+    /// Below Vulkan 1.3 [`SYNCHRONIZATION_2_EXTENSION`] (and below 1.2 [`TIMELINE_SEMAPHORE_EXTENSION`])
+    /// must also be passed to [`Self::with_extensions`]
+    pub fn with_synchronization2(mut self) -> Self {
+        self.synchronization2 = true;
+        self
+    }
+
+    /// Enables [`HDR_METADATA_EXTENSION`]
+    pub fn with_hdr_metadata(mut self) -> Self {
+        self.hdr_metadata = true;
+        self.with_extensions(vec![HDR_METADATA_EXTENSION])
+    }
+
     pub fn with_extensions(mut self, names: Vec<&'static CStr>) -> Self {
-        self.extensions.extend(names.iter().map(|name| name.as_ptr()).collect::<Vec<_>>());
+        self.extensions.extend(names);
         self
     }
 
@@ -38,7 +95,7 @@ impl<'n> DeviceBuilder<'n> {
         self
     }
 
-    pub fn with_instance(mut self, instance: &'n ash::Instance) -> Self {
+    pub fn with_instance(mut self, instance: &'n crate::Instance) -> Self {
         self.insatnce = Some(instance);
         self
     }
@@ -48,11 +105,42 @@ impl<'n> DeviceBuilder<'n> {
         self
     }
 
-    pub fn build(self) -> Device {
+    pub fn build(self) -> VulkanResult<Device> {
+
+        let instance = self.insatnce.ok_or(VulkanError::missing("DeviceBuilder", "instance"))?;
+        let phys_dev = self.phys_dev.ok_or(VulkanError::missing("DeviceBuilder", "physical device"))?;
+        let family = self.family.ok_or(VulkanError::missing("DeviceBuilder", "queue family"))?;
+
+        let device_api_version = unsafe { instance.raw.get_physical_device_properties(*phys_dev) }.api_version;
+        let api_version = instance.api_version.min(device_api_version);
+        let supported = FeatureSet::query(&instance.raw, *phys_dev, api_version)?;
 
-        let instance = self.insatnce.expect("Instance is missing");
-        let phys_dev = self.phys_dev.expect("Physcial Device is missing");
-        let family = self.family.expect("Queue Family is missing");
+        let mut required = self.required
+            .with_extensions(self.extensions)
+            .with_core(|f| self.features.unwrap_or(f));
+
+        // Ниже 1.2/1.3 FeatureSet::query кладёт поддержку расширений в те же поля
+        if self.descriptor_indexing {
+            required = required.with_vulkan12(bindless_vulkan12_features);
+        }
+
+        if self.synchronization2 {
+            required = required
+                .with_vulkan12(|f| f.timeline_semaphore(true))
+                .with_vulkan13(|f| f.synchronization2(true));
+        }
+
+        if self.dynamic_rendering {
+            required = required.with_vulkan13(|f| f.dynamic_rendering(true));
+        }
+
+        let missing = required.missing(&supported);
+        if !missing.is_empty() {
+            return Err(VulkanError::Device(DeviceError::MissingFeatures(missing)));
+        }
+
+        let mut enabled = required;
+        enabled.merge(&self.optional.intersection(&supported));
 
         let mut priorities: Vec<Vec<f32>> = vec![];
 
@@ -60,8 +148,7 @@ impl<'n> DeviceBuilder<'n> {
             priorities.push((1..i.properties.queue_count+1).map(|ndx| 1.0 / (ndx as f32)).collect::<Vec<f32>>());
         }
 
-        let extensions = self.extensions;
-        let features = self.features.unwrap_or(PhysicalDeviceFeatures::default());
+        let extensions = enabled.extensions.iter().map(|name| name.as_ptr()).collect::<Vec<_>>();
         let mut queue_infos = vec![];
 
         for i in family {
@@ -72,12 +159,61 @@ impl<'n> DeviceBuilder<'n> {
             queue_infos.push(queue_info);
         }
 
-        let create_info = DeviceCreateInfo::default()
+        let mut vulkan11 = enabled.vulkan11;
+        let mut vulkan12 = enabled.vulkan12;
+        let mut vulkan13 = enabled.vulkan13;
+        let mut descriptor_indexing = vulkan12_descriptor_indexing(&enabled.vulkan12);
+        let mut dynamic_rendering = dynamic_rendering_features();
+        let mut timeline_semaphore = timeline_semaphore_features();
+        let mut synchronization2 = synchronization2_features();
+
+        // Начиная с 1.2/1.3 отдельные структуры нельзя цеплять вместе с VulkanXXFeatures
+        let core_1_2 = api_version >= API_VERSION_1_2;
+        let core_1_3 = api_version >= API_VERSION_1_3;
+
+        let mut create_info = DeviceCreateInfo::default()
             .queue_create_infos(&queue_infos)
             .enabled_extension_names(&extensions)
-            .enabled_features(&features);
+            .enabled_features(&enabled.core);
+
+        if core_1_2 {
+            create_info = create_info
+                .push_next(&mut vulkan11)
+                .push_next(&mut vulkan12);
+        } else {
+            // Только то, что прошло проверку поддержки, в том числе из optional
+            if let Some(descriptor_indexing) = descriptor_indexing.as_mut() {
+                create_info = create_info.push_next(descriptor_indexing);
+            }
+
+            if enabled.vulkan12.timeline_semaphore == TRUE {
+                create_info = create_info.push_next(&mut timeline_semaphore);
+            }
+        }
 
-        let device = unsafe { instance.create_device(*phys_dev, &create_info, None).unwrap() };
-        Device { raw: device }
+        if core_1_3 {
+            create_info = create_info.push_next(&mut vulkan13);
+        } else {
+            if enabled.vulkan13.dynamic_rendering == TRUE {
+                create_info = create_info.push_next(&mut dynamic_rendering);
+            }
+
+            if enabled.vulkan13.synchronization2 == TRUE {
+                create_info = create_info.push_next(&mut synchronization2);
+            }
+        }
+
+        let device = unsafe { instance.raw.create_device(*phys_dev, &create_info, None) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Device(DeviceError::CreateDeviceFailed(e))))?;
+
+        let enabled_sync2 = enabled.vulkan12.timeline_semaphore == TRUE && enabled.vulkan13.synchronization2 == TRUE;
+
+        Ok(Device {
+            raw: DeviceHandle::new(device, Some(instance.handle())),
+            dynamic_rendering: enabled.vulkan13.dynamic_rendering == TRUE,
+            synchronization2: enabled_sync2,
+            hdr_metadata: enabled.has_extension(HDR_METADATA_EXTENSION),
+            features: enabled
+        })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,165 @@
+use std::ffi::CStr;
+
+use ash::vk;
+
+use crate::PhysicalDeviceInfo;
+
+pub const DYNAMIC_RENDERING_EXTENSION: &CStr = c"VK_KHR_dynamic_rendering";
+
+/// True for Vulkan 1.3 devices or devices with `VK_KHR_dynamic_rendering`
+pub fn supports_dynamic_rendering(info: &PhysicalDeviceInfo) -> bool {
+    info.phys_prop.api_version >= vk::API_VERSION_1_3 || supports_dynamic_rendering_extension(info)
+}
+
+pub fn supports_dynamic_rendering_extension(info: &PhysicalDeviceInfo) -> bool {
+    info.extensions.iter().any(|x| x.extension_name_as_c_str() == Ok(DYNAMIC_RENDERING_EXTENSION))
+}
+
+pub(crate) fn dynamic_rendering_features() -> vk::PhysicalDeviceDynamicRenderingFeatures<'static> {
+    vk::PhysicalDeviceDynamicRenderingFeatures::default()
+        .dynamic_rendering(true)
+}
+
+/// Swapchain image rendered by [`DynamicRendering::begin_swapchain_rendering`]
+#[derive(Debug, Clone, Copy)]
+pub struct SwapchainTarget {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub extent: vk::Extent2D
+}
+
+///
+/// Entry points for `vkCmdBeginRendering` / `vkCmdEndRendering`.
+/// Core functions are used on Vulkan 1.3, the KHR ones otherwise
+///
+#[derive(Clone)]
+pub enum DynamicRendering {
+    Core,
+    Khr(ash::khr::dynamic_rendering::Device)
+}
+
+impl DynamicRendering {
+
+    /// `api_version` is the lower of instance and device versions
+    pub fn new(instance: &ash::Instance, device: &ash::Device, api_version: u32) -> Self {
+        if api_version >= vk::API_VERSION_1_3 {
+            DynamicRendering::Core
+        } else {
+            DynamicRendering::Khr(ash::khr::dynamic_rendering::Device::new(instance, device))
+        }
+    }
+
+    pub fn begin_rendering(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, info: &vk::RenderingInfo) {
+        unsafe {
+            match self {
+                DynamicRendering::Core => device.cmd_begin_rendering(command_buffer, info),
+                DynamicRendering::Khr(loader) => loader.cmd_begin_rendering(command_buffer, info)
+            }
+        }
+    }
+
+    pub fn end_rendering(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            match self {
+                DynamicRendering::Core => device.cmd_end_rendering(command_buffer),
+                DynamicRendering::Khr(loader) => loader.cmd_end_rendering(command_buffer)
+            }
+        }
+    }
+
+    ///
+    /// Moves a swapchain image to COLOR_ATTACHMENT_OPTIMAL and begins rendering into it.
+    /// Without a render pass the layout transitions are done here instead of by the subpass
+    ///
+    /// # Example
+    /// ```
+    /// let dr = ctx.device.dynamic_rendering.as_ref().unwrap();
+    /// dr.begin_swapchain_rendering(device, cbuf, SwapchainTarget { image, view, extent }, [0.0, 0.0, 0.0, 1.0]);
+    /// // draw
+    /// dr.end_swapchain_rendering(device, cbuf, image);
+    /// ```
+    ///
+    pub fn begin_swapchain_rendering(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        target: SwapchainTarget,
+        clear_color: [f32; 4]
+    ) {
+
+        let SwapchainTarget { image, view, extent } = target;
+
+        let barrier = color_image_barrier(image)
+            .src_access_mask(vk::AccessFlags::empty())
+            .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier]
+            );
+        }
+
+        let color_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(vk::ClearValue {
+                color: vk::ClearColorValue { float32: clear_color }
+            })
+        ];
+
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent })
+            .layer_count(1)
+            .color_attachments(&color_attachments);
+
+        self.begin_rendering(device, command_buffer, &rendering_info);
+    }
+
+    /// Ends rendering and moves the swapchain image to PRESENT_SRC_KHR
+    pub fn end_swapchain_rendering(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, image: vk::Image) {
+
+        self.end_rendering(device, command_buffer);
+
+        let barrier = color_image_barrier(image)
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::empty())
+            .old_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .new_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier]
+            );
+        }
+    }
+}
+
+pub(crate) fn color_image_barrier<'a>(image: vk::Image) -> vk::ImageMemoryBarrier<'a> {
+    vk::ImageMemoryBarrier::default()
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        })
+}
@@ -0,0 +1,512 @@
+use std::ffi::{CStr, CString};
+use std::mem::offset_of;
+
+use ash::vk;
+
+use crate::{PhysicalDeviceError, VulkanError, VulkanResult, DYNAMIC_RENDERING_EXTENSION, SYNCHRONIZATION_2_EXTENSION, TIMELINE_SEMAPHORE_EXTENSION};
+
+const CORE_FEATURE_COUNT: usize = 55;
+const VULKAN11_FEATURE_COUNT: usize = 12;
+const VULKAN12_FEATURE_COUNT: usize = 47;
+const VULKAN13_FEATURE_COUNT: usize = 15;
+const DESCRIPTOR_INDEXING_FEATURE_COUNT: usize = 20;
+
+/// Field name and accessor of one Bool32 flag
+type FeatureField<T> = (&'static str, fn(&T) -> vk::Bool32);
+
+/// Every flag of a features struct in declaration order
+macro_rules! feature_table {
+    ($name:ident: $ty:ty, $count:expr; $($field:ident),* $(,)?) => {
+        const $name: [FeatureField<$ty>; $count] = [$((stringify!($field), |f| f.$field)),*];
+    };
+}
+
+feature_table!(CORE_FEATURES: vk::PhysicalDeviceFeatures, CORE_FEATURE_COUNT;
+    robust_buffer_access, full_draw_index_uint32, image_cube_array, independent_blend, geometry_shader,
+    tessellation_shader, sample_rate_shading, dual_src_blend, logic_op, multi_draw_indirect,
+    draw_indirect_first_instance, depth_clamp, depth_bias_clamp, fill_mode_non_solid, depth_bounds,
+    wide_lines, large_points, alpha_to_one, multi_viewport, sampler_anisotropy, texture_compression_etc2,
+    texture_compression_astc_ldr, texture_compression_bc, occlusion_query_precise, pipeline_statistics_query,
+    vertex_pipeline_stores_and_atomics, fragment_stores_and_atomics,
+    shader_tessellation_and_geometry_point_size, shader_image_gather_extended,
+    shader_storage_image_extended_formats, shader_storage_image_multisample,
+    shader_storage_image_read_without_format, shader_storage_image_write_without_format,
+    shader_uniform_buffer_array_dynamic_indexing, shader_sampled_image_array_dynamic_indexing,
+    shader_storage_buffer_array_dynamic_indexing, shader_storage_image_array_dynamic_indexing,
+    shader_clip_distance, shader_cull_distance, shader_float64, shader_int64, shader_int16,
+    shader_resource_residency, shader_resource_min_lod, sparse_binding, sparse_residency_buffer,
+    sparse_residency_image2_d, sparse_residency_image3_d, sparse_residency2_samples,
+    sparse_residency4_samples, sparse_residency8_samples, sparse_residency16_samples,
+    sparse_residency_aliased, variable_multisample_rate, inherited_queries
+);
+
+feature_table!(VULKAN11_FEATURES: vk::PhysicalDeviceVulkan11Features<'static>, VULKAN11_FEATURE_COUNT;
+    storage_buffer16_bit_access, uniform_and_storage_buffer16_bit_access, storage_push_constant16,
+    storage_input_output16, multiview, multiview_geometry_shader, multiview_tessellation_shader,
+    variable_pointers_storage_buffer, variable_pointers, protected_memory, sampler_ycbcr_conversion,
+    shader_draw_parameters
+);
+
+feature_table!(VULKAN12_FEATURES: vk::PhysicalDeviceVulkan12Features<'static>, VULKAN12_FEATURE_COUNT;
+    sampler_mirror_clamp_to_edge, draw_indirect_count, storage_buffer8_bit_access,
+    uniform_and_storage_buffer8_bit_access, storage_push_constant8, shader_buffer_int64_atomics,
+    shader_shared_int64_atomics, shader_float16, shader_int8, descriptor_indexing,
+    shader_input_attachment_array_dynamic_indexing, shader_uniform_texel_buffer_array_dynamic_indexing,
+    shader_storage_texel_buffer_array_dynamic_indexing, shader_uniform_buffer_array_non_uniform_indexing,
+    shader_sampled_image_array_non_uniform_indexing, shader_storage_buffer_array_non_uniform_indexing,
+    shader_storage_image_array_non_uniform_indexing, shader_input_attachment_array_non_uniform_indexing,
+    shader_uniform_texel_buffer_array_non_uniform_indexing,
+    shader_storage_texel_buffer_array_non_uniform_indexing,
+    descriptor_binding_uniform_buffer_update_after_bind, descriptor_binding_sampled_image_update_after_bind,
+    descriptor_binding_storage_image_update_after_bind, descriptor_binding_storage_buffer_update_after_bind,
+    descriptor_binding_uniform_texel_buffer_update_after_bind,
+    descriptor_binding_storage_texel_buffer_update_after_bind, descriptor_binding_update_unused_while_pending,
+    descriptor_binding_partially_bound, descriptor_binding_variable_descriptor_count,
+    runtime_descriptor_array, sampler_filter_minmax, scalar_block_layout, imageless_framebuffer,
+    uniform_buffer_standard_layout, shader_subgroup_extended_types, separate_depth_stencil_layouts,
+    host_query_reset, timeline_semaphore, buffer_device_address, buffer_device_address_capture_replay,
+    buffer_device_address_multi_device, vulkan_memory_model, vulkan_memory_model_device_scope,
+    vulkan_memory_model_availability_visibility_chains, shader_output_viewport_index, shader_output_layer,
+    subgroup_broadcast_dynamic_id
+);
+
+feature_table!(VULKAN13_FEATURES: vk::PhysicalDeviceVulkan13Features<'static>, VULKAN13_FEATURE_COUNT;
+    robust_image_access, inline_uniform_block, descriptor_binding_inline_uniform_block_update_after_bind,
+    pipeline_creation_cache_control, private_data, shader_demote_to_helper_invocation,
+    shader_terminate_invocation, subgroup_size_control, compute_full_subgroups, synchronization2,
+    texture_compression_astc_hdr, shader_zero_initialize_workgroup_memory, dynamic_rendering,
+    shader_integer_dot_product, maintenance4
+);
+
+/// Features that must be present, [`crate::DeviceBuilder::build`] fails otherwise
+pub type RequiredFeatures = FeatureSet;
+
+/// Features enabled only when the device supports them
+pub type OptionalFeatures = FeatureSet;
+
+///
+/// Device extensions with core and `Vulkan11/12/13` features.
+/// Used as a request for [`crate::DeviceBuilder`] and as the result in [`crate::Device::features`]
+///
+/// # Example
+/// ```
+/// let device = DeviceBuilder::new()
+///     .with_required_features(RequiredFeatures::new()
+///         .with_extensions(vec![c"VK_KHR_swapchain"])
+///         .with_vulkan12(|f| f.timeline_semaphore(true)))
+///     .with_optional_features(OptionalFeatures::new()
+///         .with_core(|f| f.fill_mode_non_solid(true)))
+///     ...
+///     .build()?;
+///
+/// if device.features.core.fill_mode_non_solid == vk::TRUE {
+///     // wireframe is available
+/// }
+/// ```
+///
+#[derive(Clone, Default)]
+pub struct FeatureSet {
+    pub extensions: Vec<CString>,
+    pub core: vk::PhysicalDeviceFeatures,
+    pub vulkan11: vk::PhysicalDeviceVulkan11Features<'static>,
+    pub vulkan12: vk::PhysicalDeviceVulkan12Features<'static>,
+    pub vulkan13: vk::PhysicalDeviceVulkan13Features<'static>
+}
+
+// p_next всегда null, цепочка собирается из копий
+unsafe impl Send for FeatureSet {}
+unsafe impl Sync for FeatureSet {}
+
+impl FeatureSet {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with_extensions(mut self, names: Vec<&'static CStr>) -> Self {
+        for name in names {
+            if !self.has_extension(name) {
+                self.extensions.push(name.to_owned());
+            }
+        }
+        self
+    }
+
+    pub fn with_core<F>(mut self, f: F) -> Self
+        where F: FnOnce(vk::PhysicalDeviceFeatures) -> vk::PhysicalDeviceFeatures {
+        self.core = f(self.core);
+        self
+    }
+
+    /// Needs a Vulkan 1.2 device
+    pub fn with_vulkan11<F>(mut self, f: F) -> Self
+        where F: FnOnce(vk::PhysicalDeviceVulkan11Features<'static>) -> vk::PhysicalDeviceVulkan11Features<'static> {
+        self.vulkan11 = f(self.vulkan11);
+        self
+    }
+
+    /// Needs a Vulkan 1.2 device
+    pub fn with_vulkan12<F>(mut self, f: F) -> Self
+        where F: FnOnce(vk::PhysicalDeviceVulkan12Features<'static>) -> vk::PhysicalDeviceVulkan12Features<'static> {
+        self.vulkan12 = f(self.vulkan12);
+        self
+    }
+
+    /// Needs a Vulkan 1.3 device
+    pub fn with_vulkan13<F>(mut self, f: F) -> Self
+        where F: FnOnce(vk::PhysicalDeviceVulkan13Features<'static>) -> vk::PhysicalDeviceVulkan13Features<'static> {
+        self.vulkan13 = f(self.vulkan13);
+        self
+    }
+
+    pub fn has_extension(&self, name: &CStr) -> bool {
+        self.extensions.iter().any(|x| x.as_c_str() == name)
+    }
+
+    /// Everything requested in `other` is present here
+    pub fn contains(&self, other: &FeatureSet) -> bool {
+        other.missing(self).is_empty()
+    }
+
+    /// Names of extensions and features requested here but absent from `supported`,
+    /// features are reported as `vulkan12.timeline_semaphore`
+    pub fn missing(&self, supported: &FeatureSet) -> Vec<String> {
+
+        let mut names = self.extensions.iter()
+            .filter(|x| !supported.has_extension(x))
+            .map(|x| x.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+
+        names.extend(self.combine(supported, |requested, supported| requested & !supported).enabled_names());
+        names
+    }
+
+    /// Requested extensions and features the device supports
+    pub fn intersection(&self, supported: &FeatureSet) -> FeatureSet {
+
+        let mut result = self.combine(supported, |requested, supported| requested & supported);
+        result.extensions = self.extensions.iter()
+            .filter(|x| supported.has_extension(x))
+            .cloned()
+            .collect();
+
+        result
+    }
+
+    pub fn merge(&mut self, other: &FeatureSet) {
+
+        let mut extensions = std::mem::take(&mut self.extensions);
+
+        for name in &other.extensions {
+            if !extensions.contains(name) {
+                extensions.push(name.clone());
+            }
+        }
+
+        *self = self.combine(other, |a, b| a | b);
+        self.extensions = extensions;
+    }
+
+    /// Extensions and features supported by `phys_dev`.
+    /// `Vulkan11/12` features are queried from 1.2, `Vulkan13` from 1.3.
+    /// On 1.1 descriptor indexing, timeline semaphore, synchronization2 and dynamic rendering
+    /// come from their extension structs, if the extension is supported
+    pub fn query(instance: &ash::Instance, phys_dev: vk::PhysicalDevice, api_version: u32) -> VulkanResult<FeatureSet> {
+
+        let extensions = unsafe { instance.enumerate_device_extension_properties(phys_dev) }
+            .map_err(|e| VulkanError::PhysicalDevice(PhysicalDeviceError::EnumerateDeviceExtensionPropertiesFailed(e)))?
+            .iter()
+            .filter_map(|x| x.extension_name_as_c_str().ok().map(CStr::to_owned))
+            .collect();
+
+        let mut result = FeatureSet { extensions, ..Default::default() };
+
+        if api_version < vk::API_VERSION_1_1 {
+            result.core = unsafe { instance.get_physical_device_features(phys_dev) };
+            return Ok(result);
+        }
+
+        let mut vulkan11 = vk::PhysicalDeviceVulkan11Features::default();
+        let mut vulkan12 = vk::PhysicalDeviceVulkan12Features::default();
+        let mut vulkan13 = vk::PhysicalDeviceVulkan13Features::default();
+        let mut descriptor_indexing = vk::PhysicalDeviceDescriptorIndexingFeatures::default();
+        let mut timeline_semaphore = vk::PhysicalDeviceTimelineSemaphoreFeatures::default();
+        let mut synchronization2 = vk::PhysicalDeviceSynchronization2Features::default();
+        let mut dynamic_rendering = vk::PhysicalDeviceDynamicRenderingFeatures::default();
+        let mut features2 = vk::PhysicalDeviceFeatures2::default();
+
+        if api_version >= vk::API_VERSION_1_2 {
+            features2 = features2.push_next(&mut vulkan11).push_next(&mut vulkan12);
+        } else {
+            if result.has_extension(ash::ext::descriptor_indexing::NAME) {
+                features2 = features2.push_next(&mut descriptor_indexing);
+            }
+
+            if result.has_extension(TIMELINE_SEMAPHORE_EXTENSION) {
+                features2 = features2.push_next(&mut timeline_semaphore);
+            }
+        }
+
+        if api_version >= vk::API_VERSION_1_3 {
+            features2 = features2.push_next(&mut vulkan13);
+        } else {
+            if result.has_extension(SYNCHRONIZATION_2_EXTENSION) {
+                features2 = features2.push_next(&mut synchronization2);
+            }
+
+            if result.has_extension(DYNAMIC_RENDERING_EXTENSION) {
+                features2 = features2.push_next(&mut dynamic_rendering);
+            }
+        }
+
+        unsafe { instance.get_physical_device_features2(phys_dev, &mut features2) };
+        result.core = features2.features;
+
+        result.vulkan11 = vk::PhysicalDeviceVulkan11Features { p_next: std::ptr::null_mut(), ..vulkan11 };
+        result.vulkan12 = vk::PhysicalDeviceVulkan12Features { p_next: std::ptr::null_mut(), ..vulkan12 };
+        result.vulkan13 = vk::PhysicalDeviceVulkan13Features { p_next: std::ptr::null_mut(), ..vulkan13 };
+
+        // Пустые структуры расширений ничего не меняют
+        if api_version < vk::API_VERSION_1_2 {
+            result.vulkan12 = descriptor_indexing_vulkan12(result.vulkan12, &descriptor_indexing)
+                .timeline_semaphore(timeline_semaphore.timeline_semaphore == vk::TRUE);
+        }
+
+        if api_version < vk::API_VERSION_1_3 {
+            result.vulkan13 = result.vulkan13
+                .synchronization2(synchronization2.synchronization2 == vk::TRUE)
+                .dynamic_rendering(dynamic_rendering.dynamic_rendering == vk::TRUE);
+        }
+
+        Ok(result)
+    }
+
+    /// Applies `op` to every feature flag, extensions are not copied
+    fn combine<F>(&self, other: &FeatureSet, op: F) -> FeatureSet
+        where F: Fn(vk::Bool32, vk::Bool32) -> vk::Bool32 {
+
+        let mut result = FeatureSet::new();
+
+        for ((result, a), b) in result.flags_mut().into_iter().zip(self.flags()).zip(other.flags()) {
+            for ((result, a), b) in result.iter_mut().zip(a).zip(b) {
+                *result = op(*a, *b) & vk::TRUE;
+            }
+        }
+
+        result
+    }
+
+    /// Bool32 fields of core, 1.1, 1.2 and 1.3 features in declaration order
+    fn flags(&self) -> [&[vk::Bool32]; 4] {
+        unsafe {
+            [
+                bool_fields(&self.core, 0, CORE_FEATURE_COUNT),
+                bool_fields(&self.vulkan11, offset_of!(vk::PhysicalDeviceVulkan11Features, storage_buffer16_bit_access), VULKAN11_FEATURE_COUNT),
+                bool_fields(&self.vulkan12, offset_of!(vk::PhysicalDeviceVulkan12Features, sampler_mirror_clamp_to_edge), VULKAN12_FEATURE_COUNT),
+                bool_fields(&self.vulkan13, offset_of!(vk::PhysicalDeviceVulkan13Features, robust_image_access), VULKAN13_FEATURE_COUNT)
+            ]
+        }
+    }
+
+    fn flags_mut(&mut self) -> [&mut [vk::Bool32]; 4] {
+        unsafe {
+            [
+                bool_fields_mut(&mut self.core, 0, CORE_FEATURE_COUNT),
+                bool_fields_mut(&mut self.vulkan11, offset_of!(vk::PhysicalDeviceVulkan11Features, storage_buffer16_bit_access), VULKAN11_FEATURE_COUNT),
+                bool_fields_mut(&mut self.vulkan12, offset_of!(vk::PhysicalDeviceVulkan12Features, sampler_mirror_clamp_to_edge), VULKAN12_FEATURE_COUNT),
+                bool_fields_mut(&mut self.vulkan13, offset_of!(vk::PhysicalDeviceVulkan13Features, robust_image_access), VULKAN13_FEATURE_COUNT)
+            ]
+        }
+    }
+
+    /// `section.field` for every enabled feature flag
+    fn enabled_names(&self) -> Vec<String> {
+        let mut names = vec![];
+        names.extend(enabled_fields("core", &self.core, &CORE_FEATURES));
+        names.extend(enabled_fields("vulkan11", &self.vulkan11, &VULKAN11_FEATURES));
+        names.extend(enabled_fields("vulkan12", &self.vulkan12, &VULKAN12_FEATURES));
+        names.extend(enabled_fields("vulkan13", &self.vulkan13, &VULKAN13_FEATURES));
+        names
+    }
+}
+
+/// Flags of `VK_EXT_descriptor_indexing` at their Vulkan 1.2 places
+pub(crate) fn descriptor_indexing_vulkan12(
+    features: vk::PhysicalDeviceVulkan12Features<'static>,
+    indexing: &vk::PhysicalDeviceDescriptorIndexingFeatures
+) -> vk::PhysicalDeviceVulkan12Features<'static> {
+    vk::PhysicalDeviceVulkan12Features {
+        shader_input_attachment_array_dynamic_indexing: indexing.shader_input_attachment_array_dynamic_indexing,
+        shader_uniform_texel_buffer_array_dynamic_indexing: indexing.shader_uniform_texel_buffer_array_dynamic_indexing,
+        shader_storage_texel_buffer_array_dynamic_indexing: indexing.shader_storage_texel_buffer_array_dynamic_indexing,
+        shader_uniform_buffer_array_non_uniform_indexing: indexing.shader_uniform_buffer_array_non_uniform_indexing,
+        shader_sampled_image_array_non_uniform_indexing: indexing.shader_sampled_image_array_non_uniform_indexing,
+        shader_storage_buffer_array_non_uniform_indexing: indexing.shader_storage_buffer_array_non_uniform_indexing,
+        shader_storage_image_array_non_uniform_indexing: indexing.shader_storage_image_array_non_uniform_indexing,
+        shader_input_attachment_array_non_uniform_indexing: indexing.shader_input_attachment_array_non_uniform_indexing,
+        shader_uniform_texel_buffer_array_non_uniform_indexing: indexing.shader_uniform_texel_buffer_array_non_uniform_indexing,
+        shader_storage_texel_buffer_array_non_uniform_indexing: indexing.shader_storage_texel_buffer_array_non_uniform_indexing,
+        descriptor_binding_uniform_buffer_update_after_bind: indexing.descriptor_binding_uniform_buffer_update_after_bind,
+        descriptor_binding_sampled_image_update_after_bind: indexing.descriptor_binding_sampled_image_update_after_bind,
+        descriptor_binding_storage_image_update_after_bind: indexing.descriptor_binding_storage_image_update_after_bind,
+        descriptor_binding_storage_buffer_update_after_bind: indexing.descriptor_binding_storage_buffer_update_after_bind,
+        descriptor_binding_uniform_texel_buffer_update_after_bind: indexing.descriptor_binding_uniform_texel_buffer_update_after_bind,
+        descriptor_binding_storage_texel_buffer_update_after_bind: indexing.descriptor_binding_storage_texel_buffer_update_after_bind,
+        descriptor_binding_update_unused_while_pending: indexing.descriptor_binding_update_unused_while_pending,
+        descriptor_binding_partially_bound: indexing.descriptor_binding_partially_bound,
+        descriptor_binding_variable_descriptor_count: indexing.descriptor_binding_variable_descriptor_count,
+        runtime_descriptor_array: indexing.runtime_descriptor_array,
+        ..features
+    }
+}
+
+/// Reverse of [`descriptor_indexing_vulkan12`], None if no descriptor indexing flag is set
+pub(crate) fn vulkan12_descriptor_indexing(features: &vk::PhysicalDeviceVulkan12Features) -> Option<vk::PhysicalDeviceDescriptorIndexingFeatures<'static>> {
+
+    let indexing = vk::PhysicalDeviceDescriptorIndexingFeatures {
+        shader_input_attachment_array_dynamic_indexing: features.shader_input_attachment_array_dynamic_indexing,
+        shader_uniform_texel_buffer_array_dynamic_indexing: features.shader_uniform_texel_buffer_array_dynamic_indexing,
+        shader_storage_texel_buffer_array_dynamic_indexing: features.shader_storage_texel_buffer_array_dynamic_indexing,
+        shader_uniform_buffer_array_non_uniform_indexing: features.shader_uniform_buffer_array_non_uniform_indexing,
+        shader_sampled_image_array_non_uniform_indexing: features.shader_sampled_image_array_non_uniform_indexing,
+        shader_storage_buffer_array_non_uniform_indexing: features.shader_storage_buffer_array_non_uniform_indexing,
+        shader_storage_image_array_non_uniform_indexing: features.shader_storage_image_array_non_uniform_indexing,
+        shader_input_attachment_array_non_uniform_indexing: features.shader_input_attachment_array_non_uniform_indexing,
+        shader_uniform_texel_buffer_array_non_uniform_indexing: features.shader_uniform_texel_buffer_array_non_uniform_indexing,
+        shader_storage_texel_buffer_array_non_uniform_indexing: features.shader_storage_texel_buffer_array_non_uniform_indexing,
+        descriptor_binding_uniform_buffer_update_after_bind: features.descriptor_binding_uniform_buffer_update_after_bind,
+        descriptor_binding_sampled_image_update_after_bind: features.descriptor_binding_sampled_image_update_after_bind,
+        descriptor_binding_storage_image_update_after_bind: features.descriptor_binding_storage_image_update_after_bind,
+        descriptor_binding_storage_buffer_update_after_bind: features.descriptor_binding_storage_buffer_update_after_bind,
+        descriptor_binding_uniform_texel_buffer_update_after_bind: features.descriptor_binding_uniform_texel_buffer_update_after_bind,
+        descriptor_binding_storage_texel_buffer_update_after_bind: features.descriptor_binding_storage_texel_buffer_update_after_bind,
+        descriptor_binding_update_unused_while_pending: features.descriptor_binding_update_unused_while_pending,
+        descriptor_binding_partially_bound: features.descriptor_binding_partially_bound,
+        descriptor_binding_variable_descriptor_count: features.descriptor_binding_variable_descriptor_count,
+        runtime_descriptor_array: features.runtime_descriptor_array,
+        ..Default::default()
+    };
+
+    let first = offset_of!(vk::PhysicalDeviceDescriptorIndexingFeatures, shader_input_attachment_array_dynamic_indexing);
+    let any = unsafe { bool_fields(&indexing, first, DESCRIPTOR_INDEXING_FEATURE_COUNT) }.contains(&vk::TRUE);
+    any.then_some(indexing)
+}
+
+/// `offset` is the first Bool32 field, `count` fields follow without gaps
+unsafe fn bool_fields<T>(features: &T, offset: usize, count: usize) -> &[vk::Bool32] {
+    debug_assert!(offset + count * size_of::<vk::Bool32>() <= size_of::<T>());
+    unsafe { std::slice::from_raw_parts((features as *const T as *const u8).add(offset) as *const vk::Bool32, count) }
+}
+
+unsafe fn bool_fields_mut<T>(features: &mut T, offset: usize, count: usize) -> &mut [vk::Bool32] {
+    debug_assert!(offset + count * size_of::<vk::Bool32>() <= size_of::<T>());
+    unsafe { std::slice::from_raw_parts_mut((features as *mut T as *mut u8).add(offset) as *mut vk::Bool32, count) }
+}
+
+fn enabled_fields<T>(section: &str, features: &T, table: &[FeatureField<T>]) -> Vec<String> {
+    table.iter()
+        .filter(|(_, get)| get(features) == vk::TRUE)
+        .map(|(field, _)| format!("{}.{}", section, field))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_features() {
+
+        let supported = FeatureSet::new()
+            .with_extensions(vec![c"VK_KHR_swapchain"])
+            .with_core(|f| f.fill_mode_non_solid(true))
+            .with_vulkan12(|f| f.timeline_semaphore(true))
+            .with_vulkan13(|f| f.synchronization2(true));
+
+        let required = RequiredFeatures::new()
+            .with_extensions(vec![c"VK_KHR_swapchain"])
+            .with_vulkan12(|f| f.timeline_semaphore(true));
+
+        let optional = OptionalFeatures::new()
+            .with_extensions(vec![c"VK_EXT_hdr_metadata"])
+            .with_core(|f| f.fill_mode_non_solid(true).wide_lines(true))
+            .with_vulkan13(|f| f.synchronization2(true).dynamic_rendering(true));
+
+        assert!(required.missing(&supported).is_empty());
+        assert_eq!(
+            optional.missing(&supported),
+            vec!["VK_EXT_hdr_metadata", "core.wide_lines", "vulkan13.dynamic_rendering"]
+        );
+
+        let mut enabled = required.clone();
+        enabled.merge(&optional.intersection(&supported));
+
+        assert!(supported.contains(&enabled));
+        assert!(enabled.contains(&required));
+        assert!(!enabled.has_extension(c"VK_EXT_hdr_metadata"));
+        assert_eq!(enabled.core.fill_mode_non_solid, vk::TRUE);
+        assert_eq!(enabled.core.wide_lines, vk::FALSE);
+        assert_eq!(enabled.vulkan12.timeline_semaphore, vk::TRUE);
+        assert_eq!(enabled.vulkan13.synchronization2, vk::TRUE);
+        assert_eq!(enabled.vulkan13.dynamic_rendering, vk::FALSE);
+    }
+
+    #[test]
+    fn test_feature_tables_match_flags() {
+        let tables = [
+            ("core", CORE_FEATURES.map(|x| x.0).to_vec()),
+            ("vulkan11", VULKAN11_FEATURES.map(|x| x.0).to_vec()),
+            ("vulkan12", VULKAN12_FEATURES.map(|x| x.0).to_vec()),
+            ("vulkan13", VULKAN13_FEATURES.map(|x| x.0).to_vec())
+        ];
+
+        for (section, (name, fields)) in tables.iter().enumerate() {
+            for (i, field) in fields.iter().enumerate() {
+                let mut set = FeatureSet::new();
+                set.flags_mut()[section][i] = vk::TRUE;
+                assert_eq!(set.enabled_names(), vec![format!("{}.{}", name, field)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_feature_counts_match_layout() {
+        let last = |first: usize, count: usize| first + (count - 1) * size_of::<vk::Bool32>();
+
+        assert_eq!(last(0, CORE_FEATURE_COUNT), offset_of!(vk::PhysicalDeviceFeatures, inherited_queries));
+        assert_eq!(
+            last(offset_of!(vk::PhysicalDeviceVulkan11Features, storage_buffer16_bit_access), VULKAN11_FEATURE_COUNT),
+            offset_of!(vk::PhysicalDeviceVulkan11Features, shader_draw_parameters)
+        );
+        assert_eq!(
+            last(offset_of!(vk::PhysicalDeviceVulkan12Features, sampler_mirror_clamp_to_edge), VULKAN12_FEATURE_COUNT),
+            offset_of!(vk::PhysicalDeviceVulkan12Features, subgroup_broadcast_dynamic_id)
+        );
+        assert_eq!(
+            last(offset_of!(vk::PhysicalDeviceVulkan13Features, robust_image_access), VULKAN13_FEATURE_COUNT),
+            offset_of!(vk::PhysicalDeviceVulkan13Features, maintenance4)
+        );
+        assert_eq!(
+            last(offset_of!(vk::PhysicalDeviceDescriptorIndexingFeatures, shader_input_attachment_array_dynamic_indexing), DESCRIPTOR_INDEXING_FEATURE_COUNT),
+            offset_of!(vk::PhysicalDeviceDescriptorIndexingFeatures, runtime_descriptor_array)
+        );
+    }
+
+    #[test]
+    fn test_descriptor_indexing_round_trip() {
+
+        assert!(vulkan12_descriptor_indexing(&vk::PhysicalDeviceVulkan12Features::default()).is_none());
+
+        let vulkan12 = crate::bindless_vulkan12_features(vk::PhysicalDeviceVulkan12Features::default());
+        let indexing = vulkan12_descriptor_indexing(&vulkan12).unwrap();
+
+        assert_eq!(indexing.runtime_descriptor_array, vk::TRUE);
+        assert_eq!(indexing.shader_storage_buffer_array_non_uniform_indexing, vk::TRUE);
+        assert_eq!(indexing.descriptor_binding_variable_descriptor_count, vk::FALSE);
+
+        let back = descriptor_indexing_vulkan12(vk::PhysicalDeviceVulkan12Features::default(), &indexing);
+        let names = enabled_fields("vulkan12", &back, &VULKAN12_FEATURES);
+        assert_eq!(names, enabled_fields("vulkan12", &vulkan12, &VULKAN12_FEATURES));
+        assert_eq!(names.len(), 8);
+    }
+}
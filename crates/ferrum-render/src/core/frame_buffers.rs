@@ -1,11 +1,14 @@
 use ash::vk::{Extent2D, ImageView, RenderPass};
 
+use crate::{PipelineError, VulkanError, VulkanResult};
+
 
 #[derive(Default)]
 pub struct FrameBufferBuilder<'n> {
     resolution: Option<Extent2D>,
     render_pass: Option<&'n RenderPass>,
     image_views: Option<&'n Vec<ImageView>>,
+    attachments: Option<&'n [ImageView]>,
     device: Option<&'n ash::Device>,
     #[allow(dead_code)]
     allocation: ()
@@ -21,6 +24,12 @@ impl<'n> FrameBufferBuilder<'n> {
         self
     }
 
+    /// Shared by every frame buffer, placed after the swapchain image view
+    pub fn attachments(mut self, attachments: &'n [ImageView]) -> Self {
+        self.attachments = Some(attachments);
+        self
+    }
+
     pub fn resolution(mut self, res: Extent2D) -> Self {
         self.resolution = Some(res);
         self
@@ -36,36 +45,41 @@ impl<'n> FrameBufferBuilder<'n> {
         self
     }
 
-    pub fn build(self) -> FrameBuffers {
+    /// Frame buffers created before a failure are destroyed
+    pub fn build(self) -> VulkanResult<FrameBuffers> {
 
-        let device = self.device.expect("Device is missing");
-        let resolution = self.resolution.expect("Resolution is missing");
-        let render_pass = self.render_pass.expect("Render Pass is missing");
+        let missing = |field| VulkanError::missing("FrameBufferBuilder", field);
+        let device = self.device.ok_or_else(|| missing("device"))?;
+        let resolution = self.resolution.ok_or_else(|| missing("resolution"))?;
+        let render_pass = self.render_pass.ok_or_else(|| missing("render pass"))?;
+        let image_views = self.image_views.ok_or_else(|| missing("image views"))?;
 
         let mut frame_buffers = vec![];
 
-        unsafe {
-
-            for i in self.image_views.unwrap() {
-
-                let image_view = vec![*i];
-
-                let create_info = ash::vk::FramebufferCreateInfo::default()
-                    .attachments(&image_view)
-                    .attachment_count(1)
-                    .width(resolution.width)
-                    .height(resolution.height)
-                    .layers(1)
-                    .render_pass(*render_pass);
-
-                let frame = device.create_framebuffer(&create_info, None).unwrap();
-                frame_buffers.push(frame);
+        for i in image_views {
+
+            let mut image_view = vec![*i];
+            image_view.extend_from_slice(self.attachments.unwrap_or(&[]));
+
+            let create_info = ash::vk::FramebufferCreateInfo::default()
+                .attachments(&image_view)
+                .width(resolution.width)
+                .height(resolution.height)
+                .layers(1)
+                .render_pass(*render_pass);
+
+            match unsafe { device.create_framebuffer(&create_info, None) } {
+                Ok(frame) => frame_buffers.push(frame),
+                Err(e) => {
+                    for frame in frame_buffers {
+                        unsafe { device.destroy_framebuffer(frame, None) };
+                    }
+                    return Err(VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateFramebufferFailed(e))));
+                }
             }
-
-
-            FrameBuffers { raw: frame_buffers }
         }
 
+        Ok(FrameBuffers { raw: frame_buffers })
     }
 }
 
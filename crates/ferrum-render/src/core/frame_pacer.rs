@@ -0,0 +1,333 @@
+use std::ffi::CStr;
+
+use ash::vk;
+
+use crate::{DeletionQueue, PhysicalDeviceInfo, SyncError, VulkanError, VulkanResult};
+
+pub const TIMELINE_SEMAPHORE_EXTENSION: &CStr = c"VK_KHR_timeline_semaphore";
+pub const SYNCHRONIZATION_2_EXTENSION: &CStr = c"VK_KHR_synchronization2";
+
+const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+const DEFAULT_TIMEOUT: u64 = 1_000_000_000;
+
+fn has_extension(info: &PhysicalDeviceInfo, name: &CStr) -> bool {
+    info.extensions.iter().any(|x| x.extension_name_as_c_str() == Ok(name))
+}
+
+pub fn supports_timeline_semaphore_extension(info: &PhysicalDeviceInfo) -> bool {
+    has_extension(info, TIMELINE_SEMAPHORE_EXTENSION)
+}
+
+pub fn supports_synchronization2_extension(info: &PhysicalDeviceInfo) -> bool {
+    has_extension(info, SYNCHRONIZATION_2_EXTENSION)
+}
+
+pub(crate) fn timeline_semaphore_features() -> vk::PhysicalDeviceTimelineSemaphoreFeatures<'static> {
+    vk::PhysicalDeviceTimelineSemaphoreFeatures::default()
+        .timeline_semaphore(true)
+}
+
+pub(crate) fn synchronization2_features() -> vk::PhysicalDeviceSynchronization2Features<'static> {
+    vk::PhysicalDeviceSynchronization2Features::default()
+        .synchronization2(true)
+}
+
+///
+/// `vkQueueSubmit2` / `vkCmdPipelineBarrier2` and timeline semaphore entry points.
+/// Core functions are used when `api_version` allows it, the KHR ones otherwise
+///
+pub struct Synchronization2 {
+    sync2: Option<ash::khr::synchronization2::Device>,
+    timeline: Option<ash::khr::timeline_semaphore::Device>
+}
+
+impl Synchronization2 {
+
+    /// `api_version` is the lower of instance and device versions
+    pub fn new(instance: &ash::Instance, device: &ash::Device, api_version: u32) -> Self {
+        Self {
+            sync2: (api_version < vk::API_VERSION_1_3).then(|| ash::khr::synchronization2::Device::new(instance, device)),
+            timeline: (api_version < vk::API_VERSION_1_2).then(|| ash::khr::timeline_semaphore::Device::new(instance, device))
+        }
+    }
+
+    pub fn queue_submit2(&self, device: &ash::Device, queue: vk::Queue, submits: &[vk::SubmitInfo2], fence: vk::Fence) -> VulkanResult<()> {
+        unsafe {
+            match &self.sync2 {
+                Some(loader) => loader.queue_submit2(queue, submits, fence),
+                None => device.queue_submit2(queue, submits, fence)
+            }
+        }.map_err(|e| VulkanError::vk(e, |e| VulkanError::Sync(SyncError::SubmitFailed(e))))
+    }
+
+    pub fn cmd_pipeline_barrier2(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, info: &vk::DependencyInfo) {
+        unsafe {
+            match &self.sync2 {
+                Some(loader) => loader.cmd_pipeline_barrier2(command_buffer, info),
+                None => device.cmd_pipeline_barrier2(command_buffer, info)
+            }
+        }
+    }
+
+    /// Returns false on timeout
+    pub fn wait_timeline(&self, device: &ash::Device, semaphore: vk::Semaphore, value: u64, timeout: u64) -> VulkanResult<bool> {
+
+        let semaphores = [semaphore];
+        let values = [value];
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+
+        let result = unsafe {
+            match &self.timeline {
+                Some(loader) => loader.wait_semaphores(&wait_info, timeout),
+                None => device.wait_semaphores(&wait_info, timeout)
+            }
+        };
+
+        match result {
+            Ok(()) => Ok(true),
+            Err(vk::Result::TIMEOUT) => Ok(false),
+            Err(e) => Err(VulkanError::vk(e, |e| VulkanError::Sync(SyncError::WaitFailed(e))))
+        }
+    }
+
+    pub fn timeline_value(&self, device: &ash::Device, semaphore: vk::Semaphore) -> VulkanResult<u64> {
+        unsafe {
+            match &self.timeline {
+                Some(loader) => loader.get_semaphore_counter_value(semaphore),
+                None => device.get_semaphore_counter_value(semaphore)
+            }
+        }.map_err(|e| VulkanError::vk(e, |e| VulkanError::Sync(SyncError::GetCounterValueFailed(e))))
+    }
+}
+
+/// Slot handed out by [`FramePacer::begin_frame`]
+#[derive(Debug, Clone, Copy)]
+pub struct FrameSlot {
+    /// Index in `0..frames_in_flight`, use it for per-frame resources
+    pub index: usize,
+    /// Pass to `acquire_next_image`
+    pub image_available: vk::Semaphore,
+    /// Timeline value signaled when this frame completes on the GPU
+    pub signal_value: u64
+}
+
+/// Command buffers of a frame and the swapchain image it acquired, see [`FramePacer::submit`]
+pub struct FrameSubmit<'a> {
+    pub frame: &'a FrameSlot,
+    pub image_index: u32,
+    pub command_buffers: &'a [vk::CommandBuffer]
+}
+
+///
+/// Frame pacing on a timeline semaphore.
+///
+/// Frames in flight are independent of swapchain image count: acquire semaphores
+/// belong to frame slots, present semaphores belong to swapchain images, because
+/// a present may still wait on one after its frame slot is reused
+///
+/// # Example
+/// ```
+/// let frame = pacer.begin_frame(device, sync2)?;
+/// let (image_index, _) = swapchain.acquire_next_image(.., frame.image_available, ..)?;
+/// // record
+/// let render_finished = pacer.submit(device, sync2, queue, FrameSubmit { frame: &frame, image_index, command_buffers: &[cbuf] })?;
+/// // present waiting on render_finished
+/// pacer.collect_garbage(device, sync2)?;
+/// ```
+///
+pub struct FramePacer {
+    pub timeline: vk::Semaphore,
+    pub deletion_queue: DeletionQueue,
+    pub timeout: u64,
+    image_available: Vec<vk::Semaphore>,
+    render_finished: Vec<vk::Semaphore>,
+    /// Last timeline value submitted from each slot
+    slot_values: Vec<u64>,
+    frame_number: u64
+}
+
+impl FramePacer {
+
+    pub fn frames_in_flight(&self) -> usize {
+        self.image_available.len()
+    }
+
+    /// Value the next submitted frame will signal
+    pub fn current_value(&self) -> u64 {
+        self.frame_number + 1
+    }
+
+    /// Waits until the slot for the next frame is free
+    pub fn begin_frame(&mut self, device: &ash::Device, sync2: &Synchronization2) -> VulkanResult<FrameSlot> {
+
+        let index = (self.frame_number % self.frames_in_flight() as u64) as usize;
+        let wait_value = self.slot_values[index];
+
+        if !sync2.wait_timeline(device, self.timeline, wait_value, self.timeout)? {
+            return Err(VulkanError::Sync(SyncError::Timeout(wait_value)));
+        }
+
+        Ok(FrameSlot {
+            index,
+            image_available: self.image_available[index],
+            signal_value: self.current_value()
+        })
+    }
+
+    ///
+    /// Submits command buffers waiting for the acquired image, signals the
+    /// timeline and the present semaphore of `image_index`, which is returned
+    ///
+    pub fn submit(
+        &mut self,
+        device: &ash::Device,
+        sync2: &Synchronization2,
+        queue: vk::Queue,
+        submit: FrameSubmit
+    ) -> VulkanResult<vk::Semaphore> {
+
+        let FrameSubmit { frame, image_index, command_buffers } = submit;
+
+        let render_finished = self.render_finished[image_index as usize];
+
+        let wait_infos = [vk::SemaphoreSubmitInfo::default()
+            .semaphore(frame.image_available)
+            .stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)];
+
+        let signal_infos = [
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(render_finished)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS),
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(self.timeline)
+                .value(frame.signal_value)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS),
+        ];
+
+        let command_buffer_infos = command_buffers.iter()
+            .map(|x| vk::CommandBufferSubmitInfo::default().command_buffer(*x))
+            .collect::<Vec<_>>();
+
+        let submit_info = vk::SubmitInfo2::default()
+            .wait_semaphore_infos(&wait_infos)
+            .command_buffer_infos(&command_buffer_infos)
+            .signal_semaphore_infos(&signal_infos);
+
+        sync2.queue_submit2(device, queue, &[submit_info], vk::Fence::null())?;
+
+        self.slot_values[frame.index] = frame.signal_value;
+        self.frame_number += 1;
+
+        Ok(render_finished)
+    }
+
+    /// Destroys deferred resources the GPU is done with
+    pub fn collect_garbage(&mut self, device: &ash::Device, sync2: &Synchronization2) -> VulkanResult<()> {
+        let completed = sync2.timeline_value(device, self.timeline)?;
+        self.deletion_queue.flush(device, completed);
+        Ok(())
+    }
+
+    /// Recreates present semaphores after the swapchain image count changed.
+    /// Old semaphores go to the deletion queue
+    pub fn set_image_count(&mut self, device: &ash::Device, image_count: usize) -> VulkanResult<()> {
+
+        if image_count == self.render_finished.len() {
+            return Ok(());
+        }
+
+        let last_use = self.frame_number;
+        for semaphore in self.render_finished.drain(..) {
+            self.deletion_queue.push(last_use, crate::DeferredResource::Semaphore(semaphore));
+        }
+
+        self.render_finished = create_binary_semaphores(device, image_count)?;
+        Ok(())
+    }
+
+    /// Device must be idle
+    pub fn destroy(&mut self, device: &ash::Device) {
+        self.deletion_queue.flush_all(device);
+        unsafe {
+            for semaphore in self.image_available.drain(..).chain(self.render_finished.drain(..)) {
+                device.destroy_semaphore(semaphore, None);
+            }
+            device.destroy_semaphore(self.timeline, None);
+        }
+    }
+}
+
+fn create_binary_semaphores(device: &ash::Device, count: usize) -> VulkanResult<Vec<vk::Semaphore>> {
+    (0..count).map(|_| {
+        unsafe { device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Sync(SyncError::CreateSemaphoreFailed(e))))
+    }).collect()
+}
+
+#[derive(Default)]
+pub struct FramePacerBuilder<'n> {
+    device: Option<&'n ash::Device>,
+    frames_in_flight: Option<usize>,
+    image_count: Option<usize>,
+    timeout: Option<u64>
+}
+
+impl<'n> FramePacerBuilder<'n> {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with_device(mut self, device: &'n ash::Device) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    /// Default: 2
+    pub fn with_frames_in_flight(mut self, count: usize) -> Self {
+        self.frames_in_flight = Some(count);
+        self
+    }
+
+    /// Swapchain image count
+    pub fn with_image_count(mut self, count: usize) -> Self {
+        self.image_count = Some(count);
+        self
+    }
+
+    /// Nanoseconds to wait for a frame slot. Default: 1 second
+    pub fn with_timeout(mut self, timeout: u64) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Missing device or image count are reported as [`VulkanError::MissingField`]
+    pub fn build(self) -> VulkanResult<FramePacer> {
+
+        let device = self.device.ok_or(VulkanError::missing("FramePacerBuilder", "device"))?;
+        let image_count = self.image_count.ok_or(VulkanError::missing("FramePacerBuilder", "image count"))?;
+        let frames_in_flight = self.frames_in_flight.unwrap_or(DEFAULT_FRAMES_IN_FLIGHT).max(1);
+
+        let mut type_info = vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+
+        let create_info = vk::SemaphoreCreateInfo::default()
+            .push_next(&mut type_info);
+
+        let timeline = unsafe { device.create_semaphore(&create_info, None) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Sync(SyncError::CreateSemaphoreFailed(e))))?;
+
+        Ok(FramePacer {
+            timeline,
+            deletion_queue: DeletionQueue::new(),
+            timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+            image_available: create_binary_semaphores(device, frames_in_flight)?,
+            render_finished: create_binary_semaphores(device, image_count)?,
+            slot_values: vec![0; frames_in_flight],
+            frame_number: 0
+        })
+    }
+}
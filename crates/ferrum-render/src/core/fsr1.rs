@@ -0,0 +1,572 @@
+use ash::vk::{self, Extent2D, Format, PhysicalDeviceMemoryProperties, ShaderModule};
+
+use crate::{
+    AttachmentImageBuilder, ComputePipeline, ComputePipelineBuilder, DeferredResource, DescriptorPool, DescriptorPoolBuilder,
+    DescriptorSetLayout, DescriptorSetLayoutBuilder, DeviceHandle, PipelineError, ResourceOwner, VulkanError, VulkanResult
+};
+
+/// Format of the EASU and RCAS storage images, `rgba16f` in the shaders
+pub const FSR1_OUTPUT_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// `local_size` of `fsr1-easu.comp` and `fsr1-rcas.comp`
+const WORKGROUP_SIZE: [u32; 3] = [8, 8, 1];
+
+/// Recommended by AMD, in stops
+const DEFAULT_SHARPNESS: f32 = 0.2;
+
+/// FSR 1.0 quality mode, the scene is rendered `scale_factor` times smaller than the display
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FsrQuality {
+    /// 1.3x
+    UltraQuality,
+    /// 1.5x
+    #[default]
+    Quality,
+    /// 1.7x
+    Balanced,
+    /// 2.0x
+    Performance
+}
+
+impl FsrQuality {
+
+    /// Display size / render size per dimension
+    pub fn scale_factor(self) -> f32 {
+        match self {
+            FsrQuality::UltraQuality => 1.3,
+            FsrQuality::Quality => 1.5,
+            FsrQuality::Balanced => 1.7,
+            FsrQuality::Performance => 2.0
+        }
+    }
+
+    /// Scene resolution for the `display` extent
+    pub fn render_extent(self, display: Extent2D) -> Extent2D {
+        let factor = self.scale_factor();
+        Extent2D {
+            width: ((display.width as f32 / factor) as u32).max(1),
+            height: ((display.height as f32 / factor) as u32).max(1)
+        }
+    }
+}
+
+/// Push constants of `fsr1-easu.comp`, `FsrEasuCon` without the gather offsets
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EasuConstants {
+    /// Input viewport / output size
+    pub scale: [f32; 2],
+    /// Output pixel to the input pixel grid
+    pub offset: [f32; 2],
+    /// Last readable input texel
+    pub input_max: [u32; 2],
+    pub output_size: [u32; 2]
+}
+
+impl EasuConstants {
+
+    pub fn new(input: Extent2D, output: Extent2D) -> Self {
+        let scale = [
+            input.width as f32 / output.width as f32,
+            input.height as f32 / output.height as f32
+        ];
+
+        Self {
+            scale,
+            offset: [0.5 * scale[0] - 0.5, 0.5 * scale[1] - 0.5],
+            input_max: [input.width.saturating_sub(1), input.height.saturating_sub(1)],
+            output_size: [output.width, output.height]
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, std::mem::size_of::<Self>()) }
+    }
+}
+
+/// Push constants of `fsr1-rcas.comp`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RcasConstants {
+    pub output_size: [u32; 2],
+    /// `exp2(-stops)`, see [`RcasConstants::new`]
+    pub sharpness: f32
+}
+
+impl RcasConstants {
+
+    /// `sharpness` in stops: 0.0 is the sharpest, every +1.0 halves the effect
+    pub fn new(output: Extent2D, sharpness: f32) -> Self {
+        Self {
+            output_size: [output.width, output.height],
+            sharpness: (-sharpness.max(0.0)).exp2()
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, std::mem::size_of::<Self>()) }
+    }
+}
+
+/// Scene read by [`Fsr1Pass`], in SHADER_READ_ONLY_OPTIMAL when the pass is recorded
+#[derive(Debug, Clone, Copy)]
+pub struct Fsr1Input {
+    pub view: vk::ImageView,
+    /// Render resolution, usually [`Fsr1Pass::render_extent`]
+    pub extent: Extent2D
+}
+
+///
+/// Images and descriptor sets of one [`Fsr1Pass`] input, recreate them with the swapchain.
+/// Handles are queued for destruction on drop
+///
+pub struct Fsr1Targets {
+    /// EASU result, RCAS input
+    pub intermediate: vk::Image,
+    /// Upscaled and sharpened scene, SHADER_READ_ONLY_OPTIMAL after [`Fsr1Pass::record`]
+    pub output: vk::Image,
+    pub output_view: vk::ImageView,
+    pub easu_set: vk::DescriptorSet,
+    pub rcas_set: vk::DescriptorSet,
+    pub input_extent: Extent2D,
+    pub output_extent: Extent2D,
+    _pool: DescriptorPool,
+    _owner: ResourceOwner
+}
+
+///
+/// FidelityFX Super Resolution 1.0: EASU upscales the scene to the display extent,
+/// RCAS sharpens the result. Both are compute shaders, record the pass after the scene
+/// and sample [`Fsr1Targets::output`] in the final fullscreen pass.
+///
+/// The input is expected in perceptual 0..1 space, after tone mapping and before film grain or UI.
+/// Set 0 holds the input as SAMPLED_IMAGE (binding 0), SAMPLER (binding 1) and
+/// the result as STORAGE_IMAGE (binding 2)
+///
+/// # Example
+/// ```
+/// let fsr = Fsr1PassBuilder::new()
+///     .with_device(device)
+///     .with_easu_shader(easu.compute_shader) // fsr1-easu-comp.spv
+///     .with_rcas_shader(rcas.compute_shader) // fsr1-rcas-comp.spv
+///     .with_quality(FsrQuality::Balanced)
+///     .build()?;
+///
+/// // the scene is rendered at this size, in SHADER_READ_ONLY_OPTIMAL when the pass is recorded
+/// let scene_extent = fsr.render_extent(ctx.window.extent());
+/// let input = Fsr1Input { view: scene.view, extent: scene_extent };
+/// let targets = fsr.create_targets(device, &memory_prop, input, ctx.window.extent())?;
+///
+/// // outside of a render pass
+/// fsr.record(device, cbuf, &targets);
+/// ```
+///
+pub struct Fsr1Pass {
+    pub easu: ComputePipeline,
+    pub rcas: ComputePipeline,
+    pub set_layout: DescriptorSetLayout,
+    /// Nearest, clamp to edge
+    pub sampler: vk::Sampler,
+    pub quality: FsrQuality,
+    /// In stops, 0.0 is the sharpest
+    pub sharpness: f32,
+    _owner: ResourceOwner
+}
+
+impl Fsr1Pass {
+
+    pub fn render_extent(&self, display: Extent2D) -> Extent2D {
+        self.quality.render_extent(display)
+    }
+
+    ///
+    /// Creates the intermediate and output images of `output_extent` and
+    /// the descriptor sets reading `input`
+    ///
+    pub fn create_targets(
+        &self,
+        device: &DeviceHandle,
+        memory_prop: &PhysicalDeviceMemoryProperties,
+        input: Fsr1Input,
+        output_extent: Extent2D
+    ) -> VulkanResult<Fsr1Targets> {
+
+        let Fsr1Input { view: input, extent: input_extent } = input;
+
+        let create_image = || AttachmentImageBuilder::new()
+            .with_device(device)
+            .with_memory_properties(memory_prop)
+            .with_extent(output_extent)
+            .with_format(FSR1_OUTPUT_FORMAT)
+            .with_usage(vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED)
+            .build()
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateImageFailed(e))));
+
+        let intermediate = create_image()?;
+        let output = match create_image() {
+            Ok(output) => output,
+            Err(e) => {
+                intermediate.destroy(device);
+                return Err(e);
+            }
+        };
+
+        // Сначала view, потом изображения
+        let owner = ResourceOwner::new(device, vec![
+            DeferredResource::ImageView(intermediate.view),
+            DeferredResource::ImageView(output.view),
+            DeferredResource::Image(intermediate.raw, intermediate.memory),
+            DeferredResource::Image(output.raw, output.memory)
+        ]);
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(2),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::SAMPLER)
+                .descriptor_count(2),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(2),
+        ];
+
+        let pool = DescriptorPoolBuilder::new()
+            .with_device(device)
+            .with_pool_sizes(&pool_sizes)
+            .with_max_sets(2)
+            .build()?;
+
+        let set_layouts = [self.set_layout.raw, self.set_layout.raw];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool.raw)
+            .set_layouts(&set_layouts);
+
+        let sets = unsafe { device.allocate_descriptor_sets(&allocate_info) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::AllocateDescriptorSetFailed(e))))?;
+
+        // EASU: вход -> intermediate, RCAS: intermediate -> output
+        let reads = [
+            [vk::DescriptorImageInfo::default().image_view(input).image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)],
+            [vk::DescriptorImageInfo::default().image_view(intermediate.view).image_layout(vk::ImageLayout::GENERAL)],
+        ];
+        let writes = [
+            [vk::DescriptorImageInfo::default().image_view(intermediate.view).image_layout(vk::ImageLayout::GENERAL)],
+            [vk::DescriptorImageInfo::default().image_view(output.view).image_layout(vk::ImageLayout::GENERAL)],
+        ];
+        let samplers = [vk::DescriptorImageInfo::default().sampler(self.sampler)];
+
+        let mut descriptor_writes = vec![];
+
+        for (i, set) in sets.iter().enumerate() {
+            descriptor_writes.push(vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(&reads[i]));
+
+            descriptor_writes.push(vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .image_info(&samplers));
+
+            descriptor_writes.push(vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(2)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(&writes[i]));
+        }
+
+        unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+        Ok(Fsr1Targets {
+            intermediate: intermediate.raw,
+            output: output.raw,
+            output_view: output.view,
+            easu_set: sets[0],
+            rcas_set: sets[1],
+            input_extent,
+            output_extent,
+            _pool: pool,
+            _owner: owner
+        })
+    }
+
+    /// Records EASU and RCAS with the barriers between them, outside of a render pass
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, targets: &Fsr1Targets) {
+
+        // Содержимое прошлого кадра не нужно, UNDEFINED -> GENERAL
+        let to_general = [targets.intermediate, targets.output].map(|image| {
+            image_barrier(image)
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(vk::AccessFlags::SHADER_WRITE)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::GENERAL)
+        });
+
+        // Сцена записана рендер-пассом перед этим
+        let scene = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ);
+
+        let easu_done = image_barrier(targets.intermediate)
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .old_layout(vk::ImageLayout::GENERAL)
+            .new_layout(vk::ImageLayout::GENERAL);
+
+        let rcas_done = image_barrier(targets.output)
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .old_layout(vk::ImageLayout::GENERAL)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+        let output = [targets.output_extent.width, targets.output_extent.height, 1];
+        let easu = EasuConstants::new(targets.input_extent, targets.output_extent);
+        let rcas = RcasConstants::new(targets.output_extent, self.sharpness);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[scene],
+                &[],
+                &to_general
+            );
+        }
+
+        self.easu.bind_descriptor_sets(device, command_buffer, 0, &[targets.easu_set]);
+        self.easu.push_constants(device, command_buffer, 0, easu.as_bytes());
+        self.easu.dispatch_threads(device, command_buffer, output, WORKGROUP_SIZE);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[easu_done]
+            );
+        }
+
+        self.rcas.bind_descriptor_sets(device, command_buffer, 0, &[targets.rcas_set]);
+        self.rcas.push_constants(device, command_buffer, 0, rcas.as_bytes());
+        self.rcas.dispatch_threads(device, command_buffer, output, WORKGROUP_SIZE);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[rcas_done]
+            );
+        }
+    }
+}
+
+fn image_barrier<'a>(image: vk::Image) -> vk::ImageMemoryBarrier<'a> {
+    vk::ImageMemoryBarrier::default()
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1
+        })
+}
+
+#[derive(Default)]
+pub struct Fsr1PassBuilder<'n> {
+    device: Option<&'n DeviceHandle>,
+    easu_shader: Option<ShaderModule>,
+    rcas_shader: Option<ShaderModule>,
+    quality: Option<FsrQuality>,
+    sharpness: Option<f32>,
+    pipeline_cache: Option<vk::PipelineCache>
+}
+
+impl<'n> Fsr1PassBuilder<'n> {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with_device(mut self, device: &'n DeviceHandle) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    pub fn with_easu_shader(mut self, shader: ShaderModule) -> Self {
+        self.easu_shader = Some(shader);
+        self
+    }
+
+    pub fn with_rcas_shader(mut self, shader: ShaderModule) -> Self {
+        self.rcas_shader = Some(shader);
+        self
+    }
+
+    /// Default: [`FsrQuality::Quality`]
+    pub fn with_quality(mut self, quality: FsrQuality) -> Self {
+        self.quality = Some(quality);
+        self
+    }
+
+    /// Default: 0.2 stops
+    pub fn with_sharpness(mut self, stops: f32) -> Self {
+        self.sharpness = Some(stops);
+        self
+    }
+
+    pub fn with_pipeline_cache(mut self, cache: vk::PipelineCache) -> Self {
+        self.pipeline_cache = Some(cache);
+        self
+    }
+
+    pub fn build(self) -> VulkanResult<Fsr1Pass> {
+
+        let device = self.device.ok_or(VulkanError::missing("Fsr1PassBuilder", "device"))?;
+        let easu_shader = self.easu_shader.ok_or(VulkanError::missing("Fsr1PassBuilder", "EASU shader"))?;
+        let rcas_shader = self.rcas_shader.ok_or(VulkanError::missing("Fsr1PassBuilder", "RCAS shader"))?;
+
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(2)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE),
+        ];
+
+        let set_layout = DescriptorSetLayoutBuilder::new()
+            .with_device(device)
+            .with_bindings(&bindings)
+            .build()?;
+
+        let set_layouts = [set_layout.raw];
+
+        let easu_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: std::mem::size_of::<EasuConstants>() as u32
+        }];
+
+        let rcas_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: std::mem::size_of::<RcasConstants>() as u32
+        }];
+
+        let build = |shader: ShaderModule, ranges: &[vk::PushConstantRange]| {
+            let mut builder = ComputePipelineBuilder::new()
+                .with_device(device)
+                .with_shader(shader)
+                .with_descriptor_set_layouts(&set_layouts)
+                .with_push_constant_ranges(ranges);
+
+            if let Some(cache) = self.pipeline_cache {
+                builder = builder.with_pipeline_cache(cache);
+            }
+
+            builder.build()
+        };
+
+        let easu = build(easu_shader, &easu_ranges)?;
+        let rcas = build(rcas_shader, &rcas_ranges)?;
+
+        // texelFetch, фильтрация не используется
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+
+        let sampler = unsafe { device.create_sampler(&sampler_info, None) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateSamplerFailed(e))))?;
+
+        Ok(Fsr1Pass {
+            easu,
+            rcas,
+            set_layout,
+            sampler,
+            quality: self.quality.unwrap_or_default(),
+            sharpness: self.sharpness.unwrap_or(DEFAULT_SHARPNESS),
+            _owner: ResourceOwner::new(device, vec![DeferredResource::Sampler(sampler)])
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PipelineReflection, ShaderReflection};
+
+    fn load(bytes: &[u8]) -> Vec<u32> {
+        bytes.chunks_exact(4).map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]])).collect()
+    }
+
+    #[test]
+    fn test_constants_match_shaders() {
+        let easu = ShaderReflection::from_spirv(&load(include_bytes!("../../../../shared/shaders/spv/fsr1-easu-comp.spv"))).unwrap();
+        let rcas = ShaderReflection::from_spirv(&load(include_bytes!("../../../../shared/shaders/spv/fsr1-rcas-comp.spv"))).unwrap();
+        let easu = PipelineReflection::merge(&[easu]).unwrap();
+        let rcas = PipelineReflection::merge(&[rcas]).unwrap();
+
+        assert_eq!(easu.push_constant_ranges[0].size as usize, std::mem::size_of::<EasuConstants>());
+        assert_eq!(rcas.push_constant_ranges[0].size as usize, std::mem::size_of::<RcasConstants>());
+
+        // texelFetch не использует сэмплер, glslang выкидывает его из тела шейдера
+        for reflection in [easu, rcas] {
+            let ty = |binding| reflection.sets[&0].iter().find(|x| x.binding == binding).map(|x| x.descriptor_type);
+            assert_eq!(ty(0), Some(vk::DescriptorType::SAMPLED_IMAGE));
+            assert_eq!(ty(1), None);
+            assert_eq!(ty(2), Some(vk::DescriptorType::STORAGE_IMAGE));
+        }
+    }
+
+    #[test]
+    fn test_quality_render_extent() {
+        let display = Extent2D { width: 3840, height: 2160 };
+
+        assert_eq!(FsrQuality::UltraQuality.render_extent(display), Extent2D { width: 2953, height: 1661 });
+        assert_eq!(FsrQuality::Quality.render_extent(display), Extent2D { width: 2560, height: 1440 });
+        assert_eq!(FsrQuality::Balanced.render_extent(display), Extent2D { width: 2258, height: 1270 });
+        assert_eq!(FsrQuality::Performance.render_extent(display), Extent2D { width: 1920, height: 1080 });
+        assert_eq!(FsrQuality::Performance.render_extent(Extent2D { width: 1, height: 1 }), Extent2D { width: 1, height: 1 });
+    }
+
+    #[test]
+    fn test_easu_rcas_constants() {
+        let easu = EasuConstants::new(Extent2D { width: 1280, height: 720 }, Extent2D { width: 1920, height: 1080 });
+        let scale = 1280.0 / 1920.0;
+
+        assert_eq!(easu.scale, [scale, 720.0 / 1080.0]);
+        assert_eq!(easu.offset[0], 0.5 * scale - 0.5);
+        assert_eq!(easu.input_max, [1279, 719]);
+
+        assert_eq!(RcasConstants::new(Extent2D { width: 8, height: 8 }, 0.0).sharpness, 1.0);
+        assert_eq!(RcasConstants::new(Extent2D { width: 8, height: 8 }, 2.0).sharpness, 0.25);
+    }
+}
@@ -1,10 +1,10 @@
 use ash::vk::{self, PhysicalDeviceMemoryProperties};
-use log::warn;
-use crate::{find_memorytype_index};
+use crate::{find_memorytype_index, BindlessDescriptorSet, BindlessHandle, DeferredResource, DeviceHandle, ResourceOwner, VulkanError, VulkanResult, PipelineError};
 use vk_mem::Allocator;
 
 ///
-/// Wraper around [`ash::vk::Buffer`] for simple use
+/// Wraper around [`ash::vk::Buffer`] for simple use.
+/// Buffer and memory are queued for destruction on drop
 /// # Panic
 /// if size == 0
 ///
@@ -13,7 +13,7 @@ use vk_mem::Allocator;
 /// ```
 /// fn main() {
 ///     let uniform_buffer = GPUBuffer::new(
-///         &device.raw,
+///         ctx.device.raw_device(),
 ///         &memory_prop,
 ///         buffer_size,
 ///         vk::BufferUsageFlags::UNIFORM_BUFFER,
@@ -24,18 +24,20 @@ use vk_mem::Allocator;
 /// }
 /// ```
 ///
-#[derive(Clone)]
 pub struct GPUBuffer {
     pub raw: vk::Buffer,
     pub memory: vk::DeviceMemory,
     pub size: u64,
+    /// Index in the bindless `buffers[]` array, set by [`GPUBuffer::new_bindless`] and released on drop
+    pub bindless: Option<BindlessHandle>,
+    _owner: ResourceOwner
 }
 
 impl GPUBuffer {
 
     /// Create [`GPUBuffer`]
     pub fn new(
-        device: &ash::Device,
+        device: &DeviceHandle,
         memory_prop: &PhysicalDeviceMemoryProperties,
         size: u64,
         usage: vk::BufferUsageFlags,
@@ -63,16 +65,47 @@ impl GPUBuffer {
             .allocation_size(req.size)
             .memory_type_index(memory_type_index);
 
-        let memory = unsafe { device.allocate_memory(&alloc_info, None)? };
+        let memory = match unsafe { device.allocate_memory(&alloc_info, None) } {
+            Ok(memory) => memory,
+            Err(e) => {
+                unsafe { device.destroy_buffer(buffer, None) };
+                return Err(e);
+            }
+        };
+
+        // Owned from here, failed bind still frees both
+        let owner = ResourceOwner::new(device, vec![DeferredResource::Buffer(buffer, memory)]);
         unsafe { device.bind_buffer_memory(buffer, memory, 0)? };
 
         Ok(Self {
             raw: buffer,
             memory,
             size,
+            bindless: None,
+            _owner: owner
         })
     }
 
+    /// Create a `STORAGE_BUFFER | TRANSFER_DST` [`GPUBuffer`] and register it in the bindless set
+    pub fn new_bindless(
+        device: &DeviceHandle,
+        memory_prop: &PhysicalDeviceMemoryProperties,
+        size: u64,
+        memory_flags: vk::MemoryPropertyFlags,
+        bindless: &mut BindlessDescriptorSet
+    ) -> VulkanResult<Self> {
+
+        let usage = vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST;
+        let mut buffer = Self::new(device, memory_prop, size, usage, memory_flags)
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateBufferFailed(e))))?;
+
+        let handle = bindless.register_storage_buffer(device, &buffer)?;
+        buffer._owner.push(bindless.deferred_release_storage_buffer(handle));
+        buffer.bindless = Some(handle);
+
+        Ok(buffer)
+    }
+
     /// Upload data into GPU Memory
     pub fn upload_data<T: Copy>(&self, device: &ash::Device, data: &[T]) {
 
@@ -101,12 +134,3 @@ impl GPUBuffer {
     }
 
 }
-
-
-impl Drop for GPUBuffer {
-    fn drop(&mut self) {
-        if self.size != 0 {
-            warn!("Memory Leak");
-        }
-    }
-}
\ No newline at end of file
@@ -0,0 +1,168 @@
+use std::ffi::CStr;
+
+use ash::vk::{self, ColorSpaceKHR, Format, SurfaceFormatKHR};
+
+use crate::{load_instance_extension_props, PhysicalDeviceInfo};
+
+/// Instance extension with the non-sRGB swapchain colour spaces
+pub const SWAPCHAIN_COLORSPACE_EXTENSION: &CStr = c"VK_EXT_swapchain_colorspace";
+/// Device extension for `vkSetHdrMetadataEXT`
+pub const HDR_METADATA_EXTENSION: &CStr = c"VK_EXT_hdr_metadata";
+
+/// HDR10, scRGB and Display-P3 surface formats are only reported with this extension enabled
+pub fn supports_swapchain_colorspace(entry: &ash::Entry) -> bool {
+    load_instance_extension_props(entry, None)
+        .map(|props| props.iter().any(|x| x.extension_name_as_c_str() == Ok(SWAPCHAIN_COLORSPACE_EXTENSION)))
+        .unwrap_or(false)
+}
+
+pub fn supports_hdr_metadata(info: &PhysicalDeviceInfo) -> bool {
+    info.extensions.iter().any(|x| x.extension_name_as_c_str() == Ok(HDR_METADATA_EXTENSION))
+}
+
+/// Colour space of the presented image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum OutputColorSpace {
+    /// 8 bit, sRGB primaries and transfer
+    #[default]
+    Srgb,
+    /// Display-P3 primaries, sRGB transfer
+    DisplayP3,
+    /// 10 bit, Rec.2020 primaries, SMPTE ST 2084 (PQ) transfer
+    Hdr10,
+    /// 16 bit float, sRGB primaries, linear, 1.0 = 80 nits
+    ScRgb
+}
+
+impl OutputColorSpace {
+
+    /// Accepted surface formats, in order of preference
+    pub fn surface_formats(self) -> &'static [(Format, ColorSpaceKHR)] {
+        match self {
+            OutputColorSpace::Srgb => &[
+                (Format::B8G8R8A8_SRGB, ColorSpaceKHR::SRGB_NONLINEAR),
+                (Format::R8G8B8A8_SRGB, ColorSpaceKHR::SRGB_NONLINEAR),
+                (Format::B8G8R8A8_UNORM, ColorSpaceKHR::SRGB_NONLINEAR),
+                (Format::R8G8B8A8_UNORM, ColorSpaceKHR::SRGB_NONLINEAR),
+                (Format::A8B8G8R8_SRGB_PACK32, ColorSpaceKHR::SRGB_NONLINEAR),
+            ],
+            OutputColorSpace::DisplayP3 => &[
+                (Format::A2B10G10R10_UNORM_PACK32, ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT),
+                (Format::B8G8R8A8_SRGB, ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT),
+                (Format::R8G8B8A8_SRGB, ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT),
+                (Format::B8G8R8A8_UNORM, ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT),
+                (Format::R8G8B8A8_UNORM, ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT),
+            ],
+            OutputColorSpace::Hdr10 => &[
+                (Format::A2B10G10R10_UNORM_PACK32, ColorSpaceKHR::HDR10_ST2084_EXT),
+                (Format::A2R10G10B10_UNORM_PACK32, ColorSpaceKHR::HDR10_ST2084_EXT),
+            ],
+            OutputColorSpace::ScRgb => &[
+                (Format::R16G16B16A16_SFLOAT, ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT),
+            ],
+        }
+    }
+
+    pub fn from_surface_format(format: &SurfaceFormatKHR) -> Option<Self> {
+        [OutputColorSpace::Srgb, OutputColorSpace::DisplayP3, OutputColorSpace::Hdr10, OutputColorSpace::ScRgb]
+            .into_iter()
+            .find(|x| x.surface_formats().contains(&(format.format, format.color_space)))
+    }
+
+    pub fn is_hdr(self) -> bool {
+        matches!(self, OutputColorSpace::Hdr10 | OutputColorSpace::ScRgb)
+    }
+
+    /// Everything except sRGB needs [`SWAPCHAIN_COLORSPACE_EXTENSION`]
+    pub fn requires_extension(self) -> bool {
+        self != OutputColorSpace::Srgb
+    }
+}
+
+///
+/// Picks the first supported format of the first supported colour space in `preferred`.
+/// sRGB is always tried last, so the result is None only when no known format is reported
+///
+/// # Example
+/// ```
+/// let formats = surface.get_surface_formats(&phys_dev.raw);
+/// let (format, color_space) = choose_surface_format(&formats, &[OutputColorSpace::Hdr10, OutputColorSpace::ScRgb]).unwrap();
+/// ```
+///
+pub fn choose_surface_format(formats: &[SurfaceFormatKHR], preferred: &[OutputColorSpace]) -> Option<(SurfaceFormatKHR, OutputColorSpace)> {
+    preferred.iter()
+        .copied()
+        .chain(std::iter::once(OutputColorSpace::Srgb))
+        .find_map(|color_space| {
+            color_space.surface_formats().iter().find_map(|(format, space)| {
+                formats.iter()
+                    .find(|x| x.format == *format && x.color_space == *space)
+                    .map(|x| (*x, color_space))
+            })
+        })
+}
+
+///
+/// HDR10 mastering metadata with Rec.2020 primaries and D65 white point.
+/// Luminance values are in nits
+///
+pub fn hdr10_metadata(max_luminance: f32, min_luminance: f32, max_content_light_level: f32, max_frame_average_light_level: f32) -> vk::HdrMetadataEXT<'static> {
+    vk::HdrMetadataEXT::default()
+        .display_primary_red(vk::XYColorEXT { x: 0.708, y: 0.292 })
+        .display_primary_green(vk::XYColorEXT { x: 0.170, y: 0.797 })
+        .display_primary_blue(vk::XYColorEXT { x: 0.131, y: 0.046 })
+        .white_point(vk::XYColorEXT { x: 0.3127, y: 0.3290 })
+        .max_luminance(max_luminance)
+        .min_luminance(min_luminance)
+        .max_content_light_level(max_content_light_level)
+        .max_frame_average_light_level(max_frame_average_light_level)
+}
+
+/// `VK_EXT_hdr_metadata` entry points
+pub struct HdrMetadata {
+    pub raw_load: ash::ext::hdr_metadata::Device
+}
+
+impl HdrMetadata {
+
+    pub fn new(instance: &ash::Instance, device: &ash::Device) -> Self {
+        Self { raw_load: ash::ext::hdr_metadata::Device::new(instance, device) }
+    }
+
+    /// Metadata is tied to the swapchain, set it again after recreation
+    pub fn set(&self, swapchain: vk::SwapchainKHR, metadata: &vk::HdrMetadataEXT) {
+        unsafe { self.raw_load.set_hdr_metadata(&[swapchain], std::slice::from_ref(metadata)) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(format: Format, color_space: ColorSpaceKHR) -> SurfaceFormatKHR {
+        SurfaceFormatKHR { format, color_space }
+    }
+
+    #[test]
+    fn test_choose_surface_format() {
+        let formats = [
+            format(Format::B8G8R8A8_UNORM, ColorSpaceKHR::SRGB_NONLINEAR),
+            format(Format::R16G16B16A16_SFLOAT, ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT),
+            format(Format::A2B10G10R10_UNORM_PACK32, ColorSpaceKHR::HDR10_ST2084_EXT),
+        ];
+
+        let (hdr10, space) = choose_surface_format(&formats, &[OutputColorSpace::Hdr10, OutputColorSpace::ScRgb]).unwrap();
+        assert_eq!(space, OutputColorSpace::Hdr10);
+        assert_eq!(hdr10.format, Format::A2B10G10R10_UNORM_PACK32);
+
+        let (_, space) = choose_surface_format(&formats, &[OutputColorSpace::DisplayP3, OutputColorSpace::ScRgb]).unwrap();
+        assert_eq!(space, OutputColorSpace::ScRgb);
+
+        // Falls back to sRGB
+        let (sdr, space) = choose_surface_format(&formats[..1], &[OutputColorSpace::Hdr10]).unwrap();
+        assert_eq!(space, OutputColorSpace::Srgb);
+        assert_eq!(sdr.format, Format::B8G8R8A8_UNORM);
+
+        assert_eq!(OutputColorSpace::from_surface_format(&formats[1]), Some(OutputColorSpace::ScRgb));
+    }
+}
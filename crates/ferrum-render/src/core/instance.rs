@@ -1,4 +1,5 @@
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
+use std::sync::Arc;
 
 use ash::{
     ext::debug_utils,
@@ -8,7 +9,7 @@ use ash::{
 };
 
 use crate::VulkanError;
-use crate::{vulkan_debug_callback, InstanceError, VulkanResult};
+use crate::{vulkan_debug_callback, InstanceError, ValidationConfig, VulkanResult};
 
 #[derive(Default)]
 pub struct InstanceBuilder<'n> {
@@ -17,23 +18,56 @@ pub struct InstanceBuilder<'n> {
     api_version: Option<u32>,
     flags: Option<InstanceCreateFlags>,
     extensions: Vec<*const i8>,
+    optional_extensions: Vec<&'static CStr>,
     layers: Vec<*const i8>,
     debug_extensions: Vec<*const i8>,
     debug_layers: Vec<*const i8>,
-    allocation_callbacks: Option<AllocationCallbacks<'static>>
+    allocation_callbacks: Option<AllocationCallbacks<'static>>,
+    validation: Option<ValidationConfig>
 }
 
 pub struct Instance {
     pub entry: ash::Entry,
     pub raw: ash::Instance,
     pub api_version: u32,
+    /// Required and the available part of optional extensions
+    pub extensions: Vec<CString>,
+    /// Created when `VK_EXT_debug_utils` is enabled
+    pub debug_callback: Option<ash::vk::DebugUtilsMessengerEXT>,
+    pub debug_utils_loader: Option<ash::ext::debug_utils::Instance>,
+    handle: InstanceHandle
+}
+
+struct InstanceInner {
+    raw: ash::Instance,
     allocation_callbacks: Option<AllocationCallbacks<'static>>,
-    #[cfg(debug_assertions)]
-    pub debug_callback: ash::vk::DebugUtilsMessengerEXT,
-    #[cfg(debug_assertions)]
-    pub debug_utils_loader: ash::ext::debug_utils::Instance,
-    #[cfg(debug_assertions)]
-    destroyed: bool
+    debug: Option<(ash::ext::debug_utils::Instance, DebugUtilsMessengerEXT)>,
+    /// `p_user_data` of the messenger, dropped after it is destroyed
+    _validation: Box<ValidationConfig>
+}
+
+// Callbacks are only passed back to Vulkan on destruction
+unsafe impl Send for InstanceInner {}
+unsafe impl Sync for InstanceInner {}
+
+impl Drop for InstanceInner {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some((loader, messenger)) = &self.debug {
+                loader.destroy_debug_utils_messenger(*messenger, None);
+            }
+            self.raw.destroy_instance(self.allocation_callbacks.as_ref());
+        }
+    }
+}
+
+///
+/// Keeps the Vulkan instance alive, it is destroyed with the last handle.
+/// [`crate::DeviceHandle`] holds one so the device is always destroyed first
+///
+#[derive(Clone)]
+pub struct InstanceHandle {
+    _inner: Arc<InstanceInner>
 }
 
 impl Instance {
@@ -46,15 +80,8 @@ impl Instance {
         &self.entry
     }
 
-    #[cfg(debug_assertions)]
-    pub fn destroy(&mut self) {
-        self.destroyed = true;
-        unsafe { self.raw().destroy_instance(self.allocation_callbacks.as_ref()); }
-    }
-
-    #[cfg(not(debug_assertions))]
-    pub fn destroy(&mut self) {
-        unsafe { self.raw().destroy_instance(self.allocation_callbacks.as_ref()); }
+    pub fn handle(&self) -> InstanceHandle {
+        self.handle.clone()
     }
 }
 
@@ -92,6 +119,13 @@ impl<'n> InstanceBuilder<'n> {
         self
     }
 
+    /// Расширения, которые включаются только если их поддерживает драйвер,
+    /// итог в [`Instance::extensions`]
+    pub fn with_optional_extensions(mut self, names: Vec<&'static CStr>) -> Self {
+        self.optional_extensions.extend(names);
+        self
+    }
+
     /// Добавляем слои для экземпляра vulkan
     pub fn with_layers(mut self, names: Vec<&'static CStr>) -> Self {
         self.layers.extend(names.iter().map(|name| name.as_ptr()));
@@ -116,12 +150,40 @@ impl<'n> InstanceBuilder<'n> {
         self
     }
 
+    ///
+    /// Enables `VK_LAYER_KHRONOS_validation` and `VK_EXT_debug_utils` in every build
+    /// and routes the messages into `log` according to `config`.
+    /// A missing validation layer is skipped with a warning.
+    /// Without it debug builds use [`ValidationConfig::default`] for the debug layers
+    ///
+    /// # Example
+    /// ```ignore
+    /// let capture = ValidationCapture::new();
+    ///
+    /// let instance = InstanceBuilder::new()
+    ///     .with_validation(ValidationConfig::new()
+    ///         .with_min_severity(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING)
+    ///         .suppress_message_id("VUID-VkSwapchainCreateInfoKHR-pNext-07781")
+    ///         .with_capture(capture.clone()))
+    ///     .with_app(app)
+    ///     .build()?;
+    /// ```
+    ///
+    pub fn with_validation(mut self, config: ValidationConfig) -> Self {
+        self.layers.push(VALIDATION_LAYER.as_ptr());
+        // Обычно его даёт сам слой валидации
+        self.optional_extensions.push(debug_utils::NAME);
+        self.validation = Some(config);
+        self
+    }
+
     pub fn build(self) -> VulkanResult<Instance> {
 
         let app = self.app.ok_or(crate::VulkanError::Instance(InstanceError::MissingApp))?;
         let entry = self.entry.unwrap();
         let flags = self.flags.unwrap_or(InstanceCreateFlags::default());
         let allocation_callbacks = self.allocation_callbacks;
+        let validation = Box::new(self.validation.unwrap_or_default());
         let mut layers = self.layers;
         let mut ext = self.extensions;
 
@@ -131,11 +193,35 @@ impl<'n> InstanceBuilder<'n> {
         #[cfg(debug_assertions)]
         add_debug_layers(&mut layers, &self.debug_layers);
 
-        assert!(check_support_layers(&entry, &layers).is_ok());
+        remove_duplicates(&mut layers);
+        remove_duplicates(&mut ext);
+
+        if !is_layer_available(&entry, VALIDATION_LAYER)? {
+            let count = layers.len();
+            layers.retain(|x| unsafe { CStr::from_ptr(*x) } != VALIDATION_LAYER);
+
+            if layers.len() != count {
+                log::warn!("{:?} is not installed, validation is disabled", VALIDATION_LAYER);
+            }
+        }
+
+        check_support_layers(&entry, &layers)?;
+
+        let available = load_available_extensions(&entry, &layers)?;
+        check_support_extensions(&available, &ext)?;
+
+        for name in self.optional_extensions {
+            if available.iter().any(|x| x.as_c_str() == name) {
+                ext.push(name.as_ptr());
+            } else {
+                log::info!("Optional instance extension {:?} is not available", name);
+            }
+        }
 
+        remove_duplicates(&mut ext);
 
-        for i in &ext {
-            unsafe { println!("ext {:?}", CStr::from_ptr(i.clone())); }
+        for name in &ext {
+            log::debug!("Instance extension {:?}", unsafe { CStr::from_ptr(*name) });
         }
 
         let create_info = InstanceCreateInfo::default()
@@ -144,27 +230,41 @@ impl<'n> InstanceBuilder<'n> {
             .enabled_layer_names(&layers)
             .flags(flags);
 
-        let instance = unsafe { entry.create_instance(&create_info, allocation_callbacks.as_ref()).expect("Error create Instance") };
+        let instance = unsafe { entry.create_instance(&create_info, allocation_callbacks.as_ref()) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Instance(InstanceError::InstanceCreationFailed(e))))?;
 
-        #[cfg(debug_assertions)]
-        let (debug_utils_loader, debug_callback) = create_debug_utils_messenger(&entry, &instance)?;
+        let debug = if ext.iter().any(|x| unsafe { CStr::from_ptr(*x) } == debug_utils::NAME) {
+            Some(create_debug_utils_messenger(&entry, &instance, &validation)?)
+        } else {
+            None
+        };
+
+        let handle = InstanceHandle {
+            _inner: Arc::new(InstanceInner {
+                raw: instance.clone(),
+                allocation_callbacks,
+                debug: debug.clone(),
+                _validation: validation
+            })
+        };
+
+        let extensions = ext.iter().map(|x| unsafe { CStr::from_ptr(*x) }.to_owned()).collect();
 
         Ok(Instance {
             raw: instance,
             entry: entry,
             api_version: app.api_version,
-            allocation_callbacks: allocation_callbacks,
-            #[cfg(debug_assertions)]
-            debug_callback,
-            #[cfg(debug_assertions)]
-            debug_utils_loader,
-            #[cfg(debug_assertions)]
-            destroyed: false
+            extensions,
+            debug_callback: debug.as_ref().map(|x| x.1),
+            debug_utils_loader: debug.map(|x| x.0),
+            handle
         })
     }
 
 }
 
+pub const VALIDATION_LAYER: &CStr = c"VK_LAYER_KHRONOS_validation";
+
 pub fn load_instance_extension_props(entry: &Entry, layer_name: Option<&CStr>) -> VkResult<Vec<ExtensionProperties>> {
     unsafe { entry.enumerate_instance_extension_properties(layer_name) }
 }
@@ -173,6 +273,11 @@ pub fn load_instance_layer_props(entry: &Entry) -> VkResult<Vec<LayerProperties>
     unsafe { entry.enumerate_instance_layer_properties() }
 }
 
+fn is_layer_available(entry: &Entry, name: &CStr) -> VulkanResult<bool> {
+    let available_layers = load_instance_layer_props(entry).map_err(|e| VulkanError::Instance(InstanceError::EnumerateInstanceLayerPropertiesFailed(e)))?;
+    Ok(available_layers.iter().any(|x| x.layer_name_as_c_str() == Ok(name)))
+}
+
 /// check if required layers available for current vulkan instance
 fn check_support_layers(entry: &Entry, required_layers: &Vec<*const i8>) -> VulkanResult<()> {
 
@@ -199,12 +304,37 @@ fn check_support_layers(entry: &Entry, required_layers: &Vec<*const i8>) -> Vulk
     Ok(())
 }
 
-/// TODO
-fn check_support_extensions() {
+/// Extensions of the implementation and of the enabled layers
+fn load_available_extensions(entry: &Entry, layers: &[*const i8]) -> VulkanResult<Vec<CString>> {
+
+    let map_err = |e| VulkanError::Instance(InstanceError::EnumerateInstanceExtensionPropertiesFailed(e));
+    let mut props = load_instance_extension_props(entry, None).map_err(map_err)?;
 
+    for layer in layers {
+        props.extend(load_instance_extension_props(entry, Some(unsafe { CStr::from_ptr(*layer) })).map_err(map_err)?);
+    }
+
+    Ok(props.iter()
+        .filter_map(|x| x.extension_name_as_c_str().ok().map(CStr::to_owned))
+        .collect())
 }
 
-fn create_debug_utils_messenger(entry: &Entry, instance: &ash::Instance) -> VulkanResult<(ash::ext::debug_utils::Instance, DebugUtilsMessengerEXT)> {
+/// check if required extensions available for current vulkan instance
+fn check_support_extensions(available: &[CString], required: &[*const i8]) -> VulkanResult<()> {
+
+    for req in required {
+        let name = unsafe { CStr::from_ptr(*req) };
+
+        if !available.iter().any(|x| x.as_c_str() == name) {
+            return Err(VulkanError::Instance(InstanceError::MissingRequiredExtension(name.to_string_lossy().into_owned())));
+        }
+    }
+
+    Ok(())
+}
+
+/// `config` must outlive the messenger
+fn create_debug_utils_messenger(entry: &Entry, instance: &ash::Instance, config: &ValidationConfig) -> VulkanResult<(ash::ext::debug_utils::Instance, DebugUtilsMessengerEXT)> {
 
     use ash::vk;
 
@@ -220,7 +350,8 @@ fn create_debug_utils_messenger(entry: &Entry, instance: &ash::Instance) -> Vulk
         vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION |
         vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
     )
-    .pfn_user_callback(Some(vulkan_debug_callback));
+    .pfn_user_callback(Some(vulkan_debug_callback))
+    .user_data(config as *const ValidationConfig as *mut std::ffi::c_void);
 
     let loader = debug_utils::Instance::new(entry, instance);
     let callback = unsafe {
@@ -231,6 +362,17 @@ fn create_debug_utils_messenger(entry: &Entry, instance: &ash::Instance) -> Vulk
     Ok((loader, callback))
 }
 
+/// `with_validation` and `with_debug_*` may request the same names
+fn remove_duplicates(names: &mut Vec<*const i8>) {
+    let mut seen = Vec::new();
+    names.retain(|x| {
+        let name = unsafe { CStr::from_ptr(*x) };
+        let is_new = !seen.contains(&name);
+        seen.push(name);
+        is_new
+    });
+}
+
 fn add_debug_layers(layers: &mut Vec<*const i8>, debug_layers: &Vec<*const i8>) {
     layers.extend(debug_layers);
 }
@@ -270,6 +412,18 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_check_support_extensions() {
+
+        let available = vec![c"VK_KHR_surface".to_owned()];
+
+        assert!(check_support_extensions(&available, &[c"VK_KHR_surface".as_ptr()]).is_ok());
+        assert!(matches!(
+            check_support_extensions(&available, &[c"VK_KHR_win32_surface".as_ptr()]),
+            Err(VulkanError::Instance(InstanceError::MissingRequiredExtension(name))) if name == "VK_KHR_win32_surface"
+        ));
+    }
+
     #[test]
     fn test_check_support_layers() {
 
@@ -289,12 +443,3 @@ mod tests {
     }
 }
 
-
-#[cfg(debug_assertions)]
-impl Drop for Instance {
-    fn drop(&mut self) {
-        if !self.destroyed {
-            log::warn!("Instance не был уничтожен");
-        }
-    }
-}
\ No newline at end of file
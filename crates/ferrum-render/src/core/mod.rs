@@ -3,15 +3,24 @@ pub(crate) mod app;
 pub(crate) mod instance;
 pub(crate) mod phys_device;
 pub(crate) mod device;
+pub(crate) mod features;
 pub(crate) mod queue;
 pub(crate) mod surface;
 pub(crate) mod utils;
+pub(crate) mod validation;
 pub(crate) mod shaders;
 pub(crate) mod image_views;
 pub(crate) mod render_pass;
 pub(crate) mod swapchain;
 pub(crate) mod command_pool;
 pub(crate) mod pipeline;
+pub(crate) mod compute_pipeline;
+pub(crate) mod pipeline_cache;
+pub(crate) mod pipeline_registry;
+pub(crate) mod dynamic_rendering;
+pub(crate) mod deletion_queue;
+pub(crate) mod resource;
+pub(crate) mod frame_pacer;
 pub(crate) mod sync;
 pub(crate) mod frame_buffers;
 pub(crate) mod gpu_buffer;
@@ -19,11 +28,24 @@ pub(crate) mod descriptor_pool;
 pub(crate) mod descriptor_set_layout;
 pub(crate) mod texture;
 pub(crate) mod sampler;
+pub(crate) mod bindless;
+pub(crate) mod reflection;
+pub(crate) mod hdr;
+pub(crate) mod tone_map;
+#[cfg(feature = "fsr1")]
+pub(crate) mod fsr1;
+#[cfg(feature = "fsr2")]
+pub(crate) mod temporal_upscale;
+pub(crate) mod attachment;
+pub(crate) mod transient;
+pub(crate) mod present;
 
 pub use utils::*;
+pub use validation::*;
 pub use app::*;
 pub use instance::*;
 pub use device::*;
+pub use features::*;
 pub use surface::*;
 pub use queue::*;
 pub use phys_device::*;
@@ -33,6 +55,13 @@ pub use render_pass::*;
 pub use swapchain::*;
 pub use command_pool::*;
 pub use pipeline::*;
+pub use compute_pipeline::*;
+pub use pipeline_cache::*;
+pub use pipeline_registry::*;
+pub use dynamic_rendering::*;
+pub use deletion_queue::*;
+pub use resource::*;
+pub use frame_pacer::*;
 pub use sync::*;
 pub use frame_buffers::*;
 pub use gpu_buffer::*;
@@ -40,3 +69,14 @@ pub use descriptor_pool::*;
 pub use descriptor_set_layout::*;
 pub use texture::*;
 pub use sampler::*;
+pub use bindless::*;
+pub use reflection::*;
+pub use hdr::*;
+pub use tone_map::*;
+#[cfg(feature = "fsr1")]
+pub use fsr1::*;
+#[cfg(feature = "fsr2")]
+pub use temporal_upscale::*;
+pub use attachment::*;
+pub use transient::*;
+pub use present::*;
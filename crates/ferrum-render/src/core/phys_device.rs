@@ -13,9 +13,7 @@ use crate::errors::*;
 ///
 pub struct PhysicalDevice {
     pub raw: ash::vk::PhysicalDevice,
-    pub phys_info: PhysicalDeviceInfo,
-    #[cfg(debug_assertions)]
-    pub destroyed: bool
+    pub phys_info: PhysicalDeviceInfo
 }
 
 #[derive(Default, Clone)]
@@ -26,7 +24,9 @@ pub struct PhysicalDeviceInfo {
     pub queue_family_prop: QueueFamilyProperties,
     pub extensions: Vec<ExtensionProperties>,
     pub layers: Vec<LayerProperties>,
-    pub support_surface: bool
+    pub support_surface: bool,
+    /// Extensions with core and `Vulkan11/12/13` features, for [`crate::RequiredFeatures`] checks
+    pub supported_features: crate::FeatureSet
 }
 
 
@@ -95,6 +95,7 @@ impl<'n> PhysicalDeviceBuilder<'n> {
         let surface = self.surface.unwrap();
 
         let support = Self::check_support_surface(&phys_dev, surface, surface_load, queue_prop_len);
+        let supported_features = crate::FeatureSet::query(instance, *phys_dev, api_version.min(phys_prop.api_version))?;
 
         Ok(PhysicalDeviceInfo{
             phys_prop,
@@ -103,7 +104,8 @@ impl<'n> PhysicalDeviceBuilder<'n> {
             features,
             extensions,
             layers,
-            support_surface: support
+            support_surface: support,
+            supported_features
         })
 
     }
@@ -251,9 +253,7 @@ impl<'n> PhysicalDeviceBuilder<'n> {
 
         Ok(PhysicalDevice {
             raw: phys_dev,
-            phys_info: phys_info.clone(),
-            #[cfg(debug_assertions)]
-            destroyed: false
+            phys_info: phys_info.clone()
         })
     }
 }
@@ -371,12 +371,3 @@ mod tests {
 
     }
 }
-
-#[cfg(debug_assertions)]
-impl Drop for PhysicalDevice {
-    fn drop(&mut self) {
-        if !self.destroyed {
-            log::warn!("Physical Device is don't destroyed, before drop")
-        }
-    }
-}
\ No newline at end of file
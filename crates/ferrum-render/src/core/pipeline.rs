@@ -1,33 +1,136 @@
+use std::ffi::CStr;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+
 use ash::vk::{self, *};
 
+use crate::{has_stencil, DeferredResource, DeviceHandle, ResourceOwner, PipelineError, ShaderProgram, VulkanError, VulkanResult};
+
+/// Common color blend setups
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// Blending disabled
+    #[default]
+    Opaque,
+    /// `src * a + dst * (1 - a)`
+    AlphaBlend,
+    /// `src + dst * (1 - a)`, color already multiplied by alpha
+    PremultipliedAlpha,
+    /// `src * a + dst`
+    Additive,
+    /// `src * dst`
+    Multiply
+}
+
+impl BlendMode {
+
+    pub fn attachment_state(self) -> PipelineColorBlendAttachmentState {
+
+        let state = PipelineColorBlendAttachmentState::default()
+            .color_write_mask(ColorComponentFlags::RGBA)
+            .color_blend_op(BlendOp::ADD)
+            .alpha_blend_op(BlendOp::ADD);
+
+        let (src_color, dst_color, src_alpha, dst_alpha) = match self {
+            BlendMode::Opaque => return state
+                .blend_enable(false)
+                .src_color_blend_factor(BlendFactor::ONE)
+                .dst_color_blend_factor(BlendFactor::ZERO)
+                .src_alpha_blend_factor(BlendFactor::ONE)
+                .dst_alpha_blend_factor(BlendFactor::ZERO),
+            BlendMode::AlphaBlend => (BlendFactor::SRC_ALPHA, BlendFactor::ONE_MINUS_SRC_ALPHA, BlendFactor::ONE, BlendFactor::ONE_MINUS_SRC_ALPHA),
+            BlendMode::PremultipliedAlpha => (BlendFactor::ONE, BlendFactor::ONE_MINUS_SRC_ALPHA, BlendFactor::ONE, BlendFactor::ONE_MINUS_SRC_ALPHA),
+            BlendMode::Additive => (BlendFactor::SRC_ALPHA, BlendFactor::ONE, BlendFactor::ONE, BlendFactor::ONE),
+            BlendMode::Multiply => (BlendFactor::DST_COLOR, BlendFactor::ZERO, BlendFactor::DST_ALPHA, BlendFactor::ZERO),
+        };
+
+        state
+            .blend_enable(true)
+            .src_color_blend_factor(src_color)
+            .dst_color_blend_factor(dst_color)
+            .src_alpha_blend_factor(src_alpha)
+            .dst_alpha_blend_factor(dst_alpha)
+    }
+}
+
+/// Depth bias, usually for shadow map passes
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub slope_factor: f32,
+    pub clamp: f32
+}
+
+/// Hashes the state that goes into [`PipelineLayoutCreateInfo`]
+pub(crate) fn hash_layout(hasher: &mut impl Hasher, set_layouts: Option<&[DescriptorSetLayout]>, ranges: Option<&[PushConstantRange]>) {
+
+    set_layouts.unwrap_or(&[]).iter().map(|x| x.as_raw()).collect::<Vec<_>>().hash(hasher);
+
+    for x in ranges.unwrap_or(&[]) {
+        (x.stage_flags.as_raw(), x.offset, x.size).hash(hasher);
+    }
+}
+
+pub(crate) unsafe fn raw_slice<'a, T>(ptr: *const T, len: u32) -> &'a [T] {
+    if ptr.is_null() || len == 0 {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(ptr, len as usize) }
+    }
+}
+
+/// Clones share the handles, they are queued for destruction with the last clone
+#[derive(Clone)]
 pub struct RenderPipeline {
     pub raw: Pipeline,
-    pub raw_layout: PipelineLayout
+    pub raw_layout: PipelineLayout,
+    _owner: Arc<ResourceOwner>
 }
 
 #[derive(Default)]
 pub struct RenderPipelineBuilder<'n> {
-    device: Option<&'n ash::Device>,
+    device: Option<&'n DeviceHandle>,
     #[allow(dead_code)]
     shader_state_infos: Option<PipelineShaderStageCreateInfo<'n>>,
     input_assembly_info: Option<PipelineInputAssemblyStateCreateInfo<'n>>,
-    #[allow(dead_code)]
-    multisampling_info: Option<PipelineMultisampleStateCreateInfo<'n>>,
     vertex_shader: Option<ShaderModule>,
     fragment_shader: Option<ShaderModule>,
+    vertex_entry_point: Option<&'n CStr>,
+    fragment_entry_point: Option<&'n CStr>,
+    vertex_hash: Option<u64>,
+    fragment_hash: Option<u64>,
     #[allow(dead_code)]
     viewports: Option<bool>,
     #[allow(dead_code)]
     scissors: Option<bool>,
-    #[allow(dead_code)]
-    color_blend_attachment_state: Option<PipelineColorBlendAttachmentState>,
-    #[allow(dead_code)]
-    color_blending_info: Option<PipelineColorBlendStateCreateInfo<'n>>,
+    color_blend_attachments: Vec<PipelineColorBlendAttachmentState>,
+    blend_constants: Option<[f32; 4]>,
+    samples: Option<SampleCountFlags>,
+    min_sample_shading: Option<f32>,
+    alpha_to_coverage: Option<bool>,
+    polygon_mode: Option<PolygonMode>,
+    cull_mode: Option<CullModeFlags>,
+    front_face: Option<FrontFace>,
+    line_width: Option<f32>,
+    depth_clamp: Option<bool>,
+    depth_bias: Option<DepthBias>,
+    depth_test: Option<bool>,
+    depth_write: Option<bool>,
+    depth_compare_op: Option<CompareOp>,
+    stencil: Option<(StencilOpState, StencilOpState)>,
+    dynamic_states: Vec<DynamicState>,
+    subpass: Option<u32>,
     vertex_input_info: Option<PipelineVertexInputStateCreateInfo<'n>>,
     resolution: Option<Extent2D>,
     format: Option<Format>,
-    render_pass: Option<&'n RenderPass>,
-    descriptor_set_layout: Option<&'n [DescriptorSetLayout]>
+    render_pass: Option<&'n crate::RenderPass>,
+    descriptor_set_layout: Option<&'n [DescriptorSetLayout]>,
+    push_constant_ranges: Option<&'n [PushConstantRange]>,
+    pipeline_cache: Option<PipelineCache>,
+    color_formats: Option<&'n [Format]>,
+    depth_format: Option<Format>,
+    stencil_format: Option<Format>,
+    depth_only: Option<bool>
 }
 
 impl<'n> RenderPipelineBuilder<'n> {
@@ -35,7 +138,9 @@ impl<'n> RenderPipelineBuilder<'n> {
         Self { ..Default::default() }
     }
 
-    pub fn with_render_pass(mut self, pass: &'n RenderPass) -> Self {
+    /// Without render pass the pipeline is created for dynamic rendering,
+    /// see [`Self::with_color_formats`]
+    pub fn with_render_pass(mut self, pass: &'n crate::RenderPass) -> Self {
         self.render_pass = Some(pass);
         self
     }
@@ -45,13 +150,100 @@ impl<'n> RenderPipelineBuilder<'n> {
         self
     }
 
+    pub fn with_push_constant_ranges(mut self, ranges: &'n [PushConstantRange]) -> Self {
+        self.push_constant_ranges = Some(ranges);
+        self
+    }
+
+    pub fn with_pipeline_cache(mut self, cache: PipelineCache) -> Self {
+        self.pipeline_cache = Some(cache);
+        self
+    }
+
+    /// Color attachment formats for dynamic rendering (no render pass).
+    /// Without it the single [`Self::with_format`] format is used
+    pub fn with_color_formats(mut self, formats: &'n [Format]) -> Self {
+        self.color_formats = Some(formats);
+        self
+    }
+
+    /// Depth attachment format for dynamic rendering
+    pub fn with_depth_format(mut self, format: Format) -> Self {
+        self.depth_format = Some(format);
+        self
+    }
+
+    /// Stencil attachment format for dynamic rendering
+    pub fn with_stencil_format(mut self, format: Format) -> Self {
+        self.stencil_format = Some(format);
+        self
+    }
+
+    /// Depth format for dynamic rendering, also the stencil format when `format` has a stencil aspect
+    pub fn with_depth_stencil_format(mut self, format: Format) -> Self {
+        self.depth_format = Some(format);
+        if has_stencil(format) {
+            self.stencil_format = Some(format);
+        }
+        self
+    }
+
+    ///
+    /// No colour attachments and an optional fragment shader, for shadow maps and depth prepasses.
+    /// Use with [`Self::with_depth_format`] or a render pass without colour attachments
+    ///
+    /// # Example
+    /// ```ignore
+    /// let shadow = RenderPipelineBuilder::new()
+    ///     .with_depth_only()
+    ///     .with_vertex_shader(shader.vertex_shader)
+    ///     .with_depth_format(Format::D32_SFLOAT)
+    ///     .with_depth_bias(DepthBias { constant_factor: 1.25, slope_factor: 1.75, clamp: 0.0 })
+    ///     ...
+    /// ```
+    ///
+    pub fn with_depth_only(mut self) -> Self {
+        self.depth_only = Some(true);
+        self
+    }
+
+    /// Without the SPIR-V hash the pipeline can not go through [`crate::PipelineRegistry`],
+    /// see [`Self::with_shader_program`]
     pub fn with_vertex_shader(mut self, shader: ShaderModule) -> Self {
         self.vertex_shader = Some(shader);
+        self.vertex_hash = None;
         self
     }
 
     pub fn with_fragment_shader(mut self, shader: ShaderModule) -> Self {
         self.fragment_shader = Some(shader);
+        self.fragment_hash = None;
+        self
+    }
+
+    /// Vertex and fragment stages of `program` with their entry points and SPIR-V hashes
+    pub fn with_shader_program(mut self, program: &'n ShaderProgram) -> Self {
+        self.vertex_shader = Some(program.vertex_shader);
+        self.vertex_entry_point = Some(&program.vertex_entry_point);
+        self.vertex_hash = Some(program.vertex_hash);
+
+        if program.fragment_shader != ShaderModule::null() {
+            self.fragment_shader = Some(program.fragment_shader);
+            self.fragment_entry_point = Some(&program.fragment_entry_point);
+            self.fragment_hash = Some(program.fragment_hash);
+        }
+        self
+    }
+
+    /// Default: `main`, see [`crate::ShaderProgram::vertex_entry_point`]
+    pub fn with_vertex_entry_point(mut self, name: &'n CStr) -> Self {
+        self.vertex_entry_point = Some(name);
+        self
+    }
+
+    /// Default: `main`
+    pub fn with_fragment_entry_point(mut self, name: &'n CStr) -> Self {
+        self.fragment_entry_point = Some(name);
         self
     }
 
@@ -83,104 +275,364 @@ impl<'n> RenderPipelineBuilder<'n> {
     //     self
     // }
 
-    pub fn with_device(mut self, dev: &'n ash::Device) -> Self {
+    pub fn with_device(mut self, dev: &'n DeviceHandle) -> Self {
         self.device = Some(dev);
         self
     }
 
-    pub fn build(self) -> RenderPipeline {
+    /// Adds a color attachment using a blend preset.
+    /// Without any attachments every colour format of the render pass subpass,
+    /// or of [`Self::with_color_formats`] / [`Self::with_format`], gets a [`BlendMode::Opaque`] one
+    pub fn add_color_attachment(mut self, mode: BlendMode) -> Self {
+        self.color_blend_attachments.push(mode.attachment_state());
+        self
+    }
 
-        let shader_states_infos = [
-            PipelineShaderStageCreateInfo::default()
-                .module(self.vertex_shader.unwrap())
-                .name(c"main")
-                .stage(ShaderStageFlags::VERTEX),
+    /// Adds a color attachment with a fully custom blend state
+    pub fn add_color_blend_attachment(mut self, state: PipelineColorBlendAttachmentState) -> Self {
+        self.color_blend_attachments.push(state);
+        self
+    }
+
+    pub fn with_blend_constants(mut self, constants: [f32; 4]) -> Self {
+        self.blend_constants = Some(constants);
+        self
+    }
+
+    /// Default: [`SampleCountFlags::TYPE_1`]
+    pub fn with_samples(mut self, samples: SampleCountFlags) -> Self {
+        self.samples = Some(samples);
+        self
+    }
+
+    /// Enables per-sample shading with the given minimum fraction
+    pub fn with_sample_shading(mut self, min_sample_shading: f32) -> Self {
+        self.min_sample_shading = Some(min_sample_shading);
+        self
+    }
+
+    pub fn with_alpha_to_coverage(mut self, enable: bool) -> Self {
+        self.alpha_to_coverage = Some(enable);
+        self
+    }
+
+    /// Default: [`PolygonMode::FILL`]
+    pub fn with_polygon_mode(mut self, mode: PolygonMode) -> Self {
+        self.polygon_mode = Some(mode);
+        self
+    }
+
+    /// Requires `fillModeNonSolid` device feature
+    pub fn with_wireframe(self) -> Self {
+        self.with_polygon_mode(PolygonMode::LINE)
+    }
+
+    /// Default: [`CullModeFlags::BACK`]
+    pub fn with_cull_mode(mut self, mode: CullModeFlags) -> Self {
+        self.cull_mode = Some(mode);
+        self
+    }
 
+    /// Default: [`FrontFace::COUNTER_CLOCKWISE`]
+    pub fn with_front_face(mut self, front_face: FrontFace) -> Self {
+        self.front_face = Some(front_face);
+        self
+    }
+
+    pub fn with_line_width(mut self, width: f32) -> Self {
+        self.line_width = Some(width);
+        self
+    }
+
+    /// Requires `depthClamp` device feature
+    pub fn with_depth_clamp(mut self, enable: bool) -> Self {
+        self.depth_clamp = Some(enable);
+        self
+    }
+
+    pub fn with_depth_bias(mut self, bias: DepthBias) -> Self {
+        self.depth_bias = Some(bias);
+        self
+    }
+
+    /// Default: true
+    pub fn with_depth_test(mut self, enable: bool) -> Self {
+        self.depth_test = Some(enable);
+        self
+    }
+
+    /// Default: true
+    pub fn with_depth_write(mut self, enable: bool) -> Self {
+        self.depth_write = Some(enable);
+        self
+    }
+
+    /// Default: [`CompareOp::LESS`]
+    pub fn with_depth_compare_op(mut self, op: CompareOp) -> Self {
+        self.depth_compare_op = Some(op);
+        self
+    }
+
+    /// Enables stencil test
+    pub fn with_stencil(mut self, front: StencilOpState, back: StencilOpState) -> Self {
+        self.stencil = Some((front, back));
+        self
+    }
+
+    /// VIEWPORT and SCISSOR are always dynamic
+    pub fn add_dynamic_state(mut self, state: DynamicState) -> Self {
+        if !self.dynamic_states.contains(&state) {
+            self.dynamic_states.push(state);
+        }
+        self
+    }
+
+    /// Default: 0
+    pub fn with_subpass(mut self, subpass: u32) -> Self {
+        self.subpass = Some(subpass);
+        self
+    }
+
+    ///
+    /// Hash of everything that affects the created pipeline, used by [`crate::PipelineRegistry`].
+    /// Shaders and the render pass are identified by their contents, handles can be reused after destruction.
+    /// Viewport size is dynamic state and is not part of the key.
+    /// `None` if a shader module was set without its SPIR-V hash
+    ///
+    pub fn hash_key(&self) -> Option<u64> {
+
+        if self.vertex_shader.is_some() && self.vertex_hash.is_none() || self.fragment_shader.is_some() && self.fragment_hash.is_none() {
+            return None;
+        }
+
+        let mut hasher = DefaultHasher::new();
+
+        self.vertex_hash.hash(&mut hasher);
+        self.fragment_hash.hash(&mut hasher);
+        self.vertex_entry_point.hash(&mut hasher);
+        self.fragment_entry_point.hash(&mut hasher);
+        self.render_pass.map(|x| x.desc_hash).hash(&mut hasher);
+        self.subpass.hash(&mut hasher);
+        self.format.map(|x| x.as_raw()).hash(&mut hasher);
+        self.color_formats.map(|x| x.iter().map(|x| x.as_raw()).collect::<Vec<_>>()).hash(&mut hasher);
+        self.depth_format.map(|x| x.as_raw()).hash(&mut hasher);
+        self.stencil_format.map(|x| x.as_raw()).hash(&mut hasher);
+        self.depth_only.hash(&mut hasher);
+
+        if let Some(info) = &self.vertex_input_info {
+            let bindings = unsafe { raw_slice(info.p_vertex_binding_descriptions, info.vertex_binding_description_count) };
+            let attributes = unsafe { raw_slice(info.p_vertex_attribute_descriptions, info.vertex_attribute_description_count) };
+
+            for x in bindings {
+                (x.binding, x.stride, x.input_rate.as_raw()).hash(&mut hasher);
+            }
+
+            for x in attributes {
+                (x.location, x.binding, x.format.as_raw(), x.offset).hash(&mut hasher);
+            }
+        }
+
+        if let Some(info) = &self.input_assembly_info {
+            (info.topology.as_raw(), info.primitive_restart_enable).hash(&mut hasher);
+        }
+
+        for x in &self.color_blend_attachments {
+            (
+                x.blend_enable,
+                x.src_color_blend_factor.as_raw(),
+                x.dst_color_blend_factor.as_raw(),
+                x.color_blend_op.as_raw(),
+                x.src_alpha_blend_factor.as_raw(),
+                x.dst_alpha_blend_factor.as_raw(),
+                x.alpha_blend_op.as_raw(),
+                x.color_write_mask.as_raw()
+            ).hash(&mut hasher);
+        }
+
+        self.blend_constants.map(|x| x.map(f32::to_bits)).hash(&mut hasher);
+        self.samples.map(|x| x.as_raw()).hash(&mut hasher);
+        self.min_sample_shading.map(f32::to_bits).hash(&mut hasher);
+        self.alpha_to_coverage.hash(&mut hasher);
+        self.polygon_mode.map(|x| x.as_raw()).hash(&mut hasher);
+        self.cull_mode.map(|x| x.as_raw()).hash(&mut hasher);
+        self.front_face.map(|x| x.as_raw()).hash(&mut hasher);
+        self.line_width.map(f32::to_bits).hash(&mut hasher);
+        self.depth_clamp.hash(&mut hasher);
+        self.depth_bias.map(|x| (x.constant_factor.to_bits(), x.slope_factor.to_bits(), x.clamp.to_bits())).hash(&mut hasher);
+        self.depth_test.hash(&mut hasher);
+        self.depth_write.hash(&mut hasher);
+        self.depth_compare_op.map(|x| x.as_raw()).hash(&mut hasher);
+
+        if let Some((front, back)) = &self.stencil {
+            for x in [front, back] {
+                (
+                    x.fail_op.as_raw(),
+                    x.pass_op.as_raw(),
+                    x.depth_fail_op.as_raw(),
+                    x.compare_op.as_raw(),
+                    x.compare_mask,
+                    x.write_mask,
+                    x.reference
+                ).hash(&mut hasher);
+            }
+        }
+
+        self.dynamic_states.iter().map(|x| x.as_raw()).collect::<Vec<_>>().hash(&mut hasher);
+        hash_layout(&mut hasher, self.descriptor_set_layout, self.push_constant_ranges);
+
+        Some(hasher.finish())
+    }
+
+    /// Explicit attachments, otherwise one opaque per colour attachment of the subpass or per dynamic rendering format
+    fn color_blend_states(&self, dynamic_formats: usize) -> Vec<PipelineColorBlendAttachmentState> {
+
+        if self.depth_only.unwrap_or(false) {
+            return vec![];
+        }
+
+        if !self.color_blend_attachments.is_empty() {
+            return self.color_blend_attachments.clone();
+        }
+
+        let count = match self.render_pass {
+            Some(pass) => pass.color_attachment_counts.get(self.subpass.unwrap_or(0) as usize).copied().unwrap_or(0) as usize,
+            None => dynamic_formats
+        };
+
+        vec![BlendMode::Opaque.attachment_state(); count]
+    }
+
+    ///
+    /// Missing device, shaders, input assembly or resolution are reported as [`VulkanError::MissingField`].
+    /// The fragment shader is optional with [`Self::with_depth_only`]
+    ///
+    pub fn build(self) -> VulkanResult<RenderPipeline> {
+
+        let missing = |field| VulkanError::missing("RenderPipelineBuilder", field);
+        let depth_only = self.depth_only.unwrap_or(false);
+
+        let device = self.device.ok_or_else(|| missing("device"))?;
+        let vertex_shader = self.vertex_shader.ok_or_else(|| missing("vertex shader"))?;
+        let fragment_shader = match (self.fragment_shader, depth_only) {
+            (None, false) => return Err(missing("fragment shader")),
+            (shader, _) => shader
+        };
+        let input_assembly_info = self.input_assembly_info.ok_or_else(|| missing("input assembly"))?;
+        let resolution = self.resolution.ok_or_else(|| missing("resolution"))?;
+
+        let mut shader_states_infos = vec![
             PipelineShaderStageCreateInfo::default()
-                .module(self.fragment_shader.unwrap())
-                .name(c"main")
-                .stage(ShaderStageFlags::FRAGMENT),
+                .module(vertex_shader)
+                .name(self.vertex_entry_point.unwrap_or(c"main"))
+                .stage(ShaderStageFlags::VERTEX),
         ];
 
+        // Для альфа-теста в тенях фрагментный шейдер всё ещё нужен
+        if let Some(fragment_shader) = fragment_shader {
+            shader_states_infos.push(PipelineShaderStageCreateInfo::default()
+                .module(fragment_shader)
+                .name(self.fragment_entry_point.unwrap_or(c"main"))
+                .stage(ShaderStageFlags::FRAGMENT));
+        }
+
         let vertex_input_info = self.vertex_input_info.unwrap_or(PipelineVertexInputStateCreateInfo::default());
-        let input_assembly_info = self.input_assembly_info.unwrap();
 
         let viewports = [Viewport {
             x: 0.0,
             y: 0.0,
-            width: self.resolution.unwrap().width as _,
-            height: self.resolution.unwrap().height as _,
+            width: resolution.width as _,
+            height: resolution.height as _,
             min_depth: 0.0,
             max_depth: 1.0,
         }];
 
         let scissors = [Rect2D {
             offset: Offset2D { x: 0, y: 0 },
-            extent: self.resolution.unwrap(),
+            extent: resolution,
         }];
 
         let viewport_info = PipelineViewportStateCreateInfo::default()
             .viewports(&viewports)
             .scissors(&scissors);
 
+        let depth_bias = self.depth_bias.unwrap_or_default();
+
         let rasterizer_info = PipelineRasterizationStateCreateInfo::default()
-            .depth_clamp_enable(false)
+            .depth_clamp_enable(self.depth_clamp.unwrap_or(false))
             .rasterizer_discard_enable(false)
-            .polygon_mode(PolygonMode::FILL)
-            .line_width(1.0)
-            .cull_mode(CullModeFlags::BACK)
-            .front_face(FrontFace::COUNTER_CLOCKWISE)
-            .depth_bias_enable(false)
-            .depth_bias_constant_factor(0.0)
-            .depth_bias_clamp(0.0)
-            .depth_bias_slope_factor(0.0);
+            .polygon_mode(self.polygon_mode.unwrap_or(PolygonMode::FILL))
+            .line_width(self.line_width.unwrap_or(1.0))
+            .cull_mode(self.cull_mode.unwrap_or(CullModeFlags::BACK))
+            .front_face(self.front_face.unwrap_or(FrontFace::COUNTER_CLOCKWISE))
+            .depth_bias_enable(self.depth_bias.is_some())
+            .depth_bias_constant_factor(depth_bias.constant_factor)
+            .depth_bias_clamp(depth_bias.clamp)
+            .depth_bias_slope_factor(depth_bias.slope_factor);
+
+        let (stencil_front, stencil_back) = self.stencil.unwrap_or_default();
 
         let depth_stencil_info = vk::PipelineDepthStencilStateCreateInfo::default()
-            .depth_test_enable(true)
-            .depth_write_enable(true)
-            .depth_compare_op(vk::CompareOp::LESS);
+            .depth_test_enable(self.depth_test.unwrap_or(true))
+            .depth_write_enable(self.depth_write.unwrap_or(true))
+            .depth_compare_op(self.depth_compare_op.unwrap_or(vk::CompareOp::LESS))
+            .stencil_test_enable(self.stencil.is_some())
+            .front(stencil_front)
+            .back(stencil_back)
+            .min_depth_bounds(0.0)
+            .max_depth_bounds(1.0);
 
         let multisampling_info = PipelineMultisampleStateCreateInfo::default()
-            .sample_shading_enable(false)
-            .rasterization_samples(SampleCountFlags::TYPE_1)
-            .min_sample_shading(1.0)
-            .alpha_to_coverage_enable(false)
+            .sample_shading_enable(self.min_sample_shading.is_some())
+            .rasterization_samples(self.samples.unwrap_or(SampleCountFlags::TYPE_1))
+            .min_sample_shading(self.min_sample_shading.unwrap_or(1.0))
+            .alpha_to_coverage_enable(self.alpha_to_coverage.unwrap_or(false))
             .alpha_to_one_enable(false);
 
-        let color_blend_attachments = [
-            PipelineColorBlendAttachmentState::default()
-            .color_write_mask(ColorComponentFlags::RGBA)
-            .blend_enable(false)
-            .src_color_blend_factor(BlendFactor::ONE)
-            .dst_color_blend_factor(BlendFactor::ZERO)
-            .color_blend_op(BlendOp::ADD)
-            .src_alpha_blend_factor(BlendFactor::ONE)
-            .dst_alpha_blend_factor(BlendFactor::ZERO)
-            .alpha_blend_op(BlendOp::ADD)
-        ];
+        let single_format = self.format.filter(|_| !depth_only).map(|x| vec![x]).unwrap_or_default();
+        let color_formats = match depth_only {
+            true => &[],
+            false => self.color_formats.unwrap_or(&single_format)
+        };
+
+        let color_blend_attachments = self.color_blend_states(color_formats.len());
 
         let color_blending_info = PipelineColorBlendStateCreateInfo::default()
             .logic_op_enable(false)
             .logic_op(LogicOp::COPY)
             .attachments(&color_blend_attachments)
-            .blend_constants([0.0, 0.0, 0.0, 0.0]);
+            .blend_constants(self.blend_constants.unwrap_or([0.0, 0.0, 0.0, 0.0]));
 
         let binding = self.descriptor_set_layout.unwrap_or(&[]);
 
+        let push_constant_ranges = self.push_constant_ranges.unwrap_or(&[]);
+
         let layout_info = PipelineLayoutCreateInfo::default()
-            .set_layouts(&binding);
+            .set_layouts(&binding)
+            .push_constant_ranges(push_constant_ranges);
 
-        let pipeline_layout = unsafe { self.device.unwrap().create_pipeline_layout(&layout_info, None).unwrap() };
+        let pipeline_layout = unsafe { device.create_pipeline_layout(&layout_info, None) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreatePipelineLayoutFailed(e))))?;
 
-        let dynamic_states = [
+        let mut dynamic_states = vec![
             vk::DynamicState::VIEWPORT,
             vk::DynamicState::SCISSOR
         ];
 
+        for state in &self.dynamic_states {
+            if !dynamic_states.contains(state) {
+                dynamic_states.push(*state);
+            }
+        }
+
         let dynamic_state_info = vk::PipelineDynamicStateCreateInfo::default()
             .dynamic_states(&dynamic_states);
 
-        let pipeline_info = GraphicsPipelineCreateInfo::default()
+        let mut rendering_info = PipelineRenderingCreateInfo::default()
+            .color_attachment_formats(color_formats)
+            .depth_attachment_format(self.depth_format.unwrap_or(Format::UNDEFINED))
+            .stencil_attachment_format(self.stencil_format.unwrap_or(Format::UNDEFINED));
+
+        let mut pipeline_info = GraphicsPipelineCreateInfo::default()
             .stages(&shader_states_infos)
             .vertex_input_state(&vertex_input_info)
             .input_assembly_state(&input_assembly_info)
@@ -190,20 +642,138 @@ impl<'n> RenderPipelineBuilder<'n> {
             .color_blend_state(&color_blending_info)
             .depth_stencil_state(&depth_stencil_info) // <--- ЭТА СТРОКА ОБЯЗАТЕЛЬНА!
             .layout(pipeline_layout)
-            .render_pass(*self.render_pass.unwrap())
             .dynamic_state(&dynamic_state_info);
 
+        // Without render pass the pipeline is used with dynamic rendering
+        pipeline_info = match self.render_pass {
+            Some(render_pass) => pipeline_info
+                .render_pass(render_pass.raw)
+                .subpass(self.subpass.unwrap_or(0)),
+            None => pipeline_info.push_next(&mut rendering_info)
+        };
+
         let pipeline = unsafe {
-            self.device.unwrap()
+            device
                 .create_graphics_pipelines(
-                    PipelineCache::null(),
+                    self.pipeline_cache.unwrap_or(PipelineCache::null()),
                     std::slice::from_ref(&pipeline_info),
                     None,
                 )
                 .map_err(|e| e.1)
         };
 
-        RenderPipeline { raw: pipeline.unwrap()[0], raw_layout: pipeline_layout }
+        match pipeline {
+            Ok(pipeline) => Ok(RenderPipeline {
+                raw: pipeline[0],
+                raw_layout: pipeline_layout,
+                _owner: Arc::new(ResourceOwner::new(device, vec![
+                    DeferredResource::Pipeline(pipeline[0]),
+                    DeferredResource::PipelineLayout(pipeline_layout)
+                ]))
+            }),
+            Err(e) => {
+                unsafe { device.destroy_pipeline_layout(pipeline_layout, None) };
+                Err(VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreatePipelineFailed(e))))
+            }
+        }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blend_presets() {
+        let opaque = BlendMode::Opaque.attachment_state();
+        assert_eq!(opaque.blend_enable, vk::FALSE);
+        assert_eq!(opaque.color_write_mask, ColorComponentFlags::RGBA);
+
+        let alpha = BlendMode::AlphaBlend.attachment_state();
+        assert_eq!(alpha.blend_enable, vk::TRUE);
+        assert_eq!(alpha.src_color_blend_factor, BlendFactor::SRC_ALPHA);
+        assert_eq!(alpha.dst_color_blend_factor, BlendFactor::ONE_MINUS_SRC_ALPHA);
+
+        let additive = BlendMode::Additive.attachment_state();
+        assert_eq!(additive.dst_color_blend_factor, BlendFactor::ONE);
+    }
+
+    #[test]
+    fn test_builder_collects_state() {
+        let builder = RenderPipelineBuilder::new()
+            .add_color_attachment(BlendMode::Opaque)
+            .add_color_attachment(BlendMode::PremultipliedAlpha)
+            .add_dynamic_state(DynamicState::DEPTH_BIAS)
+            .add_dynamic_state(DynamicState::DEPTH_BIAS)
+            .with_wireframe();
+
+        assert_eq!(builder.color_blend_attachments.len(), 2);
+        assert_eq!(builder.dynamic_states, vec![DynamicState::DEPTH_BIAS]);
+        assert_eq!(builder.polygon_mode, Some(PolygonMode::LINE));
+    }
+
+    #[test]
+    fn test_default_attachments_follow_color_formats() {
+        let formats = [Format::R8G8B8A8_UNORM, Format::R16G16B16A16_SFLOAT];
+
+        assert!(RenderPipelineBuilder::new().color_blend_states(0).is_empty());
+        assert_eq!(RenderPipelineBuilder::new().with_format(Format::B8G8R8A8_SRGB).color_blend_states(1).len(), 1);
+        assert_eq!(RenderPipelineBuilder::new().with_color_formats(&formats).color_blend_states(formats.len()).len(), 2);
+        assert!(RenderPipelineBuilder::new().with_depth_only().color_blend_states(0).is_empty());
+
+        let explicit = RenderPipelineBuilder::new().add_color_attachment(BlendMode::AlphaBlend).color_blend_states(0);
+        assert_eq!(explicit[0].blend_enable, vk::TRUE);
+    }
+
+    #[test]
+    fn test_hash_key_tracks_state() {
+        let base = RenderPipelineBuilder::new().add_color_attachment(BlendMode::Opaque);
+        let same = RenderPipelineBuilder::new().add_color_attachment(BlendMode::Opaque);
+        let blended = RenderPipelineBuilder::new().add_color_attachment(BlendMode::AlphaBlend);
+        let resized = RenderPipelineBuilder::new()
+            .add_color_attachment(BlendMode::Opaque)
+            .with_resolution(Extent2D { width: 1, height: 1 });
+        let entry_point = RenderPipelineBuilder::new()
+            .add_color_attachment(BlendMode::Opaque)
+            .with_fragment_entry_point(c"fs_main");
+
+        assert_eq!(base.hash_key(), same.hash_key());
+        assert_eq!(base.hash_key(), resized.hash_key());
+        assert_ne!(base.hash_key(), blended.hash_key());
+        assert_ne!(base.hash_key(), entry_point.hash_key());
+    }
+
+    #[test]
+    fn test_hash_key_needs_shader_content() {
+        let module = RenderPipelineBuilder::new().with_vertex_shader(ShaderModule::from_raw(1));
+        assert_eq!(module.hash_key(), None);
+
+        let mut a = RenderPipelineBuilder::new();
+        a.vertex_shader = Some(ShaderModule::from_raw(1));
+        a.vertex_hash = Some(7);
+
+        // Тот же SPIR-V в новом модуле даёт тот же ключ
+        let mut b = RenderPipelineBuilder::new();
+        b.vertex_shader = Some(ShaderModule::from_raw(2));
+        b.vertex_hash = Some(7);
+
+        assert_eq!(a.hash_key(), b.hash_key());
+        assert!(a.hash_key().is_some());
+    }
+
+    #[test]
+    fn test_depth_only_variant() {
+        let color = RenderPipelineBuilder::new().with_depth_format(Format::D32_SFLOAT);
+        let depth_only = RenderPipelineBuilder::new().with_depth_format(Format::D32_SFLOAT).with_depth_only();
+
+        assert_eq!(depth_only.depth_only, Some(true));
+        assert_ne!(color.hash_key(), depth_only.hash_key());
+    }
+
+    #[test]
+    fn test_build_reports_missing_field() {
+        let result = RenderPipelineBuilder::new().build();
+        assert!(matches!(result, Err(VulkanError::MissingField { builder: "RenderPipelineBuilder", field: "device" })));
+    }
+}
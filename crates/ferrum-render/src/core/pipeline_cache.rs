@@ -0,0 +1,216 @@
+use std::path::{Path, PathBuf};
+
+use ash::vk::{self, PhysicalDeviceProperties};
+use log::{info, warn};
+
+use crate::{PipelineCacheError, VulkanError, VulkanResult};
+
+const CACHE_FILE_MAGIC: [u8; 4] = *b"FRPC";
+const CACHE_FILE_VERSION: u32 = 1;
+const CACHE_FILE_HEADER_LEN: usize = 4 + 4 + 4 + 4 + 4 + vk::UUID_SIZE + 8;
+
+/// Identifies the device and driver a pipeline cache blob was produced by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineCacheKey {
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub driver_version: u32,
+    pub uuid: [u8; vk::UUID_SIZE]
+}
+
+impl PipelineCacheKey {
+    pub fn from_properties(props: &PhysicalDeviceProperties) -> Self {
+        Self {
+            vendor_id: props.vendor_id,
+            device_id: props.device_id,
+            driver_version: props.driver_version,
+            uuid: props.pipeline_cache_uuid
+        }
+    }
+}
+
+/// Prepends header with [`PipelineCacheKey`] to the driver data
+pub fn encode_pipeline_cache(key: &PipelineCacheKey, data: &[u8]) -> Vec<u8> {
+
+    let mut bytes = Vec::with_capacity(CACHE_FILE_HEADER_LEN + data.len());
+
+    bytes.extend_from_slice(&CACHE_FILE_MAGIC);
+    bytes.extend_from_slice(&CACHE_FILE_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&key.vendor_id.to_le_bytes());
+    bytes.extend_from_slice(&key.device_id.to_le_bytes());
+    bytes.extend_from_slice(&key.driver_version.to_le_bytes());
+    bytes.extend_from_slice(&key.uuid);
+    bytes.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(data);
+
+    bytes
+}
+
+/// Returns driver data if the file was written by the same device and driver
+pub fn decode_pipeline_cache<'a>(key: &PipelineCacheKey, bytes: &'a [u8]) -> Option<&'a [u8]> {
+
+    if bytes.len() < CACHE_FILE_HEADER_LEN || bytes[0..4] != CACHE_FILE_MAGIC {
+        return None;
+    }
+
+    let read_u32 = |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+
+    let stored = PipelineCacheKey {
+        vendor_id: read_u32(8),
+        device_id: read_u32(12),
+        driver_version: read_u32(16),
+        uuid: bytes[20..20 + vk::UUID_SIZE].try_into().unwrap()
+    };
+
+    if read_u32(4) != CACHE_FILE_VERSION || stored != *key {
+        return None;
+    }
+
+    let len_offset = 20 + vk::UUID_SIZE;
+    let len = u64::from_le_bytes(bytes[len_offset..len_offset + 8].try_into().unwrap()) as usize;
+    let data = &bytes[CACHE_FILE_HEADER_LEN..];
+
+    (data.len() == len).then_some(data)
+}
+
+///
+/// Wrapper around [`vk::PipelineCache`] persisted to disk
+///
+/// # Example
+/// ```
+/// let cache = PipelineCacheBuilder::new()
+///     .with_device(device.raw_device())
+///     .with_properties(&device.phys_dev.phys_info.phys_prop)
+///     .with_path("pipeline_cache.bin")
+///     .build()?;
+///
+/// // ... create pipelines with cache.raw ...
+///
+/// cache.save(device.raw_device())?;
+/// ```
+///
+pub struct PipelineCache {
+    pub raw: vk::PipelineCache,
+    pub key: PipelineCacheKey,
+    pub path: Option<PathBuf>
+}
+
+impl PipelineCache {
+
+    /// Writes cache data to [`Self::path`], does nothing without path
+    pub fn save(&self, device: &ash::Device) -> VulkanResult<()> {
+
+        let Some(path) = &self.path else {
+            return Ok(())
+        };
+
+        let data = unsafe { device.get_pipeline_cache_data(self.raw) }.map_err(|e| {
+            VulkanError::PipelineCache(PipelineCacheError::GetPipelineCacheDataFailed(e))
+        })?;
+
+        std::fs::write(path, encode_pipeline_cache(&self.key, &data)).map_err(|e| {
+            VulkanError::PipelineCache(PipelineCacheError::WriteFailed(e))
+        })?;
+
+        info!("Pipeline cache saved: {} bytes to {:?}", data.len(), path);
+        Ok(())
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe { device.destroy_pipeline_cache(self.raw, None) };
+    }
+}
+
+#[derive(Default)]
+pub struct PipelineCacheBuilder<'n> {
+    device: Option<&'n ash::Device>,
+    properties: Option<&'n PhysicalDeviceProperties>,
+    path: Option<PathBuf>
+}
+
+impl<'n> PipelineCacheBuilder<'n> {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with_device(mut self, device: &'n ash::Device) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    /// Device UUID and driver version are taken from here
+    pub fn with_properties(mut self, properties: &'n PhysicalDeviceProperties) -> Self {
+        self.properties = Some(properties);
+        self
+    }
+
+    /// File to load from and save to. Without it the cache lives only in memory
+    pub fn with_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    ///
+    /// A missing, corrupted or stale file is not an error, the cache starts empty
+    ///
+    /// # Panics
+    /// if device or properties is missing
+    ///
+    pub fn build(self) -> VulkanResult<PipelineCache> {
+
+        let device = self.device.ok_or(VulkanError::missing("PipelineCacheBuilder", "device"))?;
+        let key = PipelineCacheKey::from_properties(self.properties.ok_or(VulkanError::missing("PipelineCacheBuilder", "physical device properties"))?);
+
+        let file = self.path.as_ref().and_then(|path| std::fs::read(path).ok());
+
+        let initial_data = match &file {
+            Some(bytes) => {
+                let data = decode_pipeline_cache(&key, bytes);
+                if data.is_none() {
+                    warn!("Pipeline cache {:?} was created by another device or driver, ignored", self.path);
+                }
+                data.unwrap_or(&[])
+            },
+            None => &[]
+        };
+
+        let create_info = vk::PipelineCacheCreateInfo::default()
+            .initial_data(initial_data);
+
+        let raw = unsafe { device.create_pipeline_cache(&create_info, None) }.map_err(|e| {
+            VulkanError::PipelineCache(PipelineCacheError::CreatePipelineCacheFailed(e))
+        })?;
+
+        Ok(PipelineCache { raw, key, path: self.path })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> PipelineCacheKey {
+        PipelineCacheKey { vendor_id: 0x10de, device_id: 0x2484, driver_version: 42, uuid: [7; vk::UUID_SIZE] }
+    }
+
+    #[test]
+    fn test_cache_file_roundtrip() {
+        let bytes = encode_pipeline_cache(&key(), &[1, 2, 3]);
+        assert_eq!(decode_pipeline_cache(&key(), &bytes), Some(&[1u8, 2, 3][..]));
+    }
+
+    #[test]
+    fn test_cache_file_rejects_other_driver() {
+        let bytes = encode_pipeline_cache(&key(), &[1, 2, 3]);
+
+        let other_driver = PipelineCacheKey { driver_version: 43, ..key() };
+        assert_eq!(decode_pipeline_cache(&other_driver, &bytes), None);
+
+        let other_uuid = PipelineCacheKey { uuid: [0; vk::UUID_SIZE], ..key() };
+        assert_eq!(decode_pipeline_cache(&other_uuid, &bytes), None);
+
+        assert_eq!(decode_pipeline_cache(&key(), &bytes[..bytes.len() - 1]), None);
+        assert_eq!(decode_pipeline_cache(&key(), b"garbage"), None);
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+
+use ash::vk;
+
+use crate::{ComputePipeline, ComputePipelineBuilder, RenderPipeline, RenderPipelineBuilder, VulkanError, VulkanResult};
+
+///
+/// Pipelines keyed by the hash of their builder state, so the same
+/// state is compiled only once. New pipelines go through the pipeline cache.
+/// Shaders are keyed by their SPIR-V, pass them with `with_shader_program`
+///
+/// # Example
+/// ```ignore
+/// let pipeline = ctx.pipelines.render_pipeline(
+///     RenderPipelineBuilder::new()
+///         .with_device(ctx.device.raw_device())
+///         .with_shader_program(&shader)
+///         ...
+/// )?.clone();
+/// ```
+///
+#[derive(Default)]
+pub struct PipelineRegistry {
+    pub cache: vk::PipelineCache,
+    render: HashMap<u64, RenderPipeline>,
+    compute: HashMap<u64, ComputePipeline>,
+    hits: usize,
+    misses: usize
+}
+
+impl PipelineRegistry {
+
+    pub fn new(cache: vk::PipelineCache) -> Self {
+        Self { cache, ..Default::default() }
+    }
+
+    /// Fails with [`VulkanError::MissingField`] for shader modules without a SPIR-V hash
+    pub fn render_pipeline(&mut self, builder: RenderPipelineBuilder) -> VulkanResult<&RenderPipeline> {
+
+        let key = builder.hash_key().ok_or(VulkanError::missing("PipelineRegistry", "shader SPIR-V hash"))?;
+
+        if self.render.contains_key(&key) {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+            let pipeline = builder.with_pipeline_cache(self.cache).build()?;
+            self.render.insert(key, pipeline);
+        }
+
+        Ok(&self.render[&key])
+    }
+
+    /// Fails with [`VulkanError::MissingField`] for a shader module without a SPIR-V hash
+    pub fn compute_pipeline(&mut self, builder: ComputePipelineBuilder) -> VulkanResult<&ComputePipeline> {
+
+        let key = builder.hash_key().ok_or(VulkanError::missing("PipelineRegistry", "shader SPIR-V hash"))?;
+
+        if self.compute.contains_key(&key) {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+            let pipeline = builder.with_pipeline_cache(self.cache).build()?;
+            self.compute.insert(key, pipeline);
+        }
+
+        Ok(&self.compute[&key])
+    }
+
+    /// (reused, created)
+    pub fn stats(&self) -> (usize, usize) {
+        (self.hits, self.misses)
+    }
+
+    pub fn len(&self) -> usize {
+        self.render.len() + self.compute.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops all pipelines, they are destroyed once no clone is left
+    /// and the frames using them are done. The cache itself is not touched
+    pub fn clear(&mut self) {
+        self.render.clear();
+        self.compute.clear();
+    }
+}
@@ -0,0 +1,93 @@
+use ash::vk;
+
+/// Swapchain should be recreated after this acquire/present result
+pub fn needs_recreate(result: vk::Result) -> bool {
+    matches!(result, vk::Result::SUBOPTIMAL_KHR | vk::Result::ERROR_OUT_OF_DATE_KHR)
+}
+
+///
+/// Presents images of several swapchains with one `vkQueuePresentKHR`
+///
+/// # Example
+/// ```
+/// let mut batch = PresentBatch::new();
+/// batch.add(scene_view.swapchain.raw, scene_index, scene_finished);
+/// batch.add(game_view.swapchain.raw, game_index, game_finished);
+///
+/// for result in batch.present(&scene_view.swapchain.swapchain_load, queue) {
+///     // per swapchain, in insertion order
+/// }
+/// ```
+///
+#[derive(Default)]
+pub struct PresentBatch {
+    swapchains: Vec<vk::SwapchainKHR>,
+    image_indices: Vec<u32>,
+    wait_semaphores: Vec<vk::Semaphore>
+}
+
+impl PresentBatch {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    /// `wait` is signaled when rendering into the image is done
+    pub fn add(&mut self, swapchain: vk::SwapchainKHR, image_index: u32, wait: vk::Semaphore) {
+        self.swapchains.push(swapchain);
+        self.image_indices.push(image_index);
+        self.wait_semaphores.push(wait);
+    }
+
+    pub fn len(&self) -> usize {
+        self.swapchains.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.swapchains.is_empty()
+    }
+
+    /// Any swapchain loader of the device works. Returns one result per swapchain
+    pub fn present(&self, swapchain_load: &ash::khr::swapchain::Device, queue: vk::Queue) -> Vec<vk::Result> {
+
+        if self.is_empty() {
+            return vec![];
+        }
+
+        let mut results = vec![vk::Result::SUCCESS; self.len()];
+
+        let present_info = vk::PresentInfoKHR::default()
+            .wait_semaphores(&self.wait_semaphores)
+            .swapchains(&self.swapchains)
+            .image_indices(&self.image_indices)
+            .results(&mut results);
+
+        // Общий результат дублирует худший из results
+        if let Err(err) = unsafe { swapchain_load.queue_present(queue, &present_info) } {
+            log::debug!("Batched present: {}", err);
+        }
+
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_batch_collects_swapchains() {
+        let mut batch = PresentBatch::new();
+        assert!(batch.is_empty());
+
+        batch.add(vk::SwapchainKHR::null(), 2, vk::Semaphore::null());
+        batch.add(vk::SwapchainKHR::null(), 0, vk::Semaphore::null());
+
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.image_indices, [2, 0]);
+
+        assert!(needs_recreate(vk::Result::SUBOPTIMAL_KHR));
+        assert!(needs_recreate(vk::Result::ERROR_OUT_OF_DATE_KHR));
+        assert!(!needs_recreate(vk::Result::SUCCESS));
+    }
+}
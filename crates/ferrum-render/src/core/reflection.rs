@@ -0,0 +1,976 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use ash::vk;
+
+use crate::{ReflectionError, VulkanError, VulkanResult};
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+const SPIRV_HEADER_LEN: usize = 5;
+/// Since 1.4 entry points list every global variable they use
+const SPIRV_VERSION_1_4: u32 = 0x0001_0400;
+
+// Opcodes
+const OP_NAME: u32 = 5;
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_FUNCTION: u32 = 54;
+const OP_FUNCTION_END: u32 = 56;
+const OP_FUNCTION_CALL: u32 = 57;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const OP_TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+
+// Decorations
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILTIN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+// Storage classes
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+// Execution modes
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const EXECUTION_MODE_LOCAL_SIZE_ID: u32 = 38;
+
+// Image dims
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Debug, Clone)]
+enum SpirvType {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { storage_class: u32, pointee: u32 },
+    AccelerationStructure
+}
+
+struct EntryPoint {
+    execution_model: u32,
+    id: u32,
+    name: String,
+    interface: Vec<u32>
+}
+
+#[derive(Default)]
+struct Module {
+    version: u32,
+    names: HashMap<u32, String>,
+    entry_points: Vec<EntryPoint>,
+    local_size: HashMap<u32, [u32; 3]>,
+    local_size_id: HashMap<u32, [u32; 3]>,
+    types: HashMap<u32, SpirvType>,
+    constants: HashMap<u32, u32>,
+    /// (id, storage class, pointer type)
+    variables: Vec<(u32, u32, u32)>,
+    decorations: HashMap<u32, HashMap<u32, u32>>,
+    member_decorations: HashMap<(u32, u32), HashMap<u32, u32>>,
+    /// Ids referenced by the instructions of each function, including literals
+    function_uses: HashMap<u32, HashSet<u32>>,
+    function_calls: HashMap<u32, Vec<u32>>,
+    current_function: Option<u32>
+}
+
+/// Operands read by [`Module::parse_instruction`], shorter instructions are invalid
+fn min_operands(opcode: u32) -> usize {
+    match opcode {
+        OP_TYPE_BOOL | OP_TYPE_SAMPLER | OP_TYPE_SAMPLED_IMAGE | OP_TYPE_STRUCT | OP_TYPE_ACCELERATION_STRUCTURE => 1,
+        OP_TYPE_FLOAT | OP_TYPE_RUNTIME_ARRAY => 2,
+        OP_TYPE_INT | OP_TYPE_VECTOR | OP_TYPE_MATRIX | OP_TYPE_ARRAY | OP_TYPE_POINTER | OP_FUNCTION_CALL => 3,
+        OP_TYPE_IMAGE => 7,
+        _ => 0
+    }
+}
+
+fn read_string(words: &[u32]) -> (String, usize) {
+
+    let mut bytes = vec![];
+    let mut used = 0;
+
+    'outer: for word in words {
+        used += 1;
+        for byte in word.to_le_bytes() {
+            if byte == 0 {
+                break 'outer;
+            }
+            bytes.push(byte);
+        }
+    }
+
+    (String::from_utf8_lossy(&bytes).into_owned(), used)
+}
+
+impl Module {
+
+    fn parse(code: &[u32]) -> Result<Self, ReflectionError> {
+
+        if code.len() < SPIRV_HEADER_LEN {
+            return Err(ReflectionError::InvalidSpirv("module is shorter than header"));
+        }
+
+        if code[0] != SPIRV_MAGIC {
+            return Err(ReflectionError::InvalidSpirv("wrong magic number"));
+        }
+
+        let mut module = Module { version: code[1], ..Default::default() };
+        let mut cursor = SPIRV_HEADER_LEN;
+
+        while cursor < code.len() {
+
+            let opcode = code[cursor] & 0xFFFF;
+            let count = (code[cursor] >> 16) as usize;
+
+            if count == 0 || cursor + count > code.len() {
+                return Err(ReflectionError::InvalidSpirv("instruction word count out of bounds"));
+            }
+
+            module.parse_instruction(opcode, &code[cursor + 1..cursor + count])?;
+            cursor += count;
+        }
+
+        Ok(module)
+    }
+
+    fn parse_instruction(&mut self, opcode: u32, ops: &[u32]) -> Result<(), ReflectionError> {
+
+        if ops.len() < min_operands(opcode) {
+            return Err(ReflectionError::InvalidSpirv("instruction has fewer operands than its opcode needs"));
+        }
+
+        if let Some(function) = self.current_function {
+            self.function_uses.entry(function).or_default().extend(ops);
+        }
+
+        match opcode {
+
+            OP_FUNCTION if ops.len() >= 2 => {
+                self.current_function = Some(ops[1]);
+            },
+
+            OP_FUNCTION_END => {
+                self.current_function = None;
+            },
+
+            OP_FUNCTION_CALL => {
+                if let Some(function) = self.current_function {
+                    self.function_calls.entry(function).or_default().push(ops[2]);
+                }
+            },
+
+            OP_NAME if !ops.is_empty() => {
+                self.names.insert(ops[0], read_string(&ops[1..]).0);
+            },
+
+            OP_ENTRY_POINT if ops.len() >= 3 => {
+                let (name, used) = read_string(&ops[2..]);
+                self.entry_points.push(EntryPoint {
+                    execution_model: ops[0],
+                    id: ops[1],
+                    name,
+                    interface: ops[2 + used..].to_vec()
+                });
+            },
+
+            OP_EXECUTION_MODE if ops.len() >= 5 => {
+                let size = [ops[2], ops[3], ops[4]];
+                match ops[1] {
+                    EXECUTION_MODE_LOCAL_SIZE => { self.local_size.insert(ops[0], size); },
+                    EXECUTION_MODE_LOCAL_SIZE_ID => { self.local_size_id.insert(ops[0], size); },
+                    _ => {}
+                }
+            },
+
+            OP_TYPE_BOOL => { self.types.insert(ops[0], SpirvType::Bool); },
+            OP_TYPE_INT => { self.types.insert(ops[0], SpirvType::Int { width: ops[1], signed: ops[2] == 1 }); },
+            OP_TYPE_FLOAT => { self.types.insert(ops[0], SpirvType::Float { width: ops[1] }); },
+            OP_TYPE_VECTOR => { self.types.insert(ops[0], SpirvType::Vector { component: ops[1], count: ops[2] }); },
+            OP_TYPE_MATRIX => { self.types.insert(ops[0], SpirvType::Matrix { column: ops[1], count: ops[2] }); },
+            OP_TYPE_IMAGE => { self.types.insert(ops[0], SpirvType::Image { dim: ops[2], sampled: ops[6] }); },
+            OP_TYPE_SAMPLER => { self.types.insert(ops[0], SpirvType::Sampler); },
+            OP_TYPE_SAMPLED_IMAGE => { self.types.insert(ops[0], SpirvType::SampledImage); },
+            OP_TYPE_ARRAY => { self.types.insert(ops[0], SpirvType::Array { element: ops[1], length: ops[2] }); },
+            OP_TYPE_RUNTIME_ARRAY => { self.types.insert(ops[0], SpirvType::RuntimeArray { element: ops[1] }); },
+            OP_TYPE_STRUCT => { self.types.insert(ops[0], SpirvType::Struct { members: ops[1..].to_vec() }); },
+            OP_TYPE_POINTER => { self.types.insert(ops[0], SpirvType::Pointer { storage_class: ops[1], pointee: ops[2] }); },
+            OP_TYPE_ACCELERATION_STRUCTURE => { self.types.insert(ops[0], SpirvType::AccelerationStructure); },
+
+            // Only the low word is needed: array lengths and workgroup sizes
+            OP_CONSTANT | OP_SPEC_CONSTANT if ops.len() >= 3 => {
+                self.constants.insert(ops[1], ops[2]);
+            },
+
+            OP_VARIABLE if ops.len() >= 3 => {
+                self.variables.push((ops[1], ops[2], ops[0]));
+            },
+
+            OP_DECORATE if ops.len() >= 2 => {
+                let value = ops.get(2).copied().unwrap_or(0);
+                self.decorations.entry(ops[0]).or_default().insert(ops[1], value);
+            },
+
+            OP_MEMBER_DECORATE if ops.len() >= 3 => {
+                let value = ops.get(3).copied().unwrap_or(0);
+                self.member_decorations.entry((ops[0], ops[1])).or_default().insert(ops[2], value);
+            },
+
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Global variables the entry point may access. SPIR-V 1.4+ lists them in the interface,
+    /// older modules only list Input/Output there, so the functions reachable from the entry are walked
+    ///
+    fn entry_globals(&self, entry: &EntryPoint) -> HashSet<u32> {
+
+        if self.version >= SPIRV_VERSION_1_4 {
+            return entry.interface.iter().copied().collect();
+        }
+
+        let mut used: HashSet<u32> = entry.interface.iter().copied().collect();
+        let mut visited = HashSet::new();
+        let mut stack = vec![entry.id];
+
+        while let Some(function) = stack.pop() {
+            if !visited.insert(function) {
+                continue;
+            }
+
+            used.extend(self.function_uses.get(&function).into_iter().flatten());
+            stack.extend(self.function_calls.get(&function).into_iter().flatten());
+        }
+
+        used
+    }
+
+    fn decoration(&self, id: u32, decoration: u32) -> Option<u32> {
+        self.decorations.get(&id).and_then(|x| x.get(&decoration)).copied()
+    }
+
+    fn member_decoration(&self, id: u32, member: u32, decoration: u32) -> Option<u32> {
+        self.member_decorations.get(&(id, member)).and_then(|x| x.get(&decoration)).copied()
+    }
+
+    fn name(&self, id: u32) -> String {
+        self.names.get(&id).cloned().unwrap_or_default()
+    }
+
+    /// Strips (possibly nested) arrays, returns element type and descriptor count.
+    /// Runtime arrays have count 0
+    fn unwrap_arrays(&self, mut ty: u32) -> (u32, u32) {
+
+        let mut count = 1;
+
+        loop {
+            match self.types.get(&ty) {
+                Some(SpirvType::Array { element, length }) => {
+                    count *= self.constants.get(length).copied().unwrap_or(1);
+                    ty = *element;
+                },
+                Some(SpirvType::RuntimeArray { element }) => {
+                    count = 0;
+                    ty = *element;
+                },
+                _ => return (ty, count)
+            }
+        }
+    }
+
+    fn descriptor_type(&self, storage_class: u32, ty: u32) -> Option<vk::DescriptorType> {
+
+        match (storage_class, self.types.get(&ty)?) {
+
+            (STORAGE_UNIFORM_CONSTANT, SpirvType::SampledImage) => Some(vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            (STORAGE_UNIFORM_CONSTANT, SpirvType::Sampler) => Some(vk::DescriptorType::SAMPLER),
+            (STORAGE_UNIFORM_CONSTANT, SpirvType::AccelerationStructure) => Some(vk::DescriptorType::ACCELERATION_STRUCTURE_KHR),
+
+            (STORAGE_UNIFORM_CONSTANT, SpirvType::Image { dim, sampled }) => {
+                Some(match (*dim, *sampled) {
+                    (DIM_SUBPASS_DATA, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                    (DIM_BUFFER, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                    (DIM_BUFFER, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                    (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                    _ => vk::DescriptorType::SAMPLED_IMAGE,
+                })
+            },
+
+            (STORAGE_UNIFORM, SpirvType::Struct { .. }) => {
+                if self.decoration(ty, DECORATION_BUFFER_BLOCK).is_some() {
+                    Some(vk::DescriptorType::STORAGE_BUFFER)
+                } else if self.decoration(ty, DECORATION_BLOCK).is_some() {
+                    Some(vk::DescriptorType::UNIFORM_BUFFER)
+                } else {
+                    None
+                }
+            },
+
+            (STORAGE_STORAGE_BUFFER, SpirvType::Struct { .. }) => Some(vk::DescriptorType::STORAGE_BUFFER),
+
+            _ => None
+        }
+    }
+
+    /// Size in bytes of a type laid out with explicit offsets/strides
+    fn type_size(&self, ty: u32) -> u32 {
+
+        match self.types.get(&ty) {
+            Some(SpirvType::Bool) => 4,
+            Some(SpirvType::Int { width, .. }) | Some(SpirvType::Float { width }) => width / 8,
+            Some(SpirvType::Vector { component, count }) => self.type_size(*component) * count,
+            Some(SpirvType::Matrix { column, count }) => self.type_size(*column) * count,
+            Some(SpirvType::Array { element, length }) => {
+                let length = self.constants.get(length).copied().unwrap_or(1);
+                let stride = self.decoration(ty, DECORATION_ARRAY_STRIDE).unwrap_or_else(|| self.type_size(*element));
+                stride * length
+            },
+            Some(SpirvType::Struct { members }) => {
+                members.iter().enumerate().map(|(index, member)| {
+                    let offset = self.member_decoration(ty, index as u32, DECORATION_OFFSET).unwrap_or(0);
+                    let size = match self.types.get(member) {
+                        Some(SpirvType::Matrix { count, .. }) => {
+                            match self.member_decoration(ty, index as u32, DECORATION_MATRIX_STRIDE) {
+                                Some(stride) => stride * count,
+                                None => self.type_size(*member)
+                            }
+                        },
+                        _ => self.type_size(*member)
+                    };
+                    offset + size
+                }).max().unwrap_or(0)
+            },
+            _ => 0
+        }
+    }
+
+    /// Lowest member offset of a push constant block
+    fn struct_base_offset(&self, ty: u32) -> u32 {
+        match self.types.get(&ty) {
+            Some(SpirvType::Struct { members }) => {
+                (0..members.len() as u32)
+                    .filter_map(|index| self.member_decoration(ty, index, DECORATION_OFFSET))
+                    .min()
+                    .unwrap_or(0)
+            },
+            _ => 0
+        }
+    }
+
+    ///
+    /// Vertex input format, the number of attributes (matrix columns) and the locations of each.
+    /// 64-bit three and four component vectors take two locations
+    ///
+    fn input_format(&self, ty: u32) -> Option<(vk::Format, u32, u32)> {
+
+        use vk::Format as F;
+
+        let scalar_format = |component: u32, count: u32| -> Option<vk::Format> {
+            let format = match (self.types.get(&component)?, count) {
+                (SpirvType::Float { width: 32 }, 1) => F::R32_SFLOAT,
+                (SpirvType::Float { width: 32 }, 2) => F::R32G32_SFLOAT,
+                (SpirvType::Float { width: 32 }, 3) => F::R32G32B32_SFLOAT,
+                (SpirvType::Float { width: 32 }, 4) => F::R32G32B32A32_SFLOAT,
+                (SpirvType::Float { width: 64 }, 1) => F::R64_SFLOAT,
+                (SpirvType::Float { width: 64 }, 2) => F::R64G64_SFLOAT,
+                (SpirvType::Float { width: 64 }, 3) => F::R64G64B64_SFLOAT,
+                (SpirvType::Float { width: 64 }, 4) => F::R64G64B64A64_SFLOAT,
+                (SpirvType::Int { width: 32, signed: true }, 1) => F::R32_SINT,
+                (SpirvType::Int { width: 32, signed: true }, 2) => F::R32G32_SINT,
+                (SpirvType::Int { width: 32, signed: true }, 3) => F::R32G32B32_SINT,
+                (SpirvType::Int { width: 32, signed: true }, 4) => F::R32G32B32A32_SINT,
+                (SpirvType::Int { width: 32, signed: false }, 1) => F::R32_UINT,
+                (SpirvType::Int { width: 32, signed: false }, 2) => F::R32G32_UINT,
+                (SpirvType::Int { width: 32, signed: false }, 3) => F::R32G32B32_UINT,
+                (SpirvType::Int { width: 32, signed: false }, 4) => F::R32G32B32A32_UINT,
+                _ => return None
+            };
+            Some(format)
+        };
+
+        match self.types.get(&ty)? {
+            SpirvType::Float { .. } | SpirvType::Int { .. } => Some((scalar_format(ty, 1)?, 1, 1)),
+            SpirvType::Vector { component, count } => {
+                let wide = matches!(self.types.get(component), Some(SpirvType::Float { width: 64 })) && *count > 2;
+                Some((scalar_format(*component, *count)?, 1, if wide { 2 } else { 1 }))
+            },
+            SpirvType::Matrix { column, count } => {
+                let (format, _, locations) = self.input_format(*column)?;
+                Some((format, *count, locations))
+            },
+            _ => None
+        }
+    }
+}
+
+fn stage_from_execution_model(model: u32) -> vk::ShaderStageFlags {
+    match model {
+        0 => vk::ShaderStageFlags::VERTEX,
+        1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        3 => vk::ShaderStageFlags::GEOMETRY,
+        4 => vk::ShaderStageFlags::FRAGMENT,
+        5 => vk::ShaderStageFlags::COMPUTE,
+        5267 | 5364 => vk::ShaderStageFlags::TASK_EXT,
+        5268 | 5365 => vk::ShaderStageFlags::MESH_EXT,
+        5313 => vk::ShaderStageFlags::RAYGEN_KHR,
+        5314 => vk::ShaderStageFlags::INTERSECTION_KHR,
+        5315 => vk::ShaderStageFlags::ANY_HIT_KHR,
+        5316 => vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+        5317 => vk::ShaderStageFlags::MISS_KHR,
+        5318 => vk::ShaderStageFlags::CALLABLE_KHR,
+        _ => vk::ShaderStageFlags::empty()
+    }
+}
+
+/// Size in bytes of the vertex attribute formats produced by reflection
+pub fn format_size(format: vk::Format) -> u32 {
+
+    use vk::Format as F;
+
+    match format {
+        F::R32_SFLOAT | F::R32_SINT | F::R32_UINT => 4,
+        F::R32G32_SFLOAT | F::R32G32_SINT | F::R32G32_UINT | F::R64_SFLOAT => 8,
+        F::R32G32B32_SFLOAT | F::R32G32B32_SINT | F::R32G32B32_UINT => 12,
+        F::R32G32B32A32_SFLOAT | F::R32G32B32A32_SINT | F::R32G32B32A32_UINT | F::R64G64_SFLOAT => 16,
+        F::R64G64B64_SFLOAT => 24,
+        F::R64G64B64A64_SFLOAT => 32,
+        _ => 0
+    }
+}
+
+/// Descriptor declared by a shader
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    /// 0 for runtime (unbounded) arrays
+    pub count: u32,
+    pub stage_flags: vk::ShaderStageFlags,
+    pub name: String
+}
+
+/// Vertex shader input attribute
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflectedInput {
+    pub location: u32,
+    pub format: vk::Format,
+    pub name: String
+}
+
+impl ReflectedInput {
+
+    /// 64-bit three and four component vectors take two locations
+    pub fn location_count(&self) -> u32 {
+        match self.format {
+            vk::Format::R64G64B64_SFLOAT | vk::Format::R64G64B64A64_SFLOAT => 2,
+            _ => 1
+        }
+    }
+
+    fn locations(&self) -> std::ops::Range<u32> {
+        self.location..self.location + self.location_count()
+    }
+}
+
+///
+/// Interface of one shader entry point extracted from SPIR-V
+///
+/// # Example
+/// ```
+/// let vs = ShaderReflection::from_spirv(&load_spv("triangle-vert.spv"))?;
+/// assert_eq!(vs.stage, vk::ShaderStageFlags::VERTEX);
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct ShaderReflection {
+    pub stage: vk::ShaderStageFlags,
+    pub entry_point: String,
+    pub bindings: Vec<ReflectedBinding>,
+    pub push_constant: Option<vk::PushConstantRange>,
+    pub inputs: Vec<ReflectedInput>,
+    pub workgroup_size: Option<[u32; 3]>
+}
+
+impl ShaderReflection {
+
+    /// Reflects the first entry point of the module
+    pub fn from_spirv(code: &[u32]) -> VulkanResult<Self> {
+        Self::reflect(code, None)
+    }
+
+    /// Reflects the entry point with the given name
+    pub fn from_spirv_entry(code: &[u32], entry_point: &str) -> VulkanResult<Self> {
+        Self::reflect(code, Some(entry_point))
+    }
+
+    fn reflect(code: &[u32], entry_point: Option<&str>) -> VulkanResult<Self> {
+
+        let module = Module::parse(code).map_err(VulkanError::Reflection)?;
+
+        let entry = match entry_point {
+            Some(name) => module.entry_points.iter().find(|x| x.name == name),
+            None => module.entry_points.first()
+        }.ok_or_else(|| {
+            VulkanError::Reflection(ReflectionError::EntryPointNotFound(entry_point.unwrap_or("<any>").to_string()))
+        })?;
+
+        let stage = stage_from_execution_model(entry.execution_model);
+        let globals = module.entry_globals(entry);
+
+        let mut bindings = vec![];
+        let mut inputs = vec![];
+        let mut push_constant = None;
+
+        for &(id, storage_class, pointer) in &module.variables {
+
+            // Переменные других точек входа того же модуля
+            if !globals.contains(&id) {
+                continue;
+            }
+
+            let pointee = match module.types.get(&pointer) {
+                Some(SpirvType::Pointer { pointee, .. }) => *pointee,
+                _ => continue
+            };
+
+            match storage_class {
+
+                STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+
+                    let (Some(set), Some(binding)) = (
+                        module.decoration(id, DECORATION_DESCRIPTOR_SET),
+                        module.decoration(id, DECORATION_BINDING)
+                    ) else {
+                        continue
+                    };
+
+                    let (element, count) = module.unwrap_arrays(pointee);
+
+                    if let Some(descriptor_type) = module.descriptor_type(storage_class, element) {
+                        bindings.push(ReflectedBinding {
+                            set,
+                            binding,
+                            descriptor_type,
+                            count,
+                            stage_flags: stage,
+                            name: module.name(id)
+                        });
+                    }
+                },
+
+                STORAGE_PUSH_CONSTANT => {
+                    let offset = module.struct_base_offset(pointee);
+                    let size = module.type_size(pointee) - offset;
+                    push_constant = Some(vk::PushConstantRange {
+                        stage_flags: stage,
+                        offset,
+                        size
+                    });
+                },
+
+                // Inputs of entry points other than vertex are varyings, not vertex attributes.
+                // Every version lists them in the interface
+                STORAGE_INPUT if stage == vk::ShaderStageFlags::VERTEX && entry.interface.contains(&id) => {
+
+                    if module.decoration(id, DECORATION_BUILTIN).is_some() {
+                        continue;
+                    }
+
+                    let Some(location) = module.decoration(id, DECORATION_LOCATION) else {
+                        continue
+                    };
+
+                    let (format, columns, locations) = module.input_format(pointee)
+                        .ok_or(VulkanError::Reflection(ReflectionError::UnsupportedInputType(location)))?;
+
+                    for i in 0..columns {
+                        inputs.push(ReflectedInput {
+                            location: location + i * locations,
+                            format,
+                            name: module.name(id)
+                        });
+                    }
+                },
+
+                _ => {}
+            }
+        }
+
+        let workgroup_size = module.local_size.get(&entry.id).copied().or_else(|| {
+            module.local_size_id.get(&entry.id).map(|ids| {
+                ids.map(|id| module.constants.get(&id).copied().unwrap_or(1))
+            })
+        });
+
+        bindings.sort_by_key(|x| (x.set, x.binding));
+        inputs.sort_by_key(|x| x.location);
+
+        Ok(ShaderReflection {
+            stage,
+            entry_point: entry.name.clone(),
+            bindings,
+            push_constant,
+            inputs,
+            workgroup_size
+        })
+    }
+}
+
+///
+/// Interface of a whole pipeline, merged from all of its stages
+///
+/// # Example
+/// ```
+/// let reflection = PipelineReflection::merge(&[
+///     ShaderReflection::from_spirv(&vertex_code)?,
+///     ShaderReflection::from_spirv(&fragment_code)?,
+/// ])?;
+///
+/// let bindings = reflection.set_layout_bindings(0);
+/// let layout = DescriptorSetLayoutBuilder::new()
+///     .with_device(device)
+///     .with_bindings(&bindings)
+///     .build();
+/// ```
+///
+#[derive(Debug, Clone, Default)]
+pub struct PipelineReflection {
+    pub sets: BTreeMap<u32, Vec<ReflectedBinding>>,
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
+    pub vertex_inputs: Vec<ReflectedInput>,
+    pub workgroup_size: Option<[u32; 3]>
+}
+
+impl PipelineReflection {
+
+    /// Merges stages, failing when two stages declare the same
+    /// set/binding with a different descriptor type or count
+    pub fn merge(stages: &[ShaderReflection]) -> VulkanResult<Self> {
+
+        let mut result = PipelineReflection::default();
+        let mut seen_stages = vk::ShaderStageFlags::empty();
+        let mut push_constant: Option<vk::PushConstantRange> = None;
+
+        for stage in stages {
+
+            if seen_stages.intersects(stage.stage) {
+                return Err(VulkanError::Reflection(ReflectionError::DuplicateStage(format!("{:?}", stage.stage))));
+            }
+            seen_stages |= stage.stage;
+
+            for binding in &stage.bindings {
+
+                let set = result.sets.entry(binding.set).or_default();
+
+                match set.iter_mut().find(|x| x.binding == binding.binding) {
+                    Some(existing) => {
+                        if existing.descriptor_type != binding.descriptor_type || existing.count != binding.count {
+                            return Err(VulkanError::Reflection(ReflectionError::BindingMismatch {
+                                set: binding.set,
+                                binding: binding.binding,
+                                first_stage: format!("{:?}", existing.stage_flags),
+                                second_stage: format!("{:?}", binding.stage_flags),
+                                first: format!("{:?}[{}] '{}'", existing.descriptor_type, existing.count, existing.name),
+                                second: format!("{:?}[{}] '{}'", binding.descriptor_type, binding.count, binding.name),
+                            }));
+                        }
+                        existing.stage_flags |= binding.stage_flags;
+                    },
+                    None => set.push(binding.clone())
+                }
+            }
+
+            // One range visible to all stages that use push constants
+            if let Some(range) = stage.push_constant {
+                push_constant = Some(match push_constant {
+                    Some(merged) => {
+                        let start = merged.offset.min(range.offset);
+                        let end = (merged.offset + merged.size).max(range.offset + range.size);
+                        vk::PushConstantRange {
+                            stage_flags: merged.stage_flags | range.stage_flags,
+                            offset: start,
+                            size: end - start
+                        }
+                    },
+                    None => range
+                });
+            }
+
+            if stage.stage == vk::ShaderStageFlags::VERTEX {
+                result.vertex_inputs = stage.inputs.clone();
+            }
+
+            if stage.workgroup_size.is_some() {
+                result.workgroup_size = stage.workgroup_size;
+            }
+        }
+
+        for set in result.sets.values_mut() {
+            set.sort_by_key(|x| x.binding);
+        }
+
+        let mut locations: Vec<std::ops::Range<u32>> = vec![];
+        for input in &result.vertex_inputs {
+            let range = input.locations();
+
+            // Первая общая локация
+            if let Some(other) = locations.iter().find(|x| x.start < range.end && range.start < x.end) {
+                return Err(VulkanError::Reflection(ReflectionError::InputLocationConflict(other.start.max(range.start))));
+            }
+            locations.push(range);
+        }
+
+        result.push_constant_ranges = push_constant.into_iter().collect();
+
+        Ok(result)
+    }
+
+    /// Bindings for [`crate::DescriptorSetLayoutBuilder::with_bindings`].
+    /// Runtime arrays are returned with `max_runtime_array` descriptors
+    pub fn set_layout_bindings_with_runtime_array(&self, set: u32, max_runtime_array: u32) -> Vec<vk::DescriptorSetLayoutBinding<'static>> {
+        self.sets.get(&set).map(|bindings| {
+            bindings.iter().map(|x| {
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(x.binding)
+                    .descriptor_type(x.descriptor_type)
+                    .descriptor_count(if x.count == 0 { max_runtime_array } else { x.count })
+                    .stage_flags(x.stage_flags)
+            }).collect()
+        }).unwrap_or_default()
+    }
+
+    /// Bindings for [`crate::DescriptorSetLayoutBuilder::with_bindings`]
+    pub fn set_layout_bindings(&self, set: u32) -> Vec<vk::DescriptorSetLayoutBinding<'static>> {
+        self.set_layout_bindings_with_runtime_array(set, 1)
+    }
+
+    /// Tightly packed attributes in location order, all in one binding
+    pub fn vertex_attribute_descriptions(&self, binding: u32) -> Vec<vk::VertexInputAttributeDescription> {
+
+        let mut offset = 0;
+
+        self.vertex_inputs.iter().map(|input| {
+            let attribute = vk::VertexInputAttributeDescription {
+                location: input.location,
+                binding,
+                format: input.format,
+                offset
+            };
+            offset += format_size(input.format);
+            attribute
+        }).collect()
+    }
+
+    /// Binding matching [`Self::vertex_attribute_descriptions`]
+    pub fn vertex_binding_description(&self, binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription {
+            binding,
+            stride: self.vertex_inputs.iter().map(|x| format_size(x.format)).sum(),
+            input_rate: vk::VertexInputRate::VERTEX
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(bytes: &[u8]) -> Vec<u32> {
+        crate::read_shader_from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_reflect_triangle_shaders() {
+
+        let vs = ShaderReflection::from_spirv(&load(include_bytes!("../../../../shared/shaders/spv/triangle-vert.spv"))).unwrap();
+        let fs = ShaderReflection::from_spirv(&load(include_bytes!("../../../../shared/shaders/spv/triangle-frag.spv"))).unwrap();
+
+        assert_eq!(vs.stage, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(vs.entry_point, "main");
+        assert_eq!(vs.bindings.len(), 1);
+        assert_eq!(vs.bindings[0].descriptor_type, vk::DescriptorType::UNIFORM_BUFFER);
+        assert_eq!(vs.inputs.iter().map(|x| (x.location, x.format)).collect::<Vec<_>>(), vec![
+            (0, vk::Format::R32G32B32_SFLOAT),
+            (1, vk::Format::R32G32B32_SFLOAT),
+        ]);
+
+        assert_eq!(fs.stage, vk::ShaderStageFlags::FRAGMENT);
+        assert!(fs.inputs.is_empty());
+        assert_eq!(fs.bindings[0].descriptor_type, vk::DescriptorType::COMBINED_IMAGE_SAMPLER);
+        assert_eq!(fs.bindings[0].binding, 1);
+
+        let pipeline = PipelineReflection::merge(&[vs, fs]).unwrap();
+        let bindings = pipeline.set_layout_bindings(0);
+
+        assert_eq!(bindings.len(), 2);
+        assert_eq!(pipeline.vertex_binding_description(0).stride, 24);
+        assert_eq!(pipeline.vertex_attribute_descriptions(0)[1].offset, 12);
+    }
+
+    #[test]
+    fn test_merge_reports_mismatch() {
+
+        let fs = ShaderReflection::from_spirv(&load(include_bytes!("../../../../shared/shaders/spv/triangle-frag.spv"))).unwrap();
+        let mut vs = ShaderReflection::from_spirv(&load(include_bytes!("../../../../shared/shaders/spv/triangle-vert.spv"))).unwrap();
+        vs.bindings[0].binding = 1;
+
+        let result = PipelineReflection::merge(&[vs, fs]);
+        assert!(matches!(result, Err(VulkanError::Reflection(ReflectionError::BindingMismatch { set: 0, binding: 1, .. }))));
+    }
+
+    #[test]
+    fn test_merge_reports_overlapping_locations() {
+
+        let input = |location, format| ReflectedInput { location, format, name: String::new() };
+        let vertex = |inputs| ShaderReflection {
+            stage: vk::ShaderStageFlags::VERTEX,
+            entry_point: "main".to_owned(),
+            bindings: vec![],
+            push_constant: None,
+            inputs,
+            workgroup_size: None
+        };
+
+        // dvec3 на 0 занимает и 1
+        let result = PipelineReflection::merge(&[vertex(vec![
+            input(0, vk::Format::R64G64B64_SFLOAT),
+            input(1, vk::Format::R32_SFLOAT)
+        ])]);
+        assert!(matches!(result, Err(VulkanError::Reflection(ReflectionError::InputLocationConflict(1)))));
+
+        let result = PipelineReflection::merge(&[vertex(vec![
+            input(0, vk::Format::R64G64B64A64_SFLOAT),
+            input(2, vk::Format::R32_SFLOAT)
+        ])]);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_invalid_spirv() {
+        assert!(ShaderReflection::from_spirv(&[0, 1, 2, 3, 4]).is_err());
+        assert!(ShaderReflection::from_spirv(&[SPIRV_MAGIC]).is_err());
+
+        // OpTypeInt без знаковости и OpTypeImage без Sampled
+        for (opcode, ops) in [(OP_TYPE_INT, vec![1, 32]), (OP_TYPE_IMAGE, vec![1, 2, 1, 0, 0, 0])] {
+            let result = ShaderReflection::from_spirv(&module(0x0001_0000, &[inst(opcode, &ops)]));
+            assert!(matches!(result, Err(VulkanError::Reflection(ReflectionError::InvalidSpirv(_)))));
+        }
+    }
+
+    fn inst(opcode: u32, ops: &[u32]) -> Vec<u32> {
+        let mut words = vec![((ops.len() as u32 + 1) << 16) | opcode];
+        words.extend(ops);
+        words
+    }
+
+    fn module(version: u32, instructions: &[Vec<u32>]) -> Vec<u32> {
+        let mut words = vec![SPIRV_MAGIC, version, 0, 100, 0];
+        words.extend(instructions.iter().flatten());
+        words
+    }
+
+    /// Two compute entry points, `main` reaches binding 0 through a call, `other` uses binding 1
+    fn two_entry_points(version: u32) -> Vec<u32> {
+        let main = u32::from_le_bytes(*b"main");
+        let other = [u32::from_le_bytes(*b"othe"), u32::from_le_bytes(*b"r\0\0\0")];
+        let (interface_main, interface_other): (&[u32], &[u32]) = if version >= SPIRV_VERSION_1_4 { (&[20], &[21]) } else { (&[], &[]) };
+
+        module(version, &[
+            inst(OP_ENTRY_POINT, &[&[5, 1, main, 0][..], interface_main].concat()),
+            inst(OP_ENTRY_POINT, &[&[5, 2][..], &other, interface_other].concat()),
+            inst(OP_DECORATE, &[11, DECORATION_BLOCK]),
+            inst(OP_DECORATE, &[20, DECORATION_DESCRIPTOR_SET, 0]),
+            inst(OP_DECORATE, &[20, DECORATION_BINDING, 0]),
+            inst(OP_DECORATE, &[21, DECORATION_DESCRIPTOR_SET, 0]),
+            inst(OP_DECORATE, &[21, DECORATION_BINDING, 1]),
+            inst(OP_TYPE_FLOAT, &[10, 32]),
+            inst(OP_TYPE_STRUCT, &[11, 10]),
+            inst(OP_TYPE_POINTER, &[12, STORAGE_UNIFORM, 11]),
+            inst(OP_VARIABLE, &[12, 20, STORAGE_UNIFORM]),
+            inst(OP_VARIABLE, &[12, 21, STORAGE_UNIFORM]),
+            // main -> helper(3) -> %20
+            inst(OP_FUNCTION, &[30, 1, 0, 31]),
+            inst(OP_FUNCTION_CALL, &[30, 40, 3]),
+            inst(OP_FUNCTION_END, &[]),
+            inst(OP_FUNCTION, &[30, 3, 0, 31]),
+            inst(65, &[13, 41, 20, 42]),
+            inst(OP_FUNCTION_END, &[]),
+            inst(OP_FUNCTION, &[30, 2, 0, 31]),
+            inst(65, &[13, 43, 21, 42]),
+            inst(OP_FUNCTION_END, &[]),
+        ])
+    }
+
+    #[test]
+    fn test_entry_point_uses_only_its_descriptors() {
+        for version in [0x0001_0000, SPIRV_VERSION_1_4] {
+            let code = two_entry_points(version);
+
+            let main = ShaderReflection::from_spirv_entry(&code, "main").unwrap();
+            let other = ShaderReflection::from_spirv_entry(&code, "other").unwrap();
+
+            assert_eq!(main.bindings.iter().map(|x| x.binding).collect::<Vec<_>>(), vec![0]);
+            assert_eq!(other.bindings.iter().map(|x| x.binding).collect::<Vec<_>>(), vec![1]);
+        }
+    }
+
+    #[test]
+    fn test_double_vectors_take_two_locations() {
+        let main = u32::from_le_bytes(*b"main");
+
+        let code = module(0x0001_0000, &[
+            inst(OP_ENTRY_POINT, &[0, 1, main, 0, 20, 21]),
+            inst(OP_DECORATE, &[20, DECORATION_LOCATION, 0]),
+            inst(OP_DECORATE, &[21, DECORATION_LOCATION, 4]),
+            inst(OP_TYPE_FLOAT, &[10, 64]),
+            inst(OP_TYPE_VECTOR, &[11, 10, 3]),
+            inst(OP_TYPE_MATRIX, &[12, 11, 2]),
+            inst(OP_TYPE_POINTER, &[13, STORAGE_INPUT, 11]),
+            inst(OP_TYPE_POINTER, &[14, STORAGE_INPUT, 12]),
+            inst(OP_VARIABLE, &[13, 20, STORAGE_INPUT]),
+            inst(OP_VARIABLE, &[14, 21, STORAGE_INPUT]),
+        ]);
+
+        let vs = ShaderReflection::from_spirv(&code).unwrap();
+        assert_eq!(vs.inputs.iter().map(|x| (x.location, x.format)).collect::<Vec<_>>(), vec![
+            (0, vk::Format::R64G64B64_SFLOAT),
+            (4, vk::Format::R64G64B64_SFLOAT),
+            (6, vk::Format::R64G64B64_SFLOAT),
+        ]);
+    }
+}
@@ -1,14 +1,73 @@
+use std::hash::{DefaultHasher, Hash, Hasher};
+
 use ash::vk::*;
 
+use crate::{raw_slice, DeferredResource, DeviceHandle, PipelineError, ResourceOwner, VulkanError, VulkanResult};
+
 pub struct RenderPass {
-    pub raw: ash::vk::RenderPass
+    pub raw: ash::vk::RenderPass,
+    /// Hash of the attachments, subpasses and dependencies, identifies the pass in pipeline cache keys
+    pub desc_hash: u64,
+    /// Colour attachments of each subpass
+    pub color_attachment_counts: Vec<u32>,
+    _owner: ResourceOwner
+}
+
+fn hash_desc(attachments: &[ash::vk::AttachmentDescription], subpasses: &[ash::vk::SubpassDescription], dependencies: &[ash::vk::SubpassDependency]) -> u64 {
+
+    let mut hasher = DefaultHasher::new();
+    let hash_refs = |hasher: &mut DefaultHasher, refs: &[AttachmentReference]| {
+        refs.iter().map(|x| (x.attachment, x.layout.as_raw())).collect::<Vec<_>>().hash(hasher);
+    };
+
+    for x in attachments {
+        (
+            x.format.as_raw(),
+            x.samples.as_raw(),
+            x.load_op.as_raw(),
+            x.store_op.as_raw(),
+            x.stencil_load_op.as_raw(),
+            x.stencil_store_op.as_raw(),
+            x.initial_layout.as_raw(),
+            x.final_layout.as_raw()
+        ).hash(&mut hasher);
+    }
+
+    for x in subpasses {
+        x.pipeline_bind_point.as_raw().hash(&mut hasher);
+
+        let colors = unsafe { raw_slice(x.p_color_attachments, x.color_attachment_count) };
+        let inputs = unsafe { raw_slice(x.p_input_attachments, x.input_attachment_count) };
+        hash_refs(&mut hasher, colors);
+        hash_refs(&mut hasher, inputs);
+
+        // Resolve и глубина задаются указателем без своего счётчика
+        let resolves = unsafe { raw_slice(x.p_resolve_attachments, x.color_attachment_count) };
+        let depth = unsafe { raw_slice(x.p_depth_stencil_attachment, 1) };
+        hash_refs(&mut hasher, resolves);
+        hash_refs(&mut hasher, depth);
+    }
+
+    for x in dependencies {
+        (
+            x.src_subpass,
+            x.dst_subpass,
+            x.src_stage_mask.as_raw(),
+            x.dst_stage_mask.as_raw(),
+            x.src_access_mask.as_raw(),
+            x.dst_access_mask.as_raw(),
+            x.dependency_flags.as_raw()
+        ).hash(&mut hasher);
+    }
+
+    hasher.finish()
 }
 
 #[derive(Default)]
 pub struct RenderPassBuilder<'n> {
     attachments: Vec<ash::vk::AttachmentDescription>,
     dependencies: Vec<ash::vk::SubpassDependency>,
-    device: Option<&'n ash::Device>,
+    device: Option<&'n DeviceHandle>,
     subpass: Vec<ash::vk::SubpassDescription<'n>>
 }
 
@@ -18,7 +77,7 @@ impl<'n> RenderPassBuilder<'n> {
         Self { ..Default::default() }
     }
 
-    pub fn with_device(mut self, dev: &'n ash::Device) -> Self {
+    pub fn with_device(mut self, dev: &'n DeviceHandle) -> Self {
         self.device = Some(dev);
         self
     }
@@ -38,7 +97,7 @@ impl<'n> RenderPassBuilder<'n> {
         self
     }
 
-    pub fn build(self) -> RenderPass {
+    pub fn build(self) -> VulkanResult<RenderPass> {
 
         let attachment_desc = self.attachments;
         let dependency = self.dependencies;
@@ -49,9 +108,20 @@ impl<'n> RenderPassBuilder<'n> {
             .subpasses(&subpass)
             .dependencies(&dependency);
 
-        let render_pass = unsafe { self.device.unwrap().create_render_pass(&create_info, None).unwrap() };
+        let device = self.device.ok_or(VulkanError::missing("RenderPassBuilder", "device"))?;
+        let render_pass = unsafe { device.create_render_pass(&create_info, None) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateRenderPassFailed(e))))?;
+
+        let desc_hash = hash_desc(&attachment_desc, &subpass, &dependency);
+
+        let color_attachment_counts = subpass.iter().map(|x| x.color_attachment_count).collect();
 
-        RenderPass { raw: render_pass }
+        Ok(RenderPass {
+            raw: render_pass,
+            desc_hash,
+            color_attachment_counts,
+            _owner: ResourceOwner::new(device, vec![DeferredResource::RenderPass(render_pass)])
+        })
     }
 }
 
@@ -107,12 +177,13 @@ impl SubpassDependency {
 }
 
 
+/// `raw` points into the references owned here, keep it alive until the render pass is created
 #[derive(Default)]
 pub struct Subpass {
     pub raw: ash::vk::SubpassDescription<'static>,
-    p_color_attachment_ref: *const AttachmentReference,
-    p_depth_attachment_ref: *const AttachmentReference,
-    // p_resolve_attachments: *const AttachmentReference,
+    color_attachment_ref: Vec<AttachmentReference>,
+    depth_attachment_ref: Option<Box<AttachmentReference>>,
+    resolve_attachment_ref: Vec<AttachmentReference>,
     // p_preserve_attachments: *const AttachmentReference
 }
 
@@ -121,6 +192,7 @@ pub struct Subpass {
 pub struct SubpassBuilder {
     color_attachment_ref: Vec<AttachmentReference>,
     depth_attachment_ref: Option<AttachmentReference>,
+    resolve_attachment_ref: Vec<AttachmentReference>,
     bind_point: Option<PipelineBindPoint>,
     flags: Option<SubpassDescriptionFlags>
 }
@@ -146,6 +218,16 @@ impl SubpassBuilder {
         self
     }
 
+    /// One per colour attachment, for MSAA resolve
+    pub fn add_resolve_attachment_ref(mut self, resolve_attachment_ref: AttachmentReference) -> Self {
+        self.resolve_attachment_ref.push(resolve_attachment_ref);
+        self
+    }
+
+    ///
+    /// # Panics
+    /// if resolve references are given and their count differs from colour references
+    ///
     pub fn build(self) -> Subpass {
 
         let bind_point = self.bind_point.unwrap();
@@ -159,15 +241,23 @@ impl SubpassBuilder {
             subpass.raw.flags = flags;
         }
 
-        if !self.color_attachment_ref.is_empty() {
-            subpass.p_color_attachment_ref = self.color_attachment_ref.as_ptr();
-            subpass.raw.color_attachment_count = self.color_attachment_ref.len() as u32;
-            subpass.raw.p_color_attachments = subpass.p_color_attachment_ref;
+        // Буферы Vec/Box не перемещаются вместе с Subpass, указатели остаются валидными
+        subpass.color_attachment_ref = self.color_attachment_ref;
+        subpass.resolve_attachment_ref = self.resolve_attachment_ref;
+        subpass.depth_attachment_ref = self.depth_attachment_ref.map(Box::new);
+
+        if !subpass.color_attachment_ref.is_empty() {
+            subpass.raw.color_attachment_count = subpass.color_attachment_ref.len() as u32;
+            subpass.raw.p_color_attachments = subpass.color_attachment_ref.as_ptr();
+        }
+
+        if !subpass.resolve_attachment_ref.is_empty() {
+            assert_eq!(subpass.resolve_attachment_ref.len(), subpass.color_attachment_ref.len(), "Resolve and color attachment count differ");
+            subpass.raw.p_resolve_attachments = subpass.resolve_attachment_ref.as_ptr();
         }
 
-        if let Some(depth) = self.depth_attachment_ref {
-            subpass.p_depth_attachment_ref = Box::into_raw(Box::new(depth));
-            subpass.raw.p_depth_stencil_attachment = subpass.p_depth_attachment_ref;
+        if let Some(depth) = &subpass.depth_attachment_ref {
+            subpass.raw.p_depth_stencil_attachment = depth.as_ref();
         }
 
         subpass
@@ -0,0 +1,158 @@
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+use crate::core::{DeferredResource, DeletionQueue, InstanceHandle};
+
+struct Deferred {
+    queue: DeletionQueue,
+    frame: u64
+}
+
+struct Shared {
+    raw: ash::Device,
+    deferred: Mutex<Deferred>,
+    /// Keeps the instance alive until the device is destroyed
+    _instance: Option<InstanceHandle>
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(e) = self.raw.device_wait_idle() {
+                log::warn!("Device wait idle before destroy failed: {:?}", e);
+            }
+        }
+
+        let deferred = self.deferred.get_mut().unwrap_or_else(|e| e.into_inner());
+        deferred.queue.flush_all(&self.raw);
+
+        unsafe { self.raw.destroy_device(None) };
+    }
+}
+
+///
+/// Shared logical device with a per-frame deletion queue.
+/// The device is destroyed together with the last handle, after all deferred resources
+///
+/// # Example
+/// ```
+/// let device: DeviceHandle = ctx.device.raw_device().clone();
+/// device.defer(DeferredResource::Sampler(sampler));
+///
+/// // after the frame was submitted
+/// device.next_frame(frames_in_flight);
+/// ```
+///
+#[derive(Clone)]
+pub struct DeviceHandle {
+    raw: ash::Device,
+    shared: Arc<Shared>
+}
+
+impl DeviceHandle {
+
+    pub fn new(raw: ash::Device, instance: Option<InstanceHandle>) -> Self {
+        let deferred = Mutex::new(Deferred { queue: DeletionQueue::new(), frame: 0 });
+        Self {
+            raw: raw.clone(),
+            shared: Arc::new(Shared { raw, deferred, _instance: instance })
+        }
+    }
+
+    pub fn raw(&self) -> &ash::Device {
+        &self.raw
+    }
+
+    /// Destroys `resource` once the frames in flight recorded so far have completed
+    pub fn defer(&self, resource: DeferredResource) {
+        let mut deferred = self.shared.deferred.lock().unwrap_or_else(|e| e.into_inner());
+        let frame = deferred.frame;
+        deferred.queue.push(frame, resource);
+    }
+
+    ///
+    /// Advances the frame counter and destroys resources deferred before the last
+    /// `frames_in_flight` submits. Call only after a successful queue submit: frames that
+    /// were skipped or failed to acquire must not count, the GPU may still use their resources
+    ///
+    pub fn next_frame(&self, frames_in_flight: u64) {
+        let mut deferred = self.shared.deferred.lock().unwrap_or_else(|e| e.into_inner());
+        deferred.frame += 1;
+        if let Some(completed) = completed_frame(deferred.frame, frames_in_flight) {
+            deferred.queue.flush(&self.raw, completed);
+        }
+    }
+
+    /// Destroys every deferred resource, the device must be idle
+    pub fn flush_all(&self) {
+        let mut deferred = self.shared.deferred.lock().unwrap_or_else(|e| e.into_inner());
+        deferred.queue.flush_all(&self.raw);
+    }
+
+    /// Number of resources waiting for destruction
+    pub fn pending(&self) -> usize {
+        self.shared.deferred.lock().unwrap_or_else(|e| e.into_inner()).queue.len()
+    }
+}
+
+impl Deref for DeviceHandle {
+    type Target = ash::Device;
+
+    fn deref(&self) -> &Self::Target {
+        &self.raw
+    }
+}
+
+///
+/// Last counter value the GPU is done with after `frame` submits. Resources deferred at
+/// value `n` may be used by submit `n + 1`, whose fence was waited before submit `n + 1 + frames_in_flight`
+///
+fn completed_frame(frame: u64, frames_in_flight: u64) -> Option<u64> {
+    frame.checked_sub(frames_in_flight.max(1) + 1)
+}
+
+///
+/// Vulkan handles owned by a wrapper, queued to the device deletion queue on drop
+///
+pub struct ResourceOwner {
+    device: DeviceHandle,
+    resources: Vec<DeferredResource>
+}
+
+impl ResourceOwner {
+
+    pub fn new(device: &DeviceHandle, resources: Vec<DeferredResource>) -> Self {
+        Self { device: device.clone(), resources }
+    }
+
+    pub fn device(&self) -> &DeviceHandle {
+        &self.device
+    }
+
+    /// Queued together with the resources given to [`ResourceOwner::new`]
+    pub fn push(&mut self, resource: DeferredResource) {
+        self.resources.push(resource);
+    }
+}
+
+impl Drop for ResourceOwner {
+    fn drop(&mut self) {
+        for resource in self.resources.drain(..) {
+            self.device.defer(resource);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completed_frame() {
+        assert_eq!(completed_frame(2, 2), None);
+        assert_eq!(completed_frame(3, 2), Some(0));
+        assert_eq!(completed_frame(5, 3), Some(1));
+        // 0 кадров в полёте ведёт себя как 1
+        assert_eq!(completed_frame(3, 0), Some(1));
+    }
+}
@@ -1,23 +1,68 @@
 #![allow(warnings)]
 
 use std::{
-    fs::File, io::Read
+    ffi::CString, fs::File, hash::{DefaultHasher, Hash, Hasher}, io::Read
 };
 use ash::vk::{
     AllocationCallbacks, ShaderModule, ShaderModuleCreateInfo
 };
 
+pub use ferrum_shaders::{Backend, CompiledShader, ShaderCompiler, ShaderDefines, ShaderLanguage, ShaderSource, ShaderStage};
+
+use crate::{DeferredResource, DeviceHandle, ResourceOwner, PipelineReflection, ShaderError, ShaderReflection, VulkanError, VulkanResult};
+
+/// Stages that were not attached are [`ShaderModule::null`] with a `main` entry point.
+/// Modules can be dropped as soon as the pipelines using them are built
 pub struct ShaderProgram {
     pub vertex_shader: ShaderModule,
-    pub fragment_shader: ShaderModule
+    pub fragment_shader: ShaderModule,
+    pub compute_shader: ShaderModule,
+    pub vertex_entry_point: CString,
+    pub fragment_entry_point: CString,
+    pub compute_entry_point: CString,
+    /// [`spirv_hash`] of each stage, 0 for stages that were not attached
+    pub vertex_hash: u64,
+    pub fragment_hash: u64,
+    pub compute_hash: u64,
+    _owner: ResourceOwner
+}
+
+/// Content hash of SPIR-V, identifies a shader in pipeline cache keys unlike the module handle
+pub fn spirv_hash(code: &[u32]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    code.hash(&mut hasher);
+    hasher.finish()
 }
 
+///
+/// Shader modules from SPIR-V or from GLSL, WGSL and HLSL compiled when the program is built
+///
+/// # Example
+/// ```ignore
+/// let shader = ShaderProgramBuilder::new()
+///     .with_device(ctx.device.raw_device())
+///     .with_vertex_source(ShaderSource::file("shared/shaders/triangle.wgsl").with_entry_point("vs_main"))
+///     .with_fragment_source(ShaderSource::file("shared/shaders/triangle.wgsl").with_entry_point("fs_main"))
+///     .build()?;
+///
+/// let pipeline = RenderPipelineBuilder::new()
+///     .with_vertex_shader(shader.vertex_shader)
+///     .with_vertex_entry_point(&shader.vertex_entry_point)
+///     .with_fragment_shader(shader.fragment_shader)
+///     .with_fragment_entry_point(&shader.fragment_entry_point)
+///     ...
+/// ```
+///
 #[derive(Default)]
 pub struct ShaderProgramBuilder<'n> {
-    pub device: Option<&'n ash::Device>,
-    pub vertex_shader_source: Option<Vec<u32>>,
-    pub fragment_shader_source: Option<Vec<u32>>,
-    pub allocation_callbacks: Option<&'n AllocationCallbacks<'n>>
+    pub device: Option<&'n DeviceHandle>,
+    pub vertex_shader_source: Option<ShaderSource>,
+    pub fragment_shader_source: Option<ShaderSource>,
+    pub compute_shader_source: Option<ShaderSource>,
+    pub compiler: Option<&'n mut ShaderCompiler>,
+    pub allocation_callbacks: Option<&'n AllocationCallbacks<'n>>,
+    /// Vertex, fragment and compute output shared by [`Self::reflect`] and [`Self::build`]
+    compiled: [Option<CompiledShader>; 3]
 }
 
 impl<'n> ShaderProgramBuilder<'n> {
@@ -26,43 +71,171 @@ impl<'n> ShaderProgramBuilder<'n> {
         Self { ..Default::default() }
     }
 
+    /// Modules created with custom callbacks are not queued for destruction,
+    /// destroy them with the same callbacks
     pub fn with_allocation_callbacks(mut self, callback: &'n AllocationCallbacks<'n>) -> Self {
         self.allocation_callbacks = Some(callback);
         self
     }
 
-    pub fn with_device(mut self, device: &'n ash::Device) -> Self {
+    pub fn with_device(mut self, device: &'n DeviceHandle) -> Self {
         self.device = Some(device);
         self
     }
 
-    pub fn with_vertex_shader(mut self, bytes: Vec<u32>) -> Self {
-        self.vertex_shader_source = Some(bytes);
+    /// Include dirs and cache for non SPIR-V sources. Default: [`ShaderCompiler::new`]
+    pub fn with_compiler(mut self, compiler: &'n mut ShaderCompiler) -> Self {
+        self.compiler = Some(compiler);
         self
     }
 
-    pub fn with_fragment_shader(mut self, bytes: Vec<u32>) -> Self {
-        self.fragment_shader_source = Some(bytes);
+    /// SPIR-V
+    pub fn with_vertex_shader(self, bytes: Vec<u32>) -> Self {
+        self.with_vertex_source(ShaderSource::spirv(bytes))
+    }
+
+    /// SPIR-V
+    pub fn with_fragment_shader(self, bytes: Vec<u32>) -> Self {
+        self.with_fragment_source(ShaderSource::spirv(bytes))
+    }
+
+    /// SPIR-V
+    pub fn with_compute_shader(self, bytes: Vec<u32>) -> Self {
+        self.with_compute_source(ShaderSource::spirv(bytes))
+    }
+
+    pub fn with_vertex_source(mut self, source: ShaderSource) -> Self {
+        self.vertex_shader_source = Some(source);
+        self.compiled[0] = None;
         self
     }
 
-    pub fn build(self) -> ShaderProgram {
+    pub fn with_fragment_source(mut self, source: ShaderSource) -> Self {
+        self.fragment_shader_source = Some(source);
+        self.compiled[1] = None;
+        self
+    }
 
-        let callback = self.allocation_callbacks;
-        let binding = self.fragment_shader_source.unwrap();
-        let create_info = ShaderModuleCreateInfo::default()
-            .code(&binding);
+    pub fn with_compute_source(mut self, source: ShaderSource) -> Self {
+        self.compute_shader_source = Some(source);
+        self.compiled[2] = None;
+        self
+    }
 
-        let fs = unsafe { self.device.unwrap().create_shader_module(&create_info, callback) };
+    fn sources(&self) -> [(ShaderStage, Option<&ShaderSource>); 3] {
+        [
+            (ShaderStage::Vertex, self.vertex_shader_source.as_ref()),
+            (ShaderStage::Fragment, self.fragment_shader_source.as_ref()),
+            (ShaderStage::Compute, self.compute_shader_source.as_ref())
+        ]
+    }
+
+    /// Compiles stages that are not compiled yet with the attached compiler
+    fn compile_stages(&mut self) -> VulkanResult<()> {
+
+        let mut default_compiler = ShaderCompiler::new();
+        let compiler = match self.compiler.as_deref_mut() {
+            Some(compiler) => compiler,
+            None => &mut default_compiler
+        };
+
+        let sources = [
+            (ShaderStage::Vertex, &self.vertex_shader_source),
+            (ShaderStage::Fragment, &self.fragment_shader_source),
+            (ShaderStage::Compute, &self.compute_shader_source)
+        ];
 
-        //---------------------------------------------------
+        for (shader, (stage, source)) in self.compiled.iter_mut().zip(sources) {
+            if let (None, Some(source)) = (&shader, source) {
+                *shader = Some(compile(compiler, source, stage)?);
+            }
+        }
 
-        let binding = self.vertex_shader_source.unwrap();
-        let create_info = ShaderModuleCreateInfo::default()
-            .code(&binding);
+        Ok(())
+    }
+
+    ///
+    /// Reflects the attached stages and merges them into one pipeline interface.
+    /// The SPIR-V is kept, so a following [`Self::build`] does not compile again
+    ///
+    pub fn reflect(&mut self) -> VulkanResult<PipelineReflection> {
 
-        let vs = unsafe { self.device.unwrap().create_shader_module(&create_info, callback) };
+        self.compile_stages()?;
 
-        ShaderProgram { vertex_shader: vs.unwrap(), fragment_shader: fs.unwrap() }
+        let stages = self.compiled.iter()
+            .flatten()
+            .map(|x| ShaderReflection::from_spirv_entry(&x.spirv, &x.entry_point))
+            .collect::<VulkanResult<Vec<_>>>()?;
+
+        PipelineReflection::merge(&stages)
     }
-}
\ No newline at end of file
+
+    /// Sources are compiled first, modules created before a failure are destroyed
+    pub fn build(mut self) -> VulkanResult<ShaderProgram> {
+
+        let device = self.device.ok_or(VulkanError::missing("ShaderProgramBuilder", "device"))?;
+        let callback = self.allocation_callbacks;
+
+        if self.sources().iter().all(|(_, x)| x.is_none()) {
+            return Err(VulkanError::Shader(ShaderError::NoStages));
+        }
+
+        self.compile_stages()?;
+        let shaders = std::mem::take(&mut self.compiled);
+
+        let mut modules = [ShaderModule::null(); 3];
+
+        for (module, shader) in modules.iter_mut().zip(&shaders) {
+            let Some(shader) = shader else { continue };
+
+            let create_info = ShaderModuleCreateInfo::default()
+                .code(&shader.spirv);
+
+            match unsafe { device.create_shader_module(&create_info, callback) } {
+                Ok(created) => *module = created,
+                Err(e) => {
+                    for created in modules.iter().filter(|x| **x != ShaderModule::null()) {
+                        unsafe { device.destroy_shader_module(*created, callback) };
+                    }
+
+                    return Err(VulkanError::vk(e, |e| VulkanError::Shader(ShaderError::CreateShaderModuleFailed(e))));
+                }
+            }
+        }
+
+        let owned = match callback {
+            Some(_) => vec![],
+            None => modules.iter()
+                .filter(|x| **x != ShaderModule::null())
+                .map(|x| DeferredResource::ShaderModule(*x))
+                .collect()
+        };
+
+        let [vertex_hash, fragment_hash, compute_hash] = shaders.each_ref().map(|x| x.as_ref().map_or(0, |x| spirv_hash(&x.spirv)));
+
+        // Имя из SPIR-V, нулевых байтов в нём быть не может
+        let [vertex_entry_point, fragment_entry_point, compute_entry_point] = shaders.map(|x| match x {
+            Some(shader) => CString::new(shader.entry_point).unwrap_or_else(|_| c"main".to_owned()),
+            None => c"main".to_owned()
+        });
+
+        let [vertex_shader, fragment_shader, compute_shader] = modules;
+
+        Ok(ShaderProgram {
+            vertex_shader,
+            fragment_shader,
+            compute_shader,
+            vertex_entry_point,
+            fragment_entry_point,
+            compute_entry_point,
+            vertex_hash,
+            fragment_hash,
+            compute_hash,
+            _owner: ResourceOwner::new(device, owned)
+        })
+    }
+}
+
+fn compile(compiler: &mut ShaderCompiler, source: &ShaderSource, stage: ShaderStage) -> VulkanResult<CompiledShader> {
+    compiler.compile(source, stage).map_err(|e| VulkanError::Shader(ShaderError::Compile(e)))
+}
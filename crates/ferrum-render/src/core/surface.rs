@@ -2,6 +2,8 @@
 use ash::{self, vk::{AllocationCallbacks, PresentModeKHR, SurfaceCapabilitiesKHR, SurfaceFormatKHR}};
 use winit::raw_window_handle::*;
 
+use crate::{SurfaceError, VulkanError, VulkanResult};
+
 pub struct Surface {
     pub raw: ash::vk::SurfaceKHR,
     pub raw_load: ash::khr::surface::Instance,
@@ -9,19 +11,20 @@ pub struct Surface {
 
 impl Surface {
 
-    pub fn get_surface_formats(&self, phys_dev: &ash::vk::PhysicalDevice) -> Vec<SurfaceFormatKHR>{
-        let formats = unsafe { self.raw_load.get_physical_device_surface_formats(*phys_dev, self.raw).unwrap() };
-        formats
+    pub fn get_surface_formats(&self, phys_dev: &ash::vk::PhysicalDevice) -> VulkanResult<Vec<SurfaceFormatKHR>> {
+        unsafe { self.raw_load.get_physical_device_surface_formats(*phys_dev, self.raw) }
+            .map_err(|e| VulkanError::Surface(SurfaceError::GetFormatsFailed(e)))
     }
 
-    pub fn get_surface_capabilities(&self, phys_dev: &ash::vk::PhysicalDevice) -> SurfaceCapabilitiesKHR {
-        let caps = unsafe { self.raw_load.get_physical_device_surface_capabilities(*phys_dev, self.raw).unwrap() };
-        caps
+    /// Fails with `ERROR_SURFACE_LOST_KHR` after the window is destroyed
+    pub fn get_surface_capabilities(&self, phys_dev: &ash::vk::PhysicalDevice) -> VulkanResult<SurfaceCapabilitiesKHR> {
+        unsafe { self.raw_load.get_physical_device_surface_capabilities(*phys_dev, self.raw) }
+            .map_err(|e| VulkanError::Surface(SurfaceError::GetCapabilitiesFailed(e)))
     }
 
-    pub fn get_surface_present_modes(&self, phys_dev: &ash::vk::PhysicalDevice) -> Vec<PresentModeKHR> {
-        let present = unsafe { self.raw_load.get_physical_device_surface_present_modes(*phys_dev, self.raw).unwrap() };
-        present
+    pub fn get_surface_present_modes(&self, phys_dev: &ash::vk::PhysicalDevice) -> VulkanResult<Vec<PresentModeKHR>> {
+        unsafe { self.raw_load.get_physical_device_surface_present_modes(*phys_dev, self.raw) }
+            .map_err(|e| VulkanError::Surface(SurfaceError::GetPresentModesFailed(e)))
     }
 }
 
@@ -65,12 +68,17 @@ impl<'n> SurfaceBuilder<'n> {
         self
     }
 
-    pub fn build(self) -> Surface {
+    pub fn build(self) -> VulkanResult<Surface> {
         let callback = self.allocation_callbacks;
-        let display_handle = self.display_handle.unwrap();
-        let window_handle = self.window_handle.unwrap();
-        let surface = unsafe { ash_window::create_surface(self.entry.unwrap(), self.instance.unwrap(), *display_handle, *window_handle, callback).unwrap() };
-        let surface_load = ash::khr::surface::Instance::new(&self.entry.unwrap(), &self.instance.unwrap());
-        Surface { raw: surface, raw_load: surface_load }
+        let entry = self.entry.ok_or(VulkanError::missing("SurfaceBuilder", "entry"))?;
+        let instance = self.instance.ok_or(VulkanError::missing("SurfaceBuilder", "instance"))?;
+        let display_handle = self.display_handle.ok_or(VulkanError::missing("SurfaceBuilder", "display handle"))?;
+        let window_handle = self.window_handle.ok_or(VulkanError::missing("SurfaceBuilder", "window handle"))?;
+
+        let surface = unsafe { ash_window::create_surface(entry, instance, *display_handle, *window_handle, callback) }
+            .map_err(|e| VulkanError::Surface(SurfaceError::CreateSurfaceFailed(e)))?;
+
+        let surface_load = ash::khr::surface::Instance::new(entry, instance);
+        Ok(Surface { raw: surface, raw_load: surface_load })
     }
 }
@@ -1,12 +1,42 @@
 use ash::vk::{
     ColorSpaceKHR,
+    CompositeAlphaFlagsKHR,
     Extent2D,
     Format,
     Image,
+    ImageUsageFlags,
     PresentModeKHR,
+    SurfaceCapabilitiesKHR,
     SurfaceTransformFlagsKHR,
     SwapchainKHR
 };
+use log::warn;
+
+use crate::{SwapchainError, VulkanError, VulkanResult};
+
+const DEFAULT_IMAGE_COUNT: u32 = 3;
+
+/// Clamps desired image count to `[min_image_count, max_image_count]`, max 0 means no limit
+pub fn clamp_image_count(desired: u32, caps: &SurfaceCapabilitiesKHR) -> u32 {
+    let count = desired.max(caps.min_image_count);
+    if caps.max_image_count == 0 {
+        count
+    } else {
+        count.min(caps.max_image_count)
+    }
+}
+
+/// Uses `current_extent` when the surface defines it, otherwise clamps `desired` to the caps
+pub fn choose_extent(desired: Extent2D, caps: &SurfaceCapabilitiesKHR) -> Extent2D {
+    if caps.current_extent.width != u32::MAX {
+        return caps.current_extent;
+    }
+
+    Extent2D {
+        width: desired.width.clamp(caps.min_image_extent.width, caps.max_image_extent.width),
+        height: desired.height.clamp(caps.min_image_extent.height, caps.max_image_extent.height),
+    }
+}
 
 /// Vulkan swapchain abstraction representing a collection of presentable images
 /// 
@@ -15,16 +45,21 @@ use ash::vk::{
 /// - `swapchain_load`: Loaded swapchain extension functions
 pub struct Swapchain {
     pub raw: SwapchainKHR,
-    pub swapchain_load: ash::khr::swapchain::Device
+    pub swapchain_load: ash::khr::swapchain::Device,
+    pub extent: Extent2D,
+    /// Usage the images were created with
+    pub image_usage: ImageUsageFlags,
+    /// Requested image count, the driver may create more
+    pub image_count: u32
 }
 
 impl Swapchain {
     ///
     /// Get Current swapchain images
     ///
-    pub fn get_swapchain_images(&self) -> Vec<Image> {
-        let images = unsafe { self.swapchain_load.get_swapchain_images(self.raw).expect("Failed to get swapchain images") };
-        images
+    pub fn get_swapchain_images(&self) -> VulkanResult<Vec<Image>> {
+        unsafe { self.swapchain_load.get_swapchain_images(self.raw) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Swapchain(SwapchainError::GetImagesFailed(e))))
     }
 }
 
@@ -40,7 +75,11 @@ pub struct SwapchainBuilder<'n> {
     present_mode: Option<PresentModeKHR>,
     instance: Option<&'n ash::Instance>,
     device: Option<&'n ash::Device>,
-    surface: Option<&'n  ash::vk::SurfaceKHR>
+    surface: Option<&'n  ash::vk::SurfaceKHR>,
+    caps: Option<&'n SurfaceCapabilitiesKHR>,
+    image_count: Option<u32>,
+    image_usage: Option<ImageUsageFlags>,
+    old_swapchain: Option<SwapchainKHR>
 }
 
 impl<'n> SwapchainBuilder<'n> {
@@ -89,36 +128,123 @@ impl<'n> SwapchainBuilder<'n> {
         self
     }
 
-    /// # Panics
-    /// If any required parameter is not set
-    pub fn build(self) -> Swapchain {
+    /// Image count and resolution are clamped to the caps, unsupported usage is dropped
+    pub fn with_caps(mut self, caps: &'n SurfaceCapabilitiesKHR) -> Self {
+        self.caps = Some(caps);
+        self
+    }
+
+    /// Default: 3
+    pub fn with_image_count(mut self, count: u32) -> Self {
+        self.image_count = Some(count);
+        self
+    }
+
+    /// Extra usage such as TRANSFER_DST or STORAGE, COLOR_ATTACHMENT is always set
+    pub fn with_image_usage(mut self, usage: ImageUsageFlags) -> Self {
+        self.image_usage = Some(usage);
+        self
+    }
+
+    /// Swapchain being replaced, lets the driver reuse its resources
+    pub fn with_old_swapchain(mut self, old: SwapchainKHR) -> Self {
+        self.old_swapchain = Some(old);
+        self
+    }
 
-        let surface = self.surface.expect("Missing SurfaceKHR");
-        let instance = self.instance.expect("Missing Vulkan instance");
-        let device = self.device.expect("Missing logical device");
-        let format = self.format.expect("Missing swapchain format");
-        let image_color_space = self.image_color_space.expect("Missing color space");
-        let resolution = self.resolution.expect("Missing resolution");
-        let transform = self.transform.expect("Missing surface transform");
-        let present_mode = self.present_mode.expect("Missing present mode");
+    /// Missing required parameters are reported as [`VulkanError::MissingField`]
+    pub fn build(self) -> VulkanResult<Swapchain> {
+
+        let missing = |field| VulkanError::missing("SwapchainBuilder", field);
+
+        let surface = self.surface.ok_or_else(|| missing("surface"))?;
+        let instance = self.instance.ok_or_else(|| missing("instance"))?;
+        let device = self.device.ok_or_else(|| missing("device"))?;
+        let format = self.format.ok_or_else(|| missing("format"))?;
+        let image_color_space = self.image_color_space.ok_or_else(|| missing("color space"))?;
+        let resolution = self.resolution.ok_or_else(|| missing("resolution"))?;
+        let transform = self.transform.ok_or_else(|| missing("transform"))?;
+        let present_mode = self.present_mode.ok_or_else(|| missing("present mode"))?;
+
+        let desired_count = self.image_count.unwrap_or(DEFAULT_IMAGE_COUNT);
+        let mut image_usage = ImageUsageFlags::COLOR_ATTACHMENT | self.image_usage.unwrap_or_default();
+
+        let (image_count, resolution, composite_alpha) = match self.caps {
+            Some(caps) => {
+                let supported = caps.supported_usage_flags;
+                if !supported.contains(image_usage) {
+                    warn!("Swapchain usage {:?} is not supported, using {:?}", image_usage, image_usage & supported);
+                    image_usage &= supported;
+                }
+
+                let composite_alpha = [
+                    CompositeAlphaFlagsKHR::OPAQUE,
+                    CompositeAlphaFlagsKHR::INHERIT,
+                    CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+                    CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+                ].into_iter()
+                    .find(|x| caps.supported_composite_alpha.contains(*x))
+                    .unwrap_or(CompositeAlphaFlagsKHR::OPAQUE);
+
+                (clamp_image_count(desired_count, caps), choose_extent(resolution, caps), composite_alpha)
+            },
+            None => (desired_count, resolution, CompositeAlphaFlagsKHR::OPAQUE)
+        };
 
         let swapchain_create_info = ash::vk::SwapchainCreateInfoKHR::default()
             .surface(*surface)
-            .min_image_count(3)
+            .min_image_count(image_count)
             .image_color_space(image_color_space)
             .image_format(format)
             .image_extent(resolution)
-            .image_usage(ash::vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_usage(image_usage)
             .image_sharing_mode(ash::vk::SharingMode::EXCLUSIVE)
             .pre_transform(transform)
-            .composite_alpha(ash::vk::CompositeAlphaFlagsKHR::OPAQUE)
+            .composite_alpha(composite_alpha)
             .present_mode(present_mode)
             .clipped(true)
+            .old_swapchain(self.old_swapchain.unwrap_or_default())
             .image_array_layers(1);
 
         let swapchain_load = ash::khr::swapchain::Device::new(instance, device);
-        let swapchain = unsafe { swapchain_load.create_swapchain(&swapchain_create_info, None).unwrap() };
+        let swapchain = unsafe { swapchain_load.create_swapchain(&swapchain_create_info, None) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Swapchain(SwapchainError::CreateSwapchainFailed(e))))?;
+
+        Ok(Swapchain {
+            raw: swapchain,
+            swapchain_load,
+            extent: resolution,
+            image_usage,
+            image_count: desired_count
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp_image_count() {
+        let caps = SurfaceCapabilitiesKHR { min_image_count: 2, max_image_count: 3, ..Default::default() };
+        assert_eq!(clamp_image_count(1, &caps), 2);
+        assert_eq!(clamp_image_count(8, &caps), 3);
 
-        Swapchain { raw: swapchain, swapchain_load }
+        let unlimited = SurfaceCapabilitiesKHR { min_image_count: 2, max_image_count: 0, ..Default::default() };
+        assert_eq!(clamp_image_count(8, &unlimited), 8);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_choose_extent() {
+        let fixed = SurfaceCapabilitiesKHR { current_extent: Extent2D { width: 800, height: 600 }, ..Default::default() };
+        assert_eq!(choose_extent(Extent2D { width: 1, height: 1 }, &fixed), fixed.current_extent);
+
+        let free = SurfaceCapabilitiesKHR {
+            current_extent: Extent2D { width: u32::MAX, height: u32::MAX },
+            min_image_extent: Extent2D { width: 1, height: 1 },
+            max_image_extent: Extent2D { width: 4096, height: 4096 },
+            ..Default::default()
+        };
+        assert_eq!(choose_extent(Extent2D { width: 8000, height: 0 }, &free), Extent2D { width: 4096, height: 1 });
+    }
+}
@@ -0,0 +1,541 @@
+use ash::vk::{self, Extent2D, PhysicalDeviceMemoryProperties, ShaderModule};
+
+use super::dynamic_rendering::color_image_barrier;
+use crate::{
+    AttachmentImageBuilder, ComputePipeline, ComputePipelineBuilder, DeferredResource, DescriptorPool, DescriptorPoolBuilder,
+    DescriptorSetLayout, DescriptorSetLayoutBuilder, DeviceHandle, PipelineError, ResourceOwner, VulkanError, VulkanResult
+};
+
+/// Format of the history images, `rgba16f` in the shader
+pub const TEMPORAL_HISTORY_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// `local_size` of `temporal-upscale.comp`
+const WORKGROUP_SIZE: [u32; 3] = [8, 8, 1];
+
+/// Phases per 1x upscale, FSR 2 uses the same value
+const BASE_PHASE_COUNT: f32 = 8.0;
+
+/// `index`-th element of the Halton low-discrepancy sequence, in 0..1
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+
+    result
+}
+
+///
+/// Sub-pixel projection offsets, Halton(2, 3). Longer sequences for larger upscale
+/// ratios, so every display pixel is covered by a render sample
+///
+/// # Example
+/// ```
+/// let jitter = JitterSequence::for_upscale(render_extent, display_extent);
+/// let [x, y] = jitter.clip_offset(frame, render_extent);
+///
+/// // in the vertex shader, after projection
+/// // gl_Position.xy += jitter * gl_Position.w;
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JitterSequence {
+    phase_count: u32
+}
+
+impl JitterSequence {
+
+    pub fn new(phase_count: u32) -> Self {
+        Self { phase_count: phase_count.max(1) }
+    }
+
+    /// `8 * (display / render)²` phases
+    pub fn for_upscale(render: Extent2D, display: Extent2D) -> Self {
+        let ratio = display.width as f32 / render.width.max(1) as f32;
+        Self::new((BASE_PHASE_COUNT * ratio * ratio).ceil() as u32)
+    }
+
+    pub fn phase_count(&self) -> u32 {
+        self.phase_count
+    }
+
+    /// Offset of `frame` in render pixels, -0.5..0.5
+    pub fn offset(&self, frame: u64) -> [f32; 2] {
+        // Индекс 0 у Halton всегда 0, начинаем с 1
+        let index = (frame % self.phase_count as u64) as u32 + 1;
+        [halton(index, 2) - 0.5, halton(index, 3) - 0.5]
+    }
+
+    /// Offset of `frame` in clip space, added to `xy / w` after projection
+    pub fn clip_offset(&self, frame: u64, render: Extent2D) -> [f32; 2] {
+        let [x, y] = self.offset(frame);
+        [2.0 * x / render.width as f32, 2.0 * y / render.height as f32]
+    }
+}
+
+/// Push constants of `temporal-upscale.comp`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TemporalConstants {
+    /// Render pixels, see [`JitterSequence::offset`]
+    pub jitter: [f32; 2],
+    pub render_size: [u32; 2],
+    pub output_size: [u32; 2],
+    /// History is ignored, camera cut or first frame
+    pub reset: u32,
+    pub has_reactive: u32,
+    /// Closer is larger depth
+    pub inverted_depth: u32
+}
+
+impl TemporalConstants {
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, std::mem::size_of::<Self>()) }
+    }
+}
+
+/// Views read by [`TemporalUpscalePass`], in SHADER_READ_ONLY_OPTIMAL when the pass is recorded
+#[derive(Debug, Clone, Copy)]
+pub struct TemporalInputs {
+    pub color: vk::ImageView,
+    /// Depth aspect view
+    pub depth: vk::ImageView,
+    /// RG, UV offset from the current to the previous frame position, without jitter
+    pub motion_vectors: vk::ImageView,
+    /// R, 1.0 where history must not be used
+    pub reactive_mask: Option<vk::ImageView>,
+    /// Render resolution of all inputs
+    pub extent: Extent2D
+}
+
+///
+/// History images and descriptor sets of one [`TemporalUpscalePass`] input.
+/// Keep them alive across frames, recreate on resize: the first frame after that starts without history.
+/// Handles are queued for destruction on drop
+///
+pub struct TemporalUpscaleTargets {
+    /// Written on even and odd frames, GENERAL layout
+    pub history: [vk::Image; 2],
+    pub history_views: [vk::ImageView; 2],
+    pub render_extent: Extent2D,
+    pub output_extent: Extent2D,
+    pub jitter: JitterSequence,
+    sets: [vk::DescriptorSet; 2],
+    has_reactive: bool,
+    frame: u64,
+    reset: bool,
+    _pool: DescriptorPool,
+    _owner: ResourceOwner
+}
+
+impl TemporalUpscaleTargets {
+
+    /// Frames recorded since creation
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Offset of the frame being rendered, in render pixels
+    pub fn jitter_offset(&self) -> [f32; 2] {
+        self.jitter.offset(self.frame)
+    }
+
+    /// Offset of the frame being rendered, in clip space, see [`JitterSequence::clip_offset`]
+    pub fn jitter_clip_offset(&self) -> [f32; 2] {
+        self.jitter.clip_offset(self.frame, self.render_extent)
+    }
+
+    /// Drops the history on the next frame, call on camera cuts
+    pub fn reset(&mut self) {
+        self.reset = true;
+    }
+
+    /// Upscaled image of the last recorded frame, GENERAL layout
+    pub fn output_view(&self) -> vk::ImageView {
+        self.history_views[(self.frame.wrapping_sub(1) % 2) as usize]
+    }
+}
+
+///
+/// Vendor-neutral temporal upscaler in the spirit of FSR 2: jittered samples of the
+/// render resolution are accumulated into a display resolution history.
+/// History is reprojected with the motion vector of the closest depth in 3x3,
+/// clamped to the neighbourhood colour variance and replaced where the reactive mask is 1.
+///
+/// Set 0: colour, depth, motion vectors, reactive mask and history as SAMPLED_IMAGE (bindings 0-4),
+/// SAMPLER (binding 5) and the result as STORAGE_IMAGE (binding 6)
+///
+/// # Example
+/// ```
+/// let taa = TemporalUpscalePassBuilder::new()
+///     .with_device(device)
+///     .with_shader(shader.compute_shader) // temporal-upscale-comp.spv
+///     .build()?;
+///
+/// let mut targets = taa.create_targets(device, &memory_prop, inputs, ctx.window.extent())?;
+///
+/// // scene rendered with targets.jitter_clip_offset()
+/// taa.record(device, cbuf, &mut targets);
+///
+/// // final pass samples targets.output_view()
+/// ```
+///
+pub struct TemporalUpscalePass {
+    pub pipeline: ComputePipeline,
+    pub set_layout: DescriptorSetLayout,
+    /// Linear, clamp to edge
+    pub sampler: vk::Sampler,
+    pub inverted_depth: bool,
+    _owner: ResourceOwner
+}
+
+impl TemporalUpscalePass {
+
+    /// Creates two history images of `output_extent` and descriptor sets reading `inputs`
+    pub fn create_targets(
+        &self,
+        device: &DeviceHandle,
+        memory_prop: &PhysicalDeviceMemoryProperties,
+        inputs: TemporalInputs,
+        output_extent: Extent2D
+    ) -> VulkanResult<TemporalUpscaleTargets> {
+
+        let render_extent = inputs.extent;
+
+        let create_image = || AttachmentImageBuilder::new()
+            .with_device(device)
+            .with_memory_properties(memory_prop)
+            .with_extent(output_extent)
+            .with_format(TEMPORAL_HISTORY_FORMAT)
+            .with_usage(vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED)
+            .build()
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateImageFailed(e))));
+
+        let first = create_image()?;
+        let second = match create_image() {
+            Ok(second) => second,
+            Err(e) => {
+                first.destroy(device);
+                return Err(e);
+            }
+        };
+
+        let owner = ResourceOwner::new(device, vec![
+            DeferredResource::ImageView(first.view),
+            DeferredResource::ImageView(second.view),
+            DeferredResource::Image(first.raw, first.memory),
+            DeferredResource::Image(second.raw, second.memory)
+        ]);
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(10),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::SAMPLER)
+                .descriptor_count(2),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(2),
+        ];
+
+        let pool = DescriptorPoolBuilder::new()
+            .with_device(device)
+            .with_pool_sizes(&pool_sizes)
+            .with_max_sets(2)
+            .build()?;
+
+        let set_layouts = [self.set_layout.raw, self.set_layout.raw];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool.raw)
+            .set_layouts(&set_layouts);
+
+        let sets = unsafe { device.allocate_descriptor_sets(&allocate_info) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::AllocateDescriptorSetFailed(e))))?;
+
+        let read = |view: vk::ImageView, layout: vk::ImageLayout| [vk::DescriptorImageInfo::default().image_view(view).image_layout(layout)];
+
+        // Без маски на её месте цвет, шейдер его не читает
+        let inputs_info = [
+            read(inputs.color, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            read(inputs.depth, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            read(inputs.motion_vectors, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            read(inputs.reactive_mask.unwrap_or(inputs.color), vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        ];
+
+        // Набор i пишет history[i] и читает history[1 - i]
+        let history_info = [
+            read(second.view, vk::ImageLayout::GENERAL),
+            read(first.view, vk::ImageLayout::GENERAL),
+        ];
+        let output_info = [
+            read(first.view, vk::ImageLayout::GENERAL),
+            read(second.view, vk::ImageLayout::GENERAL),
+        ];
+        let samplers = [vk::DescriptorImageInfo::default().sampler(self.sampler)];
+
+        let mut descriptor_writes = vec![];
+
+        for (i, set) in sets.iter().enumerate() {
+            for (binding, info) in inputs_info.iter().chain([&history_info[i]]).enumerate() {
+                descriptor_writes.push(vk::WriteDescriptorSet::default()
+                    .dst_set(*set)
+                    .dst_binding(binding as u32)
+                    .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                    .image_info(info));
+            }
+
+            descriptor_writes.push(vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(5)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .image_info(&samplers));
+
+            descriptor_writes.push(vk::WriteDescriptorSet::default()
+                .dst_set(*set)
+                .dst_binding(6)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(&output_info[i]));
+        }
+
+        unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+        Ok(TemporalUpscaleTargets {
+            history: [first.raw, second.raw],
+            history_views: [first.view, second.view],
+            render_extent,
+            output_extent,
+            jitter: JitterSequence::for_upscale(render_extent, output_extent),
+            sets: [sets[0], sets[1]],
+            has_reactive: inputs.reactive_mask.is_some(),
+            frame: 0,
+            reset: false,
+            _pool: pool,
+            _owner: owner
+        })
+    }
+
+    /// Records the upscale of the current frame and advances the jitter, outside of a render pass
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, targets: &mut TemporalUpscaleTargets) {
+
+        let index = (targets.frame % 2) as usize;
+        let first_frame = targets.frame == 0;
+
+        // Первый кадр: обе истории из UNDEFINED, дальше они всегда в GENERAL
+        let history = targets.history.map(|image| {
+            color_image_barrier(image)
+                .src_access_mask(vk::AccessFlags::empty())
+                .dst_access_mask(vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::GENERAL)
+        });
+
+        // Цвет, глубина и векторы движения записаны рендер-пассами перед этим
+        let scene = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ);
+
+        let written = color_image_barrier(targets.history[index])
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .old_layout(vk::ImageLayout::GENERAL)
+            .new_layout(vk::ImageLayout::GENERAL);
+
+        let constants = TemporalConstants {
+            jitter: targets.jitter_offset(),
+            render_size: [targets.render_extent.width, targets.render_extent.height],
+            output_size: [targets.output_extent.width, targets.output_extent.height],
+            reset: (targets.reset || first_frame) as u32,
+            has_reactive: targets.has_reactive as u32,
+            inverted_depth: self.inverted_depth as u32
+        };
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS |
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[scene],
+                &[],
+                if first_frame { &history[..] } else { &[] }
+            );
+        }
+
+        self.pipeline.bind_descriptor_sets(device, command_buffer, 0, &[targets.sets[index]]);
+        self.pipeline.push_constants(device, command_buffer, 0, constants.as_bytes());
+        self.pipeline.dispatch_threads(device, command_buffer, [targets.output_extent.width, targets.output_extent.height, 1], WORKGROUP_SIZE);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[written]
+            );
+        }
+
+        targets.frame += 1;
+        targets.reset = false;
+    }
+}
+
+#[derive(Default)]
+pub struct TemporalUpscalePassBuilder<'n> {
+    device: Option<&'n DeviceHandle>,
+    shader: Option<ShaderModule>,
+    inverted_depth: Option<bool>,
+    pipeline_cache: Option<vk::PipelineCache>
+}
+
+impl<'n> TemporalUpscalePassBuilder<'n> {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with_device(mut self, device: &'n DeviceHandle) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    pub fn with_shader(mut self, shader: ShaderModule) -> Self {
+        self.shader = Some(shader);
+        self
+    }
+
+    /// Reversed Z, closer is larger depth. Default: false
+    pub fn with_inverted_depth(mut self, inverted: bool) -> Self {
+        self.inverted_depth = Some(inverted);
+        self
+    }
+
+    pub fn with_pipeline_cache(mut self, cache: vk::PipelineCache) -> Self {
+        self.pipeline_cache = Some(cache);
+        self
+    }
+
+    pub fn build(self) -> VulkanResult<TemporalUpscalePass> {
+
+        let device = self.device.ok_or(VulkanError::missing("TemporalUpscalePassBuilder", "device"))?;
+        let shader = self.shader.ok_or(VulkanError::missing("TemporalUpscalePassBuilder", "compute shader"))?;
+
+        let sampled = |binding: u32, ty: vk::DescriptorType| vk::DescriptorSetLayoutBinding::default()
+            .binding(binding)
+            .descriptor_type(ty)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE);
+
+        let bindings = [
+            sampled(0, vk::DescriptorType::SAMPLED_IMAGE),
+            sampled(1, vk::DescriptorType::SAMPLED_IMAGE),
+            sampled(2, vk::DescriptorType::SAMPLED_IMAGE),
+            sampled(3, vk::DescriptorType::SAMPLED_IMAGE),
+            sampled(4, vk::DescriptorType::SAMPLED_IMAGE),
+            sampled(5, vk::DescriptorType::SAMPLER),
+            sampled(6, vk::DescriptorType::STORAGE_IMAGE),
+        ];
+
+        let set_layout = DescriptorSetLayoutBuilder::new()
+            .with_device(device)
+            .with_bindings(&bindings)
+            .build()?;
+
+        let set_layouts = [set_layout.raw];
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: std::mem::size_of::<TemporalConstants>() as u32
+        }];
+
+        let mut builder = ComputePipelineBuilder::new()
+            .with_device(device)
+            .with_shader(shader)
+            .with_descriptor_set_layouts(&set_layouts)
+            .with_push_constant_ranges(&push_constant_ranges);
+
+        if let Some(cache) = self.pipeline_cache {
+            builder = builder.with_pipeline_cache(cache);
+        }
+
+        let pipeline = builder.build()?;
+
+        // История читается билинейно, остальное через texelFetch
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+
+        let sampler = unsafe { device.create_sampler(&sampler_info, None) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateSamplerFailed(e))))?;
+
+        Ok(TemporalUpscalePass {
+            pipeline,
+            set_layout,
+            sampler,
+            inverted_depth: self.inverted_depth.unwrap_or(false),
+            _owner: ResourceOwner::new(device, vec![DeferredResource::Sampler(sampler)])
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PipelineReflection, ShaderReflection};
+
+    fn load(bytes: &[u8]) -> Vec<u32> {
+        bytes.chunks_exact(4).map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]])).collect()
+    }
+
+    #[test]
+    fn test_constants_match_shader() {
+        let shader = ShaderReflection::from_spirv(&load(include_bytes!("../../../../shared/shaders/spv/temporal-upscale-comp.spv"))).unwrap();
+        let reflection = PipelineReflection::merge(&[shader]).unwrap();
+
+        assert_eq!(reflection.push_constant_ranges[0].size as usize, std::mem::size_of::<TemporalConstants>());
+
+        let set = &reflection.sets[&0];
+        assert_eq!(set[4].descriptor_type, vk::DescriptorType::SAMPLED_IMAGE);
+        assert_eq!(set[5].descriptor_type, vk::DescriptorType::SAMPLER);
+        assert_eq!(set[6].descriptor_type, vk::DescriptorType::STORAGE_IMAGE);
+    }
+
+    #[test]
+    fn test_halton() {
+        assert_eq!([1, 2, 3, 4].map(|i| halton(i, 2)), [0.5, 0.25, 0.75, 0.125]);
+        assert!((halton(1, 3) - 1.0 / 3.0).abs() < 1e-6);
+        assert!((halton(5, 3) - 7.0 / 9.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_jitter_sequence() {
+        let render = Extent2D { width: 1280, height: 720 };
+        let jitter = JitterSequence::for_upscale(render, Extent2D { width: 1920, height: 1080 });
+
+        assert_eq!(jitter.phase_count(), 18);
+        assert_eq!(JitterSequence::for_upscale(render, render).phase_count(), 8);
+        assert_eq!(jitter.offset(0), jitter.offset(18));
+        assert_ne!(jitter.offset(0), jitter.offset(1));
+
+        for frame in 0..18 {
+            let [x, y] = jitter.offset(frame);
+            assert!((-0.5..0.5).contains(&x) && (-0.5..0.5).contains(&y));
+        }
+
+        let [x, _] = jitter.clip_offset(0, render);
+        assert_eq!(x, 2.0 * jitter.offset(0)[0] / 1280.0);
+    }
+}
@@ -2,9 +2,14 @@
 use ash::vk::{self, Extent3D, Image};
 use ash::vk::Format;
 
-#[derive(Clone, Copy)]
+use crate::{BindlessDescriptorSet, BindlessHandle, DeviceHandle, ResourceOwner, VulkanResult};
+
 pub struct Texture {
-    pub raw: Image
+    pub raw: Image,
+    /// Index in the bindless `textures[]` array, see [`Texture::register_bindless`]
+    pub bindless: Option<BindlessHandle>,
+    /// Releases the bindless slot on drop, the image itself is not owned
+    _bindless_owner: Option<ResourceOwner>
 }
 
 
@@ -28,7 +33,32 @@ impl Texture {
         let image = unsafe { device.create_image(&texture_create_info, None).unwrap() };
 
         Self {
-            raw: image
+            raw: image,
+            bindless: None,
+            _bindless_owner: None
         }
     }
+
+    ///
+    /// Registers the texture in the bindless set once its view exists.
+    /// Repeated calls return the same handle, the slot is released on drop
+    ///
+    pub fn register_bindless(
+        &mut self,
+        device: &DeviceHandle,
+        bindless: &mut BindlessDescriptorSet,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler
+    ) -> VulkanResult<BindlessHandle> {
+
+        if let Some(handle) = self.bindless {
+            return Ok(handle);
+        }
+
+        let handle = bindless.register_sampled_image(device, image_view, sampler)?;
+        self.bindless = Some(handle);
+        self._bindless_owner = Some(ResourceOwner::new(device, vec![bindless.deferred_release_sampled_image(handle)]));
+
+        Ok(handle)
+    }
 }
\ No newline at end of file
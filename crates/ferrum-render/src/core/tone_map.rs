@@ -0,0 +1,278 @@
+use ash::vk::{self, Extent2D, Format, ShaderModule, SurfaceFormatKHR};
+
+use crate::{DescriptorSetLayout, DeviceHandle, DescriptorSetLayoutBuilder, OutputColorSpace, RenderPass, RenderPipeline, RenderPipelineBuilder, VulkanError, VulkanResult};
+
+const DEFAULT_PAPER_WHITE_NITS: f32 = 200.0;
+const DEFAULT_MAX_NITS: f32 = 1000.0;
+
+fn is_srgb_format(format: Format) -> bool {
+    matches!(format, Format::B8G8R8A8_SRGB | Format::R8G8B8A8_SRGB | Format::A8B8G8R8_SRGB_PACK32)
+}
+
+///
+/// Push constants of `tonemap.frag`, layout must match the shader
+///
+/// # Example
+/// ```
+/// let params = ToneMapParams::new(OutputColorSpace::Hdr10, format)
+///     .with_exposure(1.5)
+///     .with_max_nits(600.0);
+/// ```
+///
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapParams {
+    pub exposure: f32,
+    /// Brightness of 1.0 in the scene for HDR outputs
+    pub paper_white_nits: f32,
+    /// Peak brightness of the display for HDR outputs
+    pub max_nits: f32,
+    pub mode: u32,
+    /// UNORM swapchain formats need manual sRGB encoding
+    pub encode_srgb: u32
+}
+
+impl ToneMapParams {
+
+    pub fn new(color_space: OutputColorSpace, format: Format) -> Self {
+        let mode = match color_space {
+            OutputColorSpace::Srgb => 0,
+            OutputColorSpace::DisplayP3 => 1,
+            OutputColorSpace::Hdr10 => 2,
+            OutputColorSpace::ScRgb => 3
+        };
+
+        Self {
+            exposure: 1.0,
+            paper_white_nits: DEFAULT_PAPER_WHITE_NITS,
+            max_nits: DEFAULT_MAX_NITS,
+            mode,
+            encode_srgb: (!color_space.is_hdr() && !is_srgb_format(format)) as u32
+        }
+    }
+
+    pub fn with_exposure(mut self, exposure: f32) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    /// Default: 200
+    pub fn with_paper_white(mut self, nits: f32) -> Self {
+        self.paper_white_nits = nits;
+        self
+    }
+
+    /// Default: 1000, use `max_luminance` of the display when known
+    pub fn with_max_nits(mut self, nits: f32) -> Self {
+        self.max_nits = nits;
+        self
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, std::mem::size_of::<Self>()) }
+    }
+}
+
+///
+/// Fullscreen pass that maps a linear HDR scene to the swapchain colour space:
+/// ACES for sRGB / Display-P3, PQ for HDR10, linear scaling for scRGB.
+///
+/// Set 0 holds the scene as SAMPLED_IMAGE (binding 0) and SAMPLER (binding 1)
+///
+/// # Example
+/// ```
+/// let tone_map = ToneMapPassBuilder::new()
+///     .with_device(device)
+///     .with_vertex_shader(shader.vertex_shader)     // fullscreen-vert.spv
+///     .with_fragment_shader(shader.fragment_shader) // tonemap-frag.spv
+///     .with_surface_format(ctx.window.surface_format_khr)
+///     .with_render_pass(&render_pass)
+///     .build()?;
+///
+/// // inside the render pass
+/// tone_map.record(device, cbuf, scene_set, ctx.window.extent());
+/// ```
+///
+pub struct ToneMapPass {
+    pub pipeline: RenderPipeline,
+    pub set_layout: DescriptorSetLayout,
+    pub color_space: OutputColorSpace,
+    pub params: ToneMapParams
+}
+
+impl ToneMapPass {
+
+    pub fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, scene: vk::DescriptorSet, extent: Extent2D) {
+
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0
+        };
+
+        let scissor = vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent };
+
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.raw);
+            device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+            device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.raw_layout, 0, &[scene], &[]);
+            device.cmd_push_constants(command_buffer, self.pipeline.raw_layout, vk::ShaderStageFlags::FRAGMENT, 0, self.params.as_bytes());
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ToneMapPassBuilder<'n> {
+    device: Option<&'n DeviceHandle>,
+    vertex_shader: Option<ShaderModule>,
+    fragment_shader: Option<ShaderModule>,
+    surface_format: Option<SurfaceFormatKHR>,
+    render_pass: Option<&'n RenderPass>,
+    pipeline_cache: Option<vk::PipelineCache>
+}
+
+impl<'n> ToneMapPassBuilder<'n> {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with_device(mut self, device: &'n DeviceHandle) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    pub fn with_vertex_shader(mut self, shader: ShaderModule) -> Self {
+        self.vertex_shader = Some(shader);
+        self
+    }
+
+    pub fn with_fragment_shader(mut self, shader: ShaderModule) -> Self {
+        self.fragment_shader = Some(shader);
+        self
+    }
+
+    /// Swapchain format, the colour space and sRGB encoding are derived from it
+    pub fn with_surface_format(mut self, format: SurfaceFormatKHR) -> Self {
+        self.surface_format = Some(format);
+        self
+    }
+
+    /// Without render pass the pipeline is created for dynamic rendering
+    pub fn with_render_pass(mut self, render_pass: &'n RenderPass) -> Self {
+        self.render_pass = Some(render_pass);
+        self
+    }
+
+    pub fn with_pipeline_cache(mut self, cache: vk::PipelineCache) -> Self {
+        self.pipeline_cache = Some(cache);
+        self
+    }
+
+    pub fn build(self) -> VulkanResult<ToneMapPass> {
+
+        let device = self.device.ok_or(VulkanError::missing("ToneMapPassBuilder", "device"))?;
+        let surface_format = self.surface_format.ok_or(VulkanError::missing("ToneMapPassBuilder", "surface format"))?;
+        let color_space = OutputColorSpace::from_surface_format(&surface_format).unwrap_or_default();
+
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT),
+        ];
+
+        let set_layout = DescriptorSetLayoutBuilder::new()
+            .with_device(device)
+            .with_bindings(&bindings)
+            .build()?;
+
+        let set_layouts = [set_layout.raw];
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<ToneMapParams>() as u32
+        }];
+
+        let color_formats = [surface_format.format];
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+        let mut builder = RenderPipelineBuilder::new()
+            .with_device(device)
+            .with_vertex_shader(self.vertex_shader.ok_or(VulkanError::missing("ToneMapPassBuilder", "vertex shader"))?)
+            .with_fragment_shader(self.fragment_shader.ok_or(VulkanError::missing("ToneMapPassBuilder", "fragment shader"))?)
+            .with_input_assembly_info(input_assembly)
+            // Viewport is dynamic state, set in record()
+            .with_resolution(Extent2D { width: 1, height: 1 })
+            .with_cull_mode(vk::CullModeFlags::NONE)
+            .with_depth_test(false)
+            .with_depth_write(false)
+            .with_descriptor_set_layouts(&set_layouts)
+            .with_push_constant_ranges(&push_constant_ranges);
+
+        builder = match self.render_pass {
+            Some(render_pass) => builder.with_render_pass(render_pass),
+            None => builder.with_color_formats(&color_formats)
+        };
+
+        if let Some(cache) = self.pipeline_cache {
+            builder = builder.with_pipeline_cache(cache);
+        }
+
+        let pipeline = builder.build()?;
+
+        Ok(ToneMapPass {
+            pipeline,
+            set_layout,
+            color_space,
+            params: ToneMapParams::new(color_space, surface_format.format)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PipelineReflection, ShaderReflection};
+
+    fn load(bytes: &[u8]) -> Vec<u32> {
+        bytes.chunks_exact(4).map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]])).collect()
+    }
+
+    #[test]
+    fn test_params_match_shader() {
+        let vs = ShaderReflection::from_spirv(&load(include_bytes!("../../../../shared/shaders/spv/fullscreen-vert.spv"))).unwrap();
+        let fs = ShaderReflection::from_spirv(&load(include_bytes!("../../../../shared/shaders/spv/tonemap-frag.spv"))).unwrap();
+        let reflection = PipelineReflection::merge(&[vs, fs]).unwrap();
+
+        assert_eq!(reflection.push_constant_ranges[0].size as usize, std::mem::size_of::<ToneMapParams>());
+
+        let set = &reflection.sets[&0];
+        assert_eq!(set[0].descriptor_type, vk::DescriptorType::SAMPLED_IMAGE);
+        assert_eq!(set[1].descriptor_type, vk::DescriptorType::SAMPLER);
+    }
+
+    #[test]
+    fn test_params_mode() {
+        let sdr = ToneMapParams::new(OutputColorSpace::Srgb, Format::B8G8R8A8_SRGB);
+        assert_eq!((sdr.mode, sdr.encode_srgb), (0, 0));
+
+        let unorm = ToneMapParams::new(OutputColorSpace::Srgb, Format::B8G8R8A8_UNORM);
+        assert_eq!(unorm.encode_srgb, 1);
+
+        let hdr10 = ToneMapParams::new(OutputColorSpace::Hdr10, Format::A2B10G10R10_UNORM_PACK32);
+        assert_eq!((hdr10.mode, hdr10.encode_srgb), (2, 0));
+    }
+}
@@ -0,0 +1,272 @@
+use ash::vk::{self, Extent2D, Format, PhysicalDeviceMemoryProperties};
+
+use crate::{find_memorytype_index, has_stencil, DeferredResource, DeviceHandle, PipelineError, ResourceOwner, VulkanError, VulkanResult};
+
+/// Image created by the render graph for the passes between its first and last use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransientImageDesc {
+    pub format: Format,
+    pub usage: vk::ImageUsageFlags,
+    /// `None` follows the extent of the window the passes render to
+    pub extent: Option<Extent2D>
+}
+
+impl TransientImageDesc {
+
+    pub fn new(format: Format, usage: vk::ImageUsageFlags) -> Self {
+        Self { format, usage, extent: None }
+    }
+
+    pub fn with_extent(mut self, extent: Extent2D) -> Self {
+        self.extent = Some(extent);
+        self
+    }
+}
+
+/// Indices of the first and the last pass using a transient image, inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransientLifetime {
+    pub first: usize,
+    pub last: usize
+}
+
+impl TransientLifetime {
+
+    pub fn overlaps(&self, other: &TransientLifetime) -> bool {
+        self.first <= other.last && other.first <= self.last
+    }
+
+    /// Extends the lifetime to cover `pass`
+    pub fn with_pass(self, pass: usize) -> Self {
+        Self { first: self.first.min(pass), last: self.last.max(pass) }
+    }
+}
+
+/// Size, alignment and lifetime of one resource for [`plan_aliasing`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AliasRequest {
+    pub size: u64,
+    pub alignment: u64,
+    pub lifetime: TransientLifetime
+}
+
+///
+/// Places resources into one allocation, resources whose lifetimes do not overlap may share memory.
+/// Returns the offset of every request and the size of the allocation
+///
+pub fn plan_aliasing(requests: &[AliasRequest]) -> (Vec<u64>, u64) {
+
+    let mut order = (0..requests.len()).collect::<Vec<_>>();
+    // Крупные ресурсы первыми, мелкие заполняют дыры между ними
+    order.sort_by_key(|&i| std::cmp::Reverse(requests[i].size));
+
+    let mut offsets = vec![0; requests.len()];
+    let mut placed: Vec<usize> = vec![];
+    let mut total = 0;
+
+    for index in order {
+        let request = &requests[index];
+        let alignment = request.alignment.max(1);
+
+        let mut busy = placed.iter()
+            .filter(|&&other| requests[other].lifetime.overlaps(&request.lifetime))
+            .map(|&other| (offsets[other], offsets[other] + requests[other].size))
+            .collect::<Vec<_>>();
+
+        busy.sort();
+
+        let mut offset = 0;
+        for (start, end) in busy {
+            if offset + request.size <= start {
+                break;
+            }
+            offset = offset.max(end.next_multiple_of(alignment));
+        }
+
+        offsets[index] = offset;
+        total = total.max(offset + request.size);
+        placed.push(index);
+    }
+
+    (offsets, total)
+}
+
+/// One image of [`TransientImages`], valid until the graph recreates them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransientImage {
+    pub raw: vk::Image,
+    pub view: vk::ImageView,
+    pub format: Format,
+    pub extent: Extent2D,
+    pub lifetime: TransientLifetime,
+    /// Shares memory with another image, its first pass must not rely on the contents
+    pub aliased: bool
+}
+
+///
+/// Transient images of one set of passes bound to a single allocation, see [`plan_aliasing`].
+/// Handles are queued for destruction on drop
+///
+pub struct TransientImages {
+    pub images: Vec<TransientImage>,
+    /// Size of the shared allocation
+    pub memory_size: u64,
+    /// What the images would take with an allocation each
+    pub unaliased_size: u64,
+    _owner: ResourceOwner
+}
+
+impl TransientImages {
+
+    /// `descs` with a `None` extent get `extent`
+    pub fn new(
+        device: &DeviceHandle,
+        memory_prop: &PhysicalDeviceMemoryProperties,
+        descs: &[(TransientImageDesc, TransientLifetime)],
+        extent: Extent2D
+    ) -> VulkanResult<Self> {
+
+        let image_error = |e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateImageFailed(e)));
+
+        // Владелец собирает хэндлы по мере создания, при ошибке всё уходит в очередь удаления
+        let mut resources = vec![];
+        let mut images = vec![];
+
+        let result = (|| {
+            let mut requirements = vec![];
+
+            for (desc, _) in descs {
+                let extent = desc.extent.unwrap_or(extent);
+
+                let image_info = vk::ImageCreateInfo::default()
+                    .image_type(vk::ImageType::TYPE_2D)
+                    .format(desc.format)
+                    .extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+                    .mip_levels(1)
+                    .array_layers(1)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .tiling(vk::ImageTiling::OPTIMAL)
+                    .usage(desc.usage)
+                    .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+                let image = unsafe { device.create_image(&image_info, None) }.map_err(image_error)?;
+                resources.push(DeferredResource::Image(image, vk::DeviceMemory::null()));
+                requirements.push(unsafe { device.get_image_memory_requirements(image) });
+                images.push((image, extent));
+            }
+
+            let requests = requirements.iter().zip(descs).map(|(req, (_, lifetime))| AliasRequest {
+                size: req.size,
+                alignment: req.alignment,
+                lifetime: *lifetime
+            }).collect::<Vec<_>>();
+
+            let (offsets, memory_size) = plan_aliasing(&requests);
+
+            let combined = vk::MemoryRequirements {
+                size: memory_size,
+                alignment: requests.iter().map(|x| x.alignment).max().unwrap_or(1),
+                memory_type_bits: requirements.iter().fold(u32::MAX, |bits, req| bits & req.memory_type_bits)
+            };
+
+            let memory_type_index = find_memorytype_index(&combined, memory_prop, vk::MemoryPropertyFlags::DEVICE_LOCAL)
+                .ok_or(image_error(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY))?;
+
+            let alloc_info = vk::MemoryAllocateInfo::default()
+                .allocation_size(memory_size.max(1))
+                .memory_type_index(memory_type_index);
+
+            let memory = unsafe { device.allocate_memory(&alloc_info, None) }.map_err(image_error)?;
+            resources.push(DeferredResource::Custom(Box::new(move |device| unsafe { device.free_memory(memory, None) })));
+
+            let mut transient = vec![];
+
+            for (index, ((image, extent), (desc, lifetime))) in images.iter().zip(descs).enumerate() {
+                unsafe { device.bind_image_memory(*image, memory, offsets[index]) }.map_err(image_error)?;
+
+                let aspect_mask = if desc.usage.contains(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT) {
+                    if has_stencil(desc.format) {
+                        vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+                    } else {
+                        vk::ImageAspectFlags::DEPTH
+                    }
+                } else {
+                    vk::ImageAspectFlags::COLOR
+                };
+
+                let view_info = vk::ImageViewCreateInfo::default()
+                    .image(*image)
+                    .view_type(vk::ImageViewType::TYPE_2D)
+                    .format(desc.format)
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask,
+                        base_mip_level: 0,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    });
+
+                let view = unsafe { device.create_image_view(&view_info, None) }.map_err(image_error)?;
+                // Вьюхи уничтожаются раньше образов и памяти
+                resources.insert(0, DeferredResource::ImageView(view));
+
+                let range = offsets[index]..offsets[index] + requests[index].size;
+                let aliased = requests.iter().enumerate().any(|(other, request)| {
+                    other != index && offsets[other] < range.end && range.start < offsets[other] + request.size
+                });
+
+                transient.push(TransientImage {
+                    raw: *image,
+                    view,
+                    format: desc.format,
+                    extent: *extent,
+                    lifetime: *lifetime,
+                    aliased
+                });
+            }
+
+            let unaliased_size = requests.iter().map(|x| x.size).sum();
+            Ok((transient, memory_size, unaliased_size))
+        })();
+
+        let owner = ResourceOwner::new(device, resources);
+        let (images, memory_size, unaliased_size) = result?;
+
+        Ok(Self { images, memory_size, unaliased_size, _owner: owner })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(size: u64, first: usize, last: usize) -> AliasRequest {
+        AliasRequest { size, alignment: 256, lifetime: TransientLifetime { first, last } }
+    }
+
+    #[test]
+    fn test_disjoint_lifetimes_share_memory() {
+        // 0 и 1 живут одновременно, 2 начинается после них
+        let requests = [request(1024, 0, 1), request(512, 1, 2), request(1024, 3, 4)];
+        let (offsets, total) = plan_aliasing(&requests);
+
+        assert_eq!(total, 1536);
+        assert_eq!(offsets[0], 0);
+        assert_eq!(offsets[1], 1024);
+        assert_eq!(offsets[2], 0);
+    }
+
+    #[test]
+    fn test_alignment_and_gaps() {
+        let requests = [request(1000, 0, 2), request(100, 0, 0), request(300, 1, 1)];
+        let (offsets, total) = plan_aliasing(&requests);
+
+        // 100 и 300 не пересекаются по времени и оба встают за первым с выравниванием
+        assert_eq!(offsets, vec![0, 1024, 1024]);
+        assert_eq!(total, 1324);
+
+        let lifetime = TransientLifetime { first: 3, last: 3 }.with_pass(1);
+        assert_eq!(lifetime, TransientLifetime { first: 1, last: 3 });
+        assert!(!lifetime.overlaps(&TransientLifetime { first: 4, last: 5 }));
+    }
+}
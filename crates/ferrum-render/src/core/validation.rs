@@ -0,0 +1,292 @@
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use ash::vk;
+
+/// `log` target of validation messages, e.g. `RUST_LOG=vulkan=warn`
+pub const VALIDATION_LOG_TARGET: &str = "vulkan";
+
+/// One message received by [`vulkan_debug_callback`]
+#[derive(Debug, Clone)]
+pub struct ValidationMessage {
+    pub severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    /// `VUID-...` name, empty when the layer did not set it
+    pub id_name: String,
+    pub id_number: i32,
+    pub message: String,
+    /// `BUFFER 0x1234 "vertices"` for every object the message refers to
+    pub objects: Vec<String>
+}
+
+impl fmt::Display for ValidationMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{:?}] {} ({:#x}): {}", self.message_type, self.id_name, self.id_number, self.message)?;
+
+        if !self.objects.is_empty() {
+            write!(f, " | objects: {}", self.objects.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+///
+/// Collects validation errors, rendering tests fail when any error was reported
+///
+/// # Example
+/// ```
+/// let capture = ValidationCapture::new();
+///
+/// let instance = InstanceBuilder::new()
+///     .with_validation(ValidationConfig::new().with_capture(capture.clone()))
+///     ...
+///     .build()?;
+///
+/// // render a frame
+/// capture.assert_no_errors();
+/// ```
+///
+#[derive(Clone, Default)]
+pub struct ValidationCapture {
+    errors: Arc<Mutex<Vec<ValidationMessage>>>
+}
+
+impl ValidationCapture {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn errors(&self) -> Vec<ValidationMessage> {
+        self.errors.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn clear(&self) {
+        self.errors.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    ///
+    /// # Panics
+    /// if any validation error was collected, all of them are listed
+    ///
+    pub fn assert_no_errors(&self) {
+        let errors = self.errors();
+
+        if !errors.is_empty() {
+            let list = errors.iter().map(|x| x.to_string()).collect::<Vec<_>>().join("\n");
+            panic!("{} Vulkan validation error(s):\n{}", errors.len(), list);
+        }
+    }
+
+    fn push(&self, message: ValidationMessage) {
+        self.errors.lock().unwrap_or_else(|e| e.into_inner()).push(message);
+    }
+}
+
+///
+/// Routing of validation messages into `log`, passed to [`crate::InstanceBuilder::with_validation`].
+/// Severities map to `ERROR -> error`, `WARNING -> warn`, `INFO -> debug`, `VERBOSE -> trace`
+///
+#[derive(Clone)]
+pub struct ValidationConfig {
+    min_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    suppressed_names: HashSet<String>,
+    suppressed_numbers: HashSet<i32>,
+    capture: Option<ValidationCapture>
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            min_severity: vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE,
+            suppressed_names: HashSet::new(),
+            suppressed_numbers: HashSet::new(),
+            capture: None
+        }
+    }
+}
+
+impl ValidationConfig {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    /// Messages below `severity` are dropped before reaching `log`. Default: VERBOSE
+    pub fn with_min_severity(mut self, severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> Self {
+        self.min_severity = severity;
+        self
+    }
+
+    /// Drops messages by their `VUID-...` name
+    pub fn suppress_message_id(mut self, name: &str) -> Self {
+        self.suppressed_names.insert(name.to_owned());
+        self
+    }
+
+    /// Drops messages by `messageIdNumber`
+    pub fn suppress_message_id_number(mut self, number: i32) -> Self {
+        self.suppressed_numbers.insert(number);
+        self
+    }
+
+    /// Errors that are not suppressed are also stored in `capture`
+    pub fn with_capture(mut self, capture: ValidationCapture) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    fn is_suppressed(&self, message: &ValidationMessage) -> bool {
+        message.severity.as_raw() < self.min_severity.as_raw()
+            || self.suppressed_numbers.contains(&message.id_number)
+            || self.suppressed_names.contains(&message.id_name)
+    }
+
+    fn route(&self, message: ValidationMessage) {
+
+        if self.is_suppressed(&message) {
+            return;
+        }
+
+        let level = match message.severity {
+            vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => log::Level::Error,
+            vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => log::Level::Warn,
+            vk::DebugUtilsMessageSeverityFlagsEXT::INFO => log::Level::Debug,
+            _ => log::Level::Trace
+        };
+
+        log::log!(target: VALIDATION_LOG_TARGET, level, "{}", message);
+
+        if let Some(capture) = &self.capture
+            && message.severity == vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
+            capture.push(message);
+        }
+    }
+}
+
+unsafe fn lossy(ptr: *const std::ffi::c_char) -> String {
+    if ptr.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned()
+    }
+}
+
+unsafe fn read_message(
+    severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    data: &vk::DebugUtilsMessengerCallbackDataEXT<'_>
+) -> ValidationMessage {
+
+    let objects = if data.p_objects.is_null() {
+        &[][..]
+    } else {
+        unsafe { std::slice::from_raw_parts(data.p_objects, data.object_count as usize) }
+    };
+
+    let objects = objects.iter().map(|object| {
+        let name = unsafe { lossy(object.p_object_name) };
+
+        if name.is_empty() {
+            format!("{:?} {:#x}", object.object_type, object.object_handle)
+        } else {
+            format!("{:?} {:#x} {:?}", object.object_type, object.object_handle, name)
+        }
+    }).collect();
+
+    ValidationMessage {
+        severity,
+        message_type,
+        id_name: unsafe { lossy(data.p_message_id_name) },
+        id_number: data.message_id_number,
+        message: unsafe { lossy(data.p_message) },
+        objects
+    }
+}
+
+///
+/// Debug messenger callback, `p_user_data` is a [`ValidationConfig`] or null for the defaults
+///
+/// # Safety
+/// Called by the loader, `p_callback_data` and `p_user_data` must be valid or null
+///
+pub unsafe extern "system" fn vulkan_debug_callback(
+    message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
+    p_user_data: *mut std::ffi::c_void,
+) -> vk::Bool32 {
+
+    if p_callback_data.is_null() {
+        return vk::FALSE;
+    }
+
+    let message = unsafe { read_message(message_severity, message_type, &*p_callback_data) };
+
+    match unsafe { (p_user_data as *const ValidationConfig).as_ref() } {
+        Some(config) => config.route(message),
+        None => ValidationConfig::default().route(message)
+    }
+
+    vk::FALSE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(config: &ValidationConfig, severity: vk::DebugUtilsMessageSeverityFlagsEXT, id_name: &CStr, id_number: i32) {
+
+        let objects = [vk::DebugUtilsObjectNameInfoEXT::default()
+            .object_handle(vk::Buffer::null())
+            .object_name(c"vertices")];
+
+        let data = vk::DebugUtilsMessengerCallbackDataEXT::default()
+            .message_id_name(id_name)
+            .message_id_number(id_number)
+            .message(c"something went wrong")
+            .objects(&objects);
+
+        unsafe {
+            vulkan_debug_callback(
+                severity,
+                vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+                &data,
+                config as *const ValidationConfig as *mut std::ffi::c_void
+            );
+        }
+    }
+
+    #[test]
+    fn test_capture_and_suppress() {
+
+        let capture = ValidationCapture::new();
+        let config = ValidationConfig::new()
+            .with_min_severity(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING)
+            .suppress_message_id("VUID-ignored")
+            .suppress_message_id_number(42)
+            .with_capture(capture.clone());
+
+        send(&config, vk::DebugUtilsMessageSeverityFlagsEXT::ERROR, c"VUID-ignored", 1);
+        send(&config, vk::DebugUtilsMessageSeverityFlagsEXT::ERROR, c"VUID-other", 42);
+        send(&config, vk::DebugUtilsMessageSeverityFlagsEXT::WARNING, c"VUID-warning", 2);
+        capture.assert_no_errors();
+
+        send(&config, vk::DebugUtilsMessageSeverityFlagsEXT::ERROR, c"VUID-real", 3);
+
+        let errors = capture.errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].id_name, "VUID-real");
+        assert_eq!(errors[0].message, "something went wrong");
+        assert_eq!(errors[0].objects, vec!["BUFFER 0x0 \"vertices\""]);
+
+        let result = std::panic::catch_unwind(|| capture.assert_no_errors());
+        assert!(result.is_err());
+
+        capture.clear();
+        capture.assert_no_errors();
+    }
+}
@@ -0,0 +1,12 @@
+use ash::vk;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DeviceError {
+    #[error("Failed to create logical device (Vulkan error: {0:?})")]
+    CreateDeviceFailed(vk::Result),
+    #[error("Failed to wait for device idle (Vulkan error: {0:?})")]
+    WaitIdleFailed(vk::Result),
+    #[error("Required device features are not supported: {}", .0.join(", "))]
+    MissingFeatures(Vec<String>)
+}
@@ -14,6 +14,8 @@ pub enum InstanceError {
     MissingRequiredExtension(String),
     #[error("Required Vulkan layer not available: {0}")]
     MissingRequiredLayer(String),
+    #[error("Failed to enumerate instance extensions (VkResult: {0:?})")]
+    EnumerateInstanceExtensionPropertiesFailed(vk::Result),
     #[error("Failed to enumerate instance layers (VkResult: {0:?})")]
     EnumerateInstanceLayerPropertiesFailed(vk::Result),
     #[error("Vulkan layer is not supported: {0:?}")]
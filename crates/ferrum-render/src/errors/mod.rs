@@ -7,6 +7,32 @@ pub use app::AppError;
 
 pub mod phys_dev;
 pub use phys_dev::PhysicalDeviceError;
+
+pub mod reflection;
+pub use reflection::ReflectionError;
+
+pub mod pipeline_cache;
+pub use pipeline_cache::PipelineCacheError;
+
+pub mod sync;
+pub use sync::SyncError;
+
+pub mod device;
+pub use device::DeviceError;
+
+pub mod surface;
+pub use surface::SurfaceError;
+
+pub mod swapchain;
+pub use swapchain::SwapchainError;
+
+pub mod pipeline;
+pub use pipeline::PipelineError;
+
+pub mod shader;
+pub use shader::ShaderError;
+
+use ash::vk;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -17,8 +43,69 @@ pub enum VulkanError {
     Instance(InstanceError),
     #[error("Physical device error: {0}")]
     PhysicalDevice(PhysicalDeviceError),
+    #[error("Shader reflection error: {0}")]
+    Reflection(ReflectionError),
+    #[error("Pipeline cache error: {0}")]
+    PipelineCache(PipelineCacheError),
+    #[error("Synchronization error: {0}")]
+    Sync(SyncError),
+    #[error("Device error: {0}")]
+    Device(DeviceError),
+    #[error("Surface error: {0}")]
+    Surface(SurfaceError),
+    #[error("Swapchain error: {0}")]
+    Swapchain(SwapchainError),
+    #[error("Pipeline error: {0}")]
+    Pipeline(PipelineError),
+    #[error("Shader error: {0}")]
+    Shader(ShaderError),
+    #[error("{builder}: {field} is missing")]
+    MissingField {
+        builder: &'static str,
+        field: &'static str
+    },
+    /// Every object of the device is lost, recreate the device and all resources
+    #[error("Device lost")]
+    DeviceLost,
     #[error("Unknown error")]
     Unknown,
 }
 
-pub type VulkanResult<T> = core::result::Result<T, VulkanError>;
\ No newline at end of file
+impl VulkanError {
+
+    pub fn missing(builder: &'static str, field: &'static str) -> Self {
+        VulkanError::MissingField { builder, field }
+    }
+
+    /// `ERROR_DEVICE_LOST` becomes [`VulkanError::DeviceLost`], other results are passed to `wrap`
+    pub fn vk(result: vk::Result, wrap: impl FnOnce(vk::Result) -> VulkanError) -> Self {
+        if result == vk::Result::ERROR_DEVICE_LOST {
+            VulkanError::DeviceLost
+        } else {
+            wrap(result)
+        }
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        matches!(self, VulkanError::DeviceLost)
+    }
+}
+
+pub type VulkanResult<T> = core::result::Result<T, VulkanError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_lost_is_separated() {
+        let lost = VulkanError::vk(vk::Result::ERROR_DEVICE_LOST, |e| VulkanError::Device(DeviceError::CreateDeviceFailed(e)));
+        assert!(lost.is_device_lost());
+
+        let other = VulkanError::vk(vk::Result::ERROR_OUT_OF_HOST_MEMORY, |e| VulkanError::Device(DeviceError::CreateDeviceFailed(e)));
+        assert!(matches!(other, VulkanError::Device(DeviceError::CreateDeviceFailed(vk::Result::ERROR_OUT_OF_HOST_MEMORY))));
+
+        let missing = VulkanError::missing("SwapchainBuilder", "surface");
+        assert_eq!(missing.to_string(), "SwapchainBuilder: surface is missing");
+    }
+}
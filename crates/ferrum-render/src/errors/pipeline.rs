@@ -0,0 +1,34 @@
+use ash::vk;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PipelineError {
+    #[error("Failed to create pipeline layout (Vulkan error: {0:?})")]
+    CreatePipelineLayoutFailed(vk::Result),
+    #[error("Failed to create pipeline (Vulkan error: {0:?})")]
+    CreatePipelineFailed(vk::Result),
+    #[error("Failed to create render pass (Vulkan error: {0:?})")]
+    CreateRenderPassFailed(vk::Result),
+    #[error("Failed to create framebuffer (Vulkan error: {0:?})")]
+    CreateFramebufferFailed(vk::Result),
+    #[error("Failed to create descriptor set layout (Vulkan error: {0:?})")]
+    CreateDescriptorSetLayoutFailed(vk::Result),
+    #[error("Failed to create descriptor pool (Vulkan error: {0:?})")]
+    CreateDescriptorPoolFailed(vk::Result),
+    #[error("Failed to allocate descriptor set (Vulkan error: {0:?})")]
+    AllocateDescriptorSetFailed(vk::Result),
+    #[error("Failed to create sampler (Vulkan error: {0:?})")]
+    CreateSamplerFailed(vk::Result),
+    #[error("Failed to create pass image (Vulkan error: {0:?})")]
+    CreateImageFailed(vk::Result),
+    #[error("Failed to create pass buffer (Vulkan error: {0:?})")]
+    CreateBufferFailed(vk::Result),
+    #[error("Bindless {0} array is full")]
+    BindlessArrayFull(&'static str),
+    #[error("Bindless {array} array of {requested} descriptors exceeds the device limit of {limit}")]
+    BindlessLimitExceeded {
+        array: &'static str,
+        requested: u32,
+        limit: u32
+    }
+}
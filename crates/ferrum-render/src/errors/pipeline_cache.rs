@@ -0,0 +1,12 @@
+use ash::vk;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PipelineCacheError {
+    #[error("Failed to create pipeline cache (Vulkan error: {0:?})")]
+    CreatePipelineCacheFailed(vk::Result),
+    #[error("Failed to get pipeline cache data (Vulkan error: {0:?})")]
+    GetPipelineCacheDataFailed(vk::Result),
+    #[error("Failed to write pipeline cache file: {0}")]
+    WriteFailed(std::io::Error)
+}
@@ -0,0 +1,24 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ReflectionError {
+    #[error("Invalid SPIR-V: {0}")]
+    InvalidSpirv(&'static str),
+    #[error("Entry point not found in SPIR-V module: {0}")]
+    EntryPointNotFound(String),
+    #[error("Unsupported type of vertex input at location {0}")]
+    UnsupportedInputType(u32),
+    #[error("Vertex input location {0} is used by more than one attribute")]
+    InputLocationConflict(u32),
+    #[error("Stages {first_stage} and {second_stage} disagree on set {set} binding {binding}: {first} vs {second}")]
+    BindingMismatch {
+        set: u32,
+        binding: u32,
+        first_stage: String,
+        second_stage: String,
+        first: String,
+        second: String
+    },
+    #[error("Stage {0} is present more than once")]
+    DuplicateStage(String)
+}
@@ -0,0 +1,13 @@
+use ash::vk;
+use ferrum_shaders::CompileError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ShaderError {
+    #[error("No shader stage attached")]
+    NoStages,
+    #[error("Failed to create shader module (Vulkan error: {0:?})")]
+    CreateShaderModuleFailed(vk::Result),
+    #[error("Failed to compile shader: {0}")]
+    Compile(CompileError)
+}
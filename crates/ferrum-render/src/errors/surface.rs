@@ -0,0 +1,24 @@
+use ash::vk;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SurfaceError {
+    #[error("Failed to create surface (Vulkan error: {0:?})")]
+    CreateSurfaceFailed(vk::Result),
+    #[error("Failed to get window handle: {0}")]
+    WindowHandle(String),
+    #[error("Failed to get surface formats (Vulkan error: {0:?})")]
+    GetFormatsFailed(vk::Result),
+    #[error("Failed to get surface capabilities (Vulkan error: {0:?})")]
+    GetCapabilitiesFailed(vk::Result),
+    #[error("Failed to get surface present modes (Vulkan error: {0:?})")]
+    GetPresentModesFailed(vk::Result),
+    #[error("Failed to get surface support (Vulkan error: {0:?})")]
+    GetSupportFailed(vk::Result),
+    #[error("Queue family {0} can't present to the surface")]
+    PresentNotSupported(u32),
+    #[error("Surface supports no formats")]
+    NoFormats,
+    #[error("Surface supports no present modes")]
+    NoPresentModes
+}
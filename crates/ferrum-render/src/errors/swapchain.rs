@@ -0,0 +1,18 @@
+use ash::vk;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SwapchainError {
+    #[error("Failed to create swapchain (Vulkan error: {0:?})")]
+    CreateSwapchainFailed(vk::Result),
+    #[error("Failed to get swapchain images (Vulkan error: {0:?})")]
+    GetImagesFailed(vk::Result),
+    #[error("Failed to create depth/MSAA attachments (Vulkan error: {0:?})")]
+    CreateAttachmentsFailed(vk::Result),
+    #[error("Failed to acquire swapchain image (Vulkan error: {0:?})")]
+    AcquireImageFailed(vk::Result),
+    #[error("Failed to present (Vulkan error: {0:?})")]
+    PresentFailed(vk::Result),
+    #[error("No supported depth format")]
+    NoDepthFormat
+}
@@ -0,0 +1,24 @@
+use ash::vk;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SyncError {
+    #[error("Failed to create semaphore (Vulkan error: {0:?})")]
+    CreateSemaphoreFailed(vk::Result),
+    #[error("Failed to wait for timeline semaphore (Vulkan error: {0:?})")]
+    WaitFailed(vk::Result),
+    #[error("Failed to create fence (Vulkan error: {0:?})")]
+    CreateFenceFailed(vk::Result),
+    #[error("Failed to wait for fence (Vulkan error: {0:?})")]
+    WaitFenceFailed(vk::Result),
+    #[error("Failed to reset fence (Vulkan error: {0:?})")]
+    ResetFenceFailed(vk::Result),
+    #[error("GPU did not reach timeline value {0} in time")]
+    Timeout(u64),
+    #[error("Failed to record command buffer (Vulkan error: {0:?})")]
+    RecordCommandBufferFailed(vk::Result),
+    #[error("Failed to submit to queue (Vulkan error: {0:?})")]
+    SubmitFailed(vk::Result),
+    #[error("Failed to get timeline semaphore value (Vulkan error: {0:?})")]
+    GetCounterValueFailed(vk::Result)
+}
@@ -0,0 +1,298 @@
+use ash::vk::Extent2D;
+
+/// `LIGHT_*` in `pbr.glsl`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LightKind {
+    Directional = 0,
+    Point = 1,
+    Spot = 2
+}
+
+///
+/// Light in world space, std430 `Light` of `pbr.glsl`.
+/// Colour is linear, intensity is a multiplier of it
+///
+/// # Example
+/// ```
+/// let lights = [
+///     Light::directional([-0.3, -1.0, -0.2], [1.0, 0.95, 0.9], 3.0),
+///     Light::point([0.0, 2.0, 0.0], [1.0, 0.4, 0.1], 20.0, 8.0),
+///     Light::spot([4.0, 3.0, 0.0], [0.0, -1.0, 0.0], [1.0; 3], 40.0, 10.0).with_cone(0.2, 0.4).with_shadows(),
+/// ];
+/// ```
+///
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    pub position: [f32; 3],
+    /// Light reaches zero at this distance, used for culling
+    pub range: f32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Direction the light travels, directional and spot
+    pub direction: [f32; 3],
+    pub kind: u32,
+    /// Cone falloff as `cos(angle) * scale + offset`
+    pub spot_scale: f32,
+    pub spot_offset: f32,
+    /// Set by [`crate::ShadowRenderer::update`], -1 without a shadow
+    pub shadow: i32,
+    /// Requests a shadow, see [`Light::with_shadows`]
+    pub cast_shadows: u32
+}
+
+impl Light {
+
+    fn new(kind: LightKind, color: [f32; 3], intensity: f32) -> Self {
+        Self {
+            position: [0.0; 3],
+            range: 0.0,
+            color,
+            intensity,
+            direction: [0.0, -1.0, 0.0],
+            kind: kind as u32,
+            spot_scale: 0.0,
+            spot_offset: 1.0,
+            shadow: -1,
+            cast_shadows: 0
+        }
+    }
+
+    pub fn directional(direction: [f32; 3], color: [f32; 3], intensity: f32) -> Self {
+        Self { direction, ..Self::new(LightKind::Directional, color, intensity) }
+    }
+
+    pub fn point(position: [f32; 3], color: [f32; 3], intensity: f32, range: f32) -> Self {
+        Self { position, range, ..Self::new(LightKind::Point, color, intensity) }
+    }
+
+    /// Cone of 0.3 and 0.5 radians, see [`Light::with_cone`]
+    pub fn spot(position: [f32; 3], direction: [f32; 3], color: [f32; 3], intensity: f32, range: f32) -> Self {
+        Self { position, range, direction, ..Self::new(LightKind::Spot, color, intensity) }
+            .with_cone(0.3, 0.5)
+    }
+
+    /// Full intensity inside `inner_angle`, zero outside `outer_angle`, half angles in radians
+    pub fn with_cone(mut self, inner_angle: f32, outer_angle: f32) -> Self {
+        let cos_outer = outer_angle.cos();
+        self.spot_scale = 1.0 / (inner_angle.cos() - cos_outer).max(1e-4);
+        self.spot_offset = -cos_outer * self.spot_scale;
+        self
+    }
+
+    /// Cascades for the first such directional light, atlas tiles for spot and point lights
+    pub fn with_shadows(mut self) -> Self {
+        self.cast_shadows = 1;
+        self
+    }
+
+    pub fn kind(&self) -> LightKind {
+        match self.kind {
+            0 => LightKind::Directional,
+            2 => LightKind::Spot,
+            _ => LightKind::Point
+        }
+    }
+}
+
+///
+/// Directional lights first, at most `max_lights`. Returns the lights to upload
+/// with the light count and the directional count, the upload is never empty
+///
+pub(crate) fn sort_lights(lights: &[Light], max_lights: u32) -> (Vec<Light>, u32, u32) {
+
+    if lights.len() > max_lights as usize {
+        log::warn!("{} lights passed to the renderer, only {} are used", lights.len(), max_lights);
+    }
+
+    let mut sorted = lights.iter()
+        .filter(|x| x.kind() == LightKind::Directional)
+        .chain(lights.iter().filter(|x| x.kind() != LightKind::Directional))
+        .take(max_lights as usize)
+        .copied()
+        .collect::<Vec<_>>();
+
+    let directional_count = sorted.iter().take_while(|x| x.kind() == LightKind::Directional).count() as u32;
+    let light_count = sorted.len() as u32;
+
+    // Пустой буфер не загрузить, шейдеры всё равно не читают дальше light_count
+    if sorted.is_empty() {
+        sorted.push(Light::point([0.0; 3], [0.0; 3], 0.0, 0.0));
+    }
+
+    (sorted, light_count, directional_count)
+}
+
+/// View-space AABB of one cluster, `ClusterBounds` of `clustered.glsl`
+pub const CLUSTER_BOUNDS_SIZE: u64 = 32;
+
+///
+/// Froxel grid: `size[0] x size[1]` screen tiles, `size[2]` depth slices
+/// spaced exponentially between the near and far planes
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClusterGrid {
+    pub size: [u32; 3],
+    /// Point and spot lights beyond this are dropped from a cluster
+    pub max_lights_per_cluster: u32
+}
+
+impl Default for ClusterGrid {
+    fn default() -> Self {
+        Self { size: [16, 9, 24], max_lights_per_cluster: 128 }
+    }
+}
+
+impl ClusterGrid {
+
+    pub fn cluster_count(&self) -> u32 {
+        self.size[0] * self.size[1] * self.size[2]
+    }
+
+    /// Pixels covered by one tile, `tileSize()` in the shaders
+    pub fn tile_size(&self, screen: Extent2D) -> [u32; 2] {
+        [screen.width.div_ceil(self.size[0]), screen.height.div_ceil(self.size[1])]
+    }
+
+    /// View depth where `slice` begins, `slice == size[2]` is the far plane
+    pub fn slice_depth(&self, slice: u32, near: f32, far: f32) -> f32 {
+        near * (far / near).powf(slice as f32 / self.size[2] as f32)
+    }
+
+    /// Slice of a positive view depth, clamped to the grid
+    pub fn slice(&self, depth: f32, near: f32, far: f32) -> u32 {
+        let slice = ((depth.max(near) / near).ln() / (far / near).ln() * self.size[2] as f32).floor();
+        (slice.max(0.0) as u32).min(self.size[2] - 1)
+    }
+}
+
+///
+/// Camera of the frame, matrices are column-major (`Mat4::to_cols_array_2d` in glam)
+/// and use a Vulkan projection looking down -Z
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClusterCamera {
+    pub view: [[f32; 4]; 4],
+    pub projection: [[f32; 4]; 4],
+    pub inverse_projection: [[f32; 4]; 4],
+    pub position: [f32; 3],
+    pub near: f32,
+    /// Last depth slice ends here, lights further away are still shaded by the last slice
+    pub far: f32
+}
+
+/// std140 `ClusterUniforms` of `clustered.glsl`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClusterUniforms {
+    pub view: [[f32; 4]; 4],
+    pub projection: [[f32; 4]; 4],
+    pub inverse_projection: [[f32; 4]; 4],
+    pub camera_position: [f32; 4],
+    pub ambient: [f32; 4],
+    pub screen_size: [f32; 2],
+    pub z_near: f32,
+    pub z_far: f32,
+    /// xyz - grid size, w - max lights per cluster
+    pub grid: [u32; 4],
+    /// x - lights, y - directional lights at the start of the buffer
+    pub counts: [u32; 4]
+}
+
+impl ClusterUniforms {
+
+    pub fn new(camera: &ClusterCamera, grid: &ClusterGrid, screen: Extent2D) -> Self {
+        let [x, y, z] = camera.position;
+
+        Self {
+            view: camera.view,
+            projection: camera.projection,
+            inverse_projection: camera.inverse_projection,
+            camera_position: [x, y, z, 1.0],
+            ambient: [0.0; 4],
+            screen_size: [screen.width as f32, screen.height as f32],
+            z_near: camera.near,
+            z_far: camera.far,
+            grid: [grid.size[0], grid.size[1], grid.size[2], grid.max_lights_per_cluster],
+            counts: [0; 4]
+        }
+    }
+
+    pub fn with_ambient(mut self, ambient: [f32; 3]) -> Self {
+        let [r, g, b] = ambient;
+        self.ambient = [r, g, b, 0.0];
+        self
+    }
+
+    /// `directional_count` lights at the start of the buffer are directional
+    pub fn with_lights(mut self, light_count: u32, directional_count: u32) -> Self {
+        self.counts = [light_count, directional_count, 0, 0];
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::{offset_of, size_of};
+
+    #[test]
+    fn test_gpu_layouts() {
+        assert_eq!(size_of::<Light>(), 64);
+        assert_eq!(offset_of!(Light, direction), 32);
+        assert_eq!(offset_of!(Light, spot_scale), 48);
+        assert_eq!(offset_of!(Light, shadow), 56);
+
+        assert_eq!(offset_of!(ClusterUniforms, camera_position), 192);
+        assert_eq!(offset_of!(ClusterUniforms, screen_size), 224);
+        assert_eq!(offset_of!(ClusterUniforms, grid), 240);
+        assert_eq!(size_of::<ClusterUniforms>(), 272);
+    }
+
+    #[test]
+    fn test_cluster_slices() {
+        let grid = ClusterGrid::default();
+        let (near, far) = (0.1, 1000.0);
+
+        assert_eq!(grid.slice_depth(0, near, far), near);
+        assert!((grid.slice_depth(24, near, far) - far).abs() < 0.01);
+
+        for slice in 0..24 {
+            let middle = (grid.slice_depth(slice, near, far) + grid.slice_depth(slice + 1, near, far)) * 0.5;
+            assert_eq!(grid.slice(middle, near, far), slice);
+        }
+
+        assert_eq!(grid.slice(0.01, near, far), 0);
+        assert_eq!(grid.slice(5000.0, near, far), 23);
+        assert_eq!(grid.tile_size(Extent2D { width: 1920, height: 1080 }), [120, 120]);
+        assert_eq!(grid.cluster_count(), 3456);
+    }
+
+    #[test]
+    fn test_sort_lights() {
+        let lights = [
+            Light::point([0.0; 3], [1.0; 3], 1.0, 5.0),
+            Light::directional([0.0, -1.0, 0.0], [1.0; 3], 1.0),
+            Light::spot([0.0; 3], [0.0, -1.0, 0.0], [1.0; 3], 1.0, 5.0),
+        ];
+
+        let (sorted, count, directional) = sort_lights(&lights, 2);
+        assert_eq!((count, directional), (2, 1));
+        assert_eq!(sorted[0].kind(), LightKind::Directional);
+        assert_eq!(sorted[1].kind(), LightKind::Point);
+
+        let (sorted, count, _) = sort_lights(&[], 8);
+        assert_eq!((sorted.len(), count), (1, 0));
+    }
+
+    #[test]
+    fn test_spot_falloff() {
+        let light = Light::spot([0.0; 3], [0.0, -1.0, 0.0], [1.0; 3], 1.0, 10.0).with_cone(0.2, 0.4);
+        let falloff = |angle: f32| (angle.cos() * light.spot_scale + light.spot_offset).clamp(0.0, 1.0);
+
+        assert_eq!(light.kind(), LightKind::Spot);
+        assert!((falloff(0.2) - 1.0).abs() < 1e-4);
+        assert!(falloff(0.4).abs() < 1e-4);
+        assert_eq!(Light::point([0.0; 3], [1.0; 3], 1.0, 5.0).kind(), LightKind::Point);
+    }
+}
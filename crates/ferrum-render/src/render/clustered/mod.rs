@@ -0,0 +1,653 @@
+pub(crate) mod light;
+
+pub use light::*;
+
+use std::mem::offset_of;
+
+use ash::vk::{self, Extent2D, PhysicalDeviceMemoryProperties, PrimitiveTopology};
+
+use crate::{
+    ComputePipeline, ComputePipelineBuilder, DescriptorPool, DescriptorPoolBuilder, DescriptorSetLayout,
+    DescriptorSetLayoutBuilder, DeviceHandle, GPUBuffer, PipelineError, RenderContext, RenderPipeline, RenderPipelineBuilder,
+    ShaderProgramBuilder, ShaderSource, ShadowRenderer, VulkanError, VulkanResult
+};
+
+/// `local_size` of `cluster-build.comp` and `light-cull.comp`
+const WORKGROUP_SIZE: [u32; 3] = [64, 1, 1];
+
+const DEFAULT_MAX_LIGHTS: u32 = 1024;
+const DEFAULT_FRAMES_IN_FLIGHT: u32 = 2;
+
+/// Vertex of meshes drawn by [`ClusteredRenderer`], locations 0 and 1 of `clustered-pbr.vert`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PbrVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3]
+}
+
+impl PbrVertex {
+
+    pub fn binding_descriptions() -> [vk::VertexInputBindingDescription; 1] {
+        [vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
+    }
+
+    pub fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 2] {
+        [
+            vk::VertexInputAttributeDescription {
+                location: 0,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: offset_of!(Self, position) as u32,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 1,
+                binding: 0,
+                format: vk::Format::R32G32B32_SFLOAT,
+                offset: offset_of!(Self, normal) as u32,
+            },
+        ]
+    }
+}
+
+/// Metallic-roughness material, constant over a draw
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PbrMaterial {
+    /// Linear colour, alpha is written to the target as is
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    /// Clamped to 0.04 in the shader
+    pub roughness: f32,
+    /// Linear colour added on top of the lighting
+    pub emissive: [f32; 3]
+}
+
+impl Default for PbrMaterial {
+    fn default() -> Self {
+        Self { base_color: [1.0; 4], metallic: 0.0, roughness: 0.5, emissive: [0.0; 3] }
+    }
+}
+
+/// `PbrPushConstants` of `pbr-material.glsl`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PbrPushConstants {
+    /// Column-major
+    pub model: [[f32; 4]; 4],
+    pub base_color: [f32; 4],
+    /// w is unused
+    pub emissive: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32
+}
+
+impl PbrPushConstants {
+
+    pub fn new(model: [[f32; 4]; 4], material: &PbrMaterial) -> Self {
+        let [r, g, b] = material.emissive;
+
+        Self {
+            model,
+            base_color: material.base_color,
+            emissive: [r, g, b, 0.0],
+            metallic: material.metallic,
+            roughness: material.roughness
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, std::mem::size_of::<Self>()) }
+    }
+}
+
+/// Indexed mesh of [`PbrVertex`] with `u32` indices in host-visible memory
+pub struct PbrMesh {
+    pub vertex_buffer: GPUBuffer,
+    pub index_buffer: GPUBuffer,
+    pub index_count: u32
+}
+
+impl PbrMesh {
+
+    pub fn new(device: &DeviceHandle, memory_prop: &PhysicalDeviceMemoryProperties, vertices: &[PbrVertex], indices: &[u32]) -> VulkanResult<Self> {
+
+        let memory_flags = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let create_buffer = |size: usize, usage: vk::BufferUsageFlags| {
+            GPUBuffer::new(device, memory_prop, size as u64, usage, memory_flags)
+                .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateBufferFailed(e))))
+        };
+
+        let vertex_buffer = create_buffer(std::mem::size_of_val(vertices), vk::BufferUsageFlags::VERTEX_BUFFER)?;
+        let index_buffer = create_buffer(std::mem::size_of_val(indices), vk::BufferUsageFlags::INDEX_BUFFER)?;
+
+        vertex_buffer.upload_data(device, vertices);
+        index_buffer.upload_data(device, indices);
+
+        Ok(Self { vertex_buffer, index_buffer, index_count: indices.len() as u32 })
+    }
+}
+
+/// Host-written buffers of one frame slot
+struct FrameData {
+    uniforms: GPUBuffer,
+    lights: GPUBuffer,
+    set: vk::DescriptorSet
+}
+
+///
+/// Forward+ renderer: view-space cluster AABBs and per-cluster light lists are built in compute,
+/// meshes are then shaded with metallic-roughness PBR using only the lights of their cluster.
+/// Directional lights are not culled and light every fragment.
+///
+/// Set 0: `ClusterUniforms` as UNIFORM_BUFFER (binding 0), lights, cluster bounds,
+/// light counts and light indices as STORAGE_BUFFER (bindings 1-4).
+/// Set 1 of the PBR pipeline with [`ClusteredRendererBuilder::with_shadows`]: [`ShadowRenderer::set_layout`]
+///
+/// # Example
+/// ```
+/// let mut clustered = ClusteredRendererBuilder::new()
+///     .with_graphics_device(&ctx)
+///     .with_cluster_build_source(ShaderSource::file("shared/shaders/cluster-build.comp"))
+///     .with_light_cull_source(ShaderSource::file("shared/shaders/light-cull.comp"))
+///     .with_vertex_source(ShaderSource::file("shared/shaders/clustered-pbr.vert"))
+///     .with_fragment_source(ShaderSource::file("shared/shaders/clustered-pbr.frag"))
+///     .build()?;
+///
+/// // every frame, before recording
+/// clustered.update(device, &camera, ctx.window.extent(), &lights);
+///
+/// // outside of a render pass
+/// clustered.record_culling(device, cbuf);
+///
+/// // inside
+/// clustered.bind(device, cbuf, ctx.window.extent());
+/// clustered.draw(device, cbuf, &mesh, &PbrPushConstants::new(model, &material));
+/// ```
+///
+pub struct ClusteredRenderer {
+    pub cluster_build: ComputePipeline,
+    pub light_cull: ComputePipeline,
+    pub pipeline: RenderPipeline,
+    pub set_layout: DescriptorSetLayout,
+    pub grid: ClusterGrid,
+    /// Capacity of the light buffers, extra lights are dropped by [`ClusteredRenderer::update`]
+    pub max_lights: u32,
+    /// Linear colour added to every fragment
+    pub ambient: [f32; 3],
+    frames: Vec<FrameData>,
+    current: usize,
+    // Пишутся и читаются только GPU, барьеры в record_culling
+    _clusters: GPUBuffer,
+    _light_counts: GPUBuffer,
+    _light_indices: GPUBuffer,
+    _pool: DescriptorPool
+}
+
+impl ClusteredRenderer {
+
+    ///
+    /// Writes the camera and lights of the next frame into the next buffer slot,
+    /// lights over [`ClusteredRenderer::max_lights`] are dropped
+    ///
+    pub fn update(&mut self, device: &ash::Device, camera: &ClusterCamera, screen: Extent2D, lights: &[Light]) {
+
+        let (sorted, light_count, directional_count) = sort_lights(lights, self.max_lights);
+
+        let uniforms = ClusterUniforms::new(camera, &self.grid, screen)
+            .with_ambient(self.ambient)
+            .with_lights(light_count, directional_count);
+
+        self.current = (self.current + 1) % self.frames.len();
+        let frame = &self.frames[self.current];
+
+        frame.uniforms.upload_data(device, &[uniforms]);
+        frame.lights.upload_data(device, &sorted);
+    }
+
+    /// Records cluster construction and light culling of the last [`ClusteredRenderer::update`], outside of a render pass
+    pub fn record_culling(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+
+        let set = self.frames[self.current].set;
+        let clusters = self.grid.cluster_count();
+
+        // Прошлый кадр ещё может читать списки во фрагментном шейдере
+        let previous = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_READ)
+            .dst_access_mask(vk::AccessFlags::SHADER_WRITE);
+
+        let built = vk::MemoryBarrier::default()
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[previous],
+                &[],
+                &[]
+            );
+        }
+
+        self.cluster_build.bind_descriptor_sets(device, command_buffer, 0, &[set]);
+        self.cluster_build.dispatch_threads(device, command_buffer, [clusters, 1, 1], WORKGROUP_SIZE);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[built],
+                &[],
+                &[]
+            );
+        }
+
+        self.light_cull.bind_descriptor_sets(device, command_buffer, 0, &[set]);
+        self.light_cull.dispatch_threads(device, command_buffer, [clusters, 1, 1], WORKGROUP_SIZE);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[built],
+                &[],
+                &[]
+            );
+        }
+    }
+
+    /// Binds the PBR pipeline and the set of the current frame, inside a render pass
+    pub fn bind(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, extent: Extent2D) {
+
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0
+        };
+
+        let scissor = vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent };
+
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.raw);
+            device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+            device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.raw_layout,
+                0,
+                &[self.frames[self.current].set],
+                &[]
+            );
+        }
+    }
+
+    /// Binds the shadow maps of the current frame as set 1, for a renderer built [`ClusteredRendererBuilder::with_shadows`]
+    pub fn bind_shadows(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, shadows: &ShadowRenderer) {
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.raw_layout,
+                1,
+                &[shadows.descriptor_set()],
+                &[]
+            );
+        }
+    }
+
+    /// Draws `mesh` with `model` and `material` in push constants, after [`ClusteredRenderer::bind`]
+    pub fn draw(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, mesh: &PbrMesh, constants: &PbrPushConstants) {
+        unsafe {
+            device.cmd_push_constants(
+                command_buffer,
+                self.pipeline.raw_layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                constants.as_bytes()
+            );
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[mesh.vertex_buffer.raw], &[0]);
+            device.cmd_bind_index_buffer(command_buffer, mesh.index_buffer.raw, 0, vk::IndexType::UINT32);
+            device.cmd_draw_indexed(command_buffer, mesh.index_count, 1, 0, 0, 0);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ClusteredRendererBuilder<'n> {
+    ctx: Option<&'n RenderContext>,
+    cluster_build_shader: Option<ShaderSource>,
+    light_cull_shader: Option<ShaderSource>,
+    vertex_shader: Option<ShaderSource>,
+    fragment_shader: Option<ShaderSource>,
+    grid: Option<ClusterGrid>,
+    max_lights: Option<u32>,
+    ambient: Option<[f32; 3]>,
+    frames_in_flight: Option<u32>,
+    shadows: Option<&'n ShadowRenderer>
+}
+
+impl<'n> ClusteredRendererBuilder<'n> {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with_graphics_device(mut self, ctx: &'n RenderContext) -> Self {
+        self.ctx = Some(ctx);
+        self
+    }
+
+    /// `cluster-build.comp`
+    pub fn with_cluster_build_source(mut self, source: ShaderSource) -> Self {
+        self.cluster_build_shader = Some(source);
+        self
+    }
+
+    /// `light-cull.comp`
+    pub fn with_light_cull_source(mut self, source: ShaderSource) -> Self {
+        self.light_cull_shader = Some(source);
+        self
+    }
+
+    /// `clustered-pbr.vert`
+    pub fn with_vertex_source(mut self, source: ShaderSource) -> Self {
+        self.vertex_shader = Some(source);
+        self
+    }
+
+    /// `clustered-pbr.frag`
+    pub fn with_fragment_source(mut self, source: ShaderSource) -> Self {
+        self.fragment_shader = Some(source);
+        self
+    }
+
+    /// Default: 16x9 tiles, 24 slices, 128 lights per cluster
+    pub fn with_grid(mut self, grid: ClusterGrid) -> Self {
+        self.grid = Some(grid);
+        self
+    }
+
+    /// Default: 1024
+    pub fn with_max_lights(mut self, max_lights: u32) -> Self {
+        self.max_lights = Some(max_lights);
+        self
+    }
+
+    /// Default: 0.03 grey
+    pub fn with_ambient(mut self, ambient: [f32; 3]) -> Self {
+        self.ambient = Some(ambient);
+        self
+    }
+
+    /// Must match the render graph, default: 2
+    pub fn with_frames_in_flight(mut self, count: u32) -> Self {
+        self.frames_in_flight = Some(count);
+        self
+    }
+
+    /// Compiles the fragment shader with `SHADOWS` and adds the shadow set, see [`ClusteredRenderer::bind_shadows`]
+    pub fn with_shadows(mut self, shadows: &'n ShadowRenderer) -> Self {
+        self.shadows = Some(shadows);
+        self
+    }
+
+    pub fn build(self) -> VulkanResult<ClusteredRenderer> {
+
+        let ctx = self.ctx.ok_or(VulkanError::missing("ClusteredRendererBuilder", "render context"))?;
+        let cluster_build_shader = self.cluster_build_shader.ok_or(VulkanError::missing("ClusteredRendererBuilder", "cluster build shader"))?;
+        let light_cull_shader = self.light_cull_shader.ok_or(VulkanError::missing("ClusteredRendererBuilder", "light cull shader"))?;
+        let vertex_shader = self.vertex_shader.ok_or(VulkanError::missing("ClusteredRendererBuilder", "vertex shader"))?;
+        let fragment_shader = self.fragment_shader.ok_or(VulkanError::missing("ClusteredRendererBuilder", "fragment shader"))?;
+
+        let fragment_shader = match self.shadows {
+            Some(_) => {
+                let defines = fragment_shader.defines().clone().with_flag("SHADOWS");
+                fragment_shader.with_defines(defines)
+            }
+            None => fragment_shader
+        };
+
+        let device = ctx.device.raw_device();
+        let memory_prop = &ctx.device.phys_dev.phys_info.memory_prop;
+        let grid = self.grid.unwrap_or_default();
+        let max_lights = self.max_lights.unwrap_or(DEFAULT_MAX_LIGHTS).max(1);
+
+        // Приложение пишет кадр, пока GPU ещё читает frames_in_flight предыдущих
+        let frame_count = self.frames_in_flight.unwrap_or(DEFAULT_FRAMES_IN_FLIGHT).max(1) + 1;
+
+        let stages = vk::ShaderStageFlags::COMPUTE | vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT;
+        let binding = |binding: u32, ty: vk::DescriptorType| vk::DescriptorSetLayoutBinding::default()
+            .binding(binding)
+            .descriptor_type(ty)
+            .descriptor_count(1)
+            .stage_flags(stages);
+
+        let bindings = [
+            binding(0, vk::DescriptorType::UNIFORM_BUFFER),
+            binding(1, vk::DescriptorType::STORAGE_BUFFER),
+            binding(2, vk::DescriptorType::STORAGE_BUFFER),
+            binding(3, vk::DescriptorType::STORAGE_BUFFER),
+            binding(4, vk::DescriptorType::STORAGE_BUFFER),
+        ];
+
+        let set_layout = DescriptorSetLayoutBuilder::new()
+            .with_device(device)
+            .with_bindings(&bindings)
+            .build()?;
+
+        let set_layouts = [set_layout.raw];
+        let pbr_set_layouts = match self.shadows {
+            Some(shadows) => vec![set_layout.raw, shadows.set_layout.raw],
+            None => vec![set_layout.raw]
+        };
+
+        let compute = |source: ShaderSource| -> VulkanResult<ComputePipeline> {
+            let shader = ShaderProgramBuilder::new()
+                .with_device(device)
+                .with_compute_source(source)
+                .build()?;
+
+            ComputePipelineBuilder::new()
+                .with_device(device)
+                .with_shader(shader.compute_shader)
+                .with_entry_point(&shader.compute_entry_point)
+                .with_descriptor_set_layouts(&set_layouts)
+                .with_pipeline_cache(ctx.pipeline_cache.raw)
+                .build()
+        };
+
+        let cluster_build = compute(cluster_build_shader)?;
+        let light_cull = compute(light_cull_shader)?;
+
+        let shader = ShaderProgramBuilder::new()
+            .with_device(device)
+            .with_vertex_source(vertex_shader)
+            .with_fragment_source(fragment_shader)
+            .build()?;
+
+        let render_pass = ctx.window.render_pass.as_ref();
+
+        let binding_description = PbrVertex::binding_descriptions();
+        let attribute_description = PbrVertex::attribute_descriptions();
+
+        let vertex_input_state_info = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_attribute_descriptions(&attribute_description)
+            .vertex_binding_descriptions(&binding_description);
+
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<PbrPushConstants>() as u32
+        }];
+
+        let mut builder = RenderPipelineBuilder::new();
+
+        if let Some(render_pass) = render_pass {
+            builder = builder.with_render_pass(render_pass);
+        }
+
+        let builder = builder
+            .with_vertex_shader(shader.vertex_shader)
+            .with_vertex_entry_point(&shader.vertex_entry_point)
+            .with_fragment_shader(shader.fragment_shader)
+            .with_fragment_entry_point(&shader.fragment_entry_point)
+            .with_resolution(ctx.window.caps.current_extent)
+            .with_format(ctx.window.surface_format_khr.format)
+            .with_vertex_input_info(vertex_input_state_info)
+            .with_input_assembly_info(
+                vk::PipelineInputAssemblyStateCreateInfo::default()
+                            .topology(PrimitiveTopology::TRIANGLE_LIST)
+                            .primitive_restart_enable(false)
+            )
+            .with_device(device)
+            .with_descriptor_set_layouts(&pbr_set_layouts)
+            .with_push_constant_ranges(&push_constant_ranges)
+            .with_pipeline_cache(ctx.pipeline_cache.raw)
+            .with_samples(ctx.window.samples())
+            .with_depth_test(ctx.window.depth_format().is_some());
+
+        let pipeline = match ctx.window.depth_format() {
+            Some(format) if render_pass.is_none() => builder.with_depth_stencil_format(format),
+            _ => builder
+        }.build()?;
+
+        let create_buffer = |size: u64, usage: vk::BufferUsageFlags, memory_flags: vk::MemoryPropertyFlags| {
+            GPUBuffer::new(device, memory_prop, size, usage, memory_flags)
+                .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateBufferFailed(e))))
+        };
+
+        let host = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+        let clusters = grid.cluster_count() as u64;
+
+        let cluster_bounds = create_buffer(clusters * CLUSTER_BOUNDS_SIZE, vk::BufferUsageFlags::STORAGE_BUFFER, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+        let light_counts = create_buffer(clusters * 4, vk::BufferUsageFlags::STORAGE_BUFFER, vk::MemoryPropertyFlags::DEVICE_LOCAL)?;
+        let light_indices = create_buffer(
+            clusters * grid.max_lights_per_cluster.max(1) as u64 * 4,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL
+        )?;
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(frame_count),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(frame_count * 4),
+        ];
+
+        let pool = DescriptorPoolBuilder::new()
+            .with_device(device)
+            .with_pool_sizes(&pool_sizes)
+            .with_max_sets(frame_count)
+            .build()?;
+
+        let layouts = vec![set_layout.raw; frame_count as usize];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool.raw)
+            .set_layouts(&layouts);
+
+        let sets = unsafe { device.allocate_descriptor_sets(&allocate_info) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::AllocateDescriptorSetFailed(e))))?;
+
+        let mut frames = Vec::with_capacity(sets.len());
+
+        for set in sets {
+            let uniforms = create_buffer(std::mem::size_of::<ClusterUniforms>() as u64, vk::BufferUsageFlags::UNIFORM_BUFFER, host)?;
+            let lights = create_buffer(max_lights as u64 * std::mem::size_of::<Light>() as u64, vk::BufferUsageFlags::STORAGE_BUFFER, host)?;
+
+            let whole = |buffer: &GPUBuffer| [vk::DescriptorBufferInfo::default().buffer(buffer.raw).range(vk::WHOLE_SIZE)];
+            let infos = [
+                whole(&uniforms),
+                whole(&lights),
+                whole(&cluster_bounds),
+                whole(&light_counts),
+                whole(&light_indices),
+            ];
+
+            let descriptor_writes = infos.iter().enumerate().map(|(binding, info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(binding as u32)
+                    .descriptor_type(if binding == 0 { vk::DescriptorType::UNIFORM_BUFFER } else { vk::DescriptorType::STORAGE_BUFFER })
+                    .buffer_info(info)
+            }).collect::<Vec<_>>();
+
+            unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+            frames.push(FrameData { uniforms, lights, set });
+        }
+
+        Ok(ClusteredRenderer {
+            cluster_build,
+            light_cull,
+            pipeline,
+            set_layout,
+            grid,
+            max_lights,
+            ambient: self.ambient.unwrap_or([0.03; 3]),
+            frames,
+            current: 0,
+            _clusters: cluster_bounds,
+            _light_counts: light_counts,
+            _light_indices: light_indices,
+            _pool: pool
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PipelineReflection, ShaderReflection};
+
+    fn load(bytes: &[u8]) -> Vec<u32> {
+        bytes.chunks_exact(4).map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]])).collect()
+    }
+
+    #[test]
+    fn test_pbr_layout_matches_shaders() {
+        let vs = ShaderReflection::from_spirv(&load(include_bytes!("../../../../../shared/shaders/spv/clustered-pbr-vert.spv"))).unwrap();
+        let fs = ShaderReflection::from_spirv(&load(include_bytes!("../../../../../shared/shaders/spv/clustered-pbr-frag.spv"))).unwrap();
+
+        assert_eq!(vs.inputs.len(), PbrVertex::attribute_descriptions().len());
+
+        let reflection = PipelineReflection::merge(&[vs, fs]).unwrap();
+        assert_eq!(reflection.push_constant_ranges[0].size as usize, std::mem::size_of::<PbrPushConstants>());
+
+        let set = &reflection.sets[&0];
+        assert_eq!(set[0].descriptor_type, vk::DescriptorType::UNIFORM_BUFFER);
+        assert_eq!(set[1].descriptor_type, vk::DescriptorType::STORAGE_BUFFER);
+    }
+
+    #[test]
+    fn test_culling_layout_matches_shaders() {
+        let build = ShaderReflection::from_spirv(&load(include_bytes!("../../../../../shared/shaders/spv/cluster-build-comp.spv"))).unwrap();
+        let cull = ShaderReflection::from_spirv(&load(include_bytes!("../../../../../shared/shaders/spv/light-cull-comp.spv"))).unwrap();
+
+        assert_eq!(build.workgroup_size, Some(WORKGROUP_SIZE));
+        assert_eq!(cull.workgroup_size, Some(WORKGROUP_SIZE));
+
+        // Стадии одинаковые, merge их не объединяет
+        let reflection = PipelineReflection::merge(&[cull]).unwrap();
+        let set = &reflection.sets[&0];
+
+        assert_eq!(set.len(), 5);
+        assert_eq!(set[0].descriptor_type, vk::DescriptorType::UNIFORM_BUFFER);
+        assert!(set[1..].iter().all(|x| x.descriptor_type == vk::DescriptorType::STORAGE_BUFFER));
+    }
+}
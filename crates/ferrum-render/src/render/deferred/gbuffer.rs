@@ -0,0 +1,220 @@
+use ash::vk::{Extent2D, Format};
+
+use crate::ClusterCamera;
+
+///
+/// Formats of the GBuffer attachments. Normals are octahedral encoded into two
+/// signed components, roughness and metalness share one two-channel attachment
+///
+/// # Example
+/// ```
+/// // Без emissive и с более точными нормалями
+/// let layout = GBufferLayout::default()
+///     .with_normal(Format::R16G16_SFLOAT)
+///     .with_emissive(None);
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GBufferLayout {
+    /// RGB base colour
+    pub albedo: Format,
+    /// RG, -1..1
+    pub normal: Format,
+    /// R roughness, G metallic
+    pub material: Format,
+    /// RGB, linear and unbounded. `None` drops the attachment and the emissive term
+    pub emissive: Option<Format>,
+    /// Sampled by the lighting pass, must have no stencil aspect
+    pub depth: Format
+}
+
+impl Default for GBufferLayout {
+    fn default() -> Self {
+        Self {
+            albedo: Format::R8G8B8A8_UNORM,
+            normal: Format::R16G16_SNORM,
+            material: Format::R8G8_UNORM,
+            emissive: Some(Format::B10G11R11_UFLOAT_PACK32),
+            depth: Format::D32_SFLOAT
+        }
+    }
+}
+
+impl GBufferLayout {
+
+    pub fn with_albedo(mut self, format: Format) -> Self {
+        self.albedo = format;
+        self
+    }
+
+    pub fn with_normal(mut self, format: Format) -> Self {
+        self.normal = format;
+        self
+    }
+
+    pub fn with_material(mut self, format: Format) -> Self {
+        self.material = format;
+        self
+    }
+
+    pub fn with_emissive(mut self, format: Option<Format>) -> Self {
+        self.emissive = format;
+        self
+    }
+
+    pub fn with_depth(mut self, format: Format) -> Self {
+        self.depth = format;
+        self
+    }
+
+    /// Colour attachments in `gbuffer.frag` location order
+    pub fn color_formats(&self) -> Vec<Format> {
+        [Some(self.albedo), Some(self.normal), Some(self.material), self.emissive]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// Bytes per pixel of all attachments, without the lighting target
+    pub fn bytes_per_pixel(&self) -> u32 {
+        self.color_formats().into_iter().chain([self.depth]).map(format_size).sum()
+    }
+}
+
+/// Size of one texel of the formats a GBuffer is usually made of, 4 for unknown ones
+fn format_size(format: Format) -> u32 {
+    match format {
+        Format::R8_UNORM | Format::R8_SNORM => 1,
+        Format::R8G8_UNORM | Format::R8G8_SNORM | Format::R16_SFLOAT | Format::D16_UNORM => 2,
+        Format::R16G16B16A16_SFLOAT | Format::R32G32_SFLOAT => 8,
+        Format::R32G32B32A32_SFLOAT => 16,
+        _ => 4
+    }
+}
+
+/// std140 `DeferredUniforms` of `deferred.glsl`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeferredUniforms {
+    pub view: [[f32; 4]; 4],
+    pub projection: [[f32; 4]; 4],
+    pub inverse_projection: [[f32; 4]; 4],
+    pub ambient: [f32; 4],
+    /// xy - GBuffer size, z - lights, w - directional lights at the start of the buffer
+    pub screen: [u32; 4]
+}
+
+impl DeferredUniforms {
+
+    pub fn new(camera: &ClusterCamera, extent: Extent2D) -> Self {
+        Self {
+            view: camera.view,
+            projection: camera.projection,
+            inverse_projection: camera.inverse_projection,
+            ambient: [0.0; 4],
+            screen: [extent.width, extent.height, 0, 0]
+        }
+    }
+
+    pub fn with_ambient(mut self, ambient: [f32; 3]) -> Self {
+        let [r, g, b] = ambient;
+        self.ambient = [r, g, b, 0.0];
+        self
+    }
+
+    /// `directional_count` lights at the start of the buffer are directional
+    pub fn with_lights(mut self, light_count: u32, directional_count: u32) -> Self {
+        self.screen[2] = light_count;
+        self.screen[3] = directional_count;
+        self
+    }
+}
+
+/// Pixels per side of a lighting tile, `local_size` of `deferred-lighting.comp`
+pub const TILE_SIZE: u32 = 16;
+
+/// Format of the lit image, `rgba16f` in the shader
+pub const DEFERRED_LIGHTING_FORMAT: Format = Format::R16G16B16A16_SFLOAT;
+
+/// Workgroups of `deferred-lighting.comp`, one per tile
+pub fn lighting_tiles(extent: Extent2D) -> [u32; 2] {
+    [extent.width.div_ceil(TILE_SIZE), extent.height.div_ceil(TILE_SIZE)]
+}
+
+/// `octahedralEncode` of `octahedral.glsl`, `normal` must be normalized
+pub fn octahedral_encode(normal: [f32; 3]) -> [f32; 2] {
+    let [x, y, z] = normal;
+    let sum = x.abs() + y.abs() + z.abs();
+    let (px, py) = (x / sum, y / sum);
+
+    if z <= 0.0 {
+        [(1.0 - py.abs()) * sign_not_zero(px), (1.0 - px.abs()) * sign_not_zero(py)]
+    } else {
+        [px, py]
+    }
+}
+
+/// `octahedralDecode` of `octahedral.glsl`
+pub fn octahedral_decode(encoded: [f32; 2]) -> [f32; 3] {
+    let [ex, ey] = encoded;
+    let z = 1.0 - ex.abs() - ey.abs();
+
+    let (x, y) = if z < 0.0 {
+        ((1.0 - ey.abs()) * sign_not_zero(ex), (1.0 - ex.abs()) * sign_not_zero(ey))
+    } else {
+        (ex, ey)
+    };
+
+    let length = (x * x + y * y + z * z).sqrt();
+    [x / length, y / length, z / length]
+}
+
+fn sign_not_zero(x: f32) -> f32 {
+    if x >= 0.0 { 1.0 } else { -1.0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::{offset_of, size_of};
+
+    #[test]
+    fn test_uniforms_layout() {
+        assert_eq!(offset_of!(DeferredUniforms, ambient), 192);
+        assert_eq!(offset_of!(DeferredUniforms, screen), 208);
+        assert_eq!(size_of::<DeferredUniforms>(), 224);
+    }
+
+    #[test]
+    fn test_octahedral_round_trip() {
+        let normals = [
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, -1.0],
+            [1.0, 0.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.48, -0.6, -0.64],
+            [-0.36, 0.48, 0.8],
+        ];
+
+        for normal in normals {
+            let encoded = octahedral_encode(normal);
+            assert!(encoded.iter().all(|x| x.abs() <= 1.0));
+
+            let decoded = octahedral_decode(encoded);
+            for i in 0..3 {
+                assert!((decoded[i] - normal[i]).abs() < 1e-5, "{normal:?} -> {decoded:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_layout_formats() {
+        let layout = GBufferLayout::default();
+        assert_eq!(layout.color_formats().len(), 4);
+        assert_eq!(layout.bytes_per_pixel(), 4 + 4 + 2 + 4 + 4);
+
+        let layout = layout.with_emissive(None);
+        assert_eq!(layout.color_formats(), vec![Format::R8G8B8A8_UNORM, Format::R16G16_SNORM, Format::R8G8_UNORM]);
+        assert_eq!(lighting_tiles(Extent2D { width: 1920, height: 1080 }), [120, 68]);
+    }
+}
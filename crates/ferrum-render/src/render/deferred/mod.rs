@@ -0,0 +1,938 @@
+pub(crate) mod gbuffer;
+
+pub use gbuffer::*;
+
+use ash::vk::{self, Extent2D, PhysicalDeviceMemoryProperties, PrimitiveTopology};
+
+use crate::{
+    sort_lights, AttachmentImage, AttachmentImageBuilder, BlendMode, ClusterCamera, ComputePipeline, ComputePipelineBuilder,
+    DeferredResource, DescriptorPool, DescriptorPoolBuilder, DescriptorSetLayout, DescriptorSetLayoutBuilder, DeviceHandle,
+    GPUBuffer, GraphicsDevice, Light, PbrMesh, PbrPushConstants, PbrVertex, PipelineError, RenderContext, RenderPipeline,
+    RenderPipelineBuilder, ResourceOwner, ShaderProgramBuilder, ShaderSource, ShadowRenderer, TransientImageDesc, VulkanError, VulkanResult
+};
+
+const DEFAULT_MAX_LIGHTS: u32 = 4096;
+const DEFAULT_FRAMES_IN_FLIGHT: u32 = 2;
+
+/// What the composition pass outputs, `params.view` of `deferred-composite.frag`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DeferredView {
+    /// Lighting and emissive with exposure
+    #[default]
+    Lit = 0,
+    Albedo = 1,
+    /// World-space normals mapped to 0..1
+    Normal = 2,
+    /// Roughness in red, metallic in green
+    Material = 3,
+    Emissive = 4,
+    Depth = 5,
+    /// Lighting pass output without emissive and exposure
+    Lighting = 6
+}
+
+/// Push constants of `deferred-composite.frag`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompositeConstants {
+    pub view: u32,
+    pub exposure: f32,
+    pub has_emissive: u32
+}
+
+impl CompositeConstants {
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, std::mem::size_of::<Self>()) }
+    }
+}
+
+///
+/// GBuffer attachments and the lit image of one [`DeferredRenderer`] at one size, with the
+/// descriptor sets reading them. Recreate on resize, handles are queued for destruction on drop.
+/// Targets of [`DeferredRenderer::bind_targets`] do not own the images
+///
+pub struct GBufferTargets {
+    pub extent: Extent2D,
+    pub layout: GBufferLayout,
+    /// In [`GBufferLayout::color_formats`] order
+    pub color_views: Vec<vk::ImageView>,
+    pub depth_view: vk::ImageView,
+    /// [`DEFERRED_LIGHTING_FORMAT`], SHADER_READ_ONLY_OPTIMAL after [`DeferredRenderer::record_lighting`]
+    pub lighting_view: vk::ImageView,
+    color_images: Vec<vk::Image>,
+    depth_image: vk::Image,
+    lighting_image: vk::Image,
+    lighting_set: vk::DescriptorSet,
+    composite_set: vk::DescriptorSet,
+    _pool: DescriptorPool,
+    _owner: ResourceOwner
+}
+
+impl GBufferTargets {
+
+    /// Views in [`DeferredRenderer::target_descs`] order
+    pub fn views(&self) -> Vec<vk::ImageView> {
+        self.color_views.iter().copied().chain([self.depth_view, self.lighting_view]).collect()
+    }
+}
+
+/// Host-written buffers of one frame slot
+struct FrameData {
+    uniforms: GPUBuffer,
+    lights: GPUBuffer,
+    set: vk::DescriptorSet
+}
+
+fn image_barrier<'a>(image: vk::Image, aspect_mask: vk::ImageAspectFlags) -> vk::ImageMemoryBarrier<'a> {
+    vk::ImageMemoryBarrier::default()
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        })
+}
+
+fn set_viewport(device: &ash::Device, command_buffer: vk::CommandBuffer, extent: Extent2D) {
+
+    let viewport = vk::Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: extent.width as f32,
+        height: extent.height as f32,
+        min_depth: 0.0,
+        max_depth: 1.0
+    };
+
+    let scissor = vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent };
+
+    unsafe {
+        device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+        device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+    }
+}
+
+///
+/// Deferred renderer: meshes write albedo, octahedral normals, roughness / metalness and emissive
+/// into a [`GBufferLayout`], a 16x16 tiled compute pass culls lights against the depth bounds
+/// of each tile and shades them, the composition pass writes the result into the window.
+///
+/// Set 0 of the GBuffer and lighting passes: `DeferredUniforms` as UNIFORM_BUFFER (binding 0),
+/// lights as STORAGE_BUFFER (binding 1). Set 1 of the lighting pass: GBuffer as SAMPLED_IMAGE (bindings 0-3),
+/// SAMPLER (binding 4), the lit image as STORAGE_IMAGE (binding 5). Set 2 of the lighting pass
+/// with [`DeferredRendererBuilder::with_shadows`]: [`ShadowRenderer::set_layout`]
+///
+/// # Example
+/// ```
+/// let mut deferred = DeferredRendererBuilder::new()
+///     .with_graphics_device(&ctx)
+///     .with_gbuffer_vertex_source(ShaderSource::file("shared/shaders/gbuffer.vert"))
+///     .with_gbuffer_fragment_source(ShaderSource::file("shared/shaders/gbuffer.frag"))
+///     .with_lighting_source(ShaderSource::file("shared/shaders/deferred-lighting.comp"))
+///     .with_composite_vertex_source(ShaderSource::file("shared/shaders/fullscreen.vert"))
+///     .with_composite_fragment_source(ShaderSource::file("shared/shaders/deferred-composite.frag"))
+///     .with_layout(GBufferLayout::default().with_emissive(None))
+///     .build()?;
+///
+/// let targets = deferred.create_targets(device, &memory_prop, ctx.window.extent())?;
+///
+/// // every frame, before recording
+/// deferred.update(device, &camera, targets.extent, &lights);
+///
+/// deferred.begin_gbuffer(&ctx.device, cbuf, &targets)?;
+/// deferred.draw(device, cbuf, &mesh, &PbrPushConstants::new(model, &material));
+/// deferred.end_gbuffer(&ctx.device, cbuf, &targets)?;
+///
+/// // with_shadows: deferred.bind_shadows(device, cbuf, &shadows);
+/// deferred.record_lighting(device, cbuf, &targets);
+///
+/// // inside the window render pass
+/// deferred.record_composite(device, cbuf, &targets, ctx.window.extent());
+/// ```
+///
+pub struct DeferredRenderer {
+    pub gbuffer: RenderPipeline,
+    pub lighting: ComputePipeline,
+    pub composite: RenderPipeline,
+    pub frame_set_layout: DescriptorSetLayout,
+    pub lighting_set_layout: DescriptorSetLayout,
+    pub composite_set_layout: DescriptorSetLayout,
+    /// Nearest, clamp to edge
+    pub sampler: vk::Sampler,
+    pub layout: GBufferLayout,
+    /// Capacity of the light buffers, extra lights are dropped by [`DeferredRenderer::update`]
+    pub max_lights: u32,
+    /// Linear colour added to every lit pixel
+    pub ambient: [f32; 3],
+    pub view: DeferredView,
+    pub exposure: f32,
+    frames: Vec<FrameData>,
+    current: usize,
+    _pool: DescriptorPool,
+    _owner: ResourceOwner
+}
+
+impl DeferredRenderer {
+
+    ///
+    /// Images of [`GBufferTargets`]: the colour attachments in [`GBufferLayout::color_formats`] order,
+    /// depth and the lit image. The render graph creates them as transient images
+    ///
+    pub fn target_descs(&self) -> Vec<TransientImageDesc> {
+
+        let attachment = vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED;
+
+        let mut descs = self.layout.color_formats().into_iter().map(|x| TransientImageDesc::new(x, attachment)).collect::<Vec<_>>();
+        descs.push(TransientImageDesc::new(self.layout.depth, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED));
+        descs.push(TransientImageDesc::new(DEFERRED_LIGHTING_FORMAT, vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED));
+        descs
+    }
+
+    /// Creates the GBuffer of [`DeferredRenderer::layout`] and the lit image of `extent`, owned by the targets
+    pub fn create_targets(&self, device: &DeviceHandle, memory_prop: &PhysicalDeviceMemoryProperties, extent: Extent2D) -> VulkanResult<GBufferTargets> {
+
+        let descs = self.target_descs();
+        let mut images: Vec<AttachmentImage> = Vec::with_capacity(descs.len());
+
+        for desc in descs {
+            let (format, usage) = (desc.format, desc.usage);
+            let image = AttachmentImageBuilder::new()
+                .with_device(device)
+                .with_memory_properties(memory_prop)
+                .with_extent(extent)
+                .with_format(format)
+                .with_usage(usage)
+                .build();
+
+            match image {
+                Ok(image) => images.push(image),
+                Err(e) => {
+                    images.iter().for_each(|x| x.destroy(device));
+                    return Err(VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateImageFailed(e))));
+                }
+            }
+        }
+
+        let owner = ResourceOwner::new(device, images.iter()
+            .flat_map(|x| [DeferredResource::ImageView(x.view), DeferredResource::Image(x.raw, x.memory)])
+            .collect());
+
+        let handles = images.iter().map(|x| (x.raw, x.view)).collect::<Vec<_>>();
+        let mut targets = self.bind_targets(device, extent, &handles)?;
+        targets._owner = owner;
+
+        Ok(targets)
+    }
+
+    ///
+    /// Targets over images created elsewhere, in [`DeferredRenderer::target_descs`] order.
+    /// The images must outlive the targets
+    ///
+    pub fn bind_targets(&self, device: &DeviceHandle, extent: Extent2D, images: &[(vk::Image, vk::ImageView)]) -> VulkanResult<GBufferTargets> {
+
+        let color_count = self.layout.color_formats().len();
+
+        if images.len() != color_count + 2 {
+            return Err(VulkanError::missing("GBufferTargets", "GBuffer image"));
+        }
+
+        let colors = &images[..color_count];
+        let (depth, lighting) = (images[color_count], images[color_count + 1]);
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(10),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::SAMPLER)
+                .descriptor_count(2),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_IMAGE)
+                .descriptor_count(1),
+        ];
+
+        let pool = DescriptorPoolBuilder::new()
+            .with_device(device)
+            .with_pool_sizes(&pool_sizes)
+            .with_max_sets(2)
+            .build()?;
+
+        let set_layouts = [self.lighting_set_layout.raw, self.composite_set_layout.raw];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool.raw)
+            .set_layouts(&set_layouts);
+
+        let sets = unsafe { device.allocate_descriptor_sets(&allocate_info) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::AllocateDescriptorSetFailed(e))))?;
+
+        let read = |view: vk::ImageView, layout: vk::ImageLayout| [vk::DescriptorImageInfo::default().image_view(view).image_layout(layout)];
+        let color = |index: usize| read(colors[index].1, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+        let depth_info = read(depth.1, vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL);
+        let lighting_info = read(lighting.1, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        let output_info = read(lighting.1, vk::ImageLayout::GENERAL);
+        let samplers = [vk::DescriptorImageInfo::default().sampler(self.sampler)];
+
+        // Без emissive на его месте освещение, шейдер его не читает
+        let emissive_info = match self.layout.emissive {
+            Some(_) => color(3),
+            None => lighting_info
+        };
+
+        let lighting_inputs = [color(0), color(1), color(2), depth_info];
+        let composite_inputs = [lighting_info, emissive_info, color(0), color(1), color(2), depth_info];
+
+        let sampled = |set: vk::DescriptorSet, binding: usize, info| vk::WriteDescriptorSet::default()
+            .dst_set(set)
+            .dst_binding(binding as u32)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(info);
+
+        let mut descriptor_writes = vec![];
+        descriptor_writes.extend(lighting_inputs.iter().enumerate().map(|(binding, info)| sampled(sets[0], binding, info)));
+        descriptor_writes.extend(composite_inputs.iter().enumerate().map(|(binding, info)| sampled(sets[1], binding, info)));
+
+        for (set, binding) in [(sets[0], 4), (sets[1], 6)] {
+            descriptor_writes.push(vk::WriteDescriptorSet::default()
+                .dst_set(set)
+                .dst_binding(binding)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .image_info(&samplers));
+        }
+
+        descriptor_writes.push(vk::WriteDescriptorSet::default()
+            .dst_set(sets[0])
+            .dst_binding(5)
+            .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+            .image_info(&output_info));
+
+        unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+        Ok(GBufferTargets {
+            extent,
+            layout: self.layout,
+            color_views: colors.iter().map(|x| x.1).collect(),
+            depth_view: depth.1,
+            lighting_view: lighting.1,
+            color_images: colors.iter().map(|x| x.0).collect(),
+            depth_image: depth.0,
+            lighting_image: lighting.0,
+            lighting_set: sets[0],
+            composite_set: sets[1],
+            _pool: pool,
+            _owner: ResourceOwner::new(device, vec![])
+        })
+    }
+
+    /// Writes the camera and lights of the next frame into the next buffer slot,
+    /// lights over [`DeferredRenderer::max_lights`] are dropped
+    pub fn update(&mut self, device: &ash::Device, camera: &ClusterCamera, extent: Extent2D, lights: &[Light]) {
+
+        let (sorted, light_count, directional_count) = sort_lights(lights, self.max_lights);
+
+        let uniforms = DeferredUniforms::new(camera, extent)
+            .with_ambient(self.ambient)
+            .with_lights(light_count, directional_count);
+
+        self.current = (self.current + 1) % self.frames.len();
+        let frame = &self.frames[self.current];
+
+        frame.uniforms.upload_data(device, &[uniforms]);
+        frame.lights.upload_data(device, &sorted);
+    }
+
+    ///
+    /// Clears the GBuffer and begins rendering into it with the GBuffer pipeline bound
+    ///
+    /// Fails if dynamic rendering is not enabled on the device
+    ///
+    pub fn begin_gbuffer(&self, dev: &GraphicsDevice, command_buffer: vk::CommandBuffer, targets: &GBufferTargets) -> VulkanResult<()> {
+
+        let dynamic_rendering = dev.require_dynamic_rendering()?;
+        let device = dev.raw_device();
+
+        // Прошлый кадр ещё может читать GBuffer в освещении и композиции
+        let mut barriers = targets.color_images.iter().map(|image| {
+            image_barrier(*image, vk::ImageAspectFlags::COLOR)
+                .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .old_layout(vk::ImageLayout::UNDEFINED)
+                .new_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        }).collect::<Vec<_>>();
+
+        barriers.push(image_barrier(targets.depth_image, vk::ImageAspectFlags::DEPTH)
+            .dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL));
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &barriers
+            );
+        }
+
+        let color_attachments = targets.color_views.iter().map(|view| {
+            vk::RenderingAttachmentInfo::default()
+                .image_view(*view)
+                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+                .clear_value(vk::ClearValue { color: vk::ClearColorValue { float32: [0.0; 4] } })
+        }).collect::<Vec<_>>();
+
+        let depth_attachment = vk::RenderingAttachmentInfo::default()
+            .image_view(targets.depth_view)
+            .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } });
+
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: targets.extent })
+            .layer_count(1)
+            .color_attachments(&color_attachments)
+            .depth_attachment(&depth_attachment);
+
+        dynamic_rendering.begin_rendering(device, command_buffer, &rendering_info);
+
+        set_viewport(device, command_buffer, targets.extent);
+
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.gbuffer.raw);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.gbuffer.raw_layout,
+                0,
+                &[self.frames[self.current].set],
+                &[]
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Draws `mesh` into the GBuffer, between [`DeferredRenderer::begin_gbuffer`] and [`DeferredRenderer::end_gbuffer`]
+    pub fn draw(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, mesh: &PbrMesh, constants: &PbrPushConstants) {
+        unsafe {
+            device.cmd_push_constants(
+                command_buffer,
+                self.gbuffer.raw_layout,
+                vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                0,
+                constants.as_bytes()
+            );
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[mesh.vertex_buffer.raw], &[0]);
+            device.cmd_bind_index_buffer(command_buffer, mesh.index_buffer.raw, 0, vk::IndexType::UINT32);
+            device.cmd_draw_indexed(command_buffer, mesh.index_count, 1, 0, 0, 0);
+        }
+    }
+
+    ///
+    /// Ends GBuffer rendering and makes it readable by the lighting and composition passes
+    ///
+    /// Fails if dynamic rendering is not enabled on the device
+    ///
+    pub fn end_gbuffer(&self, dev: &GraphicsDevice, command_buffer: vk::CommandBuffer, targets: &GBufferTargets) -> VulkanResult<()> {
+
+        let dynamic_rendering = dev.require_dynamic_rendering()?;
+        let device = dev.raw_device();
+
+        dynamic_rendering.end_rendering(device, command_buffer);
+
+        let mut barriers = targets.color_images.iter().map(|image| {
+            image_barrier(*image, vk::ImageAspectFlags::COLOR)
+                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+                .dst_access_mask(vk::AccessFlags::SHADER_READ)
+                .old_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        }).collect::<Vec<_>>();
+
+        barriers.push(image_barrier(targets.depth_image, vk::ImageAspectFlags::DEPTH)
+            .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .old_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .new_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL));
+
+        // Композиция прошлого кадра могла ещё читать освещение
+        barriers.push(image_barrier(targets.lighting_image, vk::ImageAspectFlags::COLOR)
+            .dst_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::GENERAL));
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::PipelineStageFlags::COMPUTE_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &barriers
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Binds the shadow maps of the current frame as set 2 of the lighting pass, before [`DeferredRenderer::record_lighting`]
+    pub fn bind_shadows(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, shadows: &ShadowRenderer) {
+        self.lighting.bind_descriptor_sets(device, command_buffer, 2, &[shadows.descriptor_set()]);
+    }
+
+    /// Records tiled light culling and shading into [`GBufferTargets::lighting_view`], outside of a render pass
+    pub fn record_lighting(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, targets: &GBufferTargets) {
+
+        let [x, y] = lighting_tiles(targets.extent);
+
+        self.lighting.bind_descriptor_sets(device, command_buffer, 0, &[self.frames[self.current].set, targets.lighting_set]);
+        self.lighting.dispatch(device, command_buffer, [x, y, 1]);
+
+        let written = image_barrier(targets.lighting_image, vk::ImageAspectFlags::COLOR)
+            .src_access_mask(vk::AccessFlags::SHADER_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .old_layout(vk::ImageLayout::GENERAL)
+            .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[written]
+            );
+        }
+    }
+
+    /// Draws [`DeferredRenderer::view`] of `targets` over `extent`, inside the window render pass
+    pub fn record_composite(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, targets: &GBufferTargets, extent: Extent2D) {
+
+        let constants = CompositeConstants {
+            view: self.view as u32,
+            exposure: self.exposure,
+            has_emissive: targets.layout.emissive.is_some() as u32
+        };
+
+        set_viewport(device, command_buffer, extent);
+
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.composite.raw);
+            device.cmd_bind_descriptor_sets(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.composite.raw_layout, 0, &[targets.composite_set], &[]);
+            device.cmd_push_constants(command_buffer, self.composite.raw_layout, vk::ShaderStageFlags::FRAGMENT, 0, constants.as_bytes());
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct DeferredRendererBuilder<'n> {
+    ctx: Option<&'n RenderContext>,
+    gbuffer_vertex_shader: Option<ShaderSource>,
+    gbuffer_fragment_shader: Option<ShaderSource>,
+    lighting_shader: Option<ShaderSource>,
+    composite_vertex_shader: Option<ShaderSource>,
+    composite_fragment_shader: Option<ShaderSource>,
+    layout: Option<GBufferLayout>,
+    max_lights: Option<u32>,
+    ambient: Option<[f32; 3]>,
+    frames_in_flight: Option<u32>,
+    shadows: Option<&'n ShadowRenderer>
+}
+
+impl<'n> DeferredRendererBuilder<'n> {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with_graphics_device(mut self, ctx: &'n RenderContext) -> Self {
+        self.ctx = Some(ctx);
+        self
+    }
+
+    /// `gbuffer.vert`
+    pub fn with_gbuffer_vertex_source(mut self, source: ShaderSource) -> Self {
+        self.gbuffer_vertex_shader = Some(source);
+        self
+    }
+
+    /// `gbuffer.frag`
+    pub fn with_gbuffer_fragment_source(mut self, source: ShaderSource) -> Self {
+        self.gbuffer_fragment_shader = Some(source);
+        self
+    }
+
+    /// `deferred-lighting.comp`
+    pub fn with_lighting_source(mut self, source: ShaderSource) -> Self {
+        self.lighting_shader = Some(source);
+        self
+    }
+
+    /// `fullscreen.vert`
+    pub fn with_composite_vertex_source(mut self, source: ShaderSource) -> Self {
+        self.composite_vertex_shader = Some(source);
+        self
+    }
+
+    /// `deferred-composite.frag`
+    pub fn with_composite_fragment_source(mut self, source: ShaderSource) -> Self {
+        self.composite_fragment_shader = Some(source);
+        self
+    }
+
+    /// Default: [`GBufferLayout::default`]
+    pub fn with_layout(mut self, layout: GBufferLayout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    /// Default: 4096
+    pub fn with_max_lights(mut self, max_lights: u32) -> Self {
+        self.max_lights = Some(max_lights);
+        self
+    }
+
+    /// Default: 0.03 grey
+    pub fn with_ambient(mut self, ambient: [f32; 3]) -> Self {
+        self.ambient = Some(ambient);
+        self
+    }
+
+    /// Must match the render graph, default: 2
+    pub fn with_frames_in_flight(mut self, count: u32) -> Self {
+        self.frames_in_flight = Some(count);
+        self
+    }
+
+    /// Compiles the lighting shader with `SHADOWS` and adds the shadow set, see [`DeferredRenderer::bind_shadows`]
+    pub fn with_shadows(mut self, shadows: &'n ShadowRenderer) -> Self {
+        self.shadows = Some(shadows);
+        self
+    }
+
+    pub fn build(self) -> VulkanResult<DeferredRenderer> {
+
+        let missing = |field| VulkanError::missing("DeferredRendererBuilder", field);
+
+        let ctx = self.ctx.ok_or_else(|| missing("render context"))?;
+        let gbuffer_vertex_shader = self.gbuffer_vertex_shader.ok_or_else(|| missing("GBuffer vertex shader"))?;
+        let gbuffer_fragment_shader = self.gbuffer_fragment_shader.ok_or_else(|| missing("GBuffer fragment shader"))?;
+        let lighting_shader = self.lighting_shader.ok_or_else(|| missing("lighting shader"))?;
+
+        let lighting_shader = match self.shadows {
+            Some(_) => {
+                let defines = lighting_shader.defines().clone().with_flag("SHADOWS");
+                lighting_shader.with_defines(defines)
+            }
+            None => lighting_shader
+        };
+        let composite_vertex_shader = self.composite_vertex_shader.ok_or_else(|| missing("composite vertex shader"))?;
+        let composite_fragment_shader = self.composite_fragment_shader.ok_or_else(|| missing("composite fragment shader"))?;
+
+        let device = ctx.device.raw_device();
+        let memory_prop = &ctx.device.phys_dev.phys_info.memory_prop;
+        let layout = self.layout.unwrap_or_default();
+        let max_lights = self.max_lights.unwrap_or(DEFAULT_MAX_LIGHTS).max(1);
+
+        // Приложение пишет кадр, пока GPU ещё читает frames_in_flight предыдущих
+        let frame_count = self.frames_in_flight.unwrap_or(DEFAULT_FRAMES_IN_FLIGHT).max(1) + 1;
+
+        let binding = |binding: u32, ty: vk::DescriptorType, stages: vk::ShaderStageFlags| vk::DescriptorSetLayoutBinding::default()
+            .binding(binding)
+            .descriptor_type(ty)
+            .descriptor_count(1)
+            .stage_flags(stages);
+
+        let frame_stages = vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::COMPUTE;
+        let frame_bindings = [
+            binding(0, vk::DescriptorType::UNIFORM_BUFFER, frame_stages),
+            binding(1, vk::DescriptorType::STORAGE_BUFFER, frame_stages),
+        ];
+
+        let compute = vk::ShaderStageFlags::COMPUTE;
+        let lighting_bindings = [
+            binding(0, vk::DescriptorType::SAMPLED_IMAGE, compute),
+            binding(1, vk::DescriptorType::SAMPLED_IMAGE, compute),
+            binding(2, vk::DescriptorType::SAMPLED_IMAGE, compute),
+            binding(3, vk::DescriptorType::SAMPLED_IMAGE, compute),
+            binding(4, vk::DescriptorType::SAMPLER, compute),
+            binding(5, vk::DescriptorType::STORAGE_IMAGE, compute),
+        ];
+
+        let fragment = vk::ShaderStageFlags::FRAGMENT;
+        let composite_bindings = [
+            binding(0, vk::DescriptorType::SAMPLED_IMAGE, fragment),
+            binding(1, vk::DescriptorType::SAMPLED_IMAGE, fragment),
+            binding(2, vk::DescriptorType::SAMPLED_IMAGE, fragment),
+            binding(3, vk::DescriptorType::SAMPLED_IMAGE, fragment),
+            binding(4, vk::DescriptorType::SAMPLED_IMAGE, fragment),
+            binding(5, vk::DescriptorType::SAMPLED_IMAGE, fragment),
+            binding(6, vk::DescriptorType::SAMPLER, fragment),
+        ];
+
+        let set_layout = |bindings| DescriptorSetLayoutBuilder::new()
+            .with_device(device)
+            .with_bindings(bindings)
+            .build();
+
+        let frame_set_layout = set_layout(&frame_bindings)?;
+        let lighting_set_layout = set_layout(&lighting_bindings)?;
+        let composite_set_layout = set_layout(&composite_bindings)?;
+
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(PrimitiveTopology::TRIANGLE_LIST)
+            .primitive_restart_enable(false);
+
+        // GBuffer: только dynamic rendering, вложения свои
+        let shader = ShaderProgramBuilder::new()
+            .with_device(device)
+            .with_vertex_source(gbuffer_vertex_shader)
+            .with_fragment_source(gbuffer_fragment_shader)
+            .build()?;
+
+        let binding_description = PbrVertex::binding_descriptions();
+        let attribute_description = PbrVertex::attribute_descriptions();
+
+        let vertex_input_state_info = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_attribute_descriptions(&attribute_description)
+            .vertex_binding_descriptions(&binding_description);
+
+        let gbuffer_set_layouts = [frame_set_layout.raw];
+        let gbuffer_push_constants = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<PbrPushConstants>() as u32
+        }];
+
+        let color_formats = layout.color_formats();
+        let mut builder = RenderPipelineBuilder::new()
+            .with_device(device)
+            .with_vertex_shader(shader.vertex_shader)
+            .with_vertex_entry_point(&shader.vertex_entry_point)
+            .with_fragment_shader(shader.fragment_shader)
+            .with_fragment_entry_point(&shader.fragment_entry_point)
+            .with_vertex_input_info(vertex_input_state_info)
+            .with_input_assembly_info(input_assembly)
+            .with_resolution(ctx.window.extent())
+            .with_color_formats(&color_formats)
+            .with_depth_format(layout.depth)
+            .with_depth_test(true)
+            .with_descriptor_set_layouts(&gbuffer_set_layouts)
+            .with_push_constant_ranges(&gbuffer_push_constants)
+            .with_pipeline_cache(ctx.pipeline_cache.raw);
+
+        for _ in &color_formats {
+            builder = builder.add_color_attachment(BlendMode::Opaque);
+        }
+
+        let gbuffer = builder.build()?;
+
+        let shader = ShaderProgramBuilder::new()
+            .with_device(device)
+            .with_compute_source(lighting_shader)
+            .build()?;
+
+        let mut lighting_set_layouts = vec![frame_set_layout.raw, lighting_set_layout.raw];
+
+        if let Some(shadows) = self.shadows {
+            lighting_set_layouts.push(shadows.set_layout.raw);
+        }
+        let lighting = ComputePipelineBuilder::new()
+            .with_device(device)
+            .with_shader(shader.compute_shader)
+            .with_entry_point(&shader.compute_entry_point)
+            .with_descriptor_set_layouts(&lighting_set_layouts)
+            .with_pipeline_cache(ctx.pipeline_cache.raw)
+            .build()?;
+
+        // Композиция рисует в окно, как StandartPipelineBuilder
+        let shader = ShaderProgramBuilder::new()
+            .with_device(device)
+            .with_vertex_source(composite_vertex_shader)
+            .with_fragment_source(composite_fragment_shader)
+            .build()?;
+
+        let render_pass = ctx.window.render_pass.as_ref();
+        let composite_set_layouts = [composite_set_layout.raw];
+        let composite_push_constants = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<CompositeConstants>() as u32
+        }];
+
+        let mut builder = RenderPipelineBuilder::new();
+
+        if let Some(render_pass) = render_pass {
+            builder = builder.with_render_pass(render_pass);
+        }
+
+        let builder = builder
+            .with_device(device)
+            .with_vertex_shader(shader.vertex_shader)
+            .with_vertex_entry_point(&shader.vertex_entry_point)
+            .with_fragment_shader(shader.fragment_shader)
+            .with_fragment_entry_point(&shader.fragment_entry_point)
+            .with_input_assembly_info(input_assembly)
+            .with_resolution(ctx.window.caps.current_extent)
+            .with_format(ctx.window.surface_format_khr.format)
+            .with_cull_mode(vk::CullModeFlags::NONE)
+            .with_depth_test(false)
+            .with_depth_write(false)
+            .with_samples(ctx.window.samples())
+            .with_descriptor_set_layouts(&composite_set_layouts)
+            .with_push_constant_ranges(&composite_push_constants)
+            .with_pipeline_cache(ctx.pipeline_cache.raw);
+
+        let composite = match ctx.window.depth_format() {
+            Some(format) if render_pass.is_none() => builder.with_depth_stencil_format(format),
+            _ => builder
+        }.build()?;
+
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+
+        let sampler = unsafe { device.create_sampler(&sampler_info, None) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateSamplerFailed(e))))?;
+
+        let owner = ResourceOwner::new(device, vec![DeferredResource::Sampler(sampler)]);
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(frame_count),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(frame_count),
+        ];
+
+        let pool = DescriptorPoolBuilder::new()
+            .with_device(device)
+            .with_pool_sizes(&pool_sizes)
+            .with_max_sets(frame_count)
+            .build()?;
+
+        let layouts = vec![frame_set_layout.raw; frame_count as usize];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool.raw)
+            .set_layouts(&layouts);
+
+        let sets = unsafe { device.allocate_descriptor_sets(&allocate_info) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::AllocateDescriptorSetFailed(e))))?;
+
+        let create_buffer = |size: u64, usage: vk::BufferUsageFlags| {
+            GPUBuffer::new(device, memory_prop, size, usage, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT)
+                .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateBufferFailed(e))))
+        };
+
+        let mut frames = Vec::with_capacity(sets.len());
+
+        for set in sets {
+            let uniforms = create_buffer(std::mem::size_of::<DeferredUniforms>() as u64, vk::BufferUsageFlags::UNIFORM_BUFFER)?;
+            let lights = create_buffer(max_lights as u64 * std::mem::size_of::<Light>() as u64, vk::BufferUsageFlags::STORAGE_BUFFER)?;
+
+            let uniforms_info = [vk::DescriptorBufferInfo::default().buffer(uniforms.raw).range(vk::WHOLE_SIZE)];
+            let lights_info = [vk::DescriptorBufferInfo::default().buffer(lights.raw).range(vk::WHOLE_SIZE)];
+
+            let descriptor_writes = [
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&uniforms_info),
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(&lights_info),
+            ];
+
+            unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+            frames.push(FrameData { uniforms, lights, set });
+        }
+
+        Ok(DeferredRenderer {
+            gbuffer,
+            lighting,
+            composite,
+            frame_set_layout,
+            lighting_set_layout,
+            composite_set_layout,
+            sampler,
+            layout,
+            max_lights,
+            ambient: self.ambient.unwrap_or([0.03; 3]),
+            view: DeferredView::default(),
+            exposure: 1.0,
+            frames,
+            current: 0,
+            _pool: pool,
+            _owner: owner
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PipelineReflection, ShaderReflection};
+
+    fn load(bytes: &[u8]) -> Vec<u32> {
+        bytes.chunks_exact(4).map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]])).collect()
+    }
+
+    #[test]
+    fn test_gbuffer_matches_shaders() {
+        let vs = ShaderReflection::from_spirv(&load(include_bytes!("../../../../../shared/shaders/spv/gbuffer-vert.spv"))).unwrap();
+        let fs = ShaderReflection::from_spirv(&load(include_bytes!("../../../../../shared/shaders/spv/gbuffer-frag.spv"))).unwrap();
+
+        assert_eq!(vs.inputs.len(), PbrVertex::attribute_descriptions().len());
+
+        let reflection = PipelineReflection::merge(&[vs, fs]).unwrap();
+        assert_eq!(reflection.push_constant_ranges[0].size as usize, std::mem::size_of::<PbrPushConstants>());
+        assert_eq!(reflection.sets[&0][0].descriptor_type, vk::DescriptorType::UNIFORM_BUFFER);
+    }
+
+    #[test]
+    fn test_lighting_matches_shader() {
+        let shader = ShaderReflection::from_spirv(&load(include_bytes!("../../../../../shared/shaders/spv/deferred-lighting-comp.spv"))).unwrap();
+        assert_eq!(shader.workgroup_size, Some([TILE_SIZE, TILE_SIZE, 1]));
+
+        let reflection = PipelineReflection::merge(&[shader]).unwrap();
+        assert_eq!(reflection.sets[&0][1].descriptor_type, vk::DescriptorType::STORAGE_BUFFER);
+
+        // pointSampler нужен только texelFetch, поэтому в отражении его нет
+        let ty = |binding| reflection.sets[&1].iter().find(|x| x.binding == binding).map(|x| x.descriptor_type);
+        assert_eq!(ty(3), Some(vk::DescriptorType::SAMPLED_IMAGE));
+        assert_eq!(ty(4), None);
+        assert_eq!(ty(5), Some(vk::DescriptorType::STORAGE_IMAGE));
+    }
+
+    #[test]
+    fn test_composite_matches_shader() {
+        let fs = ShaderReflection::from_spirv(&load(include_bytes!("../../../../../shared/shaders/spv/deferred-composite-frag.spv"))).unwrap();
+        let reflection = PipelineReflection::merge(&[fs]).unwrap();
+
+        assert_eq!(reflection.push_constant_ranges[0].size as usize, std::mem::size_of::<CompositeConstants>());
+        assert_eq!(reflection.sets[&0].len(), 7);
+        assert_eq!(reflection.sets[&0][6].descriptor_type, vk::DescriptorType::SAMPLER);
+    }
+}
@@ -1,7 +1,8 @@
 use crate::{
     App,
     AppBuilder,
-    GraphicsDeviceBuilder
+    GraphicsDeviceBuilder,
+    VulkanResult
 };
 
 pub struct WithApp<'n> {
@@ -15,23 +16,22 @@ impl<'n> GraphicsDeviceBuilder<()> {
         }
     }
 
-    pub fn with_app<F>(self, build_fn: F) -> GraphicsDeviceBuilder<WithApp<'n>>
-    where F: FnOnce() -> App<'n> {
+    pub fn with_app<F>(self, build_fn: F) -> VulkanResult<GraphicsDeviceBuilder<WithApp<'n>>>
+    where F: FnOnce() -> VulkanResult<App<'n>> {
 
-        let app = build_fn();
+        let app = build_fn()?;
 
-        GraphicsDeviceBuilder {
+        Ok(GraphicsDeviceBuilder {
             state: WithApp { app },
-        }
+        })
     }
 
-    pub fn with_default_app(self) -> GraphicsDeviceBuilder<WithApp<'n>> {
+    pub fn with_default_app(self) -> VulkanResult<GraphicsDeviceBuilder<WithApp<'n>>> {
         self.with_app(|| {
             AppBuilder::new()
                 .with_app_name(c"App")
                 .with_api_version(ash::vk::API_VERSION_1_2)
                 .build()
-                .expect("Error create App")
         })
     }
 }
\ No newline at end of file
@@ -2,34 +2,95 @@
 
 use crate::{core::{
     Instance,
-}, DeviceBuilder, QueueFamily};
+}, VulkanResult, supports_hdr_metadata, DeviceBuilder, FeatureSet, DynamicRendering, HdrMetadata, OptionalFeatures, QueueFamily, RequiredFeatures, Synchronization2, DYNAMIC_RENDERING_EXTENSION, SYNCHRONIZATION_2_EXTENSION, TIMELINE_SEMAPHORE_EXTENSION};
 
 use super::*;
 
 impl GraphicsDeviceBuilder<WithQueueFamily> {
 
-    pub fn with_device<F>(self, build_fn: F) -> GraphicsDevice
-    where F: FnOnce(&Instance, &PhysicalDevice, &Vec<QueueFamily>) -> Device {
+    pub fn with_device<F>(self, build_fn: F) -> VulkanResult<GraphicsDevice>
+    where F: FnOnce(&Instance, &PhysicalDevice, &Vec<QueueFamily>) -> VulkanResult<Device> {
 
-        let device = build_fn(&self.state.instance, &self.state.phys_dev, &self.state.queue_family);
+        let device = build_fn(&self.state.instance, &self.state.phys_dev, &self.state.queue_family)?;
         let universal_queue = UniversalQueue::new(&device.raw, self.state.queue_family);
 
-        GraphicsDevice {
+        let api_version = self.state.instance.api_version.min(self.state.phys_dev.phys_info.phys_prop.api_version);
+        let dynamic_rendering = device.dynamic_rendering.then(|| {
+            DynamicRendering::new(&self.state.instance.raw, &device.raw, api_version)
+        });
+
+        let synchronization2 = device.synchronization2.then(|| {
+            Synchronization2::new(&self.state.instance.raw, &device.raw, api_version)
+        });
+
+        let hdr_metadata = device.hdr_metadata.then(|| {
+            HdrMetadata::new(&self.state.instance.raw, &device.raw)
+        });
+
+        Ok(GraphicsDevice {
             instance: self.state.instance,
             phys_dev: self.state.phys_dev,
             logical_device: device,
-            universal_queue
-        }
+            universal_queue,
+            dynamic_rendering,
+            synchronization2,
+            hdr_metadata
+        })
     }
 
-    pub fn with_default_device(self) -> GraphicsDevice {
+    /// Dynamic rendering, synchronization2 and HDR metadata are enabled when the device supports them
+    pub fn with_default_device(self) -> VulkanResult<GraphicsDevice> {
+        self.with_device_features(RequiredFeatures::new(), OptionalFeatures::new())
+    }
+
+    ///
+    /// Same as [`Self::with_default_device`] with extra feature requests,
+    /// the result is in [`GraphicsDevice::features`].
+    /// Fails with [`crate::DeviceError::MissingFeatures`] if a required feature is not supported
+    ///
+    pub fn with_device_features(self, required: RequiredFeatures, optional: OptionalFeatures) -> VulkanResult<GraphicsDevice> {
         self.with_device(|instance, phys_dev, queue_family| {
-            DeviceBuilder::new()
-                .with_extensions(vec![
-                    c"VK_KHR_swapchain"
-                ])
+
+            let mut extensions = vec![
+                c"VK_KHR_swapchain"
+            ];
+
+            let mut builder = DeviceBuilder::new()
+                .with_required_features(required)
+                .with_optional_features(optional);
+
+            let api_version = instance.api_version.min(phys_dev.phys_info.phys_prop.api_version);
+            // Ниже 1.3 поля заполнены только при поддержке расширений
+            let supported = FeatureSet::query(&instance.raw, phys_dev.raw, api_version)?;
+
+            if supported.vulkan13.dynamic_rendering == ash::vk::TRUE {
+                if api_version < ash::vk::API_VERSION_1_3 {
+                    extensions.push(DYNAMIC_RENDERING_EXTENSION);
+                }
+                builder = builder.with_dynamic_rendering();
+            }
+
+            let has_timeline = supported.vulkan12.timeline_semaphore == ash::vk::TRUE;
+            let has_sync2 = supported.vulkan13.synchronization2 == ash::vk::TRUE;
+
+            if has_timeline && has_sync2 {
+                if api_version < ash::vk::API_VERSION_1_2 {
+                    extensions.push(TIMELINE_SEMAPHORE_EXTENSION);
+                }
+                if api_version < ash::vk::API_VERSION_1_3 {
+                    extensions.push(SYNCHRONIZATION_2_EXTENSION);
+                }
+                builder = builder.with_synchronization2();
+            }
+
+            if supports_hdr_metadata(&phys_dev.phys_info) {
+                builder = builder.with_hdr_metadata();
+            }
+
+            builder
+                .with_extensions(extensions)
                 .queue_family(&queue_family)
-                .with_instance(&instance.raw)
+                .with_instance(instance)
                 .with_phys_dev(&phys_dev.raw)
                 .build()
         })
@@ -3,8 +3,9 @@ use std::ffi::CStr;
 use winit::{raw_window_handle::HasDisplayHandle, window::Window};
 
 use crate::core::{
-    App, Instance, InstanceBuilder
+    supports_swapchain_colorspace, App, Instance, InstanceBuilder, SWAPCHAIN_COLORSPACE_EXTENSION
 };
+use crate::{InstanceError, SurfaceError, VulkanError, VulkanResult};
 
 use super::*;
 
@@ -15,36 +16,43 @@ pub struct WithInstance<'n> {
 
 impl<'n, 'w> GraphicsDeviceBuilder<WithWindow<'n, 'w>> {
 
-    pub fn with_instance<F>(self, build_fn: F) -> GraphicsDeviceBuilder<WithInstance<'n>>
-    where F: FnOnce(App<'n>, &'w Window) -> Instance {
+    pub fn with_instance<F>(self, build_fn: F) -> VulkanResult<GraphicsDeviceBuilder<WithInstance<'n>>>
+    where F: FnOnce(App<'n>, &'w Window) -> VulkanResult<Instance> {
 
-        let instance = build_fn(self.state.app.clone(), self.state.window);
+        let instance = build_fn(self.state.app.clone(), self.state.window)?;
 
-        GraphicsDeviceBuilder {
+        Ok(GraphicsDeviceBuilder {
             state: WithInstance {
                 app: self.state.app,
                 instance: instance
             }
-        }
+        })
     }
 
-    pub fn with_default_instance(self) -> GraphicsDeviceBuilder<WithInstance<'n>> {
+    pub fn with_default_instance(self) -> VulkanResult<GraphicsDeviceBuilder<WithInstance<'n>>> {
 
         self.with_instance(|app, window| {
 
             let raw_display_handle = window
                 .display_handle()
-                .expect("Error get display handle")
+                .map_err(|e| VulkanError::Surface(SurfaceError::WindowHandle(e.to_string())))?
                 .as_raw();
 
             let window_ext = ash_window::enumerate_required_extensions(raw_display_handle)
-                .unwrap()
+                .map_err(|e| VulkanError::Instance(InstanceError::EnumerateInstanceExtensionPropertiesFailed(e)))?
                 .iter()
                 .map(|&ptr| unsafe { CStr::from_ptr(ptr) })
                 .collect::<Vec<_>>();
 
-            InstanceBuilder::new()
-                .with_extensions(window_ext)
+            let mut builder = InstanceBuilder::new()
+                .with_extensions(window_ext);
+
+            // Needed for HDR10, scRGB and Display-P3 surface formats
+            if supports_swapchain_colorspace(&app.entry) {
+                builder = builder.with_extensions(vec![SWAPCHAIN_COLORSPACE_EXTENSION]);
+            }
+
+            builder
                 .with_debug_layers(vec![
                     c"VK_LAYER_KHRONOS_validation"
                 ])
@@ -54,7 +62,6 @@ impl<'n, 'w> GraphicsDeviceBuilder<WithWindow<'n, 'w>> {
                 ])
                 .with_app(app)
                 .build()
-                .unwrap()
         })
 
     }
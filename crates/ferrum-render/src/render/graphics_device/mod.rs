@@ -22,7 +22,15 @@ use crate::{
     Instance,
     PhysicalDevice,
     Device,
-    UniversalQueue
+    DeviceHandle,
+    FeatureSet,
+    DynamicRendering,
+    HdrMetadata,
+    Synchronization2,
+    UniversalQueue,
+    DeviceError,
+    VulkanError,
+    VulkanResult
 };
 
 pub struct GraphicsDeviceBuilder<S> {
@@ -34,10 +42,28 @@ pub struct GraphicsDevice {
     pub phys_dev: PhysicalDevice,
     pub logical_device: Device,
     pub universal_queue: UniversalQueue,
+    /// Some when the device was created with dynamic rendering enabled
+    pub dynamic_rendering: Option<DynamicRendering>,
+    /// Some when the device was created with timeline semaphores and synchronization2
+    pub synchronization2: Option<Synchronization2>,
+    /// Some when the device was created with `VK_EXT_hdr_metadata`
+    pub hdr_metadata: Option<HdrMetadata>
 }
 
 impl GraphicsDevice {
-    pub fn raw_device(&self) -> &ash::Device {
+    /// Derefs to [`ash::Device`], clone it to own resources
+    pub fn raw_device(&self) -> &DeviceHandle {
         &self.logical_device.raw
     }
+
+    /// Extensions and features enabled on the logical device
+    pub fn features(&self) -> &FeatureSet {
+        &self.logical_device.features
+    }
+
+    /// Dynamic rendering loader, a missing feature error if the device was created without it
+    pub fn require_dynamic_rendering(&self) -> VulkanResult<&DynamicRendering> {
+        self.dynamic_rendering.as_ref()
+            .ok_or_else(|| VulkanError::Device(DeviceError::MissingFeatures(vec!["dynamicRendering".to_string()])))
+    }
 }
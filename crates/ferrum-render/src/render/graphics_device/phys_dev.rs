@@ -3,7 +3,7 @@ use ash::vk::PhysicalDeviceType;
 
 use crate::{core::{
     Instance,  Surface,
-}, PhysicalDeviceBuilder, PhysicalDeviceInfo};
+}, PhysicalDeviceBuilder, PhysicalDeviceInfo, VulkanResult};
 
 use super::*;
 
@@ -14,20 +14,20 @@ pub struct WithPhysicalDevice {
 
 impl<'n> GraphicsDeviceBuilder<WithInstance<'n>> {
 
-    pub fn with_phys_dev<F>(self, surface: &Surface, build_fn: F) -> GraphicsDeviceBuilder<WithPhysicalDevice>
-    where F: FnOnce(&Instance, &Surface) -> PhysicalDevice {
+    pub fn with_phys_dev<F>(self, surface: &Surface, build_fn: F) -> VulkanResult<GraphicsDeviceBuilder<WithPhysicalDevice>>
+    where F: FnOnce(&Instance, &Surface) -> VulkanResult<PhysicalDevice> {
 
-        let phys_dev = build_fn(&self.state.instance, surface);
+        let phys_dev = build_fn(&self.state.instance, surface)?;
 
-        GraphicsDeviceBuilder {
+        Ok(GraphicsDeviceBuilder {
             state: WithPhysicalDevice {
                 instance: self.state.instance,
                 phys_dev
             }
-        }
+        })
     }
 
-    pub fn with_default_phys_dev(self, surface: &Surface) -> GraphicsDeviceBuilder<WithPhysicalDevice> {
+    pub fn with_default_phys_dev(self, surface: &Surface) -> VulkanResult<GraphicsDeviceBuilder<WithPhysicalDevice>> {
         self.with_phys_dev(surface, |instance, surface| {
 
             const PRIORITY_GPU: &[PhysicalDeviceType] = &[
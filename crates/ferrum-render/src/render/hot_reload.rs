@@ -0,0 +1,219 @@
+use std::ops::Index;
+use std::path::{Path, PathBuf};
+
+use ferrum_shaders::{Backend, CompiledShader, FileWatcher, ShaderCompiler, ShaderDefines, ShaderStage};
+
+use crate::{RenderContext, RenderPipeline, VulkanResult};
+
+/// Rebuilds a pipeline from freshly compiled SPIR-V
+pub type PipelineRebuild = Box<dyn Fn(&RenderContext, &ShaderSet) -> VulkanResult<RenderPipeline>>;
+
+/// Compiled stages of one hot-reloaded pipeline
+pub struct ShaderSet {
+    shaders: Vec<CompiledShader>
+}
+
+impl ShaderSet {
+
+    pub fn get(&self, stage: ShaderStage) -> Option<&Vec<u32>> {
+        self.shaders.iter().find(|x| x.stage == stage).map(|x| &x.spirv)
+    }
+}
+
+/// Panics if the stage was not registered
+impl Index<ShaderStage> for ShaderSet {
+    type Output = Vec<u32>;
+
+    fn index(&self, stage: ShaderStage) -> &Vec<u32> {
+        self.get(stage).unwrap_or_else(|| panic!("No {} shader in the set", stage))
+    }
+}
+
+struct HotPipeline {
+    name: &'static str,
+    sources: Vec<PathBuf>,
+    defines: ShaderDefines,
+    /// Sources and includes of the last successful build
+    dependencies: Vec<PathBuf>,
+    rebuild: PipelineRebuild,
+    error: Option<String>
+}
+
+///
+/// Recompiles shaders when their sources or includes change and rebuilds the pipelines using them.
+/// Enabled in debug builds only, in release [`ShaderHotReload::poll`] does nothing.
+/// On a compile or pipeline error the old pipeline stays, the error is logged and kept in [`ShaderHotReload::errors`]
+///
+/// # Example
+/// ```ignore
+/// let mut reload = ShaderHotReload::new();
+///
+/// reload.watch_render_pipeline("pipe", &["shared/shaders/triangle.vert", "shared/shaders/triangle.frag"], &ShaderDefines::new(), move |ctx, shaders| {
+///     StandartPipelineBuilder::new()
+///         .with_graphics_device(ctx)
+///         .with_vertex_shader(shaders[ShaderStage::Vertex].clone())
+///         .with_fragment_shader(shaders[ShaderStage::Fragment].clone())
+///         .build(set_layout)
+/// });
+///
+/// // RenderGraph swaps the pipeline named "pipe" before recording a frame
+/// graph.set_shader_reload(reload);
+/// ```
+///
+pub struct ShaderHotReload {
+    enabled: bool,
+    compiler: ShaderCompiler,
+    watcher: Option<FileWatcher>,
+    pipelines: Vec<HotPipeline>
+}
+
+impl Default for ShaderHotReload {
+    fn default() -> Self {
+        Self {
+            enabled: cfg!(debug_assertions),
+            compiler: ShaderCompiler::new(),
+            watcher: None,
+            pipelines: vec![]
+        }
+    }
+}
+
+impl ShaderHotReload {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    /// Default: enabled in debug builds
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.compiler = self.compiler.with_backend(backend);
+        self
+    }
+
+    pub fn with_include_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.compiler = self.compiler.with_include_dir(dir);
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    ///
+    /// Watches `sources` (stage from the extension) and their includes.
+    /// The current pipeline is not built here, it is replaced on the first change
+    ///
+    pub fn watch_render_pipeline<F>(&mut self, name: &'static str, sources: &[impl AsRef<Path>], defines: &ShaderDefines, rebuild: F)
+        where F: Fn(&RenderContext, &ShaderSet) -> VulkanResult<RenderPipeline> + 'static
+    {
+        if !self.enabled {
+            return;
+        }
+
+        if self.watcher.is_none() {
+            match FileWatcher::new() {
+                Ok(watcher) => self.watcher = Some(watcher),
+                Err(e) => {
+                    log::warn!("Shader hot-reload disabled, failed to create file watcher: {}", e);
+                    self.enabled = false;
+                    return;
+                }
+            }
+        }
+
+        let sources = sources.iter().map(|x| x.as_ref().to_path_buf()).collect::<Vec<_>>();
+        let mut dependencies = vec![];
+
+        for source in &sources {
+            match self.compiler.dependencies(source, defines) {
+                Ok(files) => dependencies.extend(files),
+                // Сломанный include всё равно надо отслеживать, хотя бы сам файл
+                Err(e) => {
+                    log::warn!("{}", e);
+                    dependencies.push(source.clone());
+                }
+            }
+        }
+
+        let mut pipeline = HotPipeline { name, sources, defines: defines.clone(), dependencies: vec![], rebuild: Box::new(rebuild), error: None };
+        self.set_dependencies(&mut pipeline, dependencies);
+        self.pipelines.push(pipeline);
+    }
+
+    /// Last error of every pipeline that failed to reload
+    pub fn errors(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        self.pipelines.iter().filter_map(|x| Some((x.name, x.error.as_deref()?)))
+    }
+
+    ///
+    /// Rebuilt pipelines by name, call at a frame boundary and replace the old ones.
+    /// Old pipelines are destroyed through the deletion queue once in-flight frames are done
+    ///
+    pub fn poll(&mut self, ctx: &RenderContext) -> Vec<(&'static str, RenderPipeline)> {
+
+        let Some(watcher) = &mut self.watcher else { return vec![] };
+        let changed = watcher.changed();
+
+        if changed.is_empty() {
+            return vec![];
+        }
+
+        let mut pipelines = std::mem::take(&mut self.pipelines);
+        let mut rebuilt = vec![];
+
+        for pipeline in pipelines.iter_mut().filter(|x| x.dependencies.iter().any(|x| changed.contains(x))) {
+            match self.rebuild(ctx, pipeline) {
+                Ok(new) => {
+                    log::info!("Reloaded {:?} pipeline", pipeline.name);
+                    pipeline.error = None;
+                    rebuilt.push((pipeline.name, new));
+                }
+                Err(e) => {
+                    log::error!("Failed to reload {:?} pipeline, keeping the old one:\n{}", pipeline.name, e);
+                    pipeline.error = Some(e);
+                }
+            }
+        }
+
+        self.pipelines = pipelines;
+        rebuilt
+    }
+
+    fn rebuild(&mut self, ctx: &RenderContext, pipeline: &mut HotPipeline) -> Result<RenderPipeline, String> {
+
+        let mut shaders = vec![];
+        let mut errors = vec![];
+
+        // Компилируем все стадии, чтобы показать все ошибки сразу
+        for source in &pipeline.sources {
+            match self.compiler.compile_file(source, &pipeline.defines) {
+                Ok(shader) => shaders.push(shader),
+                Err(e) => errors.push(e.to_string())
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+
+        let dependencies = shaders.iter().flat_map(|x| x.dependencies.iter().cloned()).collect();
+        let set = ShaderSet { shaders };
+        let new = (pipeline.rebuild)(ctx, &set).map_err(|e| e.to_string())?;
+
+        // Include мог добавиться или пропасть
+        self.set_dependencies(pipeline, dependencies);
+        Ok(new)
+    }
+
+    fn set_dependencies(&mut self, pipeline: &mut HotPipeline, dependencies: Vec<PathBuf>) {
+
+        let Some(watcher) = &mut self.watcher else { return };
+
+        pipeline.dependencies = dependencies.iter().map(|x| watcher.watch(x)).collect();
+    }
+}
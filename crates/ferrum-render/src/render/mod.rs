@@ -1,10 +1,19 @@
-
 pub(crate) mod graphics_device;
 pub(crate) mod window_manager;
 pub(crate) mod render_context;
 pub(crate) mod standart_pipeline;
+#[cfg(feature = "watch")]
+pub(crate) mod hot_reload;
+pub(crate) mod clustered;
+pub(crate) mod deferred;
+pub(crate) mod shadow;
 
 pub use window_manager::*;
 pub use graphics_device::*;
 pub use render_context::*;
-pub use standart_pipeline::*;
\ No newline at end of file
+pub use standart_pipeline::*;
+#[cfg(feature = "watch")]
+pub use hot_reload::*;
+pub use clustered::*;
+pub use deferred::*;
+pub use shadow::*;
\ No newline at end of file
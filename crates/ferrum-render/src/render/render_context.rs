@@ -1,13 +1,19 @@
-use std::{ffi::CStr, sync::Arc};
+use std::{collections::HashMap, ffi::CStr, path::Path, sync::Arc};
 
-use ash::vk::{Format, PresentModeKHR};
+use ash::vk::{Format, PresentModeKHR, SampleCountFlags};
+use winit::window::{Window, WindowId};
 
-use crate::{AppBuilder, GraphicsDevice, GraphicsDeviceBuilder, InstanceBuilder, WindowManager, WindowManagerBuilder};
+use crate::{supports_swapchain_colorspace, AppBuilder, GraphicsDevice, GraphicsDeviceBuilder, InstanceBuilder, OutputColorSpace, PipelineCache, PipelineCacheBuilder, PipelineRegistry, WindowManager, WindowManagerBuilder, SWAPCHAIN_COLORSPACE_EXTENSION, SurfaceError, ValidationConfig, VulkanError, VulkanResult};
 
 
 pub struct RenderContext {
     pub device: Arc<GraphicsDevice>,
-    pub window: WindowManager
+    /// Primary window, the device was chosen for its surface
+    pub window: WindowManager,
+    /// Additional windows sharing the device, see [`RenderContext::add_window`]
+    pub windows: HashMap<WindowId, WindowManager>,
+    pub pipeline_cache: PipelineCache,
+    pub pipelines: PipelineRegistry
 }
 
 pub struct RenderContextParams<'n> {
@@ -17,16 +23,30 @@ pub struct RenderContextParams<'n> {
     pub engine_name: Option<&'static CStr>,
     pub engine_version: Option<u32>,
     pub format_prioriry: Option<&'n [Format]>,
-    pub present_mode_priority: Option<&'n [PresentModeKHR]>
+    pub present_mode_priority: Option<&'n [PresentModeKHR]>,
+    /// Pipeline cache file, loaded on start and saved when context is dropped
+    pub pipeline_cache_path: Option<&'n Path>,
+    /// Use dynamic rendering instead of render pass and frame buffers when the device supports it
+    pub dynamic_rendering: Option<bool>,
+    /// Preferred output colour spaces, sRGB is used when none is supported
+    pub color_spaces: Option<&'n [OutputColorSpace]>,
+    /// Depth buffer, with stencil when `Some(true)`
+    pub depth_stencil: Option<bool>,
+    /// MSAA sample count, clamped to the device limits
+    pub samples: Option<SampleCountFlags>,
+    /// Enables the validation layer with `log` routing, also in release builds
+    pub validation: Option<ValidationConfig>
 }
 
 impl RenderContext {
 
-    pub fn new(window: winit::window::Window, params: RenderContextParams) -> Self {
+    pub fn new(window: winit::window::Window, params: RenderContextParams) -> VulkanResult<Self> {
 
         let api_version = params.api_version.unwrap_or(ash::vk::API_VERSION_1_0);
         let app_name = params.app_name.unwrap_or(c"None");
         let app_version = params.app_version.unwrap_or(0);
+        let color_spaces = params.color_spaces.unwrap_or(&[]);
+        let validation = params.validation;
 
         let device = GraphicsDeviceBuilder::new()
             .with_app(|| {
@@ -35,72 +55,244 @@ impl RenderContext {
                     .with_app_name(app_name)
                     .with_app_version(app_version)
                     .build()
-                    .unwrap()
-            })
+            })?
             .with_window(&window)
             .with_instance(|app, _| {
-                InstanceBuilder::new()
+                let mut builder = InstanceBuilder::new();
+
+                if color_spaces.iter().any(|x| x.requires_extension()) && supports_swapchain_colorspace(&app.entry) {
+                    builder = builder.with_extensions(vec![SWAPCHAIN_COLORSPACE_EXTENSION]);
+                }
+
+                if let Some(config) = validation {
+                    builder = builder.with_validation(config);
+                }
+
+                builder
                     .with_app(app)
                     .build()
-                    .unwrap()
-            });
+            })?;
 
         // TODO
 
         let window = WindowManagerBuilder::new(window)
-            .with_default_surface(&device.state.instance);
+            .with_default_surface(&device.state.instance)?;
 
         let device: Arc<GraphicsDevice> = device
-            .with_default_phys_dev(&window.state.surface)
+            .with_default_phys_dev(&window.state.surface)?
             .with_default_queue_family(&window.state.surface)
-            .with_default_device()
+            .with_default_device()?
             .into();
 
         let window = window
             .with_graphics_device(device.clone())
-            .with_default_format()
-            .with_default_mode()
-            .with_default_swapchain()
-            .with_default_render_pass()
-            .with_default_image_views()
-            .with_default_frame_buffers();
+            .with_color_space(color_spaces)?
+            .with_default_mode()?
+            .with_default_swapchain()?;
 
-        Self::from(device, window)
+        let window = match params.depth_stencil {
+            Some(stencil) => window.with_depth_buffer(stencil)?,
+            None => window
+        };
+
+        let window = match params.samples {
+            Some(samples) => window.with_samples(samples),
+            None => window
+        };
+
+        let window = if params.dynamic_rendering.unwrap_or(false) && device.dynamic_rendering.is_some() {
+            window.without_render_pass()?
+        } else {
+            window.with_default_render_pass()?
+        };
+
+        let window = window
+            .with_default_image_views()?
+            .with_default_frame_buffers()?;
+
+        Self::with_pipeline_cache(device, window, params.pipeline_cache_path)
     }
 
-    pub fn from(device: Arc<GraphicsDevice>, window: WindowManager) -> Self {
-        Self {
-            device,
-            window
+    pub fn from(device: Arc<GraphicsDevice>, window: WindowManager) -> VulkanResult<Self> {
+        Self::with_pipeline_cache(device, window, None)
+    }
+
+    fn with_pipeline_cache(device: Arc<GraphicsDevice>, window: WindowManager, path: Option<&Path>) -> VulkanResult<Self> {
+
+        let mut builder = PipelineCacheBuilder::new()
+            .with_device(device.raw_device())
+            .with_properties(&device.phys_dev.phys_info.phys_prop);
+
+        if let Some(path) = path {
+            builder = builder.with_path(path);
         }
+
+        let pipeline_cache = builder.build()?;
+        let pipelines = PipelineRegistry::new(pipeline_cache.raw);
+
+        Ok(Self {
+            device,
+            window,
+            windows: HashMap::new(),
+            pipeline_cache,
+            pipelines
+        })
     }
 
-    pub fn default(window: winit::window::Window) -> Self {
+    pub fn default(window: winit::window::Window) -> VulkanResult<Self> {
 
         let device = GraphicsDeviceBuilder::new()
-            .with_default_app()
+            .with_default_app()?
             .with_window(&window)
-            .with_default_instance();
+            .with_default_instance()?;
 
         let window = WindowManagerBuilder::new(window)
-            .with_default_surface(&device.state.instance);
+            .with_default_surface(&device.state.instance)?;
 
         let device: Arc<GraphicsDevice> = device
-            .with_default_phys_dev(&window.state.surface)
+            .with_default_phys_dev(&window.state.surface)?
             .with_default_queue_family(&window.state.surface)
-            .with_default_device()
+            .with_default_device()?
             .into();
 
         let window = window
             .with_graphics_device(device.clone())
-            .with_default_format()
-            .with_default_mode()
-            .with_default_swapchain()
-            .with_default_render_pass()
-            .with_default_image_views()
-            .with_default_frame_buffers();
+            .with_default_format()?
+            .with_default_mode()?
+            .with_default_swapchain()?
+            .with_depth_buffer(false)?
+            .with_default_render_pass()?
+            .with_default_image_views()?
+            .with_default_frame_buffers()?;
 
         Self::from(device, window)
     }
 }
 
+impl RenderContext {
+
+    ///
+    /// Creates a surface and swapchain for one more window on the same device.
+    /// Colour space, depth format, samples and render pass mode follow the primary window.
+    /// Fails with [`SurfaceError::PresentNotSupported`] if the graphics queue can't present to it
+    ///
+    /// # Example
+    /// ```
+    /// let id = ctx.add_window(event_loop.create_window(attrs)?)?;
+    /// graph.add_window_pass(id, "Preview", |res, ctx, frame| { ... });
+    /// ```
+    ///
+    pub fn add_window(&mut self, window: Window) -> VulkanResult<WindowId> {
+
+        let device = &self.device;
+        let primary = &self.window;
+
+        let window = WindowManagerBuilder::new(window)
+            .with_default_surface(&device.instance)?;
+
+        let surface = &window.state.surface;
+        let graphics_index = device.universal_queue.graphics_index();
+        let supported = unsafe {
+            surface.raw_load.get_physical_device_surface_support(device.phys_dev.raw, graphics_index, surface.raw)
+        }.map_err(|e| VulkanError::vk(e, |e| VulkanError::Surface(SurfaceError::GetSupportFailed(e))))?;
+
+        // Кадры всех окон идут через одну графическую очередь
+        if !supported {
+            return Err(VulkanError::Surface(SurfaceError::PresentNotSupported(graphics_index)));
+        }
+
+        let mut window = window
+            .with_graphics_device(device.clone())
+            .with_color_space(&[primary.output_color_space()])?
+            .with_default_mode()?
+            .with_default_swapchain()?
+            .with_samples(primary.samples());
+
+        if let Some(format) = primary.depth_format() {
+            window = window.with_depth_format(format);
+        }
+
+        let window = if primary.is_dynamic_rendering() {
+            window.without_render_pass()?
+        } else {
+            window.with_default_render_pass()?
+        };
+
+        let window = window
+            .with_default_image_views()?
+            .with_default_frame_buffers()?;
+
+        let id = window.id();
+        self.windows.insert(id, window);
+        Ok(id)
+    }
+
+    /// Waits for the device and destroys the window resources, the primary window can't be removed
+    pub fn remove_window(&mut self, id: WindowId) -> Option<Window> {
+
+        let mut window = self.windows.remove(&id)?;
+        let device = self.device.raw_device();
+
+        unsafe { device.device_wait_idle().ok() };
+        window.destroy(device);
+
+        Some(window.raw)
+    }
+
+    pub fn window(&self, id: WindowId) -> Option<&WindowManager> {
+        if self.window.id() == id {
+            return Some(&self.window);
+        }
+
+        self.windows.get(&id)
+    }
+
+    pub fn window_mut(&mut self, id: WindowId) -> Option<&mut WindowManager> {
+        if self.window.id() == id {
+            return Some(&mut self.window);
+        }
+
+        self.windows.get_mut(&id)
+    }
+
+    /// Primary window first
+    pub fn window_ids(&self) -> Vec<WindowId> {
+        std::iter::once(self.window.id()).chain(self.windows.keys().copied()).collect()
+    }
+
+    /// Handles `WindowEvent::Resized` of any window
+    pub fn resize_window(&mut self, id: WindowId, width: u32, height: u32) -> VulkanResult<()> {
+        let device = self.device.clone();
+
+        match self.window_mut(id) {
+            Some(window) => window.resize(&device, width, height),
+            None => Ok(())
+        }
+    }
+}
+
+impl Drop for RenderContext {
+    fn drop(&mut self) {
+
+        let device = self.device.raw_device();
+
+        unsafe { device.device_wait_idle().ok() };
+
+        self.pipelines.clear();
+
+        for window in self.windows.values_mut() {
+            window.destroy(device);
+        }
+
+        self.window.destroy(device);
+
+        if let Err(err) = self.pipeline_cache.save(device) {
+            log::warn!("{}", err);
+        }
+
+        self.pipeline_cache.destroy(device);
+
+        // Ресурсы, которые ещё живут (граф, буферы), уничтожатся вместе с последним DeviceHandle
+        device.flush_all();
+    }
+}
@@ -0,0 +1,192 @@
+use crate::{Light, LightKind};
+
+use super::cascade::{look_at, mul, normalize, perspective, up_for, Mat4};
+
+/// Square region of the shadow atlas in texels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtlasTile {
+    pub offset: [u32; 2],
+    pub size: u32
+}
+
+impl AtlasTile {
+
+    /// Offset and size in UV, `rect` of `ShadowMatrix` in `shadow.glsl`
+    pub fn uv_rect(&self, atlas_size: u32) -> [f32; 4] {
+        let scale = 1.0 / atlas_size as f32;
+        [self.offset[0] as f32 * scale, self.offset[1] as f32 * scale, self.size as f32 * scale, self.size as f32 * scale]
+    }
+}
+
+///
+/// Quadtree allocator of square power of two tiles. Tiles are handed out again
+/// every frame after [`ShadowAtlas::clear`]
+///
+/// # Example
+/// ```
+/// use ferrum_render::ShadowAtlas;
+///
+/// let mut atlas = ShadowAtlas::new(4096);
+/// let spot = atlas.allocate(512);
+/// let point = atlas.allocate_many(256, 6);
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct ShadowAtlas {
+    pub size: u32,
+    free: Vec<AtlasTile>
+}
+
+impl ShadowAtlas {
+
+    /// `size` is rounded up to a power of two
+    pub fn new(size: u32) -> Self {
+        let size = size.max(1).next_power_of_two();
+        Self { size, free: vec![AtlasTile { offset: [0, 0], size }] }
+    }
+
+    pub fn clear(&mut self) {
+        self.free = vec![AtlasTile { offset: [0, 0], size: self.size }];
+    }
+
+    /// Smallest free tile that fits, split down to `size` rounded up to a power of two
+    pub fn allocate(&mut self, size: u32) -> Option<AtlasTile> {
+
+        let size = size.max(1).next_power_of_two();
+
+        let (index, _) = self.free.iter()
+            .enumerate()
+            .filter(|(_, x)| x.size >= size)
+            .min_by_key(|(_, x)| x.size)?;
+
+        let mut tile = self.free.swap_remove(index);
+
+        // Четыре четверти, первая делится дальше
+        while tile.size > size {
+            let half = tile.size / 2;
+            let [x, y] = tile.offset;
+
+            self.free.push(AtlasTile { offset: [x + half, y], size: half });
+            self.free.push(AtlasTile { offset: [x, y + half], size: half });
+            self.free.push(AtlasTile { offset: [x + half, y + half], size: half });
+            tile = AtlasTile { offset: [x, y], size: half };
+        }
+
+        Some(tile)
+    }
+
+    /// All `count` tiles or none of them
+    pub fn allocate_many(&mut self, size: u32, count: usize) -> Option<Vec<AtlasTile>> {
+
+        let free = self.free.clone();
+        let tiles = (0..count).map(|_| self.allocate(size)).collect::<Option<Vec<_>>>();
+
+        if tiles.is_none() {
+            self.free = free;
+        }
+
+        tiles
+    }
+}
+
+/// Directions of the point light faces, in the order `localShadow` of `shadow.glsl` picks them
+pub const POINT_FACES: [[f32; 3]; 6] = [
+    [1.0, 0.0, 0.0],
+    [-1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, -1.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [0.0, 0.0, -1.0],
+];
+
+/// Closest plane of local light projections as a fraction of the range
+const NEAR_FRACTION: f32 = 0.01;
+
+/// Near and far planes of [`local_light_views`], `params.xy` of `ShadowMatrix`
+pub fn local_light_planes(light: &Light) -> [f32; 2] {
+    let near = (light.range * NEAR_FRACTION).max(0.01);
+    [near, light.range.max(near * 2.0)]
+}
+
+///
+/// View-projection and vertical field of view of each face of a spot or point light,
+/// one for spot lights and [`POINT_FACES`] for point lights
+///
+pub fn local_light_views(light: &Light) -> Vec<(Mat4, f32)> {
+
+    let [near, far] = local_light_planes(light);
+
+    let face = |direction: [f32; 3], fov: f32| {
+        let target = [light.position[0] + direction[0], light.position[1] + direction[1], light.position[2] + direction[2]];
+        let view = look_at(light.position, target, up_for(direction));
+        (mul(&perspective(fov, near, far), &view), fov)
+    };
+
+    match light.kind() {
+        LightKind::Point => POINT_FACES.iter().map(|x| face(*x, std::f32::consts::FRAC_PI_2)).collect(),
+        LightKind::Spot => {
+            // Внешний конус из spot_scale и spot_offset, с запасом на фильтрацию
+            let cos_outer = (-light.spot_offset / light.spot_scale).clamp(-1.0, 1.0);
+            let fov = (cos_outer.acos() * 2.0 + 0.05).min(std::f32::consts::PI - 0.1);
+            vec![face(normalize(light.direction), fov)]
+        }
+        LightKind::Directional => vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::shadow::cascade::transform;
+
+    fn overlaps(a: &AtlasTile, b: &AtlasTile) -> bool {
+        a.offset[0] < b.offset[0] + b.size && b.offset[0] < a.offset[0] + a.size &&
+        a.offset[1] < b.offset[1] + b.size && b.offset[1] < a.offset[1] + a.size
+    }
+
+    #[test]
+    fn test_atlas_allocation() {
+        let mut atlas = ShadowAtlas::new(1024);
+        let mut tiles = vec![atlas.allocate(512).unwrap()];
+        tiles.extend(atlas.allocate_many(256, 6).unwrap());
+        tiles.push(atlas.allocate(200).unwrap());
+
+        assert_eq!(tiles[7].size, 256);
+        assert!(tiles.iter().all(|x| x.offset[0] + x.size <= 1024 && x.offset[1] + x.size <= 1024));
+
+        for (i, a) in tiles.iter().enumerate() {
+            for b in &tiles[i + 1..] {
+                assert!(!overlaps(a, b), "{a:?} {b:?}");
+            }
+        }
+
+        // Свободно места на пять тайлов 256, шесть не влезут и ничего не займут
+        assert!(atlas.allocate_many(256, 6).is_none());
+        assert!(atlas.allocate(512).is_some());
+        assert!(atlas.allocate(512).is_none());
+        assert_eq!(atlas.allocate(128).unwrap().size, 128);
+
+        atlas.clear();
+        assert_eq!(atlas.allocate(1024).unwrap(), AtlasTile { offset: [0, 0], size: 1024 });
+        assert_eq!(AtlasTile { offset: [512, 0], size: 256 }.uv_rect(1024), [0.5, 0.0, 0.25, 0.25]);
+    }
+
+    #[test]
+    fn test_local_light_views() {
+        let point = Light::point([1.0, 2.0, 3.0], [1.0; 3], 1.0, 10.0);
+        let views = local_light_views(&point);
+        assert_eq!(views.len(), 6);
+
+        // Точка на оси грани попадает в её центр
+        for ((view, _), direction) in views.iter().zip(POINT_FACES) {
+            let p = [1.0 + direction[0] * 5.0, 2.0 + direction[1] * 5.0, 3.0 + direction[2] * 5.0];
+            let [x, y, z, w] = transform(view, p);
+            assert!((x / w).abs() < 1e-4 && (y / w).abs() < 1e-4 && (0.0..1.0).contains(&(z / w)));
+        }
+
+        let spot = Light::spot([0.0; 3], [0.0, -1.0, 0.0], [1.0; 3], 1.0, 10.0).with_cone(0.2, 0.4);
+        let views = local_light_views(&spot);
+        assert_eq!(views.len(), 1);
+        assert!(views[0].1 > 0.8 && views[0].1 < 0.9);
+    }
+}
@@ -0,0 +1,279 @@
+use crate::{ClusterCamera, ShadowSettings};
+
+pub(crate) type Mat4 = [[f32; 4]; 4];
+
+/// Most cascades `shadow.glsl` can hold
+pub const MAX_CASCADES: u32 = 4;
+
+/// Light view and bounds of one cascade, all in world units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowCascade {
+    pub view_projection: Mat4,
+    /// Camera depth where the cascade ends
+    pub split: f32,
+    /// World size of one shadow map texel
+    pub texel_size: f32,
+    /// Distance between the near and far planes of the projection
+    pub depth_range: f32
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+pub(crate) fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let length = dot(a, a).sqrt().max(1e-12);
+    [a[0] / length, a[1] / length, a[2] / length]
+}
+
+/// Column-major, `a * b`
+pub(crate) fn mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut out = [[0.0; 4]; 4];
+
+    for (column, out) in out.iter_mut().enumerate() {
+        for (row, out) in out.iter_mut().enumerate() {
+            *out = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+
+    out
+}
+
+pub(crate) fn transform(m: &Mat4, p: [f32; 3]) -> [f32; 4] {
+    let mut out = m[3];
+
+    for (column, x) in p.iter().enumerate() {
+        for (row, out) in out.iter_mut().enumerate() {
+            *out += m[column][row] * x;
+        }
+    }
+
+    out
+}
+
+/// Looks down -Z, like the camera
+pub(crate) fn look_at(eye: [f32; 3], target: [f32; 3], up: [f32; 3]) -> Mat4 {
+    let f = normalize(sub(target, eye));
+    let s = normalize(cross(f, up));
+    let u = cross(s, f);
+
+    [
+        [s[0], u[0], -f[0], 0.0],
+        [s[1], u[1], -f[1], 0.0],
+        [s[2], u[2], -f[2], 0.0],
+        [-dot(s, eye), -dot(u, eye), dot(f, eye), 1.0],
+    ]
+}
+
+/// Any up vector that is not parallel to `direction`
+pub(crate) fn up_for(direction: [f32; 3]) -> [f32; 3] {
+    if normalize(direction)[1].abs() > 0.99 { [1.0, 0.0, 0.0] } else { [0.0, 1.0, 0.0] }
+}
+
+/// Depth 0..1 between `near` and `far` in front of the view
+pub(crate) fn orthographic(bounds: [f32; 4], near: f32, far: f32) -> Mat4 {
+    let [left, right, bottom, top] = bounds;
+
+    [
+        [2.0 / (right - left), 0.0, 0.0, 0.0],
+        [0.0, 2.0 / (top - bottom), 0.0, 0.0],
+        [0.0, 0.0, -1.0 / (far - near), 0.0],
+        [-(right + left) / (right - left), -(top + bottom) / (top - bottom), -near / (far - near), 1.0],
+    ]
+}
+
+/// Depth 0..1 between `near` and `far`, `fov_y` in radians
+pub(crate) fn perspective(fov_y: f32, near: f32, far: f32) -> Mat4 {
+    let f = 1.0 / (fov_y * 0.5).tan();
+
+    [
+        [f, 0.0, 0.0, 0.0],
+        [0.0, f, 0.0, 0.0],
+        [0.0, 0.0, far / (near - far), -1.0],
+        [0.0, 0.0, near * far / (near - far), 0.0],
+    ]
+}
+
+/// Inverse of a view matrix without scale
+pub(crate) fn rigid_inverse(m: &Mat4) -> Mat4 {
+    let t = [m[3][0], m[3][1], m[3][2]];
+    let column = |i: usize| [m[i][0], m[i][1], m[i][2]];
+
+    // Столбцы транспонированного поворота - строки исходного
+    [
+        [m[0][0], m[1][0], m[2][0], 0.0],
+        [m[0][1], m[1][1], m[2][1], 0.0],
+        [m[0][2], m[1][2], m[2][2], 0.0],
+        [-dot(column(0), t), -dot(column(1), t), -dot(column(2), t), 1.0],
+    ]
+}
+
+///
+/// Camera depths where cascades end, blending logarithmic and uniform splits.
+/// `lambda` 1 is fully logarithmic
+///
+pub fn cascade_splits(near: f32, far: f32, count: u32, lambda: f32) -> Vec<f32> {
+    (1..=count).map(|i| {
+        let t = i as f32 / count as f32;
+        let log = near * (far / near).powf(t);
+        let uniform = near + (far - near) * t;
+        lambda * log + (1.0 - lambda) * uniform
+    }).collect()
+}
+
+/// World-space corners of the camera frustum between two view depths
+fn frustum_corners(camera: &ClusterCamera, slice: [f32; 2]) -> [[f32; 3]; 8] {
+    let camera_to_world = rigid_inverse(&camera.view);
+    let mut corners = [[0.0; 3]; 8];
+
+    for (i, corner) in corners.iter_mut().enumerate() {
+        let x = if i & 1 == 0 { -1.0 } else { 1.0 };
+        let y = if i & 2 == 0 { -1.0 } else { 1.0 };
+        let depth = slice[i / 4];
+
+        // Луч через угол экрана с z = -1, не зависит от раскладки глубины проекции
+        let [px, py, pz, pw] = transform(&camera.inverse_projection, [x, y, 0.5]);
+        let (rx, ry) = (px / pw / (-pz / pw), py / pw / (-pz / pw));
+        let [wx, wy, wz, _] = transform(&camera_to_world, [rx * depth, ry * depth, -depth]);
+
+        *corner = [wx, wy, wz];
+    }
+
+    corners
+}
+
+///
+/// Fits a cascade around the bounding sphere of a frustum slice. The sphere does not change
+/// as the camera turns, its centre is snapped to shadow map texels so edges do not shimmer
+///
+pub fn fit_cascade(camera: &ClusterCamera, direction: [f32; 3], slice: [f32; 2], settings: &ShadowSettings) -> ShadowCascade {
+
+    let corners = frustum_corners(camera, slice);
+    let center = corners.iter().fold([0.0; 3], |a, c| [a[0] + c[0] / 8.0, a[1] + c[1] / 8.0, a[2] + c[2] / 8.0]);
+
+    let radius = corners.iter().map(|c| dot(sub(*c, center), sub(*c, center)).sqrt()).fold(0.0, f32::max);
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let resolution = settings.cascade_resolution as f32;
+    let texel_size = 2.0 * radius / resolution;
+
+    // Поворот без сдвига, сетка текселей неподвижна в мире
+    let direction = normalize(direction);
+    let light_view = look_at([0.0; 3], direction, up_for(direction));
+
+    let [cx, cy, cz, _] = transform(&light_view, center);
+    let (cx, cy) = ((cx / texel_size).floor() * texel_size, (cy / texel_size).floor() * texel_size);
+
+    let near = -cz - radius - settings.caster_extension;
+    let far = -cz + radius;
+
+    let projection = orthographic([cx - radius, cx + radius, cy - radius, cy + radius], near, far);
+
+    ShadowCascade {
+        view_projection: mul(&projection, &light_view),
+        split: slice[1],
+        texel_size,
+        depth_range: far - near
+    }
+}
+
+/// Cascades covering the camera up to [`ShadowSettings::max_distance`]
+pub fn fit_cascades(camera: &ClusterCamera, direction: [f32; 3], settings: &ShadowSettings) -> Vec<ShadowCascade> {
+
+    let count = settings.cascade_count.clamp(1, MAX_CASCADES);
+    let far = camera.far.min(settings.max_distance);
+    let splits = cascade_splits(camera.near, far, count, settings.split_lambda);
+
+    let mut start = camera.near;
+    splits.into_iter().map(|end| {
+        let cascade = fit_cascade(camera, direction, [start, end], settings);
+        start = end;
+        cascade
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY: Mat4 = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+
+    fn camera(position: [f32; 3], target: [f32; 3]) -> ClusterCamera {
+        let (near, far) = (0.1, 200.0);
+        let projection = perspective(1.0, near, far);
+
+        // Обратная перспектива для квадратного экрана
+        let f = 1.0 / 0.5f32.tan();
+        let inverse_projection = [
+            [1.0 / f, 0.0, 0.0, 0.0],
+            [0.0, 1.0 / f, 0.0, 0.0],
+            [0.0, 0.0, 0.0, (near - far) / (near * far)],
+            [0.0, 0.0, -1.0, 1.0 / near],
+        ];
+
+        ClusterCamera { view: look_at(position, target, [0.0, 1.0, 0.0]), projection, inverse_projection, position, near, far }
+    }
+
+    #[test]
+    fn test_matrices() {
+        let projection = perspective(1.0, 0.5, 10.0);
+        let near = transform(&projection, [0.0, 0.0, -0.5]);
+        let far = transform(&projection, [0.0, 0.0, -10.0]);
+        assert!((near[2] / near[3]).abs() < 1e-5);
+        assert!((far[2] / far[3] - 1.0).abs() < 1e-5);
+
+        let view = look_at([1.0, 2.0, 3.0], [4.0, 0.0, -1.0], [0.0, 1.0, 0.0]);
+        let round_trip = mul(&rigid_inverse(&view), &view);
+        for (a, b) in round_trip.iter().flatten().zip(IDENTITY.iter().flatten()) {
+            assert!((a - b).abs() < 1e-5);
+        }
+
+        let camera = camera([0.0; 3], [0.0, 0.0, -1.0]);
+        let clip = transform(&mul(&camera.projection, &camera.inverse_projection), [0.3, -0.2, 0.7]);
+        assert!((clip[0] / clip[3] - 0.3).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_cascade_splits() {
+        let splits = cascade_splits(0.1, 100.0, 4, 0.75);
+        assert_eq!(splits.len(), 4);
+        assert!((splits[3] - 100.0).abs() < 1e-3);
+        assert!(splits.windows(2).all(|x| x[0] < x[1]));
+
+        let uniform = cascade_splits(4.0, 100.0, 4, 0.0);
+        for (a, b) in uniform.iter().zip([28.0, 52.0, 76.0, 100.0]) {
+            assert!((a - b).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_cascades_are_stable() {
+        let settings = ShadowSettings::default();
+        let direction = [-0.4, -1.0, -0.3];
+
+        // Поворот камеры не меняет размер каскада
+        let a = fit_cascade(&camera([0.0, 2.0, 0.0], [0.0, 2.0, -1.0]), direction, [0.1, 10.0], &settings);
+        let b = fit_cascade(&camera([0.0, 2.0, 0.0], [1.0, 1.5, 0.3]), direction, [0.1, 10.0], &settings);
+        assert_eq!(a.texel_size, b.texel_size);
+
+        // Малый сдвиг сдвигает проекцию на целое число текселей
+        let c = fit_cascade(&camera([0.013, 2.0, 0.0], [0.013, 2.0, -1.0]), direction, [0.1, 10.0], &settings);
+        let shift = (c.view_projection[3][0] - a.view_projection[3][0]) * settings.cascade_resolution as f32 * 0.5;
+        assert!((shift - shift.round()).abs() < 1e-2, "{shift}");
+
+        // Точки среза попадают в каскад
+        let camera = camera([0.0, 2.0, 0.0], [0.0, 2.0, -1.0]);
+        for corner in frustum_corners(&camera, [0.1, 10.0]) {
+            let [x, y, z, w] = transform(&a.view_projection, corner);
+            assert!(x.abs() / w <= 1.0 && y.abs() / w <= 1.0 && (0.0..=1.0).contains(&(z / w)));
+        }
+    }
+}
@@ -0,0 +1,758 @@
+pub(crate) mod cascade;
+pub(crate) mod atlas;
+pub(crate) mod settings;
+
+pub use cascade::*;
+pub use atlas::*;
+pub use settings::*;
+
+use std::mem::offset_of;
+
+use ash::vk::{self, Extent2D, PrimitiveTopology};
+
+use crate::{
+    AttachmentImage, AttachmentImageBuilder, ClusterCamera, DeferredResource, DepthBias, DescriptorPool, DescriptorPoolBuilder,
+    DescriptorSetLayout, DescriptorSetLayoutBuilder, DeviceError, DynamicRendering, GPUBuffer, GraphicsDevice, Light, LightKind, PbrMesh, PbrVertex,
+    PipelineError, RenderContext, RenderPipeline, RenderPipelineBuilder, ResourceOwner, ShaderProgramBuilder, ShaderSource,
+    VulkanError, VulkanResult
+};
+
+use cascade::rigid_inverse;
+
+const DEFAULT_FRAMES_IN_FLIGHT: u32 = 2;
+
+/// `ShadowPushConstants` of `shadow-depth.vert`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowPushConstants {
+    /// Column-major
+    pub model: [[f32; 4]; 4],
+    pub view_projection: [[f32; 4]; 4]
+}
+
+impl ShadowPushConstants {
+
+    pub fn new(model: [[f32; 4]; 4], view: &ShadowView) -> Self {
+        Self { model, view_projection: view.view_projection }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, std::mem::size_of::<Self>()) }
+    }
+}
+
+/// One cascade or atlas tile rendered by [`ShadowRenderer`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowView {
+    pub view_projection: [[f32; 4]; 4],
+    /// Cascade index, 0 for atlas tiles
+    pub layer: u32,
+    /// Texels of the shadow map drawn into
+    pub rect: vk::Rect2D,
+    /// Index into the lights given to [`ShadowRenderer::update`]
+    pub light: usize
+}
+
+/// Depth target and tiles of one rendering scope
+struct ShadowPass<'a> {
+    target: vk::ImageView,
+    area: vk::Rect2D,
+    views: &'a [ShadowView],
+    bias: DepthBias
+}
+
+/// Host-written buffers of one frame slot
+struct FrameData {
+    uniforms: GPUBuffer,
+    matrices: GPUBuffer,
+    set: vk::DescriptorSet
+}
+
+fn depth_barrier<'a>(image: vk::Image, layer_count: u32) -> vk::ImageMemoryBarrier<'a> {
+    vk::ImageMemoryBarrier::default()
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::DEPTH,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count,
+        })
+}
+
+///
+/// Shadow maps of the lights with [`Light::with_shadows`]: cascades of the first such directional light
+/// in a layered depth image, spot lights and the six faces of point lights in tiles of one atlas.
+/// Cascades are fitted to bounding spheres and snapped to texels, so they do not shimmer when the camera moves.
+///
+/// Meshes are drawn with a depth-only variant of [`RenderPipelineBuilder`] and dynamic depth bias.
+/// Shading passes read the maps through [`ShadowRenderer::descriptor_set`], `shadow.glsl` with `SHADOWS` defined:
+/// `ShadowUniforms` as UNIFORM_BUFFER (binding 0), atlas matrices as STORAGE_BUFFER (binding 1),
+/// cascades and atlas as SAMPLED_IMAGE (bindings 2-5), comparison and nearest SAMPLER (bindings 6, 7)
+///
+/// # Example
+/// ```ignore
+/// let mut shadows = ShadowRendererBuilder::new()
+///     .with_graphics_device(&ctx)
+///     .with_vertex_source(ShaderSource::file("shared/shaders/shadow-depth.vert"))
+///     .with_settings(ShadowSettings::default().with_filter(ShadowFilter::Pcf { radius: 2.0 }))
+///     .build()?;
+///
+/// let mut clustered = ClusteredRendererBuilder::new()
+///     ...
+///     .with_shadows(&shadows)
+///     .build()?;
+///
+/// // every frame, before the renderers copy the lights
+/// shadows.update(device, &camera, &mut lights);
+/// clustered.update(device, &camera, ctx.window.extent(), &lights);
+///
+/// // outside of a render pass
+/// shadows.record_cascades(&ctx.device, cbuf, |view| {
+///     shadows.draw(device, cbuf, &mesh, &ShadowPushConstants::new(model, view));
+/// });
+/// shadows.record_atlas(&ctx.device, cbuf, |view| { ... });
+///
+/// // inside
+/// clustered.bind(device, cbuf, ctx.window.extent());
+/// clustered.bind_shadows(device, cbuf, &shadows);
+/// ```
+///
+pub struct ShadowRenderer {
+    pub pipeline: RenderPipeline,
+    pub set_layout: DescriptorSetLayout,
+    /// Resolutions and the depth format are fixed at build, the rest is read every frame
+    pub settings: ShadowSettings,
+    /// LINEAR with LESS_OR_EQUAL comparison, white border
+    pub comparison_sampler: vk::Sampler,
+    /// NEAREST, for the PCSS blocker search
+    pub point_sampler: vk::Sampler,
+    atlas: ShadowAtlas,
+    dynamic_rendering: DynamicRendering,
+    cascade_image: vk::Image,
+    cascade_layers: Vec<vk::ImageView>,
+    atlas_image: vk::Image,
+    atlas_view: vk::ImageView,
+    cascade_views: Vec<ShadowView>,
+    atlas_views: Vec<ShadowView>,
+    frames: Vec<FrameData>,
+    current: usize,
+    _pool: DescriptorPool,
+    _owner: ResourceOwner
+}
+
+impl ShadowRenderer {
+
+    ///
+    /// Fits the cascades and hands out atlas tiles for the next frame, writing [`Light::shadow`]
+    /// so the lights can be passed on to the renderers. Local lights closest to the camera
+    /// get tiles first, lights that do not fit are drawn without a shadow
+    ///
+    pub fn update(&mut self, device: &ash::Device, camera: &ClusterCamera, lights: &mut [Light]) {
+
+        self.atlas.clear();
+        self.cascade_views.clear();
+        self.atlas_views.clear();
+
+        for light in lights.iter_mut() {
+            light.shadow = -1;
+        }
+
+        let settings = self.settings;
+        let resolution = settings.cascade_resolution;
+        let mut cascades = vec![];
+
+        let sun = lights.iter().position(|x| x.cast_shadows != 0 && x.kind() == LightKind::Directional);
+
+        if let Some(index) = sun {
+            lights[index].shadow = 0;
+            cascades = fit_cascades(camera, lights[index].direction, &settings);
+
+            self.cascade_views.extend(cascades.iter().enumerate().map(|(layer, cascade)| ShadowView {
+                view_projection: cascade.view_projection,
+                layer: layer as u32,
+                rect: vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: Extent2D { width: resolution, height: resolution } },
+                light: index
+            }));
+        }
+
+        let distance = |light: &Light| {
+            let d = [light.position[0] - camera.position[0], light.position[1] - camera.position[1], light.position[2] - camera.position[2]];
+            (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt() - light.range
+        };
+
+        let mut local = lights.iter()
+            .enumerate()
+            .filter(|(_, x)| x.cast_shadows != 0 && x.kind() != LightKind::Directional)
+            .map(|(i, x)| (i, distance(x)))
+            .collect::<Vec<_>>();
+
+        local.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let light_radius = match settings.filter {
+            ShadowFilter::Pcss { light_radius, .. } => light_radius,
+            _ => 0.0
+        };
+
+        let mut matrices = vec![];
+        let mut dropped = 0;
+
+        for (index, _) in local {
+            let light = &mut lights[index];
+
+            let (size, count) = match light.kind() {
+                LightKind::Point => (settings.point_resolution, 6),
+                _ => (settings.spot_resolution, 1)
+            };
+
+            let Some(tiles) = self.atlas.allocate_many(size, count) else {
+                dropped += 1;
+                continue;
+            };
+
+            light.shadow = matrices.len() as i32;
+            let [near, far] = local_light_planes(light);
+
+            for (tile, (view_projection, fov)) in tiles.iter().zip(local_light_views(light)) {
+                let texel_size = 2.0 * (fov * 0.5).tan() / tile.size as f32;
+
+                matrices.push(ShadowMatrix {
+                    view_projection,
+                    rect: tile.uv_rect(self.atlas.size),
+                    params: [near, far, texel_size, light_radius]
+                });
+
+                self.atlas_views.push(ShadowView {
+                    view_projection,
+                    layer: 0,
+                    rect: vk::Rect2D {
+                        offset: vk::Offset2D { x: tile.offset[0] as i32, y: tile.offset[1] as i32 },
+                        extent: Extent2D { width: tile.size, height: tile.size }
+                    },
+                    light: index
+                });
+            }
+        }
+
+        if dropped > 0 {
+            log::warn!("{} shadowed lights do not fit into the {}px shadow atlas and are drawn without shadows", dropped, self.atlas.size);
+        }
+
+        // Пустой буфер не загрузить, шейдеры читают только по light.shadow
+        if matrices.is_empty() {
+            matrices.push(ShadowMatrix { view_projection: [[0.0; 4]; 4], rect: [0.0; 4], params: [0.0; 4] });
+        }
+
+        let uniforms = ShadowUniforms::new(&settings, rigid_inverse(&camera.view)).with_cascades(&cascades);
+
+        self.current = (self.current + 1) % self.frames.len();
+        let frame = &self.frames[self.current];
+
+        frame.uniforms.upload_data(device, &[uniforms]);
+        frame.matrices.upload_data(device, &matrices);
+    }
+
+    /// Cascades of the last [`ShadowRenderer::update`]
+    pub fn cascade_views(&self) -> &[ShadowView] {
+        &self.cascade_views
+    }
+
+    /// Atlas tiles of the last [`ShadowRenderer::update`], six in a row for point lights
+    pub fn atlas_views(&self) -> &[ShadowView] {
+        &self.atlas_views
+    }
+
+    /// Set of the current frame for the shading passes
+    pub fn descriptor_set(&self) -> vk::DescriptorSet {
+        self.frames[self.current].set
+    }
+
+    ///
+    /// Renders every cascade, calling `draw` inside each, and makes them readable by shaders.
+    /// Outside of a render pass
+    ///
+    pub fn record_cascades(&self, dev: &GraphicsDevice, command_buffer: vk::CommandBuffer, mut draw: impl FnMut(&ShadowView)) {
+
+        let layers = self.cascade_layers.len() as u32;
+        self.begin(dev.raw_device(), command_buffer, self.cascade_image, layers);
+
+        for view in &self.cascade_views {
+            let pass = ShadowPass {
+                target: self.cascade_layers[view.layer as usize],
+                area: view.rect,
+                views: std::slice::from_ref(view),
+                bias: self.settings.bias.cascade
+            };
+
+            self.render(dev, command_buffer, &pass, &mut draw);
+        }
+
+        self.end(dev.raw_device(), command_buffer, self.cascade_image, layers);
+    }
+
+    ///
+    /// Clears the atlas and renders every tile, calling `draw` inside each, then makes it readable by shaders.
+    /// Outside of a render pass
+    ///
+    pub fn record_atlas(&self, dev: &GraphicsDevice, command_buffer: vk::CommandBuffer, mut draw: impl FnMut(&ShadowView)) {
+
+        let size = self.atlas.size;
+        let area = vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent: Extent2D { width: size, height: size } };
+
+        let pass = ShadowPass { target: self.atlas_view, area, views: &self.atlas_views, bias: self.settings.bias.local };
+
+        self.begin(dev.raw_device(), command_buffer, self.atlas_image, 1);
+        self.render(dev, command_buffer, &pass, &mut draw);
+        self.end(dev.raw_device(), command_buffer, self.atlas_image, 1);
+    }
+
+    /// Draws `mesh` into the current cascade or tile, inside the `draw` callback
+    pub fn draw(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, mesh: &PbrMesh, constants: &ShadowPushConstants) {
+        unsafe {
+            device.cmd_push_constants(command_buffer, self.pipeline.raw_layout, vk::ShaderStageFlags::VERTEX, 0, constants.as_bytes());
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[mesh.vertex_buffer.raw], &[0]);
+            device.cmd_bind_index_buffer(command_buffer, mesh.index_buffer.raw, 0, vk::IndexType::UINT32);
+            device.cmd_draw_indexed(command_buffer, mesh.index_count, 1, 0, 0, 0);
+        }
+    }
+
+    fn begin(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, image: vk::Image, layers: u32) {
+
+        // Прошлый кадр ещё может читать карту при освещении
+        let barrier = depth_barrier(image, layers)
+            .dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .old_layout(vk::ImageLayout::UNDEFINED)
+            .new_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier]
+            );
+        }
+    }
+
+    fn render(&self, dev: &GraphicsDevice, command_buffer: vk::CommandBuffer, pass: &ShadowPass, draw: &mut impl FnMut(&ShadowView)) {
+
+        let dynamic_rendering = &self.dynamic_rendering;
+        let device = dev.raw_device();
+        let bias = pass.bias;
+
+        let depth_attachment = vk::RenderingAttachmentInfo::default()
+            .image_view(pass.target)
+            .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } });
+
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(pass.area)
+            .layer_count(1)
+            .depth_attachment(&depth_attachment);
+
+        dynamic_rendering.begin_rendering(device, command_buffer, &rendering_info);
+
+        unsafe {
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline.raw);
+            device.cmd_set_depth_bias(command_buffer, bias.constant_factor, bias.clamp, bias.slope_factor);
+        }
+
+        for view in pass.views {
+            let viewport = vk::Viewport {
+                x: view.rect.offset.x as f32,
+                y: view.rect.offset.y as f32,
+                width: view.rect.extent.width as f32,
+                height: view.rect.extent.height as f32,
+                min_depth: 0.0,
+                max_depth: 1.0
+            };
+
+            unsafe {
+                device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+                device.cmd_set_scissor(command_buffer, 0, &[view.rect]);
+            }
+
+            draw(view);
+        }
+
+        dynamic_rendering.end_rendering(device, command_buffer);
+    }
+
+    fn end(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, image: vk::Image, layers: u32) {
+
+        let barrier = depth_barrier(image, layers)
+            .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ)
+            .old_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .new_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL);
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::PipelineStageFlags::FRAGMENT_SHADER | vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[barrier]
+            );
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ShadowRendererBuilder<'n> {
+    ctx: Option<&'n RenderContext>,
+    vertex_shader: Option<ShaderSource>,
+    settings: Option<ShadowSettings>,
+    frames_in_flight: Option<u32>
+}
+
+impl<'n> ShadowRendererBuilder<'n> {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with_graphics_device(mut self, ctx: &'n RenderContext) -> Self {
+        self.ctx = Some(ctx);
+        self
+    }
+
+    /// `shadow-depth.vert`
+    pub fn with_vertex_source(mut self, source: ShaderSource) -> Self {
+        self.vertex_shader = Some(source);
+        self
+    }
+
+    /// Default: [`ShadowSettings::default`]
+    pub fn with_settings(mut self, settings: ShadowSettings) -> Self {
+        self.settings = Some(settings);
+        self
+    }
+
+    /// Must match the render graph, default: 2
+    pub fn with_frames_in_flight(mut self, count: u32) -> Self {
+        self.frames_in_flight = Some(count);
+        self
+    }
+
+    /// Fails with [`DeviceError::MissingFeatures`] if dynamic rendering is not enabled on the device
+    pub fn build(self) -> VulkanResult<ShadowRenderer> {
+
+        let missing = |field| VulkanError::missing("ShadowRendererBuilder", field);
+
+        let ctx = self.ctx.ok_or_else(|| missing("render context"))?;
+        let vertex_shader = self.vertex_shader.ok_or_else(|| missing("vertex shader"))?;
+
+        // Карты рисуются без render pass
+        let dynamic_rendering = ctx.device.dynamic_rendering.clone()
+            .ok_or_else(|| VulkanError::Device(DeviceError::MissingFeatures(vec!["dynamicRendering".to_string()])))?;
+
+        let device = ctx.device.raw_device();
+        let memory_prop = &ctx.device.phys_dev.phys_info.memory_prop;
+        let mut settings = self.settings.unwrap_or_default();
+        settings.cascade_count = settings.cascade_count.clamp(1, MAX_CASCADES);
+        let atlas = ShadowAtlas::new(settings.atlas_size);
+
+        // Приложение пишет кадр, пока GPU ещё читает frames_in_flight предыдущих
+        let frame_count = self.frames_in_flight.unwrap_or(DEFAULT_FRAMES_IN_FLIGHT).max(1) + 1;
+
+        let stages = vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT | vk::ShaderStageFlags::COMPUTE;
+        let binding = |binding: u32, ty: vk::DescriptorType| vk::DescriptorSetLayoutBinding::default()
+            .binding(binding)
+            .descriptor_type(ty)
+            .descriptor_count(1)
+            .stage_flags(stages);
+
+        let bindings = [
+            binding(0, vk::DescriptorType::UNIFORM_BUFFER),
+            binding(1, vk::DescriptorType::STORAGE_BUFFER),
+            binding(2, vk::DescriptorType::SAMPLED_IMAGE),
+            binding(3, vk::DescriptorType::SAMPLED_IMAGE),
+            binding(4, vk::DescriptorType::SAMPLED_IMAGE),
+            binding(5, vk::DescriptorType::SAMPLED_IMAGE),
+            binding(6, vk::DescriptorType::SAMPLER),
+            binding(7, vk::DescriptorType::SAMPLER),
+        ];
+
+        let set_layout = DescriptorSetLayoutBuilder::new()
+            .with_device(device)
+            .with_bindings(&bindings)
+            .build()?;
+
+        let shader = ShaderProgramBuilder::new()
+            .with_device(device)
+            .with_vertex_source(vertex_shader)
+            .build()?;
+
+        // Только позиция, нормаль тени не нужна
+        let binding_description = PbrVertex::binding_descriptions();
+        let attribute_description = [vk::VertexInputAttributeDescription {
+            location: 0,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: offset_of!(PbrVertex, position) as u32,
+        }];
+
+        let vertex_input_state_info = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_attribute_descriptions(&attribute_description)
+            .vertex_binding_descriptions(&binding_description);
+
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: std::mem::size_of::<ShadowPushConstants>() as u32
+        }];
+
+        // Обе стороны: тонкие и открытые меши тоже отбрасывают тень
+        let pipeline = RenderPipelineBuilder::new()
+            .with_device(device)
+            .with_depth_only()
+            .with_vertex_shader(shader.vertex_shader)
+            .with_vertex_entry_point(&shader.vertex_entry_point)
+            .with_vertex_input_info(vertex_input_state_info)
+            .with_input_assembly_info(
+                vk::PipelineInputAssemblyStateCreateInfo::default()
+                    .topology(PrimitiveTopology::TRIANGLE_LIST)
+                    .primitive_restart_enable(false)
+            )
+            .with_resolution(Extent2D { width: settings.cascade_resolution, height: settings.cascade_resolution })
+            .with_depth_format(settings.depth_format)
+            .with_depth_test(true)
+            .with_cull_mode(vk::CullModeFlags::NONE)
+            .with_depth_bias(settings.bias.cascade)
+            .add_dynamic_state(vk::DynamicState::DEPTH_BIAS)
+            .with_push_constant_ranges(&push_constant_ranges)
+            .with_pipeline_cache(ctx.pipeline_cache.raw)
+            .build()?;
+
+        let usage = vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED;
+        let image = |extent: Extent2D, layers: Option<u32>| -> VulkanResult<AttachmentImage> {
+            let builder = AttachmentImageBuilder::new()
+                .with_device(device)
+                .with_memory_properties(memory_prop)
+                .with_extent(extent)
+                .with_format(settings.depth_format)
+                .with_usage(usage);
+
+            match layers {
+                Some(layers) => builder.with_layers(layers),
+                None => builder
+            }.build().map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateImageFailed(e))))
+        };
+
+        let cascade_extent = Extent2D { width: settings.cascade_resolution, height: settings.cascade_resolution };
+        let cascade_map = image(cascade_extent, Some(settings.cascade_count))?;
+
+        let atlas_map = match image(Extent2D { width: atlas.size, height: atlas.size }, None) {
+            Ok(image) => image,
+            Err(e) => {
+                cascade_map.destroy(device);
+                return Err(e);
+            }
+        };
+
+        let mut resources = [&cascade_map, &atlas_map].iter()
+            .flat_map(|x| [DeferredResource::ImageView(x.view), DeferredResource::Image(x.raw, x.memory)])
+            .collect::<Vec<_>>();
+
+        let mut cascade_layers = Vec::with_capacity(settings.cascade_count as usize);
+
+        for layer in 0..settings.cascade_count {
+            let view_info = vk::ImageViewCreateInfo::default()
+                .image(cascade_map.raw)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(settings.depth_format)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::DEPTH,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: layer,
+                    layer_count: 1,
+                });
+
+            match unsafe { device.create_image_view(&view_info, None) } {
+                Ok(view) => {
+                    resources.push(DeferredResource::ImageView(view));
+                    cascade_layers.push(view);
+                }
+                Err(e) => {
+                    drop(ResourceOwner::new(device, resources));
+                    return Err(VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateImageFailed(e))));
+                }
+            }
+        }
+
+        let comparison_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+            .compare_enable(true)
+            .compare_op(vk::CompareOp::LESS_OR_EQUAL);
+
+        let point_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+
+        let mut samplers = [vk::Sampler::null(); 2];
+
+        for (sampler, info) in samplers.iter_mut().zip([comparison_info, point_info]) {
+            match unsafe { device.create_sampler(&info, None) } {
+                Ok(created) => {
+                    resources.push(DeferredResource::Sampler(created));
+                    *sampler = created;
+                }
+                Err(e) => {
+                    drop(ResourceOwner::new(device, resources));
+                    return Err(VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateSamplerFailed(e))));
+                }
+            }
+        }
+
+        let [comparison_sampler, point_sampler] = samplers;
+        let owner = ResourceOwner::new(device, resources);
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(frame_count),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(frame_count),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(frame_count * 4),
+            vk::DescriptorPoolSize::default()
+                .ty(vk::DescriptorType::SAMPLER)
+                .descriptor_count(frame_count * 2),
+        ];
+
+        let pool = DescriptorPoolBuilder::new()
+            .with_device(device)
+            .with_pool_sizes(&pool_sizes)
+            .with_max_sets(frame_count)
+            .build()?;
+
+        let layouts = vec![set_layout.raw; frame_count as usize];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(pool.raw)
+            .set_layouts(&layouts);
+
+        let sets = unsafe { device.allocate_descriptor_sets(&allocate_info) }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::AllocateDescriptorSetFailed(e))))?;
+
+        let create_buffer = |size: u64, usage: vk::BufferUsageFlags| {
+            GPUBuffer::new(device, memory_prop, size, usage, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT)
+                .map_err(|e| VulkanError::vk(e, |e| VulkanError::Pipeline(PipelineError::CreateBufferFailed(e))))
+        };
+
+        let read = |view: vk::ImageView| [vk::DescriptorImageInfo::default().image_view(view).image_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)];
+        let maps = [read(cascade_map.view), read(cascade_map.view), read(atlas_map.view), read(atlas_map.view)];
+        let sampler_infos = [
+            [vk::DescriptorImageInfo::default().sampler(comparison_sampler)],
+            [vk::DescriptorImageInfo::default().sampler(point_sampler)],
+        ];
+
+        let mut frames = Vec::with_capacity(sets.len());
+
+        for set in sets {
+            let uniforms = create_buffer(std::mem::size_of::<ShadowUniforms>() as u64, vk::BufferUsageFlags::UNIFORM_BUFFER)?;
+            let matrices = create_buffer(
+                settings.max_atlas_tiles() as u64 * std::mem::size_of::<ShadowMatrix>() as u64,
+                vk::BufferUsageFlags::STORAGE_BUFFER
+            )?;
+
+            let uniforms_info = [vk::DescriptorBufferInfo::default().buffer(uniforms.raw).range(vk::WHOLE_SIZE)];
+            let matrices_info = [vk::DescriptorBufferInfo::default().buffer(matrices.raw).range(vk::WHOLE_SIZE)];
+
+            let mut descriptor_writes = vec![
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&uniforms_info),
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(1)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(&matrices_info),
+            ];
+
+            descriptor_writes.extend(maps.iter().enumerate().map(|(i, info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(2 + i as u32)
+                    .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                    .image_info(info)
+            }));
+
+            descriptor_writes.extend(sampler_infos.iter().enumerate().map(|(i, info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(6 + i as u32)
+                    .descriptor_type(vk::DescriptorType::SAMPLER)
+                    .image_info(info)
+            }));
+
+            unsafe { device.update_descriptor_sets(&descriptor_writes, &[]) };
+
+            frames.push(FrameData { uniforms, matrices, set });
+        }
+
+        Ok(ShadowRenderer {
+            pipeline,
+            set_layout,
+            settings,
+            comparison_sampler,
+            point_sampler,
+            atlas,
+            dynamic_rendering,
+            cascade_image: cascade_map.raw,
+            cascade_layers,
+            atlas_image: atlas_map.raw,
+            atlas_view: atlas_map.view,
+            cascade_views: vec![],
+            atlas_views: vec![],
+            frames,
+            current: 0,
+            _pool: pool,
+            _owner: owner
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{PipelineReflection, ShaderReflection};
+
+    fn load(bytes: &[u8]) -> Vec<u32> {
+        bytes.chunks_exact(4).map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]])).collect()
+    }
+
+    #[test]
+    fn test_depth_shader_matches_layout() {
+        let vs = ShaderReflection::from_spirv(&load(include_bytes!("../../../../../shared/shaders/spv/shadow-depth-vert.spv"))).unwrap();
+        assert_eq!(vs.inputs.len(), 1);
+
+        let reflection = PipelineReflection::merge(&[vs]).unwrap();
+        assert_eq!(reflection.push_constant_ranges[0].size as usize, std::mem::size_of::<ShadowPushConstants>());
+        assert!(reflection.sets.is_empty());
+    }
+}
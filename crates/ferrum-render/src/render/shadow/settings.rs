@@ -0,0 +1,246 @@
+use ash::vk::Format;
+
+use crate::{DepthBias, ShadowCascade, MAX_CASCADES};
+
+/// Filtering of shadow edges, `SHADOW_FILTER_*` in `shadow.glsl`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShadowFilter {
+    /// One bilinear comparison
+    Hard,
+    /// Fixed kernel, `radius` in texels
+    Pcf { radius: f32 },
+    ///
+    /// Percentage-closer soft shadows: the penumbra grows with the distance to the blocker.
+    /// `sun_size` is the tangent of the sun's angular radius, `light_radius` the world
+    /// radius of spot and point lights, `max_radius` caps the kernel in texels
+    ///
+    Pcss { sun_size: f32, light_radius: f32, max_radius: f32 }
+}
+
+impl ShadowFilter {
+
+    fn mode(&self) -> u32 {
+        match self {
+            ShadowFilter::Hard => 0,
+            ShadowFilter::Pcf { .. } => 1,
+            ShadowFilter::Pcss { .. } => 2
+        }
+    }
+}
+
+/// Biases against shadow acne, hardware depth bias is applied while rendering the maps
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowBias {
+    pub cascade: DepthBias,
+    pub local: DepthBias,
+    /// Receivers are moved along the normal by this many texels before the lookup
+    pub normal_offset: f32
+}
+
+impl Default for ShadowBias {
+    fn default() -> Self {
+        Self {
+            cascade: DepthBias { constant_factor: 1.25, slope_factor: 1.75, clamp: 0.0 },
+            local: DepthBias { constant_factor: 1.0, slope_factor: 1.5, clamp: 0.0 },
+            normal_offset: 1.0
+        }
+    }
+}
+
+///
+/// Resolution and filtering of [`crate::ShadowRenderer`]
+///
+/// # Example
+/// ```
+/// use ferrum_render::{ShadowFilter, ShadowSettings};
+///
+/// let settings = ShadowSettings::default()
+///     .with_cascades(3, 2048)
+///     .with_max_distance(80.0)
+///     .with_filter(ShadowFilter::Pcss { sun_size: 0.02, light_radius: 0.1, max_radius: 12.0 });
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+    /// 1 to 4
+    pub cascade_count: u32,
+    pub cascade_resolution: u32,
+    /// Blend of logarithmic (1) and uniform (0) cascade splits
+    pub split_lambda: f32,
+    /// Cascades end here or at the camera far plane
+    pub max_distance: f32,
+    /// Casters this far behind a cascade towards the light still cast into it
+    pub caster_extension: f32,
+    /// Side of the spot and point light atlas, a power of two
+    pub atlas_size: u32,
+    pub spot_resolution: u32,
+    /// Per cube face
+    pub point_resolution: u32,
+    /// Must have no stencil aspect
+    pub depth_format: Format,
+    pub filter: ShadowFilter,
+    /// Taps of PCF and of the PCSS blocker search, at most 16
+    pub samples: u32,
+    pub bias: ShadowBias
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            cascade_count: 4,
+            cascade_resolution: 2048,
+            split_lambda: 0.75,
+            max_distance: 150.0,
+            caster_extension: 50.0,
+            atlas_size: 4096,
+            spot_resolution: 512,
+            point_resolution: 256,
+            depth_format: Format::D32_SFLOAT,
+            filter: ShadowFilter::Pcf { radius: 1.5 },
+            samples: 16,
+            bias: ShadowBias::default()
+        }
+    }
+}
+
+impl ShadowSettings {
+
+    pub fn with_cascades(mut self, count: u32, resolution: u32) -> Self {
+        self.cascade_count = count.clamp(1, MAX_CASCADES);
+        self.cascade_resolution = resolution;
+        self
+    }
+
+    pub fn with_split_lambda(mut self, lambda: f32) -> Self {
+        self.split_lambda = lambda;
+        self
+    }
+
+    pub fn with_max_distance(mut self, distance: f32) -> Self {
+        self.max_distance = distance;
+        self
+    }
+
+    pub fn with_caster_extension(mut self, distance: f32) -> Self {
+        self.caster_extension = distance;
+        self
+    }
+
+    pub fn with_atlas(mut self, size: u32, spot_resolution: u32, point_resolution: u32) -> Self {
+        self.atlas_size = size;
+        self.spot_resolution = spot_resolution;
+        self.point_resolution = point_resolution;
+        self
+    }
+
+    pub fn with_depth_format(mut self, format: Format) -> Self {
+        self.depth_format = format;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: ShadowFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_samples(mut self, samples: u32) -> Self {
+        self.samples = samples.clamp(1, 16);
+        self
+    }
+
+    pub fn with_bias(mut self, bias: ShadowBias) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    /// Most atlas tiles, and so entries of `ShadowMatrices`, in one frame
+    pub fn max_atlas_tiles(&self) -> u32 {
+        let smallest = self.spot_resolution.min(self.point_resolution).max(1).next_power_of_two();
+        (self.atlas_size.next_power_of_two() / smallest).max(1).pow(2)
+    }
+}
+
+/// std140 `ShadowUniforms` of `shadow.glsl`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowUniforms {
+    pub cascades: [[[f32; 4]; 4]; 4],
+    pub inverse_view: [[f32; 4]; 4],
+    pub splits: [f32; 4],
+    pub texel_sizes: [f32; 4],
+    pub depth_ranges: [f32; 4],
+    /// x - cascades, y - filter, z - samples
+    pub params: [u32; 4],
+    /// x - PCF radius, y - sun size, z - max penumbra, w - normal offset
+    pub filter: [f32; 4]
+}
+
+impl ShadowUniforms {
+
+    pub fn new(settings: &ShadowSettings, inverse_view: [[f32; 4]; 4]) -> Self {
+
+        let filter = match settings.filter {
+            ShadowFilter::Hard => [0.0; 3],
+            ShadowFilter::Pcf { radius } => [radius, 0.0, radius],
+            ShadowFilter::Pcss { sun_size, max_radius, .. } => [1.0, sun_size, max_radius]
+        };
+
+        Self {
+            cascades: [[[0.0; 4]; 4]; 4],
+            inverse_view,
+            splits: [0.0; 4],
+            texel_sizes: [0.0; 4],
+            depth_ranges: [0.0; 4],
+            params: [0, settings.filter.mode(), settings.samples.clamp(1, 16), 0],
+            filter: [filter[0], filter[1], filter[2], settings.bias.normal_offset]
+        }
+    }
+
+    pub fn with_cascades(mut self, cascades: &[ShadowCascade]) -> Self {
+        for (i, cascade) in cascades.iter().take(MAX_CASCADES as usize).enumerate() {
+            self.cascades[i] = cascade.view_projection;
+            self.splits[i] = cascade.split;
+            self.texel_sizes[i] = cascade.texel_size;
+            self.depth_ranges[i] = cascade.depth_range;
+        }
+
+        self.params[0] = cascades.len().min(MAX_CASCADES as usize) as u32;
+        self
+    }
+}
+
+/// std430 `ShadowMatrix` of `shadow.glsl`, one per atlas tile
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowMatrix {
+    pub view_projection: [[f32; 4]; 4],
+    /// UV offset and size of the tile
+    pub rect: [f32; 4],
+    /// x - near, y - far, z - texel size at distance 1, w - light radius for PCSS
+    pub params: [f32; 4]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::{offset_of, size_of};
+
+    #[test]
+    fn test_gpu_layouts() {
+        assert_eq!(offset_of!(ShadowUniforms, inverse_view), 256);
+        assert_eq!(offset_of!(ShadowUniforms, params), 368);
+        assert_eq!(size_of::<ShadowUniforms>(), 400);
+        assert_eq!(size_of::<ShadowMatrix>(), 96);
+    }
+
+    #[test]
+    fn test_settings() {
+        let settings = ShadowSettings::default().with_cascades(9, 1024).with_samples(64);
+        assert_eq!((settings.cascade_count, settings.samples), (4, 16));
+        assert_eq!(settings.max_atlas_tiles(), 256);
+
+        let uniforms = ShadowUniforms::new(&settings.with_filter(ShadowFilter::Hard), [[0.0; 4]; 4]);
+        assert_eq!(uniforms.params, [0, 0, 16, 0]);
+        assert_eq!(uniforms.filter[3], settings.bias.normal_offset);
+    }
+}
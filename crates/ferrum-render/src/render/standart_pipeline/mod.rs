@@ -1,25 +1,25 @@
 use std::mem::offset_of;
 
 use ash::vk::{self,
-    AttachmentReference,
     DescriptorSetLayout,
     PrimitiveTopology
 };
 
 use crate::{
     RenderContext,
-    RenderPassBuilder,
     RenderPipeline,
     RenderPipelineBuilder,
     ShaderProgramBuilder,
-    SubpassBuilder
+    ShaderSource,
+    VulkanError,
+    VulkanResult
 };
 
 #[derive(Default)]
 pub struct StandartPipelineBuilder<'n> {
     pub ctx: Option<&'n RenderContext>,
-    pub vertex_shader: Option<Vec<u32>>,
-    pub fragment_shader: Option<Vec<u32>>
+    pub vertex_shader: Option<ShaderSource>,
+    pub fragment_shader: Option<ShaderSource>
 }
 
 #[repr(C)]
@@ -70,57 +70,40 @@ impl<'n> StandartPipelineBuilder<'n> {
         self
     }
 
-    pub fn with_vertex_shader(mut self, bytes: Vec<u32>) -> Self {
-        self.vertex_shader = Some(bytes);
+    /// SPIR-V
+    pub fn with_vertex_shader(self, bytes: Vec<u32>) -> Self {
+        self.with_vertex_source(ShaderSource::spirv(bytes))
+    }
+
+    /// SPIR-V
+    pub fn with_fragment_shader(self, bytes: Vec<u32>) -> Self {
+        self.with_fragment_source(ShaderSource::spirv(bytes))
+    }
+
+    pub fn with_vertex_source(mut self, source: ShaderSource) -> Self {
+        self.vertex_shader = Some(source);
         self
     }
 
-    pub fn with_fragment_shader(mut self, bytes: Vec<u32>) -> Self {
-        self.fragment_shader = Some(bytes);
+    pub fn with_fragment_source(mut self, source: ShaderSource) -> Self {
+        self.fragment_shader = Some(source);
         self
     }
 
-    pub fn build(self, desc: DescriptorSetLayout) -> RenderPipeline {
+    pub fn build(self, desc: DescriptorSetLayout) -> VulkanResult<RenderPipeline> {
 
-        let ctx = self.ctx.unwrap();
+        let ctx = self.ctx.ok_or(VulkanError::missing("StandartPipelineBuilder", "render context"))?;
+        let vertex_shader = self.vertex_shader.ok_or(VulkanError::missing("StandartPipelineBuilder", "vertex shader"))?;
+        let fragment_shader = self.fragment_shader.ok_or(VulkanError::missing("StandartPipelineBuilder", "fragment shader"))?;
 
         let shader = ShaderProgramBuilder::new()
             .with_device(&ctx.device.logical_device.raw)
-            .with_fragment_shader(self.fragment_shader.unwrap())
-            .with_vertex_shader(self.vertex_shader.unwrap())
-            .build();
-
-        let subpass = SubpassBuilder::new()
-            .add_color_attachment_ref(
-                AttachmentReference::default()
-                    .attachment(0)
-                    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
-            )
-            .with_bind_point(vk::PipelineBindPoint::GRAPHICS)
-            .build();
-
-        let render_pass = RenderPassBuilder::new()
-            .with_device(&ctx.device.logical_device.raw)
-            .add_subpass(subpass.raw)
-            .add_subpass_dependency(
-                vk::SubpassDependency {
-                    src_subpass: vk::SUBPASS_EXTERNAL,
-                    src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                    dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-                    dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                    ..Default::default()
-                })
-            .add_attachments_desc(vk::AttachmentDescription {
-                    format: ctx.window.surface_format_khr.format,
-                    samples: vk::SampleCountFlags::TYPE_1,
-                    load_op: vk::AttachmentLoadOp::CLEAR,
-                    store_op: vk::AttachmentStoreOp::STORE,
-                    final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
-                    ..Default::default()
-                })
-            .build();
-
+            .with_fragment_source(fragment_shader)
+            .with_vertex_source(vertex_shader)
+            .build()?;
 
+        // Dynamic rendering: window has no render pass, pipeline uses the surface format
+        let render_pass = ctx.window.render_pass.as_ref();
 
         let binding_description = Vertex::get_binding_descriptions();
         let attribute_description = Vertex::get_attribute_descriptions();
@@ -129,9 +112,18 @@ impl<'n> StandartPipelineBuilder<'n> {
             .vertex_attribute_descriptions(&attribute_description)
             .vertex_binding_descriptions(&binding_description);
 
-        let pipeline = RenderPipelineBuilder::new()
+        let set_layouts = [desc];
+        let mut builder = RenderPipelineBuilder::new();
+
+        if let Some(render_pass) = render_pass {
+            builder = builder.with_render_pass(render_pass);
+        }
+
+        let pipeline = builder
             .with_vertex_shader(shader.vertex_shader)
+            .with_vertex_entry_point(&shader.vertex_entry_point)
             .with_fragment_shader(shader.fragment_shader)
+            .with_fragment_entry_point(&shader.fragment_entry_point)
             .with_resolution(ctx.window.caps.current_extent)
             .with_format(ctx.window.surface_format_khr.format)
             .with_vertex_input_info(vertex_input_state_info)
@@ -140,12 +132,16 @@ impl<'n> StandartPipelineBuilder<'n> {
                             .topology(PrimitiveTopology::TRIANGLE_LIST)
                             .primitive_restart_enable(false)
             )
-            .with_render_pass(&render_pass.raw)
             .with_device(&ctx.device.logical_device.raw)
-            .with_descriptor_set_layouts(&[desc])
-            .build();
-
-        pipeline
+            .with_descriptor_set_layouts(&set_layouts)
+            .with_pipeline_cache(ctx.pipeline_cache.raw)
+            .with_samples(ctx.window.samples())
+            .with_depth_test(ctx.window.depth_format().is_some());
+
+        match ctx.window.depth_format() {
+            Some(format) if render_pass.is_none() => pipeline.with_depth_stencil_format(format),
+            _ => pipeline
+        }.build()
     }
 }
 
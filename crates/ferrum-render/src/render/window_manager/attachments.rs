@@ -0,0 +1,284 @@
+use ash::vk::{self, Extent2D, Format, ImageView, PhysicalDeviceMemoryProperties, SampleCountFlags};
+
+use crate::{
+    clamp_sample_count, find_depth_format, has_stencil, AttachmentImage, AttachmentImageBuilder, GraphicsDevice, SwapchainError, VulkanError, VulkanResult, WindowManager, WindowManagerBuilder, WithSwapchain
+};
+
+/// Depth and MSAA setup of the window render targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentConfig {
+    pub depth_format: Option<Format>,
+    pub samples: SampleCountFlags
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        Self { depth_format: None, samples: SampleCountFlags::TYPE_1 }
+    }
+}
+
+impl AttachmentConfig {
+
+    pub fn is_msaa(&self) -> bool {
+        self.samples != SampleCountFlags::TYPE_1
+    }
+
+    /// Attachment index of the depth image in the default render pass
+    pub fn depth_index(&self) -> Option<u32> {
+        self.depth_format.map(|_| 1)
+    }
+
+    /// Attachment index the subpass renders colour to, the swapchain image (0) is the resolve target with MSAA
+    pub fn color_index(&self) -> u32 {
+        if self.is_msaa() { 1 + self.depth_format.is_some() as u32 } else { 0 }
+    }
+}
+
+///
+/// Depth and MSAA colour images owned by [`WindowManager`], recreated together with the swapchain.
+/// Frame buffer attachment order: swapchain image, depth, MSAA colour
+///
+#[derive(Default)]
+pub struct WindowAttachments {
+    pub depth: Option<AttachmentImage>,
+    pub color: Option<AttachmentImage>
+}
+
+impl WindowAttachments {
+
+    pub fn new(
+        device: &ash::Device,
+        memory_prop: &PhysicalDeviceMemoryProperties,
+        config: &AttachmentConfig,
+        color_format: Format,
+        extent: Extent2D
+    ) -> Result<Self, vk::Result> {
+
+        let depth = config.depth_format.map(|format| {
+            AttachmentImageBuilder::new()
+                .with_device(device)
+                .with_memory_properties(memory_prop)
+                .with_extent(extent)
+                .with_format(format)
+                .with_samples(config.samples)
+                .with_usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
+                .build()
+        }).transpose()?;
+
+        let color = config.is_msaa().then(|| {
+            AttachmentImageBuilder::new()
+                .with_device(device)
+                .with_memory_properties(memory_prop)
+                .with_extent(extent)
+                .with_format(color_format)
+                .with_samples(config.samples)
+                .with_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT)
+                .build()
+        }).transpose()?;
+
+        Ok(Self { depth, color })
+    }
+
+    /// Views placed after the swapchain image view in frame buffers
+    pub fn views(&self) -> Vec<ImageView> {
+        self.depth.iter().chain(self.color.iter()).map(|x| x.view).collect()
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        for image in self.depth.iter().chain(self.color.iter()) {
+            image.destroy(device);
+        }
+    }
+}
+
+impl WindowManagerBuilder<WithSwapchain> {
+
+    ///
+    /// Adds a depth buffer in the best supported format, see [`crate::DEPTH_FORMATS`].
+    /// Fails if the device supports none of the formats
+    ///
+    pub fn with_depth_buffer(self, stencil: bool) -> VulkanResult<Self> {
+        let device = &self.state.device;
+        let format = find_depth_format(&device.instance.raw, device.phys_dev.raw, stencil)
+            .ok_or(VulkanError::Swapchain(SwapchainError::NoDepthFormat))?;
+
+        Ok(self.with_depth_format(format))
+    }
+
+    pub fn with_depth_format(mut self, format: Format) -> Self {
+        self.state.attachments.depth_format = Some(format);
+        self
+    }
+
+    /// MSAA with resolve into the swapchain image, clamped to the device limits
+    pub fn with_samples(mut self, samples: SampleCountFlags) -> Self {
+        let limits = &self.state.device.phys_dev.phys_info.phys_prop.limits;
+        let clamped = clamp_sample_count(samples, limits);
+
+        if clamped != samples {
+            log::warn!("{:?} samples is not supported, using {:?}", samples, clamped);
+        }
+
+        self.state.attachments.samples = clamped;
+        self
+    }
+}
+
+impl WindowManager {
+
+    pub fn samples(&self) -> SampleCountFlags {
+        self.attachment_config.samples
+    }
+
+    pub fn depth_format(&self) -> Option<Format> {
+        self.attachment_config.depth_format
+    }
+
+    /// One value per frame buffer attachment, depth is cleared to 1.0
+    pub fn clear_values(&self, color: [f32; 4]) -> Vec<vk::ClearValue> {
+
+        let color = vk::ClearValue { color: vk::ClearColorValue { float32: color } };
+        let mut values = vec![color];
+
+        if self.attachments.depth.is_some() {
+            values.push(vk::ClearValue { depth_stencil: vk::ClearDepthStencilValue { depth: 1.0, stencil: 0 } });
+        }
+
+        if self.attachments.color.is_some() {
+            values.push(color);
+        }
+
+        values
+    }
+
+    ///
+    /// Dynamic rendering counterpart of the default render pass: clears colour and depth,
+    /// renders into the MSAA target when there is one and resolves into the swapchain image
+    ///
+    /// Fails if dynamic rendering is not enabled on the device
+    ///
+    pub fn begin_rendering(&self, dev: &GraphicsDevice, command_buffer: vk::CommandBuffer, image_index: u32, clear_color: [f32; 4]) -> VulkanResult<()> {
+
+        let dynamic_rendering = dev.require_dynamic_rendering()?;
+        let device = dev.raw_device();
+        let extent = self.extent();
+
+        let swapchain_image = self.swapchain_images[image_index as usize];
+        let swapchain_view = self.image_views.raw[image_index as usize];
+
+        let mut color_barriers = vec![color_barrier(swapchain_image)];
+        color_barriers.extend(self.attachments.color.iter().map(|x| color_barrier(x.raw)));
+
+        let depth_barriers = self.attachments.depth.iter().map(|x| {
+            let aspect_mask = if has_stencil(x.format) {
+                vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+            } else {
+                vk::ImageAspectFlags::DEPTH
+            };
+
+            // The previous frame may still write the shared depth image
+            image_barrier(x.raw, aspect_mask)
+                .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+                .new_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        }).collect::<Vec<_>>();
+
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &color_barriers
+            );
+
+            if !depth_barriers.is_empty() {
+                device.cmd_pipeline_barrier(
+                    command_buffer,
+                    vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                    vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &depth_barriers
+                );
+            }
+        }
+
+        let clear_values = self.clear_values(clear_color);
+
+        let color_attachment = match &self.attachments.color {
+            Some(msaa) => vk::RenderingAttachmentInfo::default()
+                .image_view(msaa.view)
+                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .resolve_mode(vk::ResolveModeFlags::AVERAGE)
+                .resolve_image_view(swapchain_view)
+                .resolve_image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE),
+            None => vk::RenderingAttachmentInfo::default()
+                .image_view(swapchain_view)
+                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::STORE)
+        }.clear_value(clear_values[0]);
+
+        let depth_attachment = self.attachments.depth.as_ref().map(|depth| {
+            vk::RenderingAttachmentInfo::default()
+                .image_view(depth.view)
+                .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                .clear_value(clear_values[1])
+        });
+
+        let color_attachments = [color_attachment];
+        let mut rendering_info = vk::RenderingInfo::default()
+            .render_area(vk::Rect2D { offset: vk::Offset2D { x: 0, y: 0 }, extent })
+            .layer_count(1)
+            .color_attachments(&color_attachments);
+
+        if let Some(depth) = &depth_attachment {
+            rendering_info = rendering_info.depth_attachment(depth);
+
+            if self.attachments.depth.as_ref().is_some_and(|x| has_stencil(x.format)) {
+                rendering_info = rendering_info.stencil_attachment(depth);
+            }
+        }
+
+        dynamic_rendering.begin_rendering(device, command_buffer, &rendering_info);
+        Ok(())
+    }
+
+    /// Ends rendering and moves the swapchain image to PRESENT_SRC_KHR
+    pub fn end_rendering(&self, dev: &GraphicsDevice, command_buffer: vk::CommandBuffer, image_index: u32) -> VulkanResult<()> {
+        let dynamic_rendering = dev.require_dynamic_rendering()?;
+        let swapchain_image = self.swapchain_images[image_index as usize];
+        dynamic_rendering.end_swapchain_rendering(dev.raw_device(), command_buffer, swapchain_image);
+        Ok(())
+    }
+}
+
+fn image_barrier<'a>(image: vk::Image, aspect_mask: vk::ImageAspectFlags) -> vk::ImageMemoryBarrier<'a> {
+    vk::ImageMemoryBarrier::default()
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .old_layout(vk::ImageLayout::UNDEFINED)
+        .subresource_range(vk::ImageSubresourceRange {
+            aspect_mask,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        })
+}
+
+fn color_barrier<'a>(image: vk::Image) -> vk::ImageMemoryBarrier<'a> {
+    image_barrier(image, vk::ImageAspectFlags::COLOR)
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .new_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+}
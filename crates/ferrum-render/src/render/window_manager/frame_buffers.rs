@@ -6,6 +6,8 @@ use crate::{
     FrameBuffers,
     ImageViews,
     RenderPass,
+    VulkanError,
+    VulkanResult,
     WindowManager,
     WindowManagerBuilder,
     WithImageViews
@@ -13,35 +15,61 @@ use crate::{
 
 impl WindowManagerBuilder<WithImageViews> {
 
-    pub fn with_frame_buffers<F>(self, build_fn: F) -> WindowManager
-        where F: FnOnce(&ash::Device, &Vec<ash::vk::ImageView>, &RenderPass, &SurfaceCapabilitiesKHR) -> FrameBuffers {
+    /// Fails without a render pass, use [`Self::without_frame_buffers`] for dynamic rendering
+    pub fn with_frame_buffers<F>(self, build_fn: F) -> VulkanResult<WindowManager>
+        where F: FnOnce(&ash::Device, &Vec<ash::vk::ImageView>, &RenderPass, &SurfaceCapabilitiesKHR) -> VulkanResult<FrameBuffers> {
 
             let device = &self.state.device.logical_device.raw;
+            let render_pass = self.state.render_pass.as_ref().ok_or(VulkanError::missing("WindowManagerBuilder", "render pass"))?;
             let frame_buffers = build_fn(
-                &device,
+                device,
                 &self.state.image_views.raw,
-                &self.state.render_pass,
+                render_pass,
                 &self.state.caps
-            );
-
-            WindowManager {
-                raw: self.state.window,
-                surface: self.state.surface,
-                surface_format_khr: self.state.format,
-                mode: self.state.mode,
-                caps: self.state.caps,
-                swapchain: self.state.swapchain,
-                render_pass: self.state.render_pass,
-                image_views: self.state.image_views,
-                frame_buffers,
-            }
+            )?;
+
+            Ok(self.finish(Some(frame_buffers)))
+    }
+
+    /// For dynamic rendering, no frame buffers are created
+    pub fn without_frame_buffers(self) -> WindowManager {
+        self.finish(None)
+    }
+
+    fn finish(self, frame_buffers: Option<FrameBuffers>) -> WindowManager {
+        WindowManager {
+            raw: self.state.window,
+            surface: self.state.surface,
+            surface_format_khr: self.state.format,
+            mode: self.state.mode,
+            caps: self.state.caps,
+            swapchain: self.state.swapchain,
+            swapchain_images: self.state.swapchain_images,
+            render_pass: self.state.render_pass,
+            image_views: self.state.image_views,
+            frame_buffers,
+            minimized: false,
+            retired: vec![],
+            hdr_metadata: None,
+            attachment_config: self.state.attachment_config,
+            attachments: self.state.attachments
+        }
     }
 
-    pub fn with_default_frame_buffers(self) -> WindowManager {
+    /// Frame buffers are created only when there is a render pass
+    pub fn with_default_frame_buffers(self) -> VulkanResult<WindowManager> {
+
+        if self.state.render_pass.is_none() {
+            return Ok(self.without_frame_buffers());
+        }
+
+        let attachments = self.state.attachments.views();
+
         self.with_frame_buffers(|device, image_views, render_pass, caps| {
                 FrameBufferBuilder::new()
                     .device(device)
                     .image_views(image_views)
+                    .attachments(&attachments)
                     .resolution(caps.current_extent)
                     .render_pass(&render_pass.raw)
                     .build()
@@ -4,7 +4,7 @@ use ash::vk::{Format, Image, PresentModeKHR, SurfaceCapabilitiesKHR, SurfaceForm
 use winit::window::Window;
 
 use crate::{
-    Device, GraphicsDevice, ImageViews, ImageViewsBuilder, RenderPass, Surface, Swapchain, WindowManagerBuilder, WithRenderPass
+    AttachmentConfig, Device, GraphicsDevice, ImageViews, ImageViewsBuilder, RenderPass, Surface, Swapchain, SwapchainError, VulkanError, VulkanResult, WindowAttachments, WindowManagerBuilder, WithRenderPass
 };
 
 pub struct WithImageViews {
@@ -15,19 +15,41 @@ pub struct WithImageViews {
     pub mode: PresentModeKHR,
     pub caps: SurfaceCapabilitiesKHR,
     pub swapchain: Swapchain,
-    pub render_pass: RenderPass,
-    pub image_views: ImageViews
+    pub swapchain_images: Vec<Image>,
+    pub render_pass: Option<RenderPass>,
+    pub image_views: ImageViews,
+    pub attachment_config: AttachmentConfig,
+    pub attachments: WindowAttachments
 }
 
 impl WindowManagerBuilder<WithRenderPass> {
-    pub fn with_image_views<F>(self, build_fn: F) -> WindowManagerBuilder<WithImageViews>
+    pub fn with_image_views<F>(self, build_fn: F) -> VulkanResult<WindowManagerBuilder<WithImageViews>>
         where F: FnOnce(&ash::Device, Format, Vec<Image>) -> ImageViews {
 
-            let swapchain_images = self.state.swapchain.get_swapchain_images();
+            let swapchain_images = self.state.swapchain.get_swapchain_images()?;
             let device = &self.state.device.logical_device.raw;
-            let image_views = build_fn(&device, self.state.format.format, swapchain_images);
+            let image_views = build_fn(&device, self.state.format.format, swapchain_images.clone());
 
-            WindowManagerBuilder { state: WithImageViews {
+            let attachments = WindowAttachments::new(
+                device,
+                &self.state.device.phys_dev.phys_info.memory_prop,
+                &self.state.attachments,
+                self.state.format.format,
+                self.state.swapchain.extent
+            );
+
+            let attachments = match attachments {
+                Ok(attachments) => attachments,
+                Err(e) => {
+                    for view in &image_views.raw {
+                        unsafe { device.destroy_image_view(*view, None) };
+                    }
+
+                    return Err(VulkanError::vk(e, |e| VulkanError::Swapchain(SwapchainError::CreateAttachmentsFailed(e))));
+                }
+            };
+
+            Ok(WindowManagerBuilder { state: WithImageViews {
                 device: self.state.device,
                 window: self.state.window,
                 surface: self.state.surface,
@@ -35,12 +57,15 @@ impl WindowManagerBuilder<WithRenderPass> {
                 mode: self.state.mode,
                 caps: self.state.caps,
                 swapchain: self.state.swapchain,
+                swapchain_images,
                 render_pass: self.state.render_pass,
-                image_views
-            }}
+                image_views,
+                attachment_config: self.state.attachments,
+                attachments
+            }})
     }
 
-    pub fn with_default_image_views(self) -> WindowManagerBuilder<WithImageViews> {
+    pub fn with_default_image_views(self) -> VulkanResult<WindowManagerBuilder<WithImageViews>> {
         self.with_image_views(|device, format, swapchain_images| {
             ImageViewsBuilder::new()
                 .with_device(device)
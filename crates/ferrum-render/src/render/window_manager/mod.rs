@@ -31,54 +31,130 @@ pub use frame_buffers::*;
 pub(crate) mod graphics_device;
 pub use graphics_device::*;
 
+pub(crate) mod attachments;
+pub use attachments::*;
+
 pub struct WindowManagerBuilder<S> {
     pub state: S
 }
 
+/// Swapchain resources replaced by [`WindowManager::recreate`], kept until no frame uses them
+pub struct RetiredSwapchain {
+    pub swapchain: ash::vk::SwapchainKHR,
+    pub image_views: Vec<ash::vk::ImageView>,
+    pub frame_buffers: Vec<ash::vk::Framebuffer>,
+    pub attachments: WindowAttachments,
+    /// Frames presented since retirement
+    pub age: usize
+}
+
 pub struct WindowManager {
     pub raw: Window,
     pub surface: Surface,
     pub surface_format_khr: SurfaceFormatKHR,
     pub mode: PresentModeKHR,
+    /// `current_extent` always holds the swapchain extent
     pub caps: SurfaceCapabilitiesKHR,
     pub swapchain: Swapchain,
-    pub render_pass: RenderPass,
+    /// Images of `swapchain`, refreshed on recreation
+    pub swapchain_images: Vec<ash::vk::Image>,
+    /// None with dynamic rendering
+    pub render_pass: Option<RenderPass>,
     pub image_views: ImageViews,
-    pub frame_buffers: FrameBuffers
+    /// None with dynamic rendering
+    pub frame_buffers: Option<FrameBuffers>,
+    /// Surface has zero size, nothing can be presented
+    pub minimized: bool,
+    pub retired: Vec<RetiredSwapchain>,
+    /// Applied again after every swapchain recreation
+    pub hdr_metadata: Option<ash::vk::HdrMetadataEXT<'static>>,
+    pub attachment_config: AttachmentConfig,
+    /// Depth and MSAA images, sized with the swapchain
+    pub attachments: WindowAttachments
 }
 
 
 impl WindowManager {
 
-    pub fn resize(&mut self, dev: &Arc<GraphicsDevice>, width: u32, height: u32) {
+    pub fn id(&self) -> winit::window::WindowId {
+        self.raw.id()
+    }
 
-        if width == 0 || height == 0 {
+    pub fn is_dynamic_rendering(&self) -> bool {
+        self.render_pass.is_none()
+    }
+
+    pub fn image_count(&self) -> usize {
+        self.image_views.raw.len()
+    }
+
+    pub fn extent(&self) -> Extent2D {
+        self.swapchain.extent
+    }
+
+    /// Colour space of the swapchain images, sRGB for unknown formats
+    pub fn output_color_space(&self) -> OutputColorSpace {
+        OutputColorSpace::from_surface_format(&self.surface_format_khr).unwrap_or_default()
+    }
+
+    ///
+    /// Sends mastering display metadata to the presentation engine, see [`hdr10_metadata`].
+    /// Does nothing without `VK_EXT_hdr_metadata`
+    ///
+    pub fn set_hdr_metadata(&mut self, dev: &GraphicsDevice, metadata: ash::vk::HdrMetadataEXT<'static>) {
+
+        let Some(loader) = &dev.hdr_metadata else {
+            log::warn!("VK_EXT_hdr_metadata is not enabled, HDR metadata ignored");
             return;
+        };
+
+        loader.set(self.swapchain.raw, &metadata);
+        self.hdr_metadata = Some(metadata);
+    }
+
+    ///
+    /// Creates a new swapchain from the current window size, passing the old one as `old_swapchain`.
+    /// Old views and frame buffers are moved to [`Self::retired`], see [`Self::collect_retired`].
+    /// Returns false if the window is minimized, the swapchain is kept then.
+    /// On error the old swapchain stays in use
+    ///
+    pub fn recreate(&mut self, dev: &Arc<GraphicsDevice>) -> VulkanResult<bool> {
+
+        let caps = self.surface.get_surface_capabilities(&dev.phys_dev.raw)?;
+        let size = self.raw.inner_size();
+        let extent = choose_extent(Extent2D { width: size.width, height: size.height }, &caps);
+
+        if extent.width == 0 || extent.height == 0 {
+            self.minimized = true;
+            return Ok(false);
         }
 
         let format = &self.surface_format_khr;
         let device = &dev.logical_device.raw;
         let instance = &dev.instance.raw;
-        let transform = &self.caps.current_transform;
-        let mode = &self.mode;
-        let surface = &self.surface.raw;
-
-        unsafe { device.device_wait_idle().expect("Failed to wait for device idle"); }
-
-        let caps = self.surface.get_surface_capabilities(&dev.phys_dev.raw);
 
         let swapchain = SwapchainBuilder::new()
+                .with_caps(&caps)
                 .with_color_space(format.color_space)
                 .with_format(format.format)
-                .with_resolution(caps.current_extent)
-                .with_transform(*transform)
-                .with_present_mode(*mode)
+                .with_resolution(extent)
+                .with_transform(caps.current_transform)
+                .with_present_mode(self.mode)
+                .with_image_count(self.swapchain.image_count)
+                .with_image_usage(self.swapchain.image_usage)
+                .with_old_swapchain(self.swapchain.raw)
                 .with_instance(instance)
                 .with_device(device)
-                .with_surface(surface)
-                .build();
+                .with_surface(&self.surface.raw)
+                .build()?;
 
-        let swapchain_images = swapchain.get_swapchain_images();
+        let swapchain_images = match swapchain.get_swapchain_images() {
+            Ok(images) => images,
+            Err(err) => {
+                unsafe { swapchain.swapchain_load.destroy_swapchain(swapchain.raw, None) };
+                return Err(err);
+            }
+        };
 
         let image_views = ImageViewsBuilder::new()
             .with_device(device)
@@ -86,32 +162,146 @@ impl WindowManager {
             .with_image_views(&swapchain_images)
             .build();
 
-        let frame_buffers = FrameBufferBuilder::new()
-            .device(device)
-            .image_views(&image_views.raw)
-            .resolution(caps.current_extent)
-            .render_pass(&self.render_pass.raw)
-            .build();
+        let attachments = WindowAttachments::new(
+            device,
+            &dev.phys_dev.phys_info.memory_prop,
+            &self.attachment_config,
+            format.format,
+            swapchain.extent
+        );
 
-        unsafe {
-            for i in &self.frame_buffers.raw {
-                device.destroy_framebuffer(*i, None);
+        let attachments = match attachments {
+            Ok(attachments) => attachments,
+            Err(e) => {
+                unsafe {
+                    for view in &image_views.raw {
+                        device.destroy_image_view(*view, None);
+                    }
+
+                    swapchain.swapchain_load.destroy_swapchain(swapchain.raw, None);
+                }
+
+                return Err(VulkanError::vk(e, |e| VulkanError::Swapchain(SwapchainError::CreateAttachmentsFailed(e))));
             }
+        };
+
+        let attachment_views = attachments.views();
+
+        let frame_buffers = self.render_pass.as_ref().map(|render_pass| {
+            FrameBufferBuilder::new()
+                .device(device)
+                .image_views(&image_views.raw)
+                .attachments(&attachment_views)
+                .resolution(swapchain.extent)
+                .render_pass(&render_pass.raw)
+                .build()
+        }).transpose()?;
+
+        let old_swapchain = std::mem::replace(&mut self.swapchain, swapchain);
+        self.swapchain_images = swapchain_images;
+        let old_views = std::mem::replace(&mut self.image_views, image_views);
+        let old_frame_buffers = std::mem::replace(&mut self.frame_buffers, frame_buffers);
+        let old_attachments = std::mem::replace(&mut self.attachments, attachments);
+
+        self.retired.push(RetiredSwapchain {
+            swapchain: old_swapchain.raw,
+            image_views: old_views.raw,
+            frame_buffers: old_frame_buffers.map(|x| x.raw).unwrap_or_default(),
+            attachments: old_attachments,
+            age: 0
+        });
+
+        self.caps = caps;
+        self.caps.current_extent = self.swapchain.extent;
+        self.minimized = false;
+
+        if let (Some(loader), Some(metadata)) = (&dev.hdr_metadata, &self.hdr_metadata) {
+            loader.set(self.swapchain.raw, metadata);
+        }
+
+        log::debug!("Swapchain recreated: {:?}", self.swapchain.extent);
+        Ok(true)
+    }
+
+    ///
+    /// Call once per frame after waiting for the frame slot.
+    /// Destroys retired resources older than `frames_in_flight` frames
+    ///
+    pub fn collect_retired(&mut self, device: &ash::Device, frames_in_flight: usize) {
+
+        for retired in &mut self.retired {
+            retired.age += 1;
+        }
+
+        let (expired, alive) = std::mem::take(&mut self.retired)
+            .into_iter()
+            .partition(|x| x.age > frames_in_flight);
+
+        self.retired = alive;
+        self.destroy_swapchain_resources(device, expired);
+    }
 
-            for i in &self.image_views.raw {
-                device.destroy_image_view(*i, None);
+    /// Destroys all retired resources, the device must be idle
+    pub fn destroy_retired(&mut self, device: &ash::Device) {
+        let retired = std::mem::take(&mut self.retired);
+        self.destroy_swapchain_resources(device, retired);
+    }
+
+    fn destroy_swapchain_resources(&self, device: &ash::Device, retired: Vec<RetiredSwapchain>) {
+        unsafe {
+            for x in retired {
+                for frame_buffer in x.frame_buffers {
+                    device.destroy_framebuffer(frame_buffer, None);
+                }
+
+                for view in x.image_views {
+                    device.destroy_image_view(view, None);
+                }
+
+                x.attachments.destroy(device);
+
+                self.swapchain.swapchain_load.destroy_swapchain(x.swapchain, None);
             }
+        }
+    }
 
-            self.swapchain.swapchain_load.destroy_swapchain(self.swapchain.raw, None);
+    /// Waits for the device and recreates the swapchain immediately
+    pub fn resize(&mut self, dev: &Arc<GraphicsDevice>, width: u32, height: u32) -> VulkanResult<()> {
+
+        if width == 0 || height == 0 {
+            self.minimized = true;
+            return Ok(());
         }
 
-        self.caps = caps;
-        self.swapchain = swapchain;
-        self.image_views = image_views;
-        self.frame_buffers = frame_buffers;
+        unsafe { dev.logical_device.raw.device_wait_idle() }
+            .map_err(|e| VulkanError::vk(e, |e| VulkanError::Device(DeviceError::WaitIdleFailed(e))))?;
 
-        log::debug!("New size: {:?}", self.caps.current_extent)
+        if self.recreate(dev)? {
+            self.destroy_retired(&dev.logical_device.raw);
+        }
+
+        Ok(())
     }
 
+    /// Destroys swapchain resources and the surface, the device must be idle.
+    /// The render pass is queued to the deletion queue
+    pub fn destroy(&mut self, device: &ash::Device) {
+
+        self.render_pass = None;
+        self.destroy_retired(device);
+        self.attachments.destroy(device);
+
+        unsafe {
+            for frame_buffer in self.frame_buffers.iter().flat_map(|x| &x.raw) {
+                device.destroy_framebuffer(*frame_buffer, None);
+            }
+
+            for view in &self.image_views.raw {
+                device.destroy_image_view(*view, None);
+            }
 
-}
\ No newline at end of file
+            self.swapchain.swapchain_load.destroy_swapchain(self.swapchain.raw, None);
+            self.surface.raw_load.destroy_surface(self.surface.raw, None);
+        }
+    }
+}
@@ -1,9 +1,9 @@
 use std::sync::Arc;
 
-use ash::vk::{Format, SurfaceFormatKHR};
+use ash::vk::SurfaceFormatKHR;
 use winit::window::Window;
 
-use crate::{GraphicsDevice, Surface, WindowManagerBuilder, WithGraphicsDevice, WithPhysicalDevice, WithSurface};
+use crate::{choose_surface_format, GraphicsDevice, OutputColorSpace, Surface, SurfaceError, VulkanError, VulkanResult, WindowManagerBuilder, WithGraphicsDevice, WithPhysicalDevice, WithSurface};
 use crate::PhysicalDevice;
 
 pub struct WithFormat {
@@ -14,44 +14,55 @@ pub struct WithFormat {
 }
 
 impl WindowManagerBuilder<WithGraphicsDevice> {
-    pub fn with_format<F>(self, build_fn: F) -> WindowManagerBuilder<WithFormat>
+    /// `build_fn` gets at least one format
+    pub fn with_format<F>(self, build_fn: F) -> VulkanResult<WindowManagerBuilder<WithFormat>>
         where F: FnOnce(Vec<SurfaceFormatKHR>) -> SurfaceFormatKHR {
 
             let phys_dev = &self.state.device.phys_dev;
-            let formats = self.state.surface.get_surface_formats(&phys_dev.raw);
+            let formats = self.state.surface.get_surface_formats(&phys_dev.raw)?;
+
+            if formats.is_empty() {
+                return Err(VulkanError::Surface(SurfaceError::NoFormats));
+            }
+
             let format = build_fn(formats);
 
-            WindowManagerBuilder { state: WithFormat {
+            Ok(WindowManagerBuilder { state: WithFormat {
                 window: self.state.window,
                 surface: self.state.surface,
                 device: self.state.device,
                 format
-            }}
+            }})
+    }
+
+    /// 8 bit sRGB format
+    pub fn with_default_format(self) -> VulkanResult<WindowManagerBuilder<WithFormat>> {
+        self.with_color_space(&[])
     }
 
-    pub fn with_default_format(self) -> WindowManagerBuilder<WithFormat> {
+    ///
+    /// Picks a format for the first supported colour space in `preferred`, falling back to sRGB.
+    /// Non-sRGB colour spaces need [`crate::SWAPCHAIN_COLORSPACE_EXTENSION`] on the instance
+    ///
+    /// # Example
+    /// ```
+    /// let window = window
+    ///     .with_graphics_device(device.clone())
+    ///     .with_color_space(&[OutputColorSpace::Hdr10, OutputColorSpace::ScRgb])?;
+    /// ```
+    ///
+    pub fn with_color_space(self, preferred: &[OutputColorSpace]) -> VulkanResult<WindowManagerBuilder<WithFormat>> {
 
         self.with_format(|formats| {
 
-                const DEFAULT_PRIORITY_FORMATS: &[Format] = &[
-                    Format::B8G8R8A8_SRGB,
-                    Format::R8G8B8A8_SRGB,
-                    Format::B8G8R8A8_UNORM,
-                    Format::R8G8B8A8_UNORM,
-                    Format::A8B8G8R8_SRGB_PACK32,
-                ];
-
-                DEFAULT_PRIORITY_FORMATS.iter()
-                    .find_map(|priority_fmt| {
-                        formats.iter()
-                            .find(|sf| sf.format == *priority_fmt)
-                            .copied()
-                    })
-                    .unwrap_or_else(|| {
-                        formats.first().copied().unwrap_or_else(|| {
-                            panic!("No supported surface format");
-                        })
-                    })
+                let (format, color_space) = choose_surface_format(&formats, preferred)
+                    .unwrap_or((formats[0], OutputColorSpace::Srgb));
+
+                if !preferred.is_empty() && !preferred.contains(&color_space) {
+                    log::warn!("None of {:?} is supported by the surface, using {:?}", preferred, color_space);
+                }
+
+                format
         })
     }
 }
\ No newline at end of file
@@ -4,7 +4,7 @@ use ash::vk::{PresentModeKHR, SurfaceCapabilitiesKHR, SurfaceFormatKHR};
 use log::warn;
 use winit::window::Window;
 
-use crate::{ GraphicsDevice, Surface, WindowManagerBuilder, WithFormat};
+use crate::{ GraphicsDevice, Surface, SurfaceError, VulkanError, VulkanResult, WindowManagerBuilder, WithFormat};
 use crate::PhysicalDevice;
 
 pub struct WithMode {
@@ -17,25 +17,31 @@ pub struct WithMode {
 }
 
 impl WindowManagerBuilder<WithFormat> {
-    pub fn with_mode<F>(self, build_fn: F) -> WindowManagerBuilder<WithMode>
+    /// `build_fn` gets at least one mode
+    pub fn with_mode<F>(self, build_fn: F) -> VulkanResult<WindowManagerBuilder<WithMode>>
         where F: FnOnce(Vec<PresentModeKHR>) -> PresentModeKHR {
 
             let phys_dev = &self.state.device.phys_dev;
-            let modes = self.state.surface.get_surface_present_modes(&phys_dev.raw);
-            let caps = self.state.surface.get_surface_capabilities(&phys_dev.raw);
+            let modes = self.state.surface.get_surface_present_modes(&phys_dev.raw)?;
+            let caps = self.state.surface.get_surface_capabilities(&phys_dev.raw)?;
+
+            if modes.is_empty() {
+                return Err(VulkanError::Surface(SurfaceError::NoPresentModes));
+            }
+
             let mode = build_fn(modes);
 
-            WindowManagerBuilder { state: WithMode {
+            Ok(WindowManagerBuilder { state: WithMode {
                 device: self.state.device,
                 window: self.state.window,
                 surface: self.state.surface,
                 format: self.state.format,
                 mode,
                 caps
-            }}
+            }})
     }
 
-    pub fn with_default_mode(self) -> WindowManagerBuilder<WithMode> {
+    pub fn with_default_mode(self) -> VulkanResult<WindowManagerBuilder<WithMode>> {
 
         self.with_mode(|present_modes| {
 
@@ -50,10 +56,8 @@ impl WindowManagerBuilder<WithFormat> {
                 .copied()
                 .unwrap_or_else(|| {
                     warn!("No priority mode is used, the first available one is used");
-                    present_modes.first().copied().unwrap_or_else(|| {
-                        panic!("No presentation modes supported!");
-                    })
-            })
+                    present_modes[0]
+                })
         })
     }
 }
\ No newline at end of file
@@ -4,7 +4,7 @@ use ash::vk::{self, AttachmentReference, Format, PresentModeKHR, SurfaceCapabili
 use winit::window::Window;
 
 use crate::{
-    Device, GraphicsDevice, RenderPass, RenderPassBuilder, SubpassBuilder, Surface, Swapchain, WindowManagerBuilder, WithSwapchain
+    has_stencil, AttachmentConfig, Device, DeviceHandle, GraphicsDevice, RenderPass, RenderPassBuilder, SubpassBuilder, Surface, Swapchain, VulkanResult, WindowManagerBuilder, WithSwapchain
 };
 
 pub struct WithRenderPass {
@@ -15,18 +15,20 @@ pub struct WithRenderPass {
     pub mode: PresentModeKHR,
     pub caps: SurfaceCapabilitiesKHR,
     pub swapchain: Swapchain,
-    pub render_pass: RenderPass
+    /// None with dynamic rendering
+    pub render_pass: Option<RenderPass>,
+    pub attachments: AttachmentConfig
 }
 
 impl WindowManagerBuilder<WithSwapchain> {
 
-    pub fn with_render_pass<F>(self, build_fn: F) -> WindowManagerBuilder<WithRenderPass>
-        where F: FnOnce(&ash::Device, &Format) -> RenderPass {
+    pub fn with_render_pass<F>(self, build_fn: F) -> VulkanResult<WindowManagerBuilder<WithRenderPass>>
+        where F: FnOnce(&DeviceHandle, &Format) -> VulkanResult<RenderPass> {
 
             let device = &self.state.device.logical_device.raw;
-            let render_pass = build_fn(device, &self.state.format.format);
+            let render_pass = build_fn(device, &self.state.format.format)?;
 
-            WindowManagerBuilder { state: WithRenderPass {
+            Ok(WindowManagerBuilder { state: WithRenderPass {
                 device: self.state.device,
                 window: self.state.window,
                 surface: self.state.surface,
@@ -34,42 +36,131 @@ impl WindowManagerBuilder<WithSwapchain> {
                 mode: self.state.mode,
                 swapchain: self.state.swapchain,
                 caps: self.state.caps,
-                render_pass
-            }}
+                render_pass: Some(render_pass),
+                attachments: self.state.attachments
+            }})
     }
 
-    pub fn with_default_render_pass(self) -> WindowManagerBuilder<WithRenderPass> {
+    /// Skips render pass and frame buffers, passes use dynamic rendering.
+    /// Fails without [`crate::GraphicsDevice::dynamic_rendering`]
+    pub fn without_render_pass(self) -> VulkanResult<WindowManagerBuilder<WithRenderPass>> {
+
+        self.state.device.require_dynamic_rendering()?;
+
+        Ok(WindowManagerBuilder { state: WithRenderPass {
+            device: self.state.device,
+            window: self.state.window,
+            surface: self.state.surface,
+            format: self.state.format,
+            mode: self.state.mode,
+            swapchain: self.state.swapchain,
+            caps: self.state.caps,
+            render_pass: None,
+            attachments: self.state.attachments
+        }})
+    }
+
+    ///
+    /// Swapchain image as attachment 0, then depth and MSAA colour when configured
+    /// with [`Self::with_depth_buffer`] and [`Self::with_samples`].
+    /// With MSAA the swapchain image is the resolve target
+    ///
+    pub fn with_default_render_pass(self) -> VulkanResult<WindowManagerBuilder<WithRenderPass>> {
+
+        let config = self.state.attachments;
+
         self.with_render_pass(|device, format| {
 
-            let subpass = SubpassBuilder::new()
+            let mut subpass = SubpassBuilder::new()
                 .add_color_attachment_ref(
+                    AttachmentReference::default()
+                        .attachment(config.color_index())
+                        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                )
+                .with_bind_point(vk::PipelineBindPoint::GRAPHICS);
+
+            if let Some(index) = config.depth_index() {
+                subpass = subpass.add_depth_attachment_ref(
+                    AttachmentReference::default()
+                        .attachment(index)
+                        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                );
+            }
+
+            if config.is_msaa() {
+                subpass = subpass.add_resolve_attachment_ref(
                     AttachmentReference::default()
                         .attachment(0)
                         .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                );
+            }
+
+            let subpass = subpass.build();
+
+            // Ждём запись предыдущего кадра в те же depth/MSAA вложения
+            let (stage_mask, src_access_mask, access_mask) = if config.depth_format.is_some() {
+                (
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+                )
+            } else {
+                (
+                    vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
                 )
-                .with_bind_point(vk::PipelineBindPoint::GRAPHICS)
-                .build();
+            };
 
-            RenderPassBuilder::new()
-                .with_device(&device)
+            let mut builder = RenderPassBuilder::new()
+                .with_device(device)
                 .add_subpass(subpass.raw)
                 .add_subpass_dependency(
                     vk::SubpassDependency {
                         src_subpass: vk::SUBPASS_EXTERNAL,
-                        src_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                        dst_access_mask: vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
-                        dst_stage_mask: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                        src_stage_mask: stage_mask,
+                        src_access_mask,
+                        dst_access_mask: access_mask,
+                        dst_stage_mask: stage_mask,
                         ..Default::default()
                     })
                 .add_attachments_desc(vk::AttachmentDescription {
                         format: *format,
                         samples: vk::SampleCountFlags::TYPE_1,
-                        load_op: vk::AttachmentLoadOp::CLEAR,
+                        // С MSAA сюда только резолвится
+                        load_op: if config.is_msaa() { vk::AttachmentLoadOp::DONT_CARE } else { vk::AttachmentLoadOp::CLEAR },
                         store_op: vk::AttachmentStoreOp::STORE,
                         final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
                         ..Default::default()
-                    })
-                .build()
+                    });
+
+            if let Some(depth_format) = config.depth_format {
+                let stencil_load_op = if has_stencil(depth_format) { vk::AttachmentLoadOp::CLEAR } else { vk::AttachmentLoadOp::DONT_CARE };
+
+                builder = builder.add_attachments_desc(vk::AttachmentDescription {
+                    format: depth_format,
+                    samples: config.samples,
+                    load_op: vk::AttachmentLoadOp::CLEAR,
+                    store_op: vk::AttachmentStoreOp::DONT_CARE,
+                    stencil_load_op,
+                    stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
+                    final_layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    ..Default::default()
+                });
+            }
+
+            if config.is_msaa() {
+                builder = builder.add_attachments_desc(vk::AttachmentDescription {
+                    format: *format,
+                    samples: config.samples,
+                    load_op: vk::AttachmentLoadOp::CLEAR,
+                    store_op: vk::AttachmentStoreOp::DONT_CARE,
+                    final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    ..Default::default()
+                });
+            }
+
+            builder.build()
         })
     }
 }
\ No newline at end of file
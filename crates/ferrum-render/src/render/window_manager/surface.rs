@@ -1,6 +1,6 @@
 #![allow(warnings)]
 
-use crate::{core::Instance, App, Surface, SurfaceBuilder, WindowManagerBuilder};
+use crate::{core::Instance, App, Surface, SurfaceBuilder, SurfaceError, VulkanError, VulkanResult, WindowManagerBuilder};
 use winit::{raw_window_handle::{HasDisplayHandle, HasWindowHandle}, window::Window};
 use super::WithWindow;
 
@@ -11,25 +11,30 @@ pub struct WithSurface {
 
 impl WindowManagerBuilder<WithWindow> {
 
-    pub fn with_surface<F>(self, instance: &Instance, build_fn: F) -> WindowManagerBuilder<WithSurface>
-    where F: FnOnce(&Window, &Instance) -> Surface {
+    pub fn with_surface<F>(self, instance: &Instance, build_fn: F) -> VulkanResult<WindowManagerBuilder<WithSurface>>
+    where F: FnOnce(&Window, &Instance) -> VulkanResult<Surface> {
 
-        let surface = build_fn(&self.state.window, instance);
+        let surface = build_fn(&self.state.window, instance)?;
 
-        WindowManagerBuilder { state:
+        Ok(WindowManagerBuilder { state:
             WithSurface {
                 window: self.state.window,
                 surface
             }
-        }
+        })
     }
 
-    pub fn with_default_surface(self, instance: &Instance) -> WindowManagerBuilder<WithSurface> {
+    pub fn with_default_surface(self, instance: &Instance) -> VulkanResult<WindowManagerBuilder<WithSurface>> {
 
         self.with_surface(instance, |window, instance| {
 
-            let raw_window_handle = window.window_handle().expect("Err").as_raw();
-            let raw_display_handle = window.display_handle().expect("Err").as_raw();
+            let raw_window_handle = window.window_handle()
+                .map_err(|e| VulkanError::Surface(SurfaceError::WindowHandle(e.to_string())))?
+                .as_raw();
+
+            let raw_display_handle = window.display_handle()
+                .map_err(|e| VulkanError::Surface(SurfaceError::WindowHandle(e.to_string())))?
+                .as_raw();
 
             SurfaceBuilder::new()
                 .with_entry(&instance.entry)
@@ -39,4 +44,4 @@ impl WindowManagerBuilder<WithWindow> {
                 .build()
         })
     }
-}
\ No newline at end of file
+}
@@ -1,9 +1,9 @@
 use std::sync::Arc;
 
-use ash::vk::{PresentModeKHR, SurfaceCapabilitiesKHR, SurfaceFormatKHR, SurfaceKHR};
+use ash::vk::{Extent2D, PresentModeKHR, SurfaceCapabilitiesKHR, SurfaceFormatKHR, SurfaceKHR};
 use winit::window::Window;
 
-use crate::{ Device, GraphicsDevice, Instance, Surface, Swapchain, SwapchainBuilder, WindowManagerBuilder, WithMode };
+use crate::{ AttachmentConfig, Device, GraphicsDevice, Instance, Surface, Swapchain, SwapchainBuilder, VulkanResult, WindowManagerBuilder, WithMode };
 
 
 pub struct WithSwapchain {
@@ -13,12 +13,13 @@ pub struct WithSwapchain {
     pub format: SurfaceFormatKHR,
     pub caps: SurfaceCapabilitiesKHR,
     pub mode: PresentModeKHR,
-    pub swapchain: Swapchain
+    pub swapchain: Swapchain,
+    pub attachments: AttachmentConfig
 }
 
 impl WindowManagerBuilder<WithMode> {
-    pub fn with_swapchain<F>(self, build_fn: F) -> WindowManagerBuilder<WithSwapchain>
-        where F: FnOnce(&ash::Instance, &ash::Device, &SurfaceKHR, &SurfaceFormatKHR, &PresentModeKHR, &SurfaceCapabilitiesKHR) -> Swapchain {
+    pub fn with_swapchain<F>(self, build_fn: F) -> VulkanResult<WindowManagerBuilder<WithSwapchain>>
+        where F: FnOnce(&ash::Instance, &ash::Device, &SurfaceKHR, &SurfaceFormatKHR, &PresentModeKHR, &SurfaceCapabilitiesKHR) -> VulkanResult<Swapchain> {
 
             let instance = &self.state.device.instance.raw;
             let device = &self.state.device.logical_device.raw;
@@ -34,27 +35,35 @@ impl WindowManagerBuilder<WithMode> {
                 format,
                 mode,
                 caps
-            );
+            )?;
 
-            WindowManagerBuilder { state: WithSwapchain {
+            // current_extent may be undefined (u32::MAX), keep the real one
+            let mut caps = self.state.caps;
+            caps.current_extent = swapchain.extent;
+
+            Ok(WindowManagerBuilder { state: WithSwapchain {
                 device: self.state.device,
                 window: self.state.window,
                 surface: self.state.surface,
                 format: self.state.format,
                 mode: self.state.mode,
-                caps: self.state.caps,
-                swapchain
-            }}
+                caps,
+                swapchain,
+                attachments: AttachmentConfig::default()
+            }})
     }
 
-    pub fn with_default_swapchain(self) -> WindowManagerBuilder<WithSwapchain> {
+    pub fn with_default_swapchain(self) -> VulkanResult<WindowManagerBuilder<WithSwapchain>> {
+
+        let size = self.state.window.inner_size();
 
         self.with_swapchain(|instance, device, surface, format, mode, caps| {
 
-            let extent = caps.current_extent;
+            let extent = Extent2D { width: size.width, height: size.height };
             let transform = caps.current_transform;
 
             SwapchainBuilder::new()
+                .with_caps(caps)
                 .with_color_space(format.color_space)
                 .with_format(format.format)
                 .with_resolution(extent)
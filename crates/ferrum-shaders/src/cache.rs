@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// FNV-1a, stable between Rust versions unlike `DefaultHasher`
+#[derive(Clone, Copy)]
+pub struct ContentHash(u64);
+
+impl ContentHash {
+
+    pub fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn write(mut self, bytes: &[u8]) -> Self {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        // Разделитель, чтобы ("ab", "c") != ("a", "bc")
+        self.0 ^= 0xff;
+        self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        self
+    }
+
+    pub fn finish(self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for ContentHash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// SPIR-V on disk by the hash of the preprocessed source, stage, entry point and compiler.
+/// Editing an included file changes the preprocessed source, so nothing stale is returned
+///
+pub struct SpirvCache {
+    dir: PathBuf
+}
+
+impl SpirvCache {
+
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.spv", key))
+    }
+
+    pub fn get(&self, key: u64) -> Option<Vec<u32>> {
+        let bytes = fs::read(self.path(key)).ok()?;
+
+        if bytes.len() % 4 != 0 || bytes.is_empty() {
+            return None;
+        }
+
+        Some(bytes.chunks_exact(4).map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]])).collect())
+    }
+
+    /// Errors are only logged, the cache is an optimisation
+    pub fn insert(&self, key: u64, spirv: &[u32]) {
+        let bytes = spirv.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<u8>>();
+
+        // Пишем во временный файл, чтобы параллельная сборка не прочитала половину
+        let tmp = self.dir.join(format!("{:016x}.{}.tmp", key, std::process::id()));
+        let result = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&tmp, bytes))
+            .and_then(|_| fs::rename(&tmp, self.path(key)));
+
+        if let Err(e) = result {
+            log::warn!("Failed to write shader cache {}: {}", self.dir.display(), e);
+        }
+    }
+}
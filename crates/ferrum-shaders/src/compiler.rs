@@ -0,0 +1,512 @@
+use std::path::{Path, PathBuf};
+
+use crate::{spirv_entry_points, CompileError, CompileResult, ContentHash, Diagnostic, PreprocessedSource, Preprocessor, ShaderDefines, ShaderLanguage, ShaderSource, ShaderStage, SpirvCache};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// Pure Rust, GLSL vertex, fragment and compute shaders
+    Naga,
+    /// glslang through shaderc, every stage
+    Shaderc
+}
+
+impl Backend {
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Naga => "naga",
+            Backend::Shaderc => "shaderc"
+        }
+    }
+}
+
+/// shaderc when the feature is enabled, otherwise naga
+impl Default for Backend {
+    fn default() -> Self {
+        if cfg!(feature = "shaderc") {
+            Backend::Shaderc
+        } else {
+            Backend::Naga
+        }
+    }
+}
+
+pub struct CompiledShader {
+    pub spirv: Vec<u32>,
+    pub stage: ShaderStage,
+    /// Name of the SPIR-V entry point, for `VkPipelineShaderStageCreateInfo`
+    pub entry_point: String,
+    /// Source and every included file, for `cargo:rerun-if-changed` and file watchers
+    pub dependencies: Vec<PathBuf>,
+    /// Taken from [`SpirvCache`] without compiling
+    pub cached: bool
+}
+
+/// Source code of one stage, `path` is used for includes and error messages only
+struct CodeRequest<'a> {
+    path: &'a Path,
+    code: &'a str,
+    language: ShaderLanguage,
+    stage: ShaderStage,
+    entry_point: Option<&'a str>,
+    defines: &'a ShaderDefines
+}
+
+///
+/// GLSL, WGSL and HLSL to SPIR-V in process, with `#include`, permutation defines and an optional on-disk cache
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use ferrum_shaders::*;
+/// # fn main() -> CompileResult<()> {
+/// let mut compiler = ShaderCompiler::new()
+///     .with_include_dir("shared/shaders/include")
+///     .with_cache_dir("target/shader-cache");
+///
+/// let shader = compiler.compile_file(Path::new("shared/shaders/triangle.vert"), &ShaderDefines::new())?;
+/// # Ok(())
+/// # }
+/// ```
+///
+#[derive(Default)]
+pub struct ShaderCompiler {
+    backend: Backend,
+    preprocessor: Preprocessor,
+    cache: Option<SpirvCache>,
+    #[cfg(feature = "shaderc")]
+    shaderc: Option<shaderc::Compiler>
+}
+
+impl ShaderCompiler {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    pub fn with_include_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.preprocessor = self.preprocessor.with_include_dir(dir);
+        self
+    }
+
+    pub fn with_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache = Some(SpirvCache::new(dir));
+        self
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Source and included files without compiling, for file watchers
+    pub fn dependencies(&self, path: &Path, defines: &ShaderDefines) -> CompileResult<Vec<PathBuf>> {
+        Ok(self.preprocessor.process_file(path, defines)?.files)
+    }
+
+    /// Stage from the extension, `triangle.vert`
+    pub fn compile_file(&mut self, path: &Path, defines: &ShaderDefines) -> CompileResult<CompiledShader> {
+        let stage = ShaderStage::from_path(path).ok_or_else(|| CompileError::UnknownStage(path.to_path_buf()))?;
+        let source = std::fs::read_to_string(path).map_err(|source| CompileError::Io { path: path.to_path_buf(), source })?;
+        self.compile_source(path, &source, stage, defines)
+    }
+
+    /// GLSL starting at `main`. `path` is used for relative includes and error messages, the file is not read
+    pub fn compile_source(&mut self, path: &Path, source: &str, stage: ShaderStage, defines: &ShaderDefines) -> CompileResult<CompiledShader> {
+        self.compile_code(CodeRequest { path, code: source, language: ShaderLanguage::Glsl, stage, entry_point: None, defines })
+    }
+
+    ///
+    /// Any [`ShaderLanguage`]. WGSL always goes through naga and HLSL through shaderc, GLSL through [`ShaderCompiler::backend`].
+    /// WGSL and HLSL clip space has Y up, it is flipped for Vulkan like `-fvk-invert-y` does
+    ///
+    pub fn compile(&mut self, source: &ShaderSource, stage: ShaderStage) -> CompileResult<CompiledShader> {
+
+        let path = source.display_path();
+
+        if source.language() == ShaderLanguage::SpirV {
+            let spirv = source.read_spirv()?;
+            let entry_point = find_entry_point(&path, &spirv, stage, source.entry_point())?;
+            let dependencies = source.path().map(Path::to_path_buf).into_iter().collect();
+            return Ok(CompiledShader { spirv, stage, entry_point, dependencies, cached: false });
+        }
+
+        let code = source.read_code()?;
+        let mut shader = self.compile_code(CodeRequest {
+            path: &path,
+            code: &code,
+            language: source.language(),
+            stage,
+            entry_point: source.entry_point(),
+            defines: source.defines()
+        })?;
+
+        // У встроенного кода нет файла, следить не за чем
+        if source.path().is_none() {
+            shader.dependencies.remove(0);
+        }
+
+        Ok(shader)
+    }
+
+    fn compile_code(&mut self, request: CodeRequest) -> CompileResult<CompiledShader> {
+
+        let CodeRequest { path, code, language, stage, entry_point, defines } = request;
+
+        if language == ShaderLanguage::Wgsl && !defines.is_empty() {
+            return Err(CompileError::DefinesNotSupported(language));
+        }
+
+        let preprocessed = self.preprocessor.process(path, code, defines)?;
+
+        let backend = match language {
+            ShaderLanguage::Wgsl => Backend::Naga,
+            ShaderLanguage::Hlsl => Backend::Shaderc,
+            _ => self.backend
+        };
+
+        let key = ContentHash::new()
+            .write(backend.name().as_bytes())
+            .write(env!("CARGO_PKG_VERSION").as_bytes())
+            .write(language.name().as_bytes())
+            .write(stage.extension().as_bytes())
+            .write(entry_point.unwrap_or_default().as_bytes())
+            .write(preprocessed.source.as_bytes())
+            .finish();
+
+        let (spirv, cached) = match self.cache.as_ref().and_then(|x| x.get(key)) {
+            Some(spirv) => (spirv, true),
+            None => {
+                let spirv = match (language, backend) {
+                    (ShaderLanguage::Wgsl, _) => compile_wgsl(&preprocessed, stage, entry_point)?,
+                    (_, Backend::Naga) => compile_naga(&preprocessed, stage)?,
+                    // GLSL всегда начинается с main
+                    (_, Backend::Shaderc) => self.compile_shaderc(&preprocessed, language, stage, entry_point.filter(|_| language == ShaderLanguage::Hlsl).unwrap_or("main"))?
+                };
+
+                if let Some(cache) = &self.cache {
+                    cache.insert(key, &spirv);
+                }
+
+                (spirv, false)
+            }
+        };
+
+        // Скомпилирована ровно одна точка входа, её имя и берём
+        let entry_point = find_entry_point(path, &spirv, stage, None)?;
+        Ok(CompiledShader { spirv, stage, entry_point, dependencies: preprocessed.files, cached })
+    }
+
+    #[cfg(feature = "shaderc")]
+    fn compile_shaderc(&mut self, preprocessed: &PreprocessedSource, language: ShaderLanguage, stage: ShaderStage, entry_point: &str) -> CompileResult<Vec<u32>> {
+
+        use shaderc::ShaderKind;
+
+        if self.shaderc.is_none() {
+            self.shaderc = shaderc::Compiler::new();
+        }
+
+        let compiler = self.shaderc.as_mut().ok_or(CompileError::NoBackend)?;
+        let mut options = shaderc::CompileOptions::new().ok_or(CompileError::NoBackend)?;
+        options.set_target_env(shaderc::TargetEnv::Vulkan, shaderc::EnvVersion::Vulkan1_2 as u32);
+        // Имена нужны для рефлексии
+        options.set_generate_debug_info();
+
+        if language == ShaderLanguage::Hlsl {
+            options.set_source_language(shaderc::SourceLanguage::HLSL);
+            options.set_auto_map_locations(true);
+            options.set_invert_y(true);
+        }
+
+        let kind = match stage {
+            ShaderStage::Vertex => ShaderKind::Vertex,
+            ShaderStage::Fragment => ShaderKind::Fragment,
+            ShaderStage::Compute => ShaderKind::Compute,
+            ShaderStage::Geometry => ShaderKind::Geometry,
+            ShaderStage::TessControl => ShaderKind::TessControl,
+            ShaderStage::TessEvaluation => ShaderKind::TessEvaluation,
+            ShaderStage::Task => ShaderKind::Task,
+            ShaderStage::Mesh => ShaderKind::Mesh,
+            ShaderStage::RayGen => ShaderKind::RayGeneration,
+            ShaderStage::AnyHit => ShaderKind::AnyHit,
+            ShaderStage::ClosestHit => ShaderKind::ClosestHit,
+            ShaderStage::Miss => ShaderKind::Miss,
+            ShaderStage::Intersection => ShaderKind::Intersection,
+            ShaderStage::Callable => ShaderKind::Callable
+        };
+
+        let name = preprocessed.files[0].display().to_string();
+
+        match compiler.compile_into_spirv(&preprocessed.source, kind, &name, entry_point, Some(&options)) {
+            Ok(artifact) => {
+                if artifact.get_num_warnings() > 0 {
+                    log::warn!("{}", artifact.get_warning_messages());
+                }
+                Ok(artifact.as_binary().to_vec())
+            }
+            Err(shaderc::Error::CompilationError(_, text)) => Err(CompileError::Compile(parse_glslang_messages(preprocessed, &name, &text))),
+            Err(e) => Err(CompileError::Compile(vec![unlocated(preprocessed, e.to_string())]))
+        }
+    }
+
+    #[cfg(not(feature = "shaderc"))]
+    fn compile_shaderc(&mut self, _: &PreprocessedSource, language: ShaderLanguage, _: ShaderStage, _: &str) -> CompileResult<Vec<u32>> {
+        match language {
+            ShaderLanguage::Hlsl => Err(CompileError::UnsupportedLanguage { language, feature: "shaderc" }),
+            _ => Err(CompileError::NoBackend)
+        }
+    }
+}
+
+fn unlocated(preprocessed: &PreprocessedSource, message: String) -> Diagnostic {
+    Diagnostic { file: preprocessed.files[0].clone(), line: 0, column: None, message }
+}
+
+/// Without `name` the only entry point of `stage`, or `main` if there are several
+fn find_entry_point(path: &Path, spirv: &[u32], stage: ShaderStage, name: Option<&str>) -> CompileResult<String> {
+
+    let entry_points = spirv_entry_points(spirv).ok_or_else(|| CompileError::InvalidSpirv(path.to_path_buf()))?;
+    let mut matching = entry_points.into_iter().filter(|(x, _)| *x == stage).map(|(_, x)| x).collect::<Vec<_>>();
+
+    let found = match name {
+        Some(name) => matching.into_iter().find(|x| x == name),
+        None if matching.len() == 1 => matching.pop(),
+        None => matching.into_iter().find(|x| x == "main")
+    };
+
+    found.ok_or_else(|| CompileError::EntryPointNotFound { path: path.to_path_buf(), stage, name: name.map(str::to_owned) })
+}
+
+#[cfg(feature = "naga")]
+fn located(preprocessed: &PreprocessedSource, location: Option<naga::SourceLocation>, message: String) -> Diagnostic {
+    match location {
+        Some(location) => {
+            let (file, line) = preprocessed.location(location.line_number);
+            Diagnostic { file: file.to_path_buf(), line, column: Some(location.line_position), message }
+        }
+        None => unlocated(preprocessed, message)
+    }
+}
+
+#[cfg(feature = "naga")]
+fn naga_stage(stage: ShaderStage) -> CompileResult<naga::ShaderStage> {
+    match stage {
+        ShaderStage::Vertex => Ok(naga::ShaderStage::Vertex),
+        ShaderStage::Fragment => Ok(naga::ShaderStage::Fragment),
+        ShaderStage::Compute => Ok(naga::ShaderStage::Compute),
+        _ => Err(CompileError::UnsupportedStage { backend: Backend::Naga.name(), stage })
+    }
+}
+
+#[cfg(feature = "naga")]
+fn write_naga(preprocessed: &PreprocessedSource, module: &naga::Module, options: &naga::back::spv::Options, pipeline: Option<&naga::back::spv::PipelineOptions>) -> CompileResult<Vec<u32>> {
+
+    use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(module)
+        .map_err(|e| {
+            let location = e.location(&preprocessed.source);
+            let error = e.into_inner();
+            let mut message = error.to_string();
+            let mut cause = std::error::Error::source(&error);
+
+            while let Some(x) = cause {
+                message += &format!(": {}", x);
+                cause = x.source();
+            }
+
+            CompileError::Compile(vec![located(preprocessed, location, message)])
+        })?;
+
+    naga::back::spv::write_vec(module, &info, options, pipeline)
+        .map_err(|e| CompileError::Compile(vec![unlocated(preprocessed, e.to_string())]))
+}
+
+#[cfg(feature = "naga")]
+fn compile_naga(preprocessed: &PreprocessedSource, stage: ShaderStage) -> CompileResult<Vec<u32>> {
+
+    use naga::front::glsl;
+
+    let source = &preprocessed.source;
+
+    let module = glsl::Frontend::default()
+        .parse(&glsl::Options::from(naga_stage(stage)?), source)
+        .map_err(|e| CompileError::Compile(e.errors.iter()
+            .map(|x| located(preprocessed, x.meta.is_defined().then(|| x.meta.location(source)), x.kind.to_string()))
+            .collect()))?;
+
+    let mut options = naga::back::spv::Options::default();
+    // GLSL уже написан под Vulkan, переворачивать Y не нужно
+    options.flags.remove(naga::back::spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+
+    write_naga(preprocessed, &module, &options, None)
+}
+
+#[cfg(feature = "naga")]
+fn compile_wgsl(preprocessed: &PreprocessedSource, stage: ShaderStage, entry_point: Option<&str>) -> CompileResult<Vec<u32>> {
+
+    let source = &preprocessed.source;
+
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| CompileError::Compile(vec![located(preprocessed, e.location(source), e.message().to_owned())]))?;
+
+    let shader_stage = naga_stage(stage)?;
+    let mut matching = module.entry_points.iter().filter(|x| x.stage == shader_stage).map(|x| x.name.as_str());
+
+    let found = match entry_point {
+        Some(name) => matching.find(|x| *x == name),
+        None => matching.next().filter(|_| matching.next().is_none())
+    };
+
+    let entry_point = found.ok_or_else(|| CompileError::EntryPointNotFound {
+        path: preprocessed.files[0].clone(),
+        stage,
+        name: entry_point.map(str::to_owned)
+    })?;
+
+    // Только выбранная точка входа, Y переворачивается как в wgpu
+    let pipeline = naga::back::spv::PipelineOptions { shader_stage, entry_point: entry_point.to_owned() };
+    write_naga(preprocessed, &module, &naga::back::spv::Options::default(), Some(&pipeline))
+}
+
+#[cfg(not(feature = "naga"))]
+fn compile_naga(_: &PreprocessedSource, _: ShaderStage) -> CompileResult<Vec<u32>> {
+    Err(CompileError::NoBackend)
+}
+
+#[cfg(not(feature = "naga"))]
+fn compile_wgsl(_: &PreprocessedSource, _: ShaderStage, _: Option<&str>) -> CompileResult<Vec<u32>> {
+    Err(CompileError::UnsupportedLanguage { language: ShaderLanguage::Wgsl, feature: "naga" })
+}
+
+/// `name:12: error: 'x' : undeclared identifier` to diagnostics in the original files
+#[cfg(any(feature = "shaderc", test))]
+fn parse_glslang_messages(preprocessed: &PreprocessedSource, name: &str, text: &str) -> Vec<Diagnostic> {
+    text.lines()
+        .filter(|x| !x.trim().is_empty())
+        .filter(|x| !x.trim_end().ends_with("generated."))
+        .map(|text| {
+            let located = text.strip_prefix(name)
+                .and_then(|x| x.strip_prefix(':'))
+                .and_then(|x| x.split_once(':'))
+                .and_then(|(line, message)| Some((line.parse::<u32>().ok()?, message.trim())));
+
+            match located {
+                Some((line, message)) => {
+                    let (file, line) = preprocessed.location(line);
+                    Diagnostic { file: file.to_path_buf(), line, column: None, message: message.to_owned() }
+                }
+                None => unlocated(preprocessed, text.trim().to_owned())
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ferrum-shaders-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_glslang_messages_are_mapped() {
+
+        let dir = temp_dir("glslang");
+        std::fs::write(dir.join("common.glsl"), "float f() { return x; }\n").unwrap();
+
+        let preprocessed = Preprocessor::new()
+            .process(&dir.join("a.frag"), "#version 450\n#include \"common.glsl\"\nvoid main() {}\n", &ShaderDefines::new())
+            .unwrap();
+
+        let name = dir.join("a.frag").display().to_string();
+        let text = format!("{}:3: error: 'x' : undeclared identifier\n1 error generated.\n", name);
+        let messages = parse_glslang_messages(&preprocessed, &name, &text);
+
+        assert_eq!(messages, vec![Diagnostic {
+            file: dir.join("common.glsl"),
+            line: 1,
+            column: None,
+            message: "error: 'x' : undeclared identifier".to_owned()
+        }]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "naga")]
+    #[test]
+    fn test_naga_compile_errors_and_cache() {
+
+        let dir = temp_dir("naga");
+        std::fs::write(dir.join("color.glsl"), "vec4 color() {\n    return vec4(COLOR, 1.0);\n}\n").unwrap();
+
+        let source = "#version 450\n#include \"color.glsl\"\nlayout(location = 0) out vec4 outColor;\nvoid main() { outColor = color(); }\n";
+
+        let mut compiler = ShaderCompiler::new()
+            .with_backend(Backend::Naga)
+            .with_cache_dir(dir.join("cache"));
+
+        let defines = ShaderDefines::new().with("COLOR", "vec3(1.0, 0.0, 0.0)");
+        let shader = compiler.compile_source(&dir.join("a.frag"), source, ShaderStage::Fragment, &defines).unwrap();
+        assert_eq!(shader.spirv[0], 0x0723_0203);
+        assert!(!shader.cached);
+        assert_eq!(shader.dependencies, vec![dir.join("a.frag"), dir.join("color.glsl")]);
+
+        let cached = compiler.compile_source(&dir.join("a.frag"), source, ShaderStage::Fragment, &defines).unwrap();
+        assert!(cached.cached);
+        assert_eq!(cached.spirv, shader.spirv);
+
+        // Без COLOR ошибка во включённом файле
+        let error = compiler.compile_source(&dir.join("a.frag"), source, ShaderStage::Fragment, &ShaderDefines::new());
+        let Err(CompileError::Compile(diagnostics)) = error else { panic!("expected a compile error") };
+        assert_eq!(diagnostics[0].file, dir.join("color.glsl"));
+        assert_eq!(diagnostics[0].line, 2);
+
+        let geometry = compiler.compile_source(&dir.join("a.geom"), source, ShaderStage::Geometry, &defines);
+        assert!(matches!(geometry, Err(CompileError::UnsupportedStage { stage: ShaderStage::Geometry, .. })));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "naga")]
+    #[test]
+    fn test_wgsl_entry_points() {
+
+        let source = ShaderSource::wgsl("
+            @vertex fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+                return vec4<f32>(f32(i), 0.0, 0.0, 1.0);
+            }
+            @fragment fn fs_main() -> @location(0) vec4<f32> { return vec4<f32>(1.0); }
+            @fragment fn fs_debug() -> @location(0) vec4<f32> { return vec4<f32>(0.5); }
+        ");
+
+        let mut compiler = ShaderCompiler::new();
+
+        let vertex = compiler.compile(&source, ShaderStage::Vertex).unwrap();
+        assert_eq!(vertex.entry_point, "vs_main");
+        assert!(vertex.dependencies.is_empty());
+
+        let fragment = compiler.compile(&source.clone().with_entry_point("fs_debug"), ShaderStage::Fragment).unwrap();
+        assert_eq!(fragment.entry_point, "fs_debug");
+        assert_eq!(spirv_entry_points(&fragment.spirv).unwrap().len(), 1);
+
+        // Две фрагментные точки входа, без имени не выбрать
+        let ambiguous = compiler.compile(&source, ShaderStage::Fragment);
+        assert!(matches!(ambiguous, Err(CompileError::EntryPointNotFound { name: None, .. })));
+
+        let defines = compiler.compile(&source.clone().with_defines(ShaderDefines::new().with_flag("X")), ShaderStage::Vertex);
+        assert!(matches!(defines, Err(CompileError::DefinesNotSupported(ShaderLanguage::Wgsl))));
+
+        let spirv = ShaderSource::spirv(vertex.spirv.clone()).with_entry_point("vs_main");
+        assert_eq!(compiler.compile(&spirv, ShaderStage::Vertex).unwrap().entry_point, "vs_main");
+        assert!(compiler.compile(&spirv, ShaderStage::Fragment).is_err());
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::{ShaderLanguage, ShaderStage};
+
+/// One compiler message mapped back to the original file, through `#include`s
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    /// 1-based, 0 when the compiler did not report a location
+    pub line: u32,
+    pub column: Option<u32>,
+    pub message: String
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.file.display())?;
+
+        if self.line > 0 {
+            write!(f, ":{}", self.line)?;
+        }
+
+        if let Some(column) = self.column {
+            write!(f, ":{}", column)?;
+        }
+
+        write!(f, ": {}", self.message)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CompileError {
+    #[error("Failed to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error
+    },
+    #[error("Unknown shader stage of {0}, expected an extension like .vert, .frag, .comp")]
+    UnknownStage(PathBuf),
+    #[error("{file}:{line}: include \"{name}\" not found")]
+    IncludeNotFound {
+        file: PathBuf,
+        line: u32,
+        name: String
+    },
+    #[error("{file}:{line}: recursive include of {name}")]
+    IncludeCycle {
+        file: PathBuf,
+        line: u32,
+        name: PathBuf
+    },
+    #[error("{backend} can not compile {stage} shaders, enable the `shaderc` feature")]
+    UnsupportedStage {
+        backend: &'static str,
+        stage: ShaderStage
+    },
+    #[error("{language} needs the `{feature}` feature")]
+    UnsupportedLanguage {
+        language: ShaderLanguage,
+        feature: &'static str
+    },
+    #[error("{0} has no preprocessor, defines are not supported")]
+    DefinesNotSupported(ShaderLanguage),
+    #[error("{path}: {}", match name {
+        Some(name) => format!("no {} entry point named \"{}\"", stage, name),
+        None => format!("no single {} entry point, choose one with `with_entry_point`", stage)
+    })]
+    EntryPointNotFound {
+        path: PathBuf,
+        stage: ShaderStage,
+        name: Option<String>
+    },
+    #[error("{0} is not SPIR-V")]
+    InvalidSpirv(PathBuf),
+    #[error("{}", .0.iter().map(|x| x.to_string()).collect::<Vec<_>>().join("\n"))]
+    Compile(Vec<Diagnostic>),
+    #[error("No shader compiler backend, enable the `naga` or `shaderc` feature")]
+    NoBackend
+}
+
+pub type CompileResult<T> = Result<T, CompileError>;
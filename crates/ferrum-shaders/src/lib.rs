@@ -0,0 +1,19 @@
+pub(crate) mod stage;
+pub(crate) mod errors;
+pub(crate) mod source;
+pub(crate) mod preprocessor;
+pub(crate) mod cache;
+pub(crate) mod compiler;
+pub(crate) mod shader_build;
+#[cfg(feature = "watch")]
+pub(crate) mod watcher;
+
+pub use stage::*;
+pub use errors::*;
+pub use source::*;
+pub use preprocessor::*;
+pub use cache::*;
+pub use compiler::*;
+pub use shader_build::*;
+#[cfg(feature = "watch")]
+pub use watcher::*;
@@ -0,0 +1,280 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{CompileError, CompileResult};
+
+///
+/// Macro values of one shader permutation, injected right after `#version`.
+/// Sorted so that equal sets give equal sources and cache keys
+///
+/// # Example
+/// ```
+/// # use ferrum_shaders::ShaderDefines;
+/// let defines = ShaderDefines::new()
+///     .with("SHADOWS", "1")
+///     .with_flag("ALPHA_TEST");
+/// ```
+///
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ShaderDefines {
+    values: BTreeMap<String, String>
+}
+
+impl ShaderDefines {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with(mut self, name: &str, value: &str) -> Self {
+        self.values.insert(name.to_owned(), value.to_owned());
+        self
+    }
+
+    /// `#define NAME` without a value
+    pub fn with_flag(self, name: &str) -> Self {
+        self.with(name, "")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+/// Source with resolved includes, every line remembers where it came from
+#[derive(Debug, Clone)]
+pub struct PreprocessedSource {
+    pub source: String,
+    /// Root file first, then every included file
+    pub files: Vec<PathBuf>,
+    /// (index in `files`, 1-based line) of each line of `source`
+    lines: Vec<(usize, u32)>
+}
+
+impl PreprocessedSource {
+
+    /// Original file and line of a 1-based line of [`PreprocessedSource::source`]
+    pub fn location(&self, line: u32) -> (&Path, u32) {
+        match self.lines.get((line as usize).wrapping_sub(1)) {
+            Some((file, line)) => (&self.files[*file], *line),
+            None => (&self.files[0], 0)
+        }
+    }
+}
+
+/// Resolves `#include "file"` (next to the including file first) and `#include <file>` (include dirs only)
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessor {
+    include_dirs: Vec<PathBuf>
+}
+
+struct State {
+    out: PreprocessedSource,
+    stack: Vec<PathBuf>,
+    once: HashSet<PathBuf>
+}
+
+/// One `#include` directive, `from` is the index of the including file
+struct Include<'a> {
+    from: usize,
+    line: u32,
+    name: &'a str,
+    system: bool
+}
+
+impl Preprocessor {
+
+    pub fn new() -> Self {
+        Self { ..Default::default() }
+    }
+
+    pub fn with_include_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.include_dirs.push(dir.into());
+        self
+    }
+
+    pub fn process_file(&self, path: &Path, defines: &ShaderDefines) -> CompileResult<PreprocessedSource> {
+        let source = read(path)?;
+        self.process(path, &source, defines)
+    }
+
+    /// `path` is only used for relative includes and error locations
+    pub fn process(&self, path: &Path, source: &str, defines: &ShaderDefines) -> CompileResult<PreprocessedSource> {
+
+        let mut state = State {
+            out: PreprocessedSource { source: String::new(), files: vec![path.to_path_buf()], lines: vec![] },
+            stack: vec![canonical(path)],
+            once: HashSet::new()
+        };
+
+        let has_version = source.lines().any(|x| x.trim_start().starts_with("#version"));
+
+        if !has_version {
+            push_defines(&mut state.out, defines, 0);
+        }
+
+        self.process_lines(&mut state, 0, source, Some(defines).filter(|_| has_version))?;
+        Ok(state.out)
+    }
+
+    fn process_lines(&self, state: &mut State, file: usize, source: &str, mut defines: Option<&ShaderDefines>) -> CompileResult<()> {
+
+        for (index, text) in source.lines().enumerate() {
+            let line = index as u32 + 1;
+            let trimmed = text.trim_start();
+
+            if let Some(rest) = directive(trimmed, "include") {
+                let (name, system) = parse_include(rest).ok_or_else(|| CompileError::IncludeNotFound {
+                    file: state.out.files[file].clone(),
+                    line,
+                    name: rest.trim().to_owned()
+                })?;
+
+                // Сохраняем нумерацию строк текущего файла
+                push_line(&mut state.out, "", file, line);
+                self.include(state, Include { from: file, line, name, system })?;
+                continue;
+            }
+
+            if directive(trimmed, "pragma").is_some_and(|x| x.trim() == "once") {
+                state.once.insert(state.stack.last().unwrap().clone());
+                push_line(&mut state.out, "", file, line);
+                continue;
+            }
+
+            // Includes are already resolved, naga does not know the extension
+            if directive(trimmed, "extension").is_some_and(|x| x.contains("GL_GOOGLE_include_directive")) {
+                push_line(&mut state.out, "", file, line);
+                continue;
+            }
+
+            push_line(&mut state.out, text, file, line);
+
+            if directive(trimmed, "version").is_some()
+                && let Some(defines) = defines.take() {
+                push_defines(&mut state.out, defines, line);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn include(&self, state: &mut State, include: Include) -> CompileResult<()> {
+
+        let Include { from, line, name, system } = include;
+        let current = state.out.files[from].clone();
+        let local = (!system).then(|| current.parent().unwrap_or(Path::new("")).join(name));
+
+        let path = local.into_iter()
+            .chain(self.include_dirs.iter().map(|x| x.join(name)))
+            .find(|x| x.is_file())
+            .ok_or_else(|| CompileError::IncludeNotFound { file: current.clone(), line, name: name.to_owned() })?;
+
+        let key = canonical(&path);
+
+        if state.once.contains(&key) {
+            return Ok(());
+        }
+
+        if state.stack.contains(&key) {
+            return Err(CompileError::IncludeCycle { file: current, line, name: path });
+        }
+
+        let source = read(&path)?;
+        let index = match state.out.files.iter().position(|x| canonical(x) == key) {
+            Some(index) => index,
+            None => {
+                state.out.files.push(path);
+                state.out.files.len() - 1
+            }
+        };
+
+        state.stack.push(key);
+        self.process_lines(state, index, &source, None)?;
+        state.stack.pop();
+
+        Ok(())
+    }
+}
+
+fn read(path: &Path) -> CompileResult<String> {
+    fs::read_to_string(path).map_err(|source| CompileError::Io { path: path.to_path_buf(), source })
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn push_line(out: &mut PreprocessedSource, text: &str, file: usize, line: u32) {
+    out.source.push_str(text);
+    out.source.push('\n');
+    out.lines.push((file, line));
+}
+
+fn push_defines(out: &mut PreprocessedSource, defines: &ShaderDefines, line: u32) {
+    for (name, value) in defines.iter() {
+        push_line(out, &format!("#define {} {}", name, value), 0, line);
+    }
+}
+
+/// `# include "x"` is valid too
+fn directive<'a>(line: &'a str, name: &str) -> Option<&'a str> {
+    line.strip_prefix('#')?.trim_start().strip_prefix(name)
+}
+
+fn parse_include(rest: &str) -> Option<(&str, bool)> {
+    let rest = rest.trim();
+
+    if let Some(name) = rest.strip_prefix('"') {
+        return name.strip_suffix('"').map(|x| (x, false));
+    }
+
+    rest.strip_prefix('<')?.strip_suffix('>').map(|x| (x, true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_includes_and_defines() {
+
+        let dir = std::env::temp_dir().join(format!("ferrum-shaders-pp-{}", std::process::id()));
+        fs::create_dir_all(dir.join("include")).unwrap();
+        fs::write(dir.join("common.glsl"), "#pragma once\nfloat common() { return 1.0; }\n").unwrap();
+        fs::write(dir.join("include/lighting.glsl"), "#include \"../common.glsl\"\nfloat light() { return 2.0; }\n").unwrap();
+
+        let source = "#version 450\n#include \"common.glsl\"\n#include <lighting.glsl>\nvoid main() {}\n";
+
+        let out = Preprocessor::new()
+            .with_include_dir(dir.join("include"))
+            .process(&dir.join("main.frag"), source, &ShaderDefines::new().with("SHADOWS", "1"))
+            .unwrap();
+
+        // common.glsl подключается один раз
+        assert_eq!(out.source.matches("float common()").count(), 1);
+        assert!(out.source.starts_with("#version 450\n#define SHADOWS 1\n"));
+        assert_eq!(out.files.len(), 3);
+
+        let line = out.source.lines().position(|x| x.contains("float light()")).unwrap() as u32 + 1;
+        assert_eq!(out.location(line), (dir.join("include").join("lighting.glsl").as_path(), 2));
+
+        let line = out.source.lines().position(|x| x.contains("void main")).unwrap() as u32 + 1;
+        assert_eq!(out.location(line), (dir.join("main.frag").as_path(), 4));
+
+        let missing = Preprocessor::new().process(&dir.join("main.frag"), "#include \"nope.glsl\"\n", &ShaderDefines::new());
+        assert!(matches!(missing, Err(CompileError::IncludeNotFound { line: 1, .. })));
+
+        fs::write(dir.join("a.glsl"), "#include \"b.glsl\"\n").unwrap();
+        fs::write(dir.join("b.glsl"), "#include \"a.glsl\"\n").unwrap();
+        let cycle = Preprocessor::new().process_file(&dir.join("a.glsl"), &ShaderDefines::new());
+        assert!(matches!(cycle, Err(CompileError::IncludeCycle { .. })));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{Backend, CompileError, ShaderCompiler, ShaderDefines, ShaderSource, ShaderStage};
+
+struct Permutation {
+    file: String,
+    suffix: String,
+    defines: ShaderDefines
+}
+
+struct Entry {
+    file: String,
+    stage: ShaderStage,
+    entry_point: String
+}
+
+///
+/// Compiles every `<name>.<stage>` file of a directory to `<name>-<stage>.spv`, for build scripts.
+/// Files with other extensions (`.glsl`) are only included, WGSL and HLSL entry points are listed explicitly.
+/// Outputs are written only when changed
+///
+/// # Example
+/// ```no_run
+/// # use ferrum_shaders::*;
+/// // build.rs
+/// fn main() {
+///     let result = ShaderBuild::new("shared/shaders", "shared/shaders/spv")
+///         .with_include_dir("shared/shaders/include")
+///         .with_permutation("triangle.frag", "untextured", ShaderDefines::new().with_flag("NO_TEXTURE"))
+///         .with_entry_point("sky.wgsl", ShaderStage::Vertex, "vs_main")
+///         .with_entry_point("sky.wgsl", ShaderStage::Fragment, "fs_main")
+///         .run();
+///
+///     if let Err(errors) = result {
+///         panic!("{}", errors.iter().map(|x| x.to_string()).collect::<Vec<_>>().join("\n"));
+///     }
+/// }
+/// ```
+///
+pub struct ShaderBuild {
+    src_dir: PathBuf,
+    out_dir: PathBuf,
+    compiler: ShaderCompiler,
+    permutations: Vec<Permutation>,
+    entries: Vec<Entry>
+}
+
+impl ShaderBuild {
+
+    /// Uses `$OUT_DIR/shader-cache` as [`crate::SpirvCache`] inside build scripts
+    pub fn new(src_dir: impl Into<PathBuf>, out_dir: impl Into<PathBuf>) -> Self {
+
+        let mut compiler = ShaderCompiler::new();
+
+        if let Some(dir) = std::env::var_os("OUT_DIR") {
+            compiler = compiler.with_cache_dir(Path::new(&dir).join("shader-cache"));
+        }
+
+        Self { src_dir: src_dir.into(), out_dir: out_dir.into(), compiler, permutations: vec![], entries: vec![] }
+    }
+
+    pub fn with_backend(mut self, backend: Backend) -> Self {
+        self.compiler = self.compiler.with_backend(backend);
+        self
+    }
+
+    pub fn with_include_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.compiler = self.compiler.with_include_dir(dir);
+        self
+    }
+
+    pub fn with_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.compiler = self.compiler.with_cache_dir(dir);
+        self
+    }
+
+    /// Additional variant of `file` written to `<name>-<stage>-<suffix>.spv`
+    pub fn with_permutation(mut self, file: &str, suffix: &str, defines: ShaderDefines) -> Self {
+        self.permutations.push(Permutation { file: file.to_owned(), suffix: suffix.to_owned(), defines });
+        self
+    }
+
+    /// `entry_point` of a WGSL, HLSL or GLSL `file` written to `<name>-<entry_point>.spv`
+    pub fn with_entry_point(mut self, file: &str, stage: ShaderStage, entry_point: &str) -> Self {
+        self.entries.push(Entry { file: file.to_owned(), stage, entry_point: entry_point.to_owned() });
+        self
+    }
+
+    /// Written `.spv` files, or every error of every shader
+    pub fn run(mut self) -> Result<Vec<PathBuf>, Vec<CompileError>> {
+
+        let in_build_script = std::env::var_os("OUT_DIR").is_some();
+
+        if in_build_script {
+            println!("cargo:rerun-if-changed={}", self.src_dir.display());
+        }
+
+        let entries = fs::read_dir(&self.src_dir)
+            .map_err(|source| vec![CompileError::Io { path: self.src_dir.clone(), source }])?;
+
+        let mut sources = entries
+            .filter_map(|x| x.ok().map(|x| x.path()))
+            .filter(|x| x.is_file() && ShaderStage::from_path(x).is_some())
+            .collect::<Vec<_>>();
+
+        sources.sort();
+
+        fs::create_dir_all(&self.out_dir)
+            .map_err(|source| vec![CompileError::Io { path: self.out_dir.clone(), source }])?;
+
+        let mut written = vec![];
+        let mut errors = vec![];
+        let mut dependencies = BTreeSet::new();
+
+        for path in &sources {
+
+            let file_name = path.file_name().and_then(|x| x.to_str()).unwrap_or_default();
+            let stem = path.file_stem().and_then(|x| x.to_str()).unwrap_or_default();
+            let stage = ShaderStage::from_path(path).unwrap();
+
+            let variants = std::iter::once((String::new(), ShaderDefines::new()))
+                .chain(self.permutations.iter()
+                    .filter(|x| x.file == file_name)
+                    .map(|x| (format!("-{}", x.suffix), x.defines.clone())));
+
+            for (suffix, defines) in variants {
+                let out = self.out_dir.join(format!("{}-{}{}.spv", stem, stage, suffix));
+
+                match self.compiler.compile_file(path, &defines) {
+                    Ok(shader) => {
+                        dependencies.extend(shader.dependencies);
+                        write_if_changed(&out, &shader.spirv, &mut written, &mut errors);
+                    }
+                    Err(e) => {
+                        dependencies.insert(path.clone());
+                        errors.push(e);
+                    }
+                }
+            }
+        }
+
+        for entry in &self.entries {
+
+            let path = self.src_dir.join(&entry.file);
+            let stem = path.file_stem().and_then(|x| x.to_str()).unwrap_or_default();
+            let out = self.out_dir.join(format!("{}-{}.spv", stem, entry.entry_point));
+            let source = ShaderSource::file(&path).with_entry_point(&entry.entry_point);
+
+            match self.compiler.compile(&source, entry.stage) {
+                Ok(shader) => {
+                    dependencies.extend(shader.dependencies);
+                    write_if_changed(&out, &shader.spirv, &mut written, &mut errors);
+                }
+                Err(e) => {
+                    dependencies.insert(path);
+                    errors.push(e);
+                }
+            }
+        }
+
+        if in_build_script {
+            for path in dependencies {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(written)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn write_if_changed(out: &Path, spirv: &[u32], written: &mut Vec<PathBuf>, errors: &mut Vec<CompileError>) {
+
+    let bytes = spirv.iter().flat_map(|x| x.to_le_bytes()).collect::<Vec<u8>>();
+
+    // Не трогаем файл, если ничего не изменилось, иначе include_bytes! пересоберёт всё
+    if fs::read(out).ok().as_deref() != Some(bytes.as_slice())
+        && let Err(source) = fs::write(out, bytes) {
+        errors.push(CompileError::Io { path: out.to_path_buf(), source });
+        return;
+    }
+
+    written.push(out.to_path_buf());
+}
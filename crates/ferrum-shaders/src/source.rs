@@ -0,0 +1,225 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::{CompileError, CompileResult, ShaderDefines, ShaderStage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderLanguage {
+    /// Always starts at `main`
+    Glsl,
+    /// naga, several entry points per file
+    Wgsl,
+    /// glslang through shaderc
+    Hlsl,
+    /// Already compiled, only the entry point is looked up
+    SpirV
+}
+
+impl ShaderLanguage {
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ShaderLanguage::Glsl => "GLSL",
+            ShaderLanguage::Wgsl => "WGSL",
+            ShaderLanguage::Hlsl => "HLSL",
+            ShaderLanguage::SpirV => "SPIR-V"
+        }
+    }
+
+    /// `.wgsl`, `.hlsl`, `.spv`, everything else is GLSL
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("wgsl") => ShaderLanguage::Wgsl,
+            Some("hlsl") => ShaderLanguage::Hlsl,
+            Some("spv") => ShaderLanguage::SpirV,
+            _ => ShaderLanguage::Glsl
+        }
+    }
+}
+
+impl fmt::Display for ShaderLanguage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+///
+/// Shader code in any supported language, compiled by [`crate::ShaderCompiler::compile`].
+/// The stage is given when compiling, one WGSL or HLSL file can hold every stage
+///
+/// # Example
+/// ```
+/// # use ferrum_shaders::*;
+/// let vertex = ShaderSource::file("shared/shaders/triangle.wgsl").with_entry_point("vs_main");
+/// let fragment = ShaderSource::hlsl("float4 PSMain() : SV_Target { return 1.0; }").with_entry_point("PSMain");
+/// let compute = ShaderSource::glsl("#version 450\nvoid main() {}").with_defines(ShaderDefines::new().with_flag("FAST"));
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct ShaderSource {
+    path: Option<PathBuf>,
+    code: Option<String>,
+    spirv: Option<Vec<u32>>,
+    language: ShaderLanguage,
+    entry_point: Option<String>,
+    defines: ShaderDefines
+}
+
+impl ShaderSource {
+
+    fn new(language: ShaderLanguage) -> Self {
+        Self { path: None, code: None, spirv: None, language, entry_point: None, defines: ShaderDefines::new() }
+    }
+
+    /// Language from the extension, see [`ShaderLanguage::from_path`]
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self { language: ShaderLanguage::from_path(&path), path: Some(path), ..Self::new(ShaderLanguage::Glsl) }
+    }
+
+    pub fn glsl(code: impl Into<String>) -> Self {
+        Self { code: Some(code.into()), ..Self::new(ShaderLanguage::Glsl) }
+    }
+
+    pub fn wgsl(code: impl Into<String>) -> Self {
+        Self { code: Some(code.into()), ..Self::new(ShaderLanguage::Wgsl) }
+    }
+
+    pub fn hlsl(code: impl Into<String>) -> Self {
+        Self { code: Some(code.into()), ..Self::new(ShaderLanguage::Hlsl) }
+    }
+
+    pub fn spirv(words: Vec<u32>) -> Self {
+        Self { spirv: Some(words), ..Self::new(ShaderLanguage::SpirV) }
+    }
+
+    /// Overrides the language guessed from the extension
+    pub fn with_language(mut self, language: ShaderLanguage) -> Self {
+        self.language = language;
+        self
+    }
+
+    /// Default: the only entry point of the stage, `main` for GLSL and HLSL
+    pub fn with_entry_point(mut self, name: &str) -> Self {
+        self.entry_point = Some(name.to_owned());
+        self
+    }
+
+    /// Not supported by WGSL, it has no preprocessor
+    pub fn with_defines(mut self, defines: ShaderDefines) -> Self {
+        self.defines = defines;
+        self
+    }
+
+    /// Name in errors and base of relative includes of inline code, the file is not read
+    pub fn with_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn language(&self) -> ShaderLanguage {
+        self.language
+    }
+
+    pub fn entry_point(&self) -> Option<&str> {
+        self.entry_point.as_deref()
+    }
+
+    pub fn defines(&self) -> &ShaderDefines {
+        &self.defines
+    }
+
+    /// File of the source, `None` for inline code without [`ShaderSource::with_path`]
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Path or `<wgsl>` for error messages
+    pub(crate) fn display_path(&self) -> PathBuf {
+        self.path.clone().unwrap_or_else(|| PathBuf::from(format!("<{}>", self.language.name().to_lowercase())))
+    }
+
+    pub(crate) fn read_code(&self) -> CompileResult<String> {
+        match (&self.code, &self.path) {
+            (Some(code), _) => Ok(code.clone()),
+            (None, Some(path)) => std::fs::read_to_string(path).map_err(|source| CompileError::Io { path: path.clone(), source }),
+            (None, None) => Ok(String::new())
+        }
+    }
+
+    pub(crate) fn read_spirv(&self) -> CompileResult<Vec<u32>> {
+
+        if let Some(words) = &self.spirv {
+            return Ok(words.clone());
+        }
+
+        let path = self.display_path();
+        let bytes = std::fs::read(&path).map_err(|source| CompileError::Io { path: path.clone(), source })?;
+
+        if bytes.len() % 4 != 0 || bytes.len() < 20 {
+            return Err(CompileError::InvalidSpirv(path));
+        }
+
+        Ok(bytes.chunks_exact(4).map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]])).collect())
+    }
+}
+
+/// (stage, name) of every `OpEntryPoint`, `None` if this is not SPIR-V
+pub fn spirv_entry_points(spirv: &[u32]) -> Option<Vec<(ShaderStage, String)>> {
+
+    const MAGIC: u32 = 0x0723_0203;
+    const OP_ENTRY_POINT: u32 = 15;
+
+    if spirv.len() < 5 || spirv[0] != MAGIC {
+        return None;
+    }
+
+    let mut entry_points = vec![];
+    let mut words = &spirv[5..];
+
+    while let Some(&first) = words.first() {
+        let count = (first >> 16) as usize;
+
+        if count == 0 || count > words.len() {
+            return None;
+        }
+
+        if first & 0xffff == OP_ENTRY_POINT && count > 3 {
+            // Строка в словах little-endian, заканчивается нулём
+            let bytes = words[3..count].iter().flat_map(|x| x.to_le_bytes()).take_while(|x| *x != 0).collect::<Vec<u8>>();
+
+            if let Some(stage) = ShaderStage::from_execution_model(words[1]) {
+                entry_points.push((stage, String::from_utf8_lossy(&bytes).into_owned()));
+            }
+        }
+
+        words = &words[count..];
+    }
+
+    Some(entry_points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spirv_entry_points() {
+
+        // OpEntryPoint Vertex %1 "vs_main", OpEntryPoint Fragment %2 "main"
+        let spirv = [
+            0x0723_0203, 0x0001_0000, 0, 3, 0,
+            6 << 16 | 15, 0, 1, u32::from_le_bytes(*b"vs_m"), u32::from_le_bytes(*b"ain\0"), 0,
+            5 << 16 | 15, 4, 2, u32::from_le_bytes(*b"main"), 0
+        ];
+
+        assert_eq!(spirv_entry_points(&spirv), Some(vec![
+            (ShaderStage::Vertex, "vs_main".to_owned()),
+            (ShaderStage::Fragment, "main".to_owned())
+        ]));
+
+        assert_eq!(spirv_entry_points(&[1, 2, 3]), None);
+        assert_eq!(ShaderLanguage::from_path(Path::new("a.wgsl")), ShaderLanguage::Wgsl);
+        assert_eq!(ShaderLanguage::from_path(Path::new("a.frag")), ShaderLanguage::Glsl);
+    }
+}
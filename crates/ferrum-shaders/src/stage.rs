@@ -0,0 +1,116 @@
+use std::fmt;
+use std::path::Path;
+
+use ash::vk;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+    Compute,
+    Geometry,
+    TessControl,
+    TessEvaluation,
+    Task,
+    Mesh,
+    RayGen,
+    AnyHit,
+    ClosestHit,
+    Miss,
+    Intersection,
+    Callable
+}
+
+impl ShaderStage {
+
+    pub const ALL: [ShaderStage; 14] = [
+        ShaderStage::Vertex,
+        ShaderStage::Fragment,
+        ShaderStage::Compute,
+        ShaderStage::Geometry,
+        ShaderStage::TessControl,
+        ShaderStage::TessEvaluation,
+        ShaderStage::Task,
+        ShaderStage::Mesh,
+        ShaderStage::RayGen,
+        ShaderStage::AnyHit,
+        ShaderStage::ClosestHit,
+        ShaderStage::Miss,
+        ShaderStage::Intersection,
+        ShaderStage::Callable
+    ];
+
+    /// File extension used by glslc, `triangle.vert`, `shadow.rgen`
+    pub fn extension(self) -> &'static str {
+        match self {
+            ShaderStage::Vertex => "vert",
+            ShaderStage::Fragment => "frag",
+            ShaderStage::Compute => "comp",
+            ShaderStage::Geometry => "geom",
+            ShaderStage::TessControl => "tesc",
+            ShaderStage::TessEvaluation => "tese",
+            ShaderStage::Task => "task",
+            ShaderStage::Mesh => "mesh",
+            ShaderStage::RayGen => "rgen",
+            ShaderStage::AnyHit => "rahit",
+            ShaderStage::ClosestHit => "rchit",
+            ShaderStage::Miss => "rmiss",
+            ShaderStage::Intersection => "rint",
+            ShaderStage::Callable => "rcall"
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.extension() == extension)
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str().and_then(Self::from_extension)
+    }
+
+    /// Stage of a SPIR-V `OpEntryPoint` execution model
+    pub fn from_execution_model(model: u32) -> Option<Self> {
+        Some(match model {
+            0 => ShaderStage::Vertex,
+            1 => ShaderStage::TessControl,
+            2 => ShaderStage::TessEvaluation,
+            3 => ShaderStage::Geometry,
+            4 => ShaderStage::Fragment,
+            5 => ShaderStage::Compute,
+            5267 | 5364 => ShaderStage::Task,
+            5268 | 5365 => ShaderStage::Mesh,
+            5313 => ShaderStage::RayGen,
+            5314 => ShaderStage::Intersection,
+            5315 => ShaderStage::AnyHit,
+            5316 => ShaderStage::ClosestHit,
+            5317 => ShaderStage::Miss,
+            5318 => ShaderStage::Callable,
+            _ => return None
+        })
+    }
+
+    pub fn vk(self) -> vk::ShaderStageFlags {
+        match self {
+            ShaderStage::Vertex => vk::ShaderStageFlags::VERTEX,
+            ShaderStage::Fragment => vk::ShaderStageFlags::FRAGMENT,
+            ShaderStage::Compute => vk::ShaderStageFlags::COMPUTE,
+            ShaderStage::Geometry => vk::ShaderStageFlags::GEOMETRY,
+            ShaderStage::TessControl => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+            ShaderStage::TessEvaluation => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+            ShaderStage::Task => vk::ShaderStageFlags::TASK_EXT,
+            ShaderStage::Mesh => vk::ShaderStageFlags::MESH_EXT,
+            ShaderStage::RayGen => vk::ShaderStageFlags::RAYGEN_KHR,
+            ShaderStage::AnyHit => vk::ShaderStageFlags::ANY_HIT_KHR,
+            ShaderStage::ClosestHit => vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+            ShaderStage::Miss => vk::ShaderStageFlags::MISS_KHR,
+            ShaderStage::Intersection => vk::ShaderStageFlags::INTERSECTION_KHR,
+            ShaderStage::Callable => vk::ShaderStageFlags::CALLABLE_KHR
+        }
+    }
+}
+
+impl fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+///
+/// Reports changed files once they were quiet for the debounce time.
+/// Parent directories are watched, so editors replacing the file on save are handled too
+///
+/// # Example
+/// ```no_run
+/// # use std::path::Path;
+/// # use ferrum_shaders::FileWatcher;
+/// let mut watcher = FileWatcher::new().unwrap();
+/// watcher.watch(Path::new("shared/shaders/triangle.frag"));
+///
+/// // every frame
+/// for path in watcher.changed() {
+///     println!("{} changed", path.display());
+/// }
+/// ```
+///
+pub struct FileWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    files: HashSet<PathBuf>,
+    dirs: HashSet<PathBuf>,
+    /// Last event of every changed file
+    pending: HashMap<PathBuf, Instant>,
+    debounce: Duration
+}
+
+impl FileWatcher {
+
+    pub fn new() -> notify::Result<Self> {
+
+        let (sender, events) = channel();
+        let watcher = notify::recommended_watcher(sender)?;
+
+        Ok(Self {
+            watcher,
+            events,
+            files: HashSet::new(),
+            dirs: HashSet::new(),
+            pending: HashMap::new(),
+            debounce: Duration::from_millis(100)
+        })
+    }
+
+    /// Default: 100 ms, editors write a file in several steps
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Canonical path, as returned by [`FileWatcher::changed`]
+    pub fn watch(&mut self, path: &Path) -> PathBuf {
+
+        let path = canonical(path);
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+
+        if !self.dirs.contains(&dir) {
+            match self.watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                Ok(()) => { self.dirs.insert(dir); },
+                Err(e) => log::warn!("Failed to watch {}: {}", dir.display(), e)
+            }
+        }
+
+        self.files.insert(path.clone());
+        path
+    }
+
+    pub fn is_watched(&self, path: &Path) -> bool {
+        self.files.contains(&canonical(path))
+    }
+
+    /// Files changed since the last call, without waiting
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+
+        let now = Instant::now();
+
+        while let Ok(event) = self.events.try_recv() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    log::warn!("File watcher error: {}", e);
+                    continue;
+                }
+            };
+
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+
+            for path in event.paths {
+                if self.files.contains(&path) {
+                    self.pending.insert(path, now);
+                }
+            }
+        }
+
+        let ready = self.pending.iter()
+            .filter(|(_, time)| now.duration_since(**time) >= self.debounce)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+
+        for path in &ready {
+            self.pending.remove(path);
+        }
+
+        ready
+    }
+}
+
+/// Not existing files keep the path, they are reported once created
+fn canonical(path: &Path) -> PathBuf {
+    if let Ok(path) = std::fs::canonicalize(path) {
+        return path;
+    }
+
+    match (path.parent().and_then(|x| std::fs::canonicalize(x).ok()), path.file_name()) {
+        (Some(dir), Some(name)) => dir.join(name),
+        _ => path.to_path_buf()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_changed_file() {
+
+        let dir = std::env::temp_dir().join(format!("ferrum-shaders-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.frag"), "void main() {}").unwrap();
+        std::fs::write(dir.join("b.frag"), "void main() {}").unwrap();
+
+        let mut watcher = FileWatcher::new().unwrap().with_debounce(Duration::from_millis(20));
+        watcher.watch(&dir.join("a.frag"));
+
+        std::fs::write(dir.join("b.frag"), "// not watched").unwrap();
+        std::fs::write(dir.join("a.frag"), "// changed").unwrap();
+
+        let start = Instant::now();
+        let mut changed = vec![];
+
+        while changed.is_empty() && start.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+            changed = watcher.changed();
+        }
+
+        assert_eq!(changed, vec![canonical(&dir.join("a.frag"))]);
+        // Событие уже отдано
+        assert!(watcher.changed().is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -7,6 +7,7 @@ use ash::vk::{self, AttachmentReference, BufferUsageFlags, CommandBuffer, Comman
 use ferrum_assets::*;
 use ferrum_render::*;
 use ferrum_graph::*;
+use ferrum_macros::ShaderUniform;
 
 use winit::raw_window_handle::*;
 use log::*;
@@ -79,8 +80,10 @@ fn rotation_matrix(angle_rad: f32, axis: [f32; 3]) -> [[f32; 4]; 4] {
 }
 
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug, Default, ShaderUniform)]
 #[repr(C)]
+#[binding(0)]
+#[stages(VERTEX)]
 struct UniformBufferObject {
     model: [[f32; 4]; 4],
     view: [[f32; 4]; 4],
@@ -103,7 +106,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         .build(&main_loop)
         .unwrap();
 
-    let mut ctx: RenderContext = RenderContext::default(window);
+    let mut ctx: RenderContext = RenderContext::default(window)?;
 
 
     //------
@@ -211,6 +214,13 @@ fn main() -> Result<(), Box<dyn Error>> {
         .expect("Failed to create sampler")
     };
 
+    // Texture пока без обёртки, уничтожается через очередь удаления после выхода из цикла
+    let _texture_owner = ResourceOwner::new(ctx.device.raw_device(), vec![
+        DeferredResource::Sampler(sampler),
+        DeferredResource::ImageView(image_view),
+        DeferredResource::Image(texture.raw, texture_image_memory)
+    ]);
+
 
     //------
 
@@ -254,11 +264,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     let layout = DescriptorSetLayoutBuilder::new()
         .with_device(ctx.device.raw_device())
         .with_bindings(&[
-            vk::DescriptorSetLayoutBinding::default()
-                .binding(0)
-                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
-                .descriptor_count(1)
-                .stage_flags(vk::ShaderStageFlags::VERTEX),
+            UniformBufferObject::descriptor_set_layout_binding(),
 
             vk::DescriptorSetLayoutBinding::default()
                 .binding(1)
@@ -267,7 +273,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .stage_flags(vk::ShaderStageFlags::FRAGMENT)
             ]
         )
-        .build();
+        .build()?;
 
     let descriptor_pool = DescriptorPoolBuilder::new()
         .with_device(ctx.device.raw_device())
@@ -281,7 +287,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
                 .descriptor_count(1)
         ])
-        .build();
+        .build()?;
 
     // Выделяем Descriptor Set
     let layout = std::slice::from_ref(&layout.raw);
@@ -332,7 +338,19 @@ fn main() -> Result<(), Box<dyn Error>> {
         .with_graphics_device(&ctx)
         .with_fragment_shader(load_spv(r"..\..\shared\shaders\spv\triangle-frag.spv"))
         .with_vertex_shader(load_spv(r"..\..\shared\shaders\spv\triangle-vert.spv"))
-        .build(layout[0]);
+        .build(layout[0])?;
+
+    // Debug-сборка: правки в shared/shaders пересобирают пайплайн без перезапуска
+    let set_layout = layout[0];
+    let mut shader_reload = ShaderHotReload::new();
+
+    shader_reload.watch_render_pipeline("pipe", &[r"..\..\shared\shaders\triangle.vert", r"..\..\shared\shaders\triangle.frag"], &ShaderDefines::new(), move |ctx, shaders| {
+        StandartPipelineBuilder::new()
+            .with_graphics_device(ctx)
+            .with_vertex_shader(shaders[ShaderStage::Vertex].clone())
+            .with_fragment_shader(shaders[ShaderStage::Fragment].clone())
+            .build(set_layout)
+    });
 
     //let (data, index) = &load_model(r"..\..\shared\assets\models\cube.obj").expect("EEEER");
 
@@ -381,6 +399,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     graph.register_buffer("image_buffer", image_buffer);
     graph.register_pipeline("pipe", pipeline);
     graph.register_descriptor_set("set", descriptor_set);
+    graph.set_shader_reload(shader_reload);
 
     graph.add_raw_pass("Simple", move |res, ctx, image_index| {
 
@@ -403,14 +422,10 @@ fn main() -> Result<(), Box<dyn Error>> {
             command_buffer
         };
 
-        let render_pass = &ctx.window.render_pass;
-        let frame_buffer = ctx.window.frame_buffers.raw[image_index as usize];
+        let render_pass = ctx.window.render_pass.as_ref().ok_or("cube example needs a render pass")?;
+        let frame_buffer = ctx.window.frame_buffers.as_ref().ok_or("cube example needs frame buffers")?.raw[image_index as usize];
 
-        let clear_values = [vk::ClearValue {
-            color: vk::ClearColorValue {
-                float32: [5.0/255.0, 5.0/255.0, 5.0/255.0, 1.0],
-            },
-        }];
+        let clear_values = ctx.window.clear_values([5.0/255.0, 5.0/255.0, 5.0/255.0, 1.0]);
 
         let render_pass_begin_info = vk::RenderPassBeginInfo::default()
             .render_pass(render_pass.raw)
@@ -502,7 +517,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 ctx.device.raw_device().cmd_copy_buffer_to_image(
                     command_buffer,
                     image_buffer.raw,
-                    texture.raw,
+                    tex.raw,
                     vk::ImageLayout::TRANSFER_DST_OPTIMAL,
                     &[buffer_copy],
                 );
@@ -561,6 +576,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut time = Instant::now();
     let mut count_frame = 0;
 
+    let mut shader_error = false;
     let mut angle = 0.0f32; // Угол в радианах
     let rotation_speed = 0.0001; // Скорость вращения (рад/сек)
 
@@ -589,7 +605,24 @@ fn main() -> Result<(), Box<dyn Error>> {
                 angle += rotation_speed * global_time.elapsed().as_millis() as f32;
                 global_time = Instant::now();
 
-                graph.execute(&ctx);
+                // Все ресурсы устройства потеряны, пересоздать их в примере негде
+                if let Err(err) = graph.execute(&mut ctx) {
+                    error!("{}", err);
+
+                    if err.is_device_lost() {
+                        ev_window.exit();
+                    }
+                }
+                // Старый пайплайн продолжает рисовать, ошибка видна в заголовке окна
+                let error = graph.shader_reload().and_then(|x| x.errors().next()).map(|(name, _)| name);
+                if error.is_some() != shader_error {
+                    shader_error = error.is_some();
+                    ctx.window.raw.set_title(&match error {
+                        Some(name) => format!("Game - shader error in {:?}, see log", name),
+                        None => "Game".to_owned()
+                    });
+                }
+
                 if time.elapsed().as_secs() >= 1 {
                     info!("FPS: {}", count_frame);
                     time = Instant::now();
@@ -605,7 +638,9 @@ fn main() -> Result<(), Box<dyn Error>> {
             winit::event::WindowEvent::Resized(size) => {
 
                 let dev = ctx.device.clone();
-                ctx.window.resize(&dev, size.width, size.height);
+                if let Err(err) = ctx.window.resize(&dev, size.width, size.height) {
+                    error!("{}", err);
+                }
 
                 let fov = std::f32::consts::PI / 3.0;
                 let aspect = size.width as f32 / size.height as f32;
@@ -0,0 +1,69 @@
+#version 450
+// PBR metallic-roughness, см. pbr.glsl. Источники берутся из кластера фрагмента
+layout(location = 0) in vec3 inWorldPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in float inViewDepth;
+
+layout(location = 0) out vec4 outColor;
+
+#include "clustered.glsl"
+
+layout(std430, set = 0, binding = 3) readonly buffer LightCounts {
+    uint lightCounts[];
+};
+
+layout(std430, set = 0, binding = 4) readonly buffer LightIndices {
+    uint lightIndices[];
+};
+
+#include "pbr-material.glsl"
+
+#ifdef SHADOWS
+#include "shadow.glsl"
+#endif
+
+void main() {
+    vec3 albedo = material.baseColor.rgb;
+    float metallic = clamp(material.metallic, 0.0, 1.0);
+    float roughness = clamp(material.roughness, 0.04, 1.0);
+    vec3 f0 = mix(vec3(0.04), albedo, metallic);
+
+    vec3 n = normalize(inNormal);
+    vec3 v = normalize(frame.cameraPosition.xyz - inWorldPosition);
+
+    vec3 color = frame.ambient.rgb * albedo;
+
+    for (uint i = 0u; i < frame.counts.y; i++) {
+        vec3 l = -normalize(lights[i].direction);
+        vec3 radiance = lights[i].color * lights[i].intensity;
+#ifdef SHADOWS
+        if (lights[i].shadow >= 0) {
+            radiance *= directionalShadow(inWorldPosition, n, inViewDepth, gl_FragCoord.xy);
+        }
+#endif
+        color += shade(n, v, l, radiance, albedo, f0, roughness, metallic);
+    }
+
+    // Кластер фрагмента, формула обратна sliceDepth
+    float logRatio = log(frame.zFar / frame.zNear);
+    float slice = floor(log(max(inViewDepth, frame.zNear) / frame.zNear) / logRatio * float(frame.grid.z));
+    uvec2 tile = uvec2(gl_FragCoord.xy / tileSize());
+    uvec3 cluster = min(uvec3(tile, uint(max(slice, 0.0))), frame.grid.xyz - 1u);
+    uint index = clusterIndex(cluster);
+
+    uint offset = index * frame.grid.w;
+    uint count = min(lightCounts[index], frame.grid.w);
+
+    for (uint i = 0u; i < count; i++) {
+        Light light = lights[lightIndices[offset + i]];
+
+        vec3 l;
+        vec3 radiance = punctualRadiance(light, light.position, light.direction, inWorldPosition, l);
+#ifdef SHADOWS
+        radiance *= localShadow(light, inWorldPosition, n, gl_FragCoord.xy);
+#endif
+        color += shade(n, v, l, radiance, albedo, f0, roughness, metallic);
+    }
+
+    outColor = vec4(color + material.emissive.rgb, material.baseColor.a);
+}
@@ -0,0 +1,23 @@
+#version 450
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+
+layout(location = 0) out vec3 outWorldPosition;
+layout(location = 1) out vec3 outNormal;
+layout(location = 2) out float outViewDepth;
+
+#include "clustered.glsl"
+
+#include "pbr-material.glsl"
+
+void main() {
+    vec4 world = material.model * vec4(inPosition, 1.0);
+    vec4 view = frame.view * world;
+
+    outWorldPosition = world.xyz;
+    // Без неравномерного масштаба mat3(model) достаточно
+    outNormal = mat3(material.model) * inNormal;
+    outViewDepth = -view.z;
+
+    gl_Position = frame.projection * view;
+}
@@ -0,0 +1,41 @@
+// Общие ресурсы кластерного освещения, set 0 у всех шейдеров
+// Раскладка совпадает с ClusterUniforms и ClusterBounds в ferrum-render
+
+#include "pbr.glsl"
+
+struct ClusterBounds {
+    vec4 minPoint;
+    vec4 maxPoint;
+};
+
+layout(set = 0, binding = 0) uniform ClusterUniforms {
+    mat4 view;
+    mat4 projection;
+    mat4 inverseProjection;
+    vec4 cameraPosition;
+    vec4 ambient;
+    vec2 screenSize;
+    float zNear;
+    float zFar;
+    // xyz - кластеры, w - максимум источников в кластере
+    uvec4 grid;
+    // x - все источники, y - directional в начале буфера
+    uvec4 counts;
+} frame;
+
+layout(std430, set = 0, binding = 1) readonly buffer Lights {
+    Light lights[];
+};
+
+vec2 tileSize() {
+    return ceil(frame.screenSize / vec2(frame.grid.xy));
+}
+
+// Экспоненциальные срезы по глубине, как в ClusterGrid::slice_depth
+float sliceDepth(float slice) {
+    return frame.zNear * pow(frame.zFar / frame.zNear, slice / float(frame.grid.z));
+}
+
+uint clusterIndex(uvec3 cluster) {
+    return cluster.x + cluster.y * frame.grid.x + cluster.z * frame.grid.x * frame.grid.y;
+}
@@ -0,0 +1,54 @@
+#version 450
+// Освещение + emissive, либо один из каналов GBuffer для отладки
+layout(location = 0) in vec2 inUv;
+layout(location = 0) out vec4 outColor;
+
+layout(set = 0, binding = 0) uniform texture2D lightingTexture;
+layout(set = 0, binding = 1) uniform texture2D emissiveTexture;
+layout(set = 0, binding = 2) uniform texture2D albedoTexture;
+layout(set = 0, binding = 3) uniform texture2D normalTexture;
+layout(set = 0, binding = 4) uniform texture2D materialTexture;
+layout(set = 0, binding = 5) uniform texture2D depthTexture;
+layout(set = 0, binding = 6) uniform sampler pointSampler;
+
+layout(push_constant) uniform CompositeConstants {
+    // DeferredView в ferrum-render, 0 - итоговое изображение
+    uint view;
+    float exposure;
+    // 0 если в раскладке нет emissive, на его месте освещение
+    uint hasEmissive;
+} params;
+
+#include "octahedral.glsl"
+
+void main() {
+    vec3 lighting = texture(sampler2D(lightingTexture, pointSampler), inUv).rgb;
+    vec3 emissive = params.hasEmissive != 0u ? texture(sampler2D(emissiveTexture, pointSampler), inUv).rgb : vec3(0.0);
+    vec3 color;
+
+    switch (params.view) {
+        case 1u:
+            color = texture(sampler2D(albedoTexture, pointSampler), inUv).rgb;
+            break;
+        case 2u:
+            color = octahedralDecode(texture(sampler2D(normalTexture, pointSampler), inUv).xy) * 0.5 + 0.5;
+            break;
+        case 3u:
+            color = vec3(texture(sampler2D(materialTexture, pointSampler), inUv).xy, 0.0);
+            break;
+        case 4u:
+            color = emissive;
+            break;
+        case 5u:
+            color = vec3(texture(sampler2D(depthTexture, pointSampler), inUv).r);
+            break;
+        case 6u:
+            color = lighting;
+            break;
+        default:
+            color = (lighting + emissive) * params.exposure;
+            break;
+    }
+
+    outColor = vec4(color, 1.0);
+}
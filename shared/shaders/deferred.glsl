@@ -0,0 +1,18 @@
+// Set 0 deferred шейдеров: кадр и источники света
+// Раскладка совпадает с DeferredUniforms в ferrum-render
+
+#include "pbr.glsl"
+#include "octahedral.glsl"
+
+layout(set = 0, binding = 0) uniform DeferredUniforms {
+    mat4 view;
+    mat4 projection;
+    mat4 inverseProjection;
+    vec4 ambient;
+    // xy - размер GBuffer, z - все источники, w - directional в начале буфера
+    uvec4 screen;
+} frame;
+
+layout(std430, set = 0, binding = 1) readonly buffer Lights {
+    Light lights[];
+};
@@ -0,0 +1,21 @@
+#version 450
+// Материал в GBuffer, освещение считает deferred-lighting.comp
+layout(location = 0) in vec3 inNormal;
+
+layout(location = 0) out vec4 outAlbedo;
+// Мировая нормаль, октаэдрическая упаковка
+layout(location = 1) out vec2 outNormal;
+// x - roughness, y - metallic
+layout(location = 2) out vec2 outMaterial;
+// Без emissive в раскладке запись отбрасывается
+layout(location = 3) out vec4 outEmissive;
+
+#include "deferred.glsl"
+#include "pbr-material.glsl"
+
+void main() {
+    outAlbedo = vec4(material.baseColor.rgb, 1.0);
+    outNormal = octahedralEncode(normalize(inNormal));
+    outMaterial = vec2(clamp(material.roughness, 0.04, 1.0), clamp(material.metallic, 0.0, 1.0));
+    outEmissive = vec4(material.emissive.rgb, 0.0);
+}
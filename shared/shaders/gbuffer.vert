@@ -0,0 +1,14 @@
+#version 450
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+
+layout(location = 0) out vec3 outNormal;
+
+#include "deferred.glsl"
+#include "pbr-material.glsl"
+
+void main() {
+    // Без неравномерного масштаба mat3(model) достаточно
+    outNormal = mat3(material.model) * inNormal;
+    gl_Position = frame.projection * frame.view * material.model * vec4(inPosition, 1.0);
+}
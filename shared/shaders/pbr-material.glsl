@@ -0,0 +1,10 @@
+#pragma once
+// Материал меша, совпадает с PbrPushConstants в ferrum-render
+layout(push_constant) uniform PbrPushConstants {
+    mat4 model;
+    vec4 baseColor;
+    // rgb - излучение, a не используется
+    vec4 emissive;
+    float metallic;
+    float roughness;
+} material;
@@ -0,0 +1,82 @@
+#pragma once
+// Источники света и BRDF metallic-roughness, общие для forward+ и deferred
+// Раскладка Light совпадает с Light в ferrum-render
+
+#define LIGHT_DIRECTIONAL 0u
+#define LIGHT_POINT 1u
+#define LIGHT_SPOT 2u
+
+struct Light {
+    vec3 position;
+    float range;
+    vec3 color;
+    float intensity;
+    // Куда светит, для directional и spot
+    vec3 direction;
+    uint kind;
+    float spotScale;
+    float spotOffset;
+    // Индекс в ShadowMatrices или каскады для directional, -1 без тени
+    int shadow;
+    uint castShadows;
+};
+
+const float PI = 3.14159265359;
+
+float distributionGgx(float nDotH, float alpha) {
+    float a2 = alpha * alpha;
+    float d = nDotH * nDotH * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+float geometrySmith(float nDotV, float nDotL, float roughness) {
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    float gv = nDotV / (nDotV * (1.0 - k) + k);
+    float gl = nDotL / (nDotL * (1.0 - k) + k);
+    return gv * gl;
+}
+
+vec3 fresnelSchlick(float cosTheta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - cosTheta, 5.0);
+}
+
+// Освещённость от одного источника, l - направление на источник
+vec3 shade(vec3 n, vec3 v, vec3 l, vec3 radiance, vec3 albedo, vec3 f0, float roughness, float metallic) {
+    vec3 h = normalize(v + l);
+    float nDotL = max(dot(n, l), 0.0);
+    float nDotV = max(dot(n, v), 1e-4);
+    float nDotH = max(dot(n, h), 0.0);
+
+    float d = distributionGgx(nDotH, roughness * roughness);
+    float g = geometrySmith(nDotV, nDotL, roughness);
+    vec3 f = fresnelSchlick(max(dot(h, v), 0.0), f0);
+
+    vec3 specular = d * g * f / max(4.0 * nDotV * nDotL, 1e-4);
+    vec3 diffuse = (1.0 - f) * (1.0 - metallic) * albedo / PI;
+
+    return (diffuse + specular) * radiance * nDotL;
+}
+
+// Обратный квадрат, плавно обнулённый к range
+float distanceAttenuation(float distance, float range) {
+    float ratio = distance / range;
+    float window = clamp(1.0 - ratio * ratio * ratio * ratio, 0.0, 1.0);
+    return window * window / max(distance * distance, 1e-4);
+}
+
+// Point и spot: radiance в точке, l - направление на источник.
+// lightPosition и lightDirection в том же пространстве, что и position
+vec3 punctualRadiance(Light light, vec3 lightPosition, vec3 lightDirection, vec3 position, out vec3 l) {
+    vec3 toLight = lightPosition - position;
+    float distance = length(toLight);
+    l = toLight / max(distance, 1e-4);
+    float attenuation = distanceAttenuation(distance, light.range);
+
+    if (light.kind == LIGHT_SPOT) {
+        float cd = dot(normalize(lightDirection), -l);
+        float spot = clamp(cd * light.spotScale + light.spotOffset, 0.0, 1.0);
+        attenuation *= spot * spot;
+    }
+
+    return light.color * light.intensity * attenuation;
+}
@@ -0,0 +1,12 @@
+#version 450
+// Только глубина: каскады и тайлы атласа рисуются одним шейдером с матрицей в push constants
+layout(location = 0) in vec3 inPosition;
+
+layout(push_constant) uniform ShadowPushConstants {
+    mat4 model;
+    mat4 viewProjection;
+} draw;
+
+void main() {
+    gl_Position = draw.viewProjection * draw.model * vec4(inPosition, 1.0);
+}
@@ -0,0 +1,248 @@
+#pragma once
+// Тени: каскады для directional, атлас для spot и point. Раскладка совпадает с ShadowUniforms
+// и ShadowMatrix в ferrum-render. Set задаётся SHADOW_SET, карты привязаны дважды:
+// со сравнением для PCF и без него для поиска блокеров PCSS
+
+#include "pbr.glsl"
+
+#ifndef SHADOW_SET
+#define SHADOW_SET 1
+#endif
+
+#define SHADOW_FILTER_HARD 0u
+#define SHADOW_FILTER_PCF 1u
+#define SHADOW_FILTER_PCSS 2u
+
+struct ShadowMatrix {
+    mat4 viewProjection;
+    // xy - смещение тайла в атласе, zw - размер, в UV
+    vec4 rect;
+    // x - near, y - far, z - размер текселя на единицу расстояния, w - размер источника
+    vec4 params;
+};
+
+layout(set = SHADOW_SET, binding = 0) uniform ShadowUniforms {
+    mat4 cascades[4];
+    mat4 inverseView;
+    // Дальняя граница каскада по глубине камеры
+    vec4 splits;
+    // Размер текселя каскада в мире
+    vec4 texelSizes;
+    // Глубина ортографической проекции каскада в мире
+    vec4 depthRanges;
+    // x - каскады, y - фильтр, z - сэмплы
+    uvec4 params;
+    // x - радиус PCF в текселях, y - угловой размер солнца, z - максимум полутени в текселях,
+    // w - смещение по нормали в текселях
+    vec4 filterParams;
+} shadow;
+
+layout(std430, set = SHADOW_SET, binding = 1) readonly buffer ShadowMatrices {
+    ShadowMatrix shadowMatrices[];
+};
+
+layout(set = SHADOW_SET, binding = 2) uniform texture2DArray cascadeMap;
+layout(set = SHADOW_SET, binding = 3) uniform texture2DArray cascadeDepth;
+layout(set = SHADOW_SET, binding = 4) uniform texture2D atlasMap;
+layout(set = SHADOW_SET, binding = 5) uniform texture2D atlasDepth;
+layout(set = SHADOW_SET, binding = 6) uniform samplerShadow shadowSampler;
+layout(set = SHADOW_SET, binding = 7) uniform sampler shadowPointSampler;
+
+const vec2 POISSON[16] = vec2[](
+    vec2(-0.94201624, -0.39906216), vec2(0.94558609, -0.76890725),
+    vec2(-0.09418410, -0.92938870), vec2(0.34495938, 0.29387760),
+    vec2(-0.91588581, 0.45771432), vec2(-0.81544232, -0.87912464),
+    vec2(-0.38277543, 0.27676845), vec2(0.97484398, 0.75648379),
+    vec2(0.44323325, -0.97511554), vec2(0.53742981, -0.47373420),
+    vec2(-0.26496911, -0.41893023), vec2(0.79197514, 0.19090188),
+    vec2(-0.24188840, 0.99706507), vec2(-0.81409955, 0.91437590),
+    vec2(0.19984126, 0.78641367), vec2(0.14383161, -0.14100790)
+);
+
+// Поворот диска на пиксель, убирает полосы ценой шума
+mat2 shadowRotation(vec2 pixel) {
+    float angle = 6.2831853 * fract(52.9829189 * fract(dot(pixel, vec2(0.06711056, 0.00583715))));
+    float s = sin(angle);
+    float c = cos(angle);
+    return mat2(c, s, -s, c);
+}
+
+uint shadowSamples() {
+    return clamp(shadow.params.z, 1u, 16u);
+}
+
+float cascadeCompare(vec2 uv, float layer, float depth) {
+    // textureLod с массивом теней в GLSL нет, нулевые градиенты дают тот же уровень 0
+    return textureGrad(sampler2DArrayShadow(cascadeMap, shadowSampler), vec4(uv, layer, depth), vec2(0.0), vec2(0.0));
+}
+
+float atlasCompare(vec2 uv, vec4 rect, float depth) {
+    // Не выходим за тайл, соседние тайлы принадлежат другим источникам
+    vec2 texel = 1.0 / vec2(textureSize(sampler2DShadow(atlasMap, shadowSampler), 0));
+    vec2 clamped = clamp(uv, rect.xy + texel, rect.xy + rect.zw - texel);
+    return textureLod(sampler2DShadow(atlasMap, shadowSampler), vec3(clamped, depth), 0.0);
+}
+
+// Средняя глубина блокеров ближе depth, отрицательная если их нет
+float cascadeBlockers(vec2 uv, float layer, float depth, float radius, mat2 rotation) {
+    float sum = 0.0;
+    float count = 0.0;
+
+    for (uint i = 0u; i < shadowSamples(); i++) {
+        vec2 offset = rotation * POISSON[i] * radius;
+        float blocker = textureLod(sampler2DArray(cascadeDepth, shadowPointSampler), vec3(uv + offset, layer), 0.0).r;
+
+        if (blocker < depth) {
+            sum += blocker;
+            count += 1.0;
+        }
+    }
+
+    return count > 0.0 ? sum / count : -1.0;
+}
+
+float atlasBlockers(vec2 uv, vec4 rect, float depth, float radius, mat2 rotation) {
+    float sum = 0.0;
+    float count = 0.0;
+
+    for (uint i = 0u; i < shadowSamples(); i++) {
+        vec2 offset = rotation * POISSON[i] * radius;
+        vec2 tap = clamp(uv + offset, rect.xy, rect.xy + rect.zw);
+        float blocker = textureLod(sampler2D(atlasDepth, shadowPointSampler), tap, 0.0).r;
+
+        if (blocker < depth) {
+            sum += blocker;
+            count += 1.0;
+        }
+    }
+
+    return count > 0.0 ? sum / count : -1.0;
+}
+
+// Линейная глубина перспективной проекции с глубиной 0..1
+float linearShadowDepth(float depth, float near, float far) {
+    return near * far / (far - depth * (far - near));
+}
+
+int shadowCascade(float viewDepth) {
+    for (uint i = 0u; i < shadow.params.x; i++) {
+        if (viewDepth < shadow.splits[i]) {
+            return int(i);
+        }
+    }
+    return -1;
+}
+
+// 1 - освещено, 0 - в тени. worldNormal нормализован, viewDepth положителен
+float directionalShadow(vec3 worldPosition, vec3 worldNormal, float viewDepth, vec2 pixel) {
+    int cascade = shadowCascade(viewDepth);
+
+    if (cascade < 0) {
+        return 1.0;
+    }
+
+    float texelSize = shadow.texelSizes[cascade];
+    vec3 position = worldPosition + worldNormal * shadow.filterParams.w * texelSize;
+    vec4 coord = shadow.cascades[cascade] * vec4(position, 1.0);
+    vec2 uv = coord.xy * 0.5 + 0.5;
+    float depth = coord.z;
+    float layer = float(cascade);
+
+    if (depth >= 1.0) {
+        return 1.0;
+    }
+
+    uint mode = shadow.params.y;
+    if (mode == SHADOW_FILTER_HARD) {
+        return cascadeCompare(uv, layer, depth);
+    }
+
+    vec2 texel = 1.0 / vec2(textureSize(sampler2DArrayShadow(cascadeMap, shadowSampler), 0).xy);
+    mat2 rotation = shadowRotation(pixel);
+    float radius = shadow.filterParams.x;
+
+    if (mode == SHADOW_FILTER_PCSS) {
+        float search = shadow.filterParams.z;
+        float blocker = cascadeBlockers(uv, layer, depth, search * texel.x, rotation);
+
+        if (blocker < 0.0) {
+            return 1.0;
+        }
+
+        // Полутень растёт с расстоянием до блокера, ортографическая глубина линейна
+        float penumbra = (depth - blocker) * shadow.depthRanges[cascade] * shadow.filterParams.y / texelSize;
+        radius = clamp(penumbra, 1.0, search);
+    }
+
+    float lit = 0.0;
+    for (uint i = 0u; i < shadowSamples(); i++) {
+        lit += cascadeCompare(uv + rotation * POISSON[i] * radius * texel, layer, depth);
+    }
+    return lit / float(shadowSamples());
+}
+
+// Spot и point из атласа, у point шесть граней подряд начиная с light.shadow
+float localShadow(Light light, vec3 worldPosition, vec3 worldNormal, vec2 pixel) {
+    if (light.shadow < 0) {
+        return 1.0;
+    }
+
+    vec3 toSurface = worldPosition - light.position;
+    int index = light.shadow;
+
+    if (light.kind == LIGHT_POINT) {
+        // Грани +X, -X, +Y, -Y, +Z, -Z
+        vec3 a = abs(toSurface);
+        if (a.x >= a.y && a.x >= a.z) {
+            index += toSurface.x > 0.0 ? 0 : 1;
+        } else if (a.y >= a.z) {
+            index += toSurface.y > 0.0 ? 2 : 3;
+        } else {
+            index += toSurface.z > 0.0 ? 4 : 5;
+        }
+    }
+
+    ShadowMatrix entry = shadowMatrices[index];
+    float distance = length(toSurface);
+    float texelSize = entry.params.z * distance;
+
+    vec3 position = worldPosition + worldNormal * shadow.filterParams.w * texelSize;
+    vec4 coord = entry.viewProjection * vec4(position, 1.0);
+    vec3 ndc = coord.xyz / coord.w;
+
+    if (coord.w <= 0.0 || ndc.z >= 1.0) {
+        return 1.0;
+    }
+
+    vec2 uv = entry.rect.xy + (ndc.xy * 0.5 + 0.5) * entry.rect.zw;
+    float depth = ndc.z;
+
+    uint mode = shadow.params.y;
+    if (mode == SHADOW_FILTER_HARD) {
+        return atlasCompare(uv, entry.rect, depth);
+    }
+
+    vec2 texel = 1.0 / vec2(textureSize(sampler2DShadow(atlasMap, shadowSampler), 0));
+    mat2 rotation = shadowRotation(pixel);
+    float radius = shadow.filterParams.x;
+
+    if (mode == SHADOW_FILTER_PCSS) {
+        float search = shadow.filterParams.z;
+        float blocker = atlasBlockers(uv, entry.rect, depth, search * texel.x, rotation);
+
+        if (blocker < 0.0) {
+            return 1.0;
+        }
+
+        float receiverDistance = linearShadowDepth(depth, entry.params.x, entry.params.y);
+        float blockerDistance = linearShadowDepth(blocker, entry.params.x, entry.params.y);
+        float penumbra = (receiverDistance - blockerDistance) / blockerDistance * entry.params.w / texelSize;
+        radius = clamp(penumbra, 1.0, search);
+    }
+
+    float lit = 0.0;
+    for (uint i = 0u; i < shadowSamples(); i++) {
+        lit += atlasCompare(uv + rotation * POISSON[i] * radius * texel, entry.rect, depth);
+    }
+    return lit / float(shadowSamples());
+}
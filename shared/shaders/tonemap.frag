@@ -0,0 +1,91 @@
+#version 450
+layout(location = 0) in vec2 inUv;
+layout(location = 0) out vec4 outColor;
+
+// Сцена в линейном Rec.709, 1.0 = paper white
+layout(set = 0, binding = 0) uniform texture2D hdrScene;
+layout(set = 0, binding = 1) uniform sampler hdrSampler;
+
+layout(push_constant) uniform ToneMapParams {
+    float exposure;
+    float paperWhiteNits;
+    float maxNits;
+    // 0 - sRGB, 1 - Display P3, 2 - HDR10 (PQ), 3 - scRGB
+    uint mode;
+    // 1 если формат swapchain UNORM и кодировать sRGB нужно вручную
+    uint encodeSrgb;
+} params;
+
+const mat3 REC709_TO_REC2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+const mat3 REC709_TO_P3 = mat3(
+    0.8225, 0.0332, 0.0171,
+    0.1774, 0.9669, 0.0724,
+    0.0000, 0.0000, 0.9108
+);
+
+vec3 acesFilm(vec3 x) {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
+
+vec3 srgbEncode(vec3 c) {
+    vec3 lo = c * 12.92;
+    vec3 hi = 1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055;
+    return mix(hi, lo, vec3(lessThanEqual(c, vec3(0.0031308))));
+}
+
+// SMPTE ST 2084, вход в нитах
+vec3 pqEncode(vec3 nits) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 y = pow(clamp(nits / 10000.0, 0.0, 1.0), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+// Мягкое ограничение яркости до maxNits, ниже paper white без изменений
+vec3 softClip(vec3 nits, float paperWhite, float maxNits) {
+    float peak = max(nits.r, max(nits.g, nits.b));
+    if (peak <= paperWhite || maxNits <= paperWhite) {
+        return min(nits, vec3(max(maxNits, paperWhite)));
+    }
+    float range = maxNits - paperWhite;
+    float over = peak - paperWhite;
+    float mapped = paperWhite + range * over / (over + range);
+    return nits * (mapped / peak);
+}
+
+void main() {
+    vec3 color = max(texture(sampler2D(hdrScene, hdrSampler), inUv).rgb * params.exposure, vec3(0.0));
+
+    if (params.mode == 2u) {
+        vec3 nits = softClip(REC709_TO_REC2020 * color * params.paperWhiteNits, params.paperWhiteNits, params.maxNits);
+        outColor = vec4(pqEncode(nits), 1.0);
+        return;
+    }
+
+    if (params.mode == 3u) {
+        // scRGB: 1.0 = 80 нит, значения выше 1.0 допустимы
+        vec3 nits = softClip(color * params.paperWhiteNits, params.paperWhiteNits, params.maxNits);
+        outColor = vec4(nits / 80.0, 1.0);
+        return;
+    }
+
+    if (params.mode == 1u) {
+        color = max(REC709_TO_P3 * color, vec3(0.0));
+    }
+
+    color = acesFilm(color);
+
+    if (params.encodeSrgb == 1u) {
+        color = srgbEncode(color);
+    }
+
+    outColor = vec4(color, 1.0);
+}